@@ -1,4 +1,18 @@
 fn main() {
+    // For `termi --version` and Help: About termi.
+    let features: Vec<String> = std::env::vars()
+        .filter_map(|(name, _)| name.strip_prefix("CARGO_FEATURE_").map(str::to_lowercase))
+        .collect();
+    println!("cargo:rustc-env=TERMI_FEATURES={}", features.join(","));
+    println!(
+        "cargo:rustc-env=TERMI_TARGET={}",
+        std::env::var("TARGET").unwrap_or_default()
+    );
+    println!(
+        "cargo:rustc-env=TERMI_PROFILE={}",
+        std::env::var("PROFILE").unwrap_or_default()
+    );
+
     #[cfg(windows)]
     {
         let mut res = winres::WindowsResource::new();
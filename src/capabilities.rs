@@ -0,0 +1,411 @@
+use crate::config::TerminalSettings;
+use crossterm::terminal;
+use std::{borrow::Cow, env};
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum ColorSupport {
+    Ansi8,
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorSupport {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "8" => Some(Self::Ansi8),
+            "16" => Some(Self::Ansi16),
+            "256" => Some(Self::Ansi256),
+            "truecolor" | "24bit" => Some(Self::TrueColor),
+            _ => None,
+        }
+    }
+}
+
+/// Characters used for icons and borders, with a plain ASCII set for
+/// terminals or fonts without Unicode support.
+pub struct Glyphs {
+    pub folder: &'static str,
+    pub file: &'static str,
+    pub running: char,
+    pub passed: char,
+    pub failed: char,
+    pub separator: char,
+    pub pointer: char,
+    pub rule: char,
+    pub bullet: char,
+    pub branch: &'static str,
+    ascii: bool,
+}
+
+impl Glyphs {
+    /// Swaps the arrows used in titles and key hints for ASCII when needed.
+    pub fn text(&self, text: String) -> String {
+        if self.ascii {
+            text.replace("↑↓", "Up/Down").replace('↔', "<->")
+        } else {
+            text
+        }
+    }
+}
+
+pub const UNICODE_GLYPHS: Glyphs = Glyphs {
+    folder: "📁",
+    file: "📄",
+    running: '●',
+    passed: '✓',
+    failed: '✗',
+    separator: '│',
+    pointer: '▶',
+    rule: '═',
+    bullet: '•',
+    branch: "⎇",
+    ascii: false,
+};
+
+// Box drawing works in the classic Windows console, but emoji are drawn one
+// cell wide (or as boxes) there, which throws off every column after them.
+pub const CONSOLE_GLYPHS: Glyphs = Glyphs {
+    folder: "+",
+    file: "-",
+    ..UNICODE_GLYPHS
+};
+
+pub const ASCII_GLYPHS: Glyphs = Glyphs {
+    folder: "+",
+    file: "-",
+    running: '*',
+    passed: 'v',
+    failed: 'x',
+    separator: '|',
+    pointer: '>',
+    rule: '=',
+    bullet: '*',
+    branch: "@",
+    ascii: true,
+};
+
+pub struct Capabilities {
+    pub colors: ColorSupport,
+    pub unicode: bool,
+    pub mouse: bool,
+    /// Emoji take two cells, as the screen model assumes.
+    pub wide_emoji: bool,
+    /// The kitty keyboard protocol, which reports combos such as `Ctrl+.`
+    /// that legacy terminals cannot encode.
+    pub keyboard_enhancement: bool,
+}
+
+// Terminals that only understand the basics: 8 colors, ASCII, no mouse.
+const LIMITED_TERMS: &[&str] = &["dumb", "linux", "vt100", "vt102", "vt220", "ansi", "cons25"];
+
+impl Capabilities {
+    /// Guesses what the terminal supports from the environment, letting the
+    /// `[terminal]` config section override each guess. The keyboard protocol
+    /// is queried from the terminal, so this must run before input is read.
+    pub fn detect(settings: &TerminalSettings) -> Self {
+        let term = env::var("TERM").unwrap_or_default();
+        let limited = LIMITED_TERMS.contains(&term.as_str()) || (term.is_empty() && !cfg!(windows));
+
+        let colors = settings
+            .colors
+            .unwrap_or_else(|| detect_colors(&term, limited));
+        let unicode = settings.unicode.unwrap_or(!limited && locale_is_utf8());
+        let mouse = settings.mouse.unwrap_or(!limited);
+        let wide_emoji = settings
+            .emoji
+            .unwrap_or(unicode && (!cfg!(windows) || modern_windows_console()));
+        let keyboard_enhancement = settings.keyboard_enhancement.unwrap_or(!limited)
+            && terminal::supports_keyboard_enhancement().unwrap_or(false);
+
+        Self {
+            colors,
+            unicode,
+            mouse,
+            wide_emoji,
+            keyboard_enhancement,
+        }
+    }
+
+    pub fn glyphs(&self) -> &'static Glyphs {
+        match (self.unicode, self.wide_emoji) {
+            (true, true) => &UNICODE_GLYPHS,
+            (true, false) => &CONSOLE_GLYPHS,
+            (false, _) => &ASCII_GLYPHS,
+        }
+    }
+}
+
+/// Windows Terminal and the terminals embedded in editors set these; the
+/// classic console host (conhost) sets neither.
+fn modern_windows_console() -> bool {
+    env::var_os("WT_SESSION").is_some() || env::var_os("TERM_PROGRAM").is_some()
+}
+
+/// Whether the console interprets escape sequences, which all output relies
+/// on. Consoles older than Windows 10 don't.
+pub fn supports_escape_sequences() -> bool {
+    #[cfg(windows)]
+    {
+        crossterm::ansi_support::supports_ansi()
+    }
+
+    #[cfg(not(windows))]
+    {
+        true
+    }
+}
+
+fn detect_colors(term: &str, limited: bool) -> ColorSupport {
+    let colorterm = env::var("COLORTERM").unwrap_or_default().to_lowercase();
+    if colorterm == "truecolor" || colorterm == "24bit" || env::var_os("WT_SESSION").is_some() {
+        ColorSupport::TrueColor
+    } else if term.contains("256color") {
+        ColorSupport::Ansi256
+    } else if limited {
+        ColorSupport::Ansi8
+    } else if cfg!(windows) {
+        // The Windows 10 console handles 24-bit color once VT mode is on.
+        ColorSupport::TrueColor
+    } else {
+        ColorSupport::Ansi16
+    }
+}
+
+fn locale_is_utf8() -> bool {
+    if cfg!(windows) {
+        return true;
+    }
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .is_some_and(|value| {
+            let value = value.to_lowercase();
+            value.contains("utf-8") || value.contains("utf8")
+        })
+}
+
+// Standard xterm values for the 16 base colors.
+const BASE_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+pub fn index_to_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => BASE_COLORS[index as usize],
+        16..=231 => {
+            let n = index - 16;
+            (
+                CUBE_LEVELS[(n / 36) as usize],
+                CUBE_LEVELS[(n / 6 % 6) as usize],
+                CUBE_LEVELS[(n % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + 10 * (index - 232);
+            (level, level, level)
+        }
+    }
+}
+
+fn rgb_to_256(rgb: (u8, u8, u8)) -> u8 {
+    let nearest_level = |c: u8| {
+        (0..6)
+            .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - c as i32).abs())
+            .unwrap_or(0) as u8
+    };
+    let cube = 16 + 36 * nearest_level(rgb.0) + 6 * nearest_level(rgb.1) + nearest_level(rgb.2);
+    let average = (rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3;
+    let gray = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+    if distance(index_to_rgb(gray), rgb) < distance(index_to_rgb(cube), rgb) {
+        gray
+    } else {
+        cube
+    }
+}
+
+fn rgb_to_16(rgb: (u8, u8, u8)) -> u8 {
+    (0..16u8)
+        .min_by_key(|&i| distance(BASE_COLORS[i as usize], rgb))
+        .unwrap_or(7)
+}
+
+fn downgrade_index(index: u8, colors: ColorSupport) -> u8 {
+    match colors {
+        ColorSupport::TrueColor | ColorSupport::Ansi256 => index,
+        ColorSupport::Ansi16 if index < 16 => index,
+        ColorSupport::Ansi16 => rgb_to_16(index_to_rgb(index)),
+        // Bright colors fall back to their normal counterparts.
+        ColorSupport::Ansi8 => downgrade_index(index, ColorSupport::Ansi16) % 8,
+    }
+}
+
+/// Rewrites 256-color and 24-bit SGR parameters in `frame` so they stay
+/// within what the terminal can display.
+pub fn downgrade_colors(frame: &[u8], colors: ColorSupport) -> Cow<'_, [u8]> {
+    if colors == ColorSupport::TrueColor {
+        return Cow::Borrowed(frame);
+    }
+
+    let mut out = Vec::with_capacity(frame.len());
+    let mut i = 0;
+    while i < frame.len() {
+        if !frame[i..].starts_with(b"\x1b[") {
+            out.push(frame[i]);
+            i += 1;
+            continue;
+        }
+        let params_start = i + 2;
+        let Some(length) = frame[params_start..]
+            .iter()
+            .position(|b| (0x40..=0x7e).contains(b))
+        else {
+            out.extend_from_slice(&frame[i..]);
+            break;
+        };
+        let end = params_start + length;
+        let params = &frame[params_start..end];
+        // Only extended colors (`38;...` / `48;...`) need rewriting.
+        if frame[end] == b'm' && params.windows(2).any(|pair| pair == b"8;") {
+            out.extend_from_slice(b"\x1b[");
+            rewrite_sgr(params, colors, &mut out);
+            out.push(b'm');
+        } else {
+            out.extend_from_slice(&frame[i..=end]);
+        }
+        i = end + 1;
+    }
+    Cow::Owned(out)
+}
+
+// The SGR parameters for a color `index` already within `colors`; 16- and
+// 8-color terminals only know the plain `30`-`37`, `90`-`97` forms and
+// their background counterparts.
+fn color_params(extended: &str, index: u8, colors: ColorSupport) -> String {
+    let base = if extended == "38" { 30 } else { 40 };
+    match colors {
+        ColorSupport::TrueColor | ColorSupport::Ansi256 => format!("{};5;{}", extended, index),
+        _ if index < 8 => (base + index).to_string(),
+        _ => (base + 60 + index - 8).to_string(),
+    }
+}
+
+fn rewrite_sgr(params: &[u8], colors: ColorSupport, out: &mut Vec<u8>) {
+    let text = String::from_utf8_lossy(params);
+    let values: Vec<&str> = text.split(';').collect();
+    let number = |i: usize| values.get(i).and_then(|v| v.parse::<u8>().ok());
+    let mut rewritten: Vec<String> = Vec::with_capacity(values.len());
+    let mut i = 0;
+    while i < values.len() {
+        let extended = matches!(values[i], "38" | "48");
+        match (extended, values.get(i + 1).copied()) {
+            (true, Some("5")) if number(i + 2).is_some() => {
+                let index = downgrade_index(number(i + 2).unwrap_or(0), colors);
+                rewritten.push(color_params(values[i], index, colors));
+                i += 3;
+            }
+            (true, Some("2")) if (2..5).all(|k| number(i + k).is_some()) => {
+                let rgb = (
+                    number(i + 2).unwrap_or(0),
+                    number(i + 3).unwrap_or(0),
+                    number(i + 4).unwrap_or(0),
+                );
+                let index = match colors {
+                    ColorSupport::Ansi256 => rgb_to_256(rgb),
+                    _ => downgrade_index(rgb_to_16(rgb), colors),
+                };
+                rewritten.push(color_params(values[i], index, colors));
+                i += 5;
+            }
+            _ => {
+                rewritten.push(values[i].to_string());
+                i += 1;
+            }
+        }
+    }
+    out.extend_from_slice(rewritten.join(";").as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn downgraded(frame: &str, colors: ColorSupport) -> String {
+        String::from_utf8(downgrade_colors(frame.as_bytes(), colors).into_owned()).unwrap()
+    }
+
+    #[test]
+    fn true_color_passes_through() {
+        let frame = "\x1b[1;38;2;255;0;0;48;5;200mx";
+        assert_eq!(downgraded(frame, ColorSupport::TrueColor), frame);
+    }
+
+    #[test]
+    fn ansi256_keeps_indexes_and_maps_rgb() {
+        assert_eq!(
+            downgraded("\x1b[38;5;200;48;2;255;0;0mx", ColorSupport::Ansi256),
+            "\x1b[38;5;200;48;5;196mx"
+        );
+    }
+
+    #[test]
+    fn ansi16_uses_plain_and_bright_codes() {
+        assert_eq!(
+            downgraded("\x1b[38;5;1;48;5;4mx", ColorSupport::Ansi16),
+            "\x1b[31;44mx"
+        );
+        assert_eq!(
+            downgraded("\x1b[1;38;5;9;48;5;12mx", ColorSupport::Ansi16),
+            "\x1b[1;91;104mx"
+        );
+        assert_eq!(
+            downgraded("\x1b[38;2;255;0;0;48;2;0;0;0mx", ColorSupport::Ansi16),
+            "\x1b[91;40mx"
+        );
+        assert_eq!(
+            downgraded("\x1b[38;5;196mx", ColorSupport::Ansi16),
+            "\x1b[91mx"
+        );
+    }
+
+    #[test]
+    fn ansi8_has_no_bright_codes() {
+        assert_eq!(
+            downgraded("\x1b[38;5;9;48;5;12mx", ColorSupport::Ansi8),
+            "\x1b[31;44mx"
+        );
+        assert_eq!(
+            downgraded("\x1b[38;2;255;0;0mx", ColorSupport::Ansi8),
+            "\x1b[31mx"
+        );
+    }
+
+    #[test]
+    fn other_sequences_are_untouched() {
+        let frame = "\x1b[2J\x1b[1;31mx\x1b[10;5H";
+        assert_eq!(downgraded(frame, ColorSupport::Ansi8), frame);
+    }
+}
@@ -0,0 +1,727 @@
+use crate::{
+    capabilities::ColorSupport,
+    keymap::{self, Action, Preset},
+};
+use std::{collections::HashMap, fs, io, time::Duration};
+use toml::Value;
+
+pub struct Scaffolder {
+    pub name: String,
+    pub program: String,
+    pub args: Vec<String>,
+    /// Run inside the (pre-created) target directory instead of its parent.
+    pub in_dir: bool,
+    /// Program and arguments run inside the target directory instead when
+    /// it already exists, such as `cargo init` for `cargo new`.
+    pub init: Option<(String, Vec<String>)>,
+}
+
+/// A command that "Evaluate Selection" hands the selected text to. `{}` in
+/// an argument is replaced by the text; without one it is appended. With
+/// `rustc` as the program, the text is built into a program that prints it
+/// instead, and the arguments go to rustc.
+pub struct Evaluator {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+pub struct Autocomplete {
+    pub buffer_words: bool,
+    pub keywords: bool,
+    /// Bundled word list, offered instead of keywords in prose buffers.
+    pub dictionary: bool,
+    pub snippets: bool,
+    pub lsp: bool,
+    pub paths: bool,
+    /// Preview a single strong match as dimmed text after the cursor.
+    pub ghost_text: bool,
+    pub min_prefix: usize,
+    pub max_results: usize,
+    /// Bonus for candidates that start with the typed text exactly.
+    pub prefix_weight: i64,
+    /// Multiplier applied to the fuzzy match score.
+    pub fuzzy_weight: i64,
+    /// Bonus for words used often in this session's project.
+    pub frequency_weight: i64,
+    /// Bonus for the most recently used word, fading with age.
+    pub recent_weight: i64,
+    /// Snippet bodies keyed by trigger word.
+    pub snippet_bodies: Vec<(String, String)>,
+}
+
+impl Default for Autocomplete {
+    fn default() -> Self {
+        Self {
+            buffer_words: true,
+            keywords: true,
+            dictionary: true,
+            snippets: true,
+            lsp: true,
+            paths: true,
+            ghost_text: false,
+            min_prefix: 1,
+            max_results: 50,
+            prefix_weight: 100,
+            fuzzy_weight: 1,
+            frequency_weight: 40,
+            recent_weight: 50,
+            snippet_bodies: vec![],
+        }
+    }
+}
+
+/// `[terminal]` overrides for capability detection; `None` means detect.
+#[derive(Default)]
+pub struct TerminalSettings {
+    pub colors: Option<ColorSupport>,
+    pub unicode: Option<bool>,
+    pub mouse: Option<bool>,
+    pub emoji: Option<bool>,
+    pub keyboard_enhancement: Option<bool>,
+}
+
+#[derive(Default)]
+pub struct Scrolling {
+    /// Lines of context kept above and below the cursor.
+    pub margin: usize,
+    /// Columns of context kept left and right of the cursor.
+    pub horizontal_margin: usize,
+    /// Allow scrolling until the last line sits at the top of the viewport.
+    pub past_end: bool,
+    /// Animate mouse-wheel and page scrolling instead of jumping.
+    pub smooth: bool,
+    /// Keep the cursor line in the middle of the viewport.
+    pub typewriter: bool,
+}
+
+#[derive(Clone, Copy, Default)]
+pub enum QuitConfirm {
+    Always,
+    #[default]
+    Unsaved,
+    Never,
+}
+
+/// `[share]`: where "Share Selection" uploads to.
+pub enum ShareService {
+    /// A GitHub Gist; the token falls back to `GITHUB_TOKEN`.
+    Gist { token: Option<String>, public: bool },
+    /// Any service that takes the text as the POST body and replies with the
+    /// paste's URL, such as https://paste.rs.
+    Endpoint { url: String },
+}
+
+impl Default for ShareService {
+    fn default() -> Self {
+        Self::Gist {
+            token: None,
+            public: false,
+        }
+    }
+}
+
+pub struct Config {
+    pub scaffolders: Vec<Scaffolder>,
+    pub autocomplete: Autocomplete,
+    pub scrolling: Scrolling,
+    pub terminal: TerminalSettings,
+    pub confirm_quit: QuitConfirm,
+    /// Later `termi path` invocations open `path` here instead.
+    pub single_instance: bool,
+    /// Column that "Reflow Paragraph" wraps prose at.
+    pub text_width: usize,
+    /// Write a UTF-8 BOM back to files that were loaded with one.
+    pub keep_bom: bool,
+    /// Reopen files where the cursor was when they were last left.
+    pub restore_cursor: bool,
+    /// Sort and deduplicate the import block before every save.
+    pub organize_imports_on_save: bool,
+    /// Files bigger than this many bytes ask before loading and can be
+    /// opened in large-file mode.
+    pub large_file_size: u64,
+    /// File header templates keyed by lowercase file extension.
+    pub headers: HashMap<String, String>,
+    pub header_author: Option<String>,
+    pub header_license: Option<String>,
+    /// Wake the main loop this often even when idle; `None` sleeps until
+    /// input or background work arrives.
+    pub idle_poll: Option<Duration>,
+    pub share: ShareService,
+    /// Evaluators keyed by lowercase file extension; `*` covers the rest.
+    pub evaluators: HashMap<String, Evaluator>,
+    /// Key bindings laid over the built-in ones.
+    pub keymap: Preset,
+    /// `[keys]`: the user's own bindings, over the preset's, by key label.
+    pub keys: Vec<(String, Action)>,
+    /// Ask GitHub for a newer release on startup. Nothing is installed.
+    pub check_updates: bool,
+}
+
+fn builtin_scaffolders() -> Vec<Scaffolder> {
+    let strings = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+    let scaffolder = |name: &str, program: &str, args: &[&str], in_dir: bool| Scaffolder {
+        name: name.to_string(),
+        program: program.to_string(),
+        args: strings(args),
+        in_dir,
+        init: None,
+    };
+    vec![
+        Scaffolder {
+            init: Some(("cargo".to_string(), strings(&["init"]))),
+            ..scaffolder(
+                "Rust binary (cargo new)",
+                "cargo",
+                &["new", "{name}"],
+                false,
+            )
+        },
+        Scaffolder {
+            init: Some(("cargo".to_string(), strings(&["init", "--lib"]))),
+            ..scaffolder(
+                "Rust library (cargo new --lib)",
+                "cargo",
+                &["new", "--lib", "{name}"],
+                false,
+            )
+        },
+        scaffolder(
+            "Node package (npm init -y)",
+            if cfg!(windows) { "npm.cmd" } else { "npm" },
+            &["init", "-y"],
+            true,
+        ),
+    ]
+}
+
+fn builtin_evaluators() -> HashMap<String, Evaluator> {
+    let evaluator = |program: &str, args: &[&str]| Evaluator {
+        program: program.to_string(),
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+    };
+    let python = if cfg!(windows) { "python" } else { "python3" };
+    let node = if cfg!(windows) { "node.exe" } else { "node" };
+    let mut evaluators = HashMap::new();
+    for ext in ["py", "pyw"] {
+        evaluators.insert(
+            ext.to_string(),
+            evaluator(
+                python,
+                &["-c", "import sys; print(eval(sys.argv[1]))", "{}"],
+            ),
+        );
+    }
+    evaluators.insert(
+        "rs".to_string(),
+        evaluator("rustc", &["--edition", "2021", "-A", "warnings"]),
+    );
+    for ext in ["js", "mjs", "cjs", "ts"] {
+        evaluators.insert(ext.to_string(), evaluator(node, &["-p", "{}"]));
+    }
+    evaluators
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            scaffolders: builtin_scaffolders(),
+            autocomplete: Autocomplete::default(),
+            scrolling: Scrolling::default(),
+            terminal: TerminalSettings::default(),
+            confirm_quit: QuitConfirm::default(),
+            single_instance: false,
+            text_width: 80,
+            keep_bom: true,
+            restore_cursor: true,
+            organize_imports_on_save: false,
+            large_file_size: 10 * 1024 * 1024,
+            headers: HashMap::new(),
+            header_author: None,
+            header_license: None,
+            idle_poll: None,
+            share: ShareService::default(),
+            evaluators: builtin_evaluators(),
+            keymap: Preset::default(),
+            keys: Vec::new(),
+            check_updates: false,
+        }
+    }
+}
+
+// A command line given as one whitespace-separated string or as an array.
+fn parse_command(command: &Value) -> Option<(String, Vec<String>)> {
+    match command {
+        Value::String(line) => {
+            let mut parts = line.split_whitespace().map(|part| part.to_string());
+            Some((parts.next()?, parts.collect()))
+        }
+        Value::Array(parts) => {
+            let mut parts = parts
+                .iter()
+                .filter_map(|part| part.as_str().map(String::from));
+            Some((parts.next()?, parts.collect()))
+        }
+        _ => None,
+    }
+}
+
+fn parse_scaffolder(value: &Value) -> Option<Scaffolder> {
+    let (program, args) = parse_command(value.get("command")?)?;
+    Some(Scaffolder {
+        name: value
+            .get("name")
+            .and_then(Value::as_str)
+            .map(String::from)
+            .unwrap_or_else(|| program.clone()),
+        program,
+        args,
+        in_dir: value
+            .get("in_dir")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        init: value.get("init_command").and_then(parse_command),
+    })
+}
+
+fn parse_autocomplete(table: &Value) -> Autocomplete {
+    let mut settings = Autocomplete::default();
+    let integer = |value: Option<&Value>| value.and_then(Value::as_integer);
+
+    if let Some(sources) = table.get("sources") {
+        let enabled =
+            |key: &str, default: bool| sources.get(key).and_then(Value::as_bool).unwrap_or(default);
+        settings.buffer_words = enabled("buffer", settings.buffer_words);
+        settings.keywords = enabled("keywords", settings.keywords);
+        settings.dictionary = enabled("dictionary", settings.dictionary);
+        settings.snippets = enabled("snippets", settings.snippets);
+        settings.lsp = enabled("lsp", settings.lsp);
+        settings.paths = enabled("paths", settings.paths);
+    }
+    if let Some(ghost_text) = table.get("ghost_text").and_then(Value::as_bool) {
+        settings.ghost_text = ghost_text;
+    }
+    if let Some(min_prefix) = integer(table.get("min_prefix")) {
+        settings.min_prefix = min_prefix.max(0) as usize;
+    }
+    if let Some(max_results) = integer(table.get("max_results")) {
+        settings.max_results = max_results.max(1) as usize;
+    }
+    if let Some(weights) = table.get("weights") {
+        let weight = |key: &str, default: i64| integer(weights.get(key)).unwrap_or(default);
+        settings.prefix_weight = weight("prefix", settings.prefix_weight);
+        settings.fuzzy_weight = weight("fuzzy", settings.fuzzy_weight);
+        settings.frequency_weight = weight("frequency", settings.frequency_weight);
+        settings.recent_weight = weight("recent", settings.recent_weight);
+    }
+    if let Some(snippets) = table.get("snippets").and_then(Value::as_table) {
+        settings.snippet_bodies = snippets
+            .iter()
+            .filter_map(|(trigger, body)| Some((trigger.clone(), body.as_str()?.to_string())))
+            .collect();
+    }
+
+    settings
+}
+
+fn parse_scrolling(table: &Value) -> Scrolling {
+    let margin = |key: &str| {
+        table
+            .get(key)
+            .and_then(Value::as_integer)
+            .map_or(0, |n| n.max(0) as usize)
+    };
+    Scrolling {
+        margin: margin("margin"),
+        horizontal_margin: margin("horizontal_margin"),
+        past_end: table
+            .get("past_end")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        smooth: table
+            .get("smooth")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        typewriter: table
+            .get("typewriter")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+    }
+}
+
+fn parse_terminal(table: &Value) -> TerminalSettings {
+    let flag = |key: &str| table.get(key).and_then(Value::as_bool);
+    TerminalSettings {
+        colors: table
+            .get("colors")
+            .and_then(|colors| match colors {
+                Value::Integer(n) => Some(n.to_string()),
+                Value::String(name) => Some(name.clone()),
+                _ => None,
+            })
+            .and_then(|name| ColorSupport::parse(&name)),
+        unicode: flag("unicode"),
+        mouse: flag("mouse"),
+        emoji: flag("emoji"),
+        keyboard_enhancement: flag("keyboard_protocol"),
+    }
+}
+
+fn parse_share(table: &Value) -> ShareService {
+    if let Some(url) = table.get("endpoint").and_then(Value::as_str) {
+        return ShareService::Endpoint {
+            url: url.to_string(),
+        };
+    }
+    ShareService::Gist {
+        token: table.get("token").and_then(Value::as_str).map(String::from),
+        public: table
+            .get("public")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+    }
+}
+
+/// Reads `config.toml` next to the dashboard state. A missing file yields the
+/// defaults; a malformed one yields the defaults plus an error message.
+pub fn load() -> (Config, Option<String>) {
+    let mut config = Config::default();
+    let path = crate::config_path();
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return (config, None);
+    };
+    let root: Value = match contents.parse() {
+        Ok(root) => root,
+        Err(err) => return (config, Some(format!("{}: {}", path.to_string_lossy(), err))),
+    };
+    // Problems that still leave the rest of the file usable.
+    let mut error = None;
+
+    if let Some(entries) = root.get("scaffolders").and_then(Value::as_array) {
+        config
+            .scaffolders
+            .extend(entries.iter().filter_map(parse_scaffolder));
+    }
+
+    if let Some(autocomplete) = root.get("autocomplete") {
+        config.autocomplete = parse_autocomplete(autocomplete);
+    }
+
+    if let Some(scrolling) = root.get("scrolling") {
+        config.scrolling = parse_scrolling(scrolling);
+    }
+
+    if let Some(terminal) = root.get("terminal") {
+        config.terminal = parse_terminal(terminal);
+    }
+
+    if let Some(editor) = root.get("editor") {
+        config.confirm_quit = match editor.get("confirm_quit").and_then(Value::as_str) {
+            Some("always") => QuitConfirm::Always,
+            Some("never") => QuitConfirm::Never,
+            _ => QuitConfirm::Unsaved,
+        };
+        config.single_instance = editor
+            .get("single_instance")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        config.keep_bom = editor
+            .get("keep_bom")
+            .and_then(Value::as_bool)
+            .unwrap_or(true);
+        config.restore_cursor = editor
+            .get("restore_cursor")
+            .and_then(Value::as_bool)
+            .unwrap_or(true);
+        config.organize_imports_on_save = editor
+            .get("organize_imports_on_save")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        if let Some(width) = editor.get("text_width").and_then(Value::as_integer) {
+            config.text_width = width.max(10) as usize;
+        }
+        if let Some(megabytes) = editor.get("large_file_mb").and_then(Value::as_integer) {
+            config.large_file_size = megabytes.max(1) as u64 * 1024 * 1024;
+        }
+        if let Some(name) = editor.get("keymap").and_then(Value::as_str) {
+            match Preset::from_name(name) {
+                Some(preset) => config.keymap = preset,
+                None => error = Some(format!("unknown keymap \"{}\"", name)),
+            }
+        }
+    }
+
+    config.idle_poll = root
+        .get("performance")
+        .and_then(|performance| performance.get("idle_poll_ms"))
+        .and_then(Value::as_integer)
+        .filter(|&ms| ms > 0)
+        .map(|ms| Duration::from_millis(ms as u64));
+
+    config.check_updates = root
+        .get("updates")
+        .and_then(|updates| updates.get("check"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+
+    if let Some(share) = root.get("share") {
+        config.share = parse_share(share);
+    }
+
+    if let Some(evaluators) = root.get("evaluators").and_then(Value::as_table) {
+        for (ext, command) in evaluators {
+            if let Some((program, args)) = parse_command(command) {
+                config.evaluators.insert(
+                    ext.trim_start_matches('.').to_lowercase(),
+                    Evaluator { program, args },
+                );
+            }
+        }
+    }
+
+    if let Some(keys) = root.get("keys").and_then(Value::as_table) {
+        for (label, name) in keys {
+            let action = name.as_str().and_then(keymap::action_named);
+            match action.filter(|_| keymap::parse_key(label).is_some()) {
+                Some(action) => config.keys.push((label.clone(), action)),
+                None => {
+                    error = Some(format!(
+                        "[keys] {} = {}: unknown key or command",
+                        label, name
+                    ))
+                }
+            }
+        }
+    }
+
+    if let Some(headers) = root.get("headers") {
+        let text = |key: &str| headers.get(key).and_then(Value::as_str).map(String::from);
+        config.header_author = text("author");
+        config.header_license = text("license");
+        if let Some(templates) = headers.get("templates").and_then(Value::as_table) {
+            config.headers = templates
+                .iter()
+                .filter_map(|(ext, template)| {
+                    Some((
+                        ext.trim_start_matches('.').to_lowercase(),
+                        template.as_str()?.to_string(),
+                    ))
+                })
+                .collect();
+        }
+    }
+
+    (config, error)
+}
+
+// The name of the table a `[name]` header line opens, read the way TOML
+// reads it, so spacing, quotes and a trailing comment don't matter.
+fn table_name(line: &str) -> Option<String> {
+    let line = line.trim_start();
+    if !line.starts_with('[') || line.starts_with("[[") {
+        return None;
+    }
+    let header = line.parse::<Value>().ok()?;
+    let mut tables = header.as_table()?.iter();
+    match (tables.next(), tables.next()) {
+        (Some((name, Value::Table(body))), None) if body.is_empty() => Some(name.clone()),
+        _ => None,
+    }
+}
+
+/// Sets `key` in the `[editor]` table of the config file, creating either
+/// as needed. The rest of the file, comments included, stays as written.
+pub fn set_editor_value(key: &str, value: &str) -> io::Result<()> {
+    let path = crate::config_path();
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+    let entry = format!("{} = {}", key, value);
+    let mut lines: Vec<String> = contents.lines().map(String::from).collect();
+    match lines
+        .iter()
+        .position(|line| table_name(line).as_deref() == Some("editor"))
+    {
+        Some(start) => {
+            let end = lines[start + 1..]
+                .iter()
+                .position(|line| line.trim_start().starts_with('['))
+                .map_or(lines.len(), |offset| start + 1 + offset);
+            let existing = lines[start + 1..end].iter().position(|line| {
+                line.split_once('=')
+                    .is_some_and(|(name, _)| name.trim() == key)
+            });
+            match existing {
+                Some(offset) => lines[start + 1 + offset] = entry,
+                None => lines.insert(start + 1, entry),
+            }
+        }
+        None => {
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+            lines.push("[editor]".to_string());
+            lines.push(entry);
+        }
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, lines.join("\n") + "\n")
+}
+
+/// Every setting at its default, as a `config.toml` for `termi --dump-config`.
+/// Settings that are detected or empty by default are left commented out.
+pub fn default_toml() -> String {
+    let config = Config::default();
+    let quote = |text: &str| Value::String(text.to_string()).to_string();
+    let command = |program: &str, args: &[String]| {
+        Value::Array(
+            std::iter::once(program)
+                .chain(args.iter().map(String::as_str))
+                .map(|part| Value::String(part.to_string()))
+                .collect(),
+        )
+        .to_string()
+    };
+    let autocomplete = &config.autocomplete;
+    let scrolling = &config.scrolling;
+    let mut out = format!(
+        "# termi's settings at their defaults. Save as {} and keep what you change.\n",
+        crate::config_path().to_string_lossy()
+    );
+
+    out.push_str(&format!(
+        "\n[editor]\n\
+         confirm_quit = {}  # \"always\", \"unsaved\" or \"never\"\n\
+         single_instance = {}\n\
+         text_width = {}\n\
+         keep_bom = {}\n\
+         restore_cursor = {}\n\
+         large_file_mb = {}\n\
+         organize_imports_on_save = {}\n\
+         keymap = {}  # \"termi\", \"vscode\", \"vim\" or \"nano\"\n",
+        quote(match config.confirm_quit {
+            QuitConfirm::Always => "always",
+            QuitConfirm::Unsaved => "unsaved",
+            QuitConfirm::Never => "never",
+        }),
+        config.single_instance,
+        config.text_width,
+        config.keep_bom,
+        config.restore_cursor,
+        config.large_file_size / (1024 * 1024),
+        config.organize_imports_on_save,
+        quote(config.keymap.name()),
+    ));
+
+    out.push_str(&format!(
+        "\n[scrolling]\n\
+         margin = {}\n\
+         horizontal_margin = {}\n\
+         past_end = {}\n\
+         smooth = {}\n\
+         typewriter = {}\n",
+        scrolling.margin,
+        scrolling.horizontal_margin,
+        scrolling.past_end,
+        scrolling.smooth,
+        scrolling.typewriter,
+    ));
+
+    out.push_str(
+        "\n# Detected from TERM, COLORTERM and the locale unless set\n\
+         [terminal]\n\
+         # colors = \"truecolor\"  # \"truecolor\", \"256\", \"16\" or \"8\"\n\
+         # unicode = true\n\
+         # mouse = true\n\
+         # emoji = true\n\
+         # keyboard_protocol = true\n",
+    );
+
+    out.push_str(&format!(
+        "\n[performance]\nidle_poll_ms = {}  # 0 sleeps until input arrives\n",
+        config.idle_poll.map_or(0, |poll| poll.as_millis())
+    ));
+
+    out.push_str(&format!(
+        "\n[updates]\ncheck = {}  # look for a newer release on startup; never installs\n",
+        config.check_updates
+    ));
+
+    out.push_str("\n# {} is the text being evaluated, appended if absent\n[evaluators]\n");
+    let mut evaluators: Vec<_> = config.evaluators.iter().collect();
+    evaluators.sort_by(|a, b| a.0.cmp(b.0));
+    for (ext, evaluator) in evaluators {
+        out.push_str(&format!(
+            "{} = {}\n",
+            quote(ext),
+            command(&evaluator.program, &evaluator.args)
+        ));
+    }
+
+    out.push_str(
+        "\n[share]\n\
+         public = false\n\
+         # token = \"ghp_...\"  # defaults to $GITHUB_TOKEN\n\
+         # endpoint = \"https://paste.rs\"  # a plain paste service instead of a gist\n\
+         \n[headers]\n\
+         # author = \"Jane Doe\"  # defaults to git user.name\n\
+         # license = \"MIT\"\n\
+         \n[headers.templates]\n\
+         # rs = \"// {{file}} - created {{date}}\\n\\n\"\n",
+    );
+
+    out.push_str(&format!(
+        "\n[autocomplete]\n\
+         min_prefix = {}\n\
+         max_results = {}\n\
+         ghost_text = {}\n\
+         \n[autocomplete.sources]\n\
+         buffer = {}\n\
+         keywords = {}\n\
+         dictionary = {}\n\
+         snippets = {}\n\
+         lsp = {}\n\
+         paths = {}\n\
+         \n[autocomplete.weights]\n\
+         prefix = {}\n\
+         fuzzy = {}\n\
+         frequency = {}\n\
+         recent = {}\n\
+         \n[autocomplete.snippets]\n\
+         # main = \"fn main() {{\\n    \\n}}\"\n",
+        autocomplete.min_prefix,
+        autocomplete.max_results,
+        autocomplete.ghost_text,
+        autocomplete.buffer_words,
+        autocomplete.keywords,
+        autocomplete.dictionary,
+        autocomplete.snippets,
+        autocomplete.lsp,
+        autocomplete.paths,
+        autocomplete.prefix_weight,
+        autocomplete.fuzzy_weight,
+        autocomplete.frequency_weight,
+        autocomplete.recent_weight,
+    ));
+
+    out.push_str("\n# Built in; entries here are listed after them\n");
+    for scaffolder in &config.scaffolders {
+        out.push_str(&format!(
+            "# [[scaffolders]]\n# name = {}\n# command = {}\n",
+            quote(&scaffolder.name),
+            command(&scaffolder.program, &scaffolder.args)
+        ));
+        if scaffolder.in_dir {
+            out.push_str("# in_dir = true\n");
+        }
+        if let Some((program, args)) = &scaffolder.init {
+            out.push_str(&format!("# init_command = {}\n", command(program, args)));
+        }
+    }
+
+    out.push_str("\n# Key bindings over the preset: termi --dump-keys lists them\n[keys]\n");
+    out
+}
@@ -0,0 +1,87 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const HEADER_EXTENSIONS: &[&str] = &["h", "hpp", "hh", "hxx", "h++"];
+const SOURCE_EXTENSIONS: &[&str] = &["c", "cpp", "cc", "cxx", "c++", "m", "mm"];
+
+// Directories never searched for a counterpart.
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target"];
+// The project search stops after looking at this many directory entries.
+const MAX_ENTRIES: usize = 50_000;
+
+fn extension(path: &Path) -> Option<String> {
+    Some(path.extension()?.to_string_lossy().to_lowercase())
+}
+
+pub fn is_c_family(path: &Path) -> bool {
+    extension(path).is_some_and(|ext| {
+        HEADER_EXTENSIONS.contains(&ext.as_str()) || SOURCE_EXTENSIONS.contains(&ext.as_str())
+    })
+}
+
+fn collect_matches(
+    dir: &Path,
+    stem: &str,
+    wanted: &[&str],
+    budget: &mut usize,
+    found: &mut Vec<PathBuf>,
+) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if *budget == 0 {
+            return;
+        }
+        *budget -= 1;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+            if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_str()) {
+                collect_matches(&path, stem, wanted, budget, found);
+            }
+        } else if path.file_stem().is_some_and(|s| s == stem)
+            && extension(&path).is_some_and(|ext| wanted.contains(&ext.as_str()))
+        {
+            found.push(path);
+        }
+    }
+}
+
+/// The header for a source file or the source for a header: a file with
+/// the same name and the other kind of extension, next to it if there is
+/// one, otherwise the one under `root` that shares the most of its path.
+pub fn find(path: &Path, root: &Path) -> Option<PathBuf> {
+    let ext = extension(path)?;
+    let wanted = if HEADER_EXTENSIONS.contains(&ext.as_str()) {
+        SOURCE_EXTENSIONS
+    } else if SOURCE_EXTENSIONS.contains(&ext.as_str()) {
+        HEADER_EXTENSIONS
+    } else {
+        return None;
+    };
+    let stem = path.file_stem()?.to_string_lossy().into_owned();
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    for ext in wanted {
+        let sibling = dir.join(format!("{}.{}", stem, ext));
+        if sibling.is_file() {
+            return Some(sibling);
+        }
+    }
+
+    let mut found = Vec::new();
+    let mut budget = MAX_ENTRIES;
+    collect_matches(root, &stem, wanted, &mut budget, &mut found);
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let shared = |other: &PathBuf| {
+        other
+            .components()
+            .zip(path.components())
+            .take_while(|(a, b)| a == b)
+            .count()
+    };
+    found.into_iter().max_by_key(shared)
+}
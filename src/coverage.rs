@@ -0,0 +1,150 @@
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+pub type LineHits = HashMap<usize, u64>;
+
+const REPORT_CANDIDATES: &[&str] = &[
+    "lcov.info",
+    "coverage/lcov.info",
+    "target/lcov.info",
+    "target/llvm-cov/lcov.info",
+    "coverage.json",
+    "coverage/coverage.json",
+    "target/coverage.json",
+    "target/llvm-cov/coverage.json",
+];
+
+pub fn find_report(root: &Path) -> Option<PathBuf> {
+    REPORT_CANDIDATES
+        .iter()
+        .map(|candidate| root.join(candidate))
+        .filter(|path| path.is_file())
+        .max_by_key(|path| {
+            fs::metadata(path)
+                .and_then(|meta| meta.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        })
+}
+
+pub fn load_report(report: &Path, root: &Path) -> io::Result<HashMap<PathBuf, LineHits>> {
+    let contents = fs::read_to_string(report)?;
+    let is_json = report
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+
+    let raw = if is_json {
+        parse_llvm_cov_json(&contents)?
+    } else {
+        parse_lcov(&contents)
+    };
+
+    Ok(raw
+        .into_iter()
+        .map(|(file, hits)| {
+            let path = PathBuf::from(&file);
+            let path = if path.is_absolute() {
+                path
+            } else {
+                root.join(path)
+            };
+            (fs::canonicalize(&path).unwrap_or(path), hits)
+        })
+        .collect())
+}
+
+fn parse_lcov(contents: &str) -> HashMap<String, LineHits> {
+    let mut files = HashMap::new();
+    let mut current: Option<(String, LineHits)> = None;
+
+    for line in contents.lines() {
+        let line = line.trim();
+        if let Some(file) = line.strip_prefix("SF:") {
+            current = Some((file.to_string(), LineHits::new()));
+        } else if let Some(data) = line.strip_prefix("DA:") {
+            let mut parts = data.split(',');
+            let (Some(line_no), Some(count)) = (parts.next(), parts.next()) else {
+                continue;
+            };
+            let (Ok(line_no), Ok(count)) = (line_no.parse::<usize>(), count.parse::<u64>()) else {
+                continue;
+            };
+            if let Some((_, hits)) = current.as_mut() {
+                let entry = hits.entry(line_no.saturating_sub(1)).or_insert(0);
+                *entry = entry.saturating_add(count);
+            }
+        } else if line == "end_of_record" {
+            if let Some((file, hits)) = current.take() {
+                files.insert(file, hits);
+            }
+        }
+    }
+
+    files
+}
+
+fn parse_llvm_cov_json(contents: &str) -> io::Result<HashMap<String, LineHits>> {
+    let root: Value = serde_json::from_str(contents).map_err(io::Error::other)?;
+    let mut files = HashMap::new();
+
+    for export in root["data"].as_array().into_iter().flatten() {
+        for file in export["files"].as_array().into_iter().flatten() {
+            let Some(name) = file["filename"].as_str() else {
+                continue;
+            };
+            let segments: Vec<(usize, u64, bool, bool)> = file["segments"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|segment| {
+                    let segment = segment.as_array()?;
+                    Some((
+                        segment.first()?.as_u64()? as usize,
+                        segment.get(2)?.as_u64()?,
+                        segment.get(3)?.as_bool()?,
+                        segment.get(4)?.as_bool()?,
+                    ))
+                })
+                .collect();
+            files.insert(name.to_string(), segments_to_lines(&segments));
+        }
+    }
+
+    Ok(files)
+}
+
+// Mirrors llvm-cov's line view: a line is executable when a region starts on it
+// or a counted region wraps into it, and its count is the largest of those.
+fn segments_to_lines(segments: &[(usize, u64, bool, bool)]) -> LineHits {
+    let mut hits = LineHits::new();
+    let Some(last_line) = segments.iter().map(|segment| segment.0).max() else {
+        return hits;
+    };
+
+    let mut index = 0;
+    let mut wrapped: Option<(u64, bool)> = None;
+    for line in 1..=last_line {
+        let mut count: Option<u64> = wrapped.filter(|(_, has)| *has).map(|(c, _)| c);
+        let mut last_on_line = None;
+        while index < segments.len() && segments[index].0 == line {
+            let (_, segment_count, has_count, is_entry) = segments[index];
+            if has_count && is_entry {
+                count = Some(count.unwrap_or(0).max(segment_count));
+            }
+            last_on_line = Some((segment_count, has_count));
+            index += 1;
+        }
+        if let Some(count) = count {
+            hits.insert(line - 1, count);
+        }
+        if last_on_line.is_some() {
+            wrapped = last_on_line;
+        }
+    }
+
+    hits
+}
@@ -0,0 +1,240 @@
+#[derive(Clone, Copy, PartialEq)]
+pub enum DiffKind {
+    Equal,
+    Delete,
+    Insert,
+}
+
+#[derive(Clone)]
+pub struct DiffLine {
+    pub kind: DiffKind,
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub text: String,
+}
+
+#[derive(Clone)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+impl Hunk {
+    pub fn header(&self) -> String {
+        format!(
+            "@@ -{},{} +{},{} @@",
+            self.old_start + 1,
+            self.old_len,
+            self.new_start + 1,
+            self.new_len
+        )
+    }
+}
+
+// Myers' diff in linear space: find the snake in the middle of a shortest
+// edit script, then solve the two sides of it the same way. Remembering
+// every step of the search instead takes memory proportional to the
+// number of edits times the length of the files.
+fn myers(old: &[&str], new: &[&str], ops: &mut Vec<DiffKind>) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+
+    ops.extend(std::iter::repeat_n(DiffKind::Equal, prefix));
+    if old.is_empty() {
+        ops.extend(std::iter::repeat_n(DiffKind::Insert, new.len()));
+    } else if new.is_empty() {
+        ops.extend(std::iter::repeat_n(DiffKind::Delete, old.len()));
+    } else {
+        let (x0, y0, x1, y1) = middle_snake(old, new);
+        myers(&old[..x0], &new[..y0], ops);
+        ops.extend(std::iter::repeat_n(DiffKind::Equal, x1 - x0));
+        myers(&old[x1..], &new[y1..], ops);
+    }
+    ops.extend(std::iter::repeat_n(DiffKind::Equal, suffix));
+}
+
+// Searches from both ends at once until the paths meet, and returns the
+// start and end of the snake where they do. The backward search runs over
+// the reversed lines, so its diagonal `c` is the forward diagonal `delta - c`.
+fn middle_snake(old: &[&str], new: &[&str]) -> (usize, usize, usize, usize) {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let max = (n + m + 1) / 2;
+    let offset = max + 1;
+    let mut forward = vec![0_isize; 2 * max as usize + 3];
+    let mut backward = forward.clone();
+    let at = |k: isize| (k + offset) as usize;
+
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                forward[at(k + 1)]
+            } else {
+                forward[at(k - 1)] + 1
+            };
+            let (x0, y0) = (x, x - k);
+            let mut y = y0;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[at(k)] = x;
+            let c = delta - k;
+            if odd && (-(d - 1)..=d - 1).contains(&c) && x + backward[at(c)] >= n {
+                return (x0 as usize, y0 as usize, x as usize, y as usize);
+            }
+        }
+        for c in (-d..=d).step_by(2) {
+            let mut x = if c == -d || (c != d && backward[at(c - 1)] < backward[at(c + 1)]) {
+                backward[at(c + 1)]
+            } else {
+                backward[at(c - 1)] + 1
+            };
+            let (x0, y0) = (x, x - c);
+            let mut y = y0;
+            while x < n && y < m && old[(n - 1 - x) as usize] == new[(m - 1 - y) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[at(c)] = x;
+            let k = delta - c;
+            if !odd && (-d..=d).contains(&k) && forward[at(k)] + x >= n {
+                return (
+                    (n - x) as usize,
+                    (m - y) as usize,
+                    (n - x0) as usize,
+                    (m - y0) as usize,
+                );
+            }
+        }
+    }
+    unreachable!("the searches meet within (n + m + 1) / 2 steps")
+}
+
+pub fn diff_lines(old: &[String], new: &[String]) -> Vec<DiffLine> {
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid: Vec<&str> = old[prefix..old.len() - suffix]
+        .iter()
+        .map(|s| s.as_str())
+        .collect();
+    let new_mid: Vec<&str> = new[prefix..new.len() - suffix]
+        .iter()
+        .map(|s| s.as_str())
+        .collect();
+
+    let mut ops = vec![DiffKind::Equal; prefix];
+    myers(&old_mid, &new_mid, &mut ops);
+    ops.extend(std::iter::repeat_n(DiffKind::Equal, suffix));
+
+    let mut lines = Vec::with_capacity(ops.len());
+    let (mut i, mut j) = (0, 0);
+    for kind in ops {
+        match kind {
+            DiffKind::Equal => {
+                lines.push(DiffLine {
+                    kind,
+                    old_line: Some(i),
+                    new_line: Some(j),
+                    text: new[j].clone(),
+                });
+                i += 1;
+                j += 1;
+            }
+            DiffKind::Delete => {
+                lines.push(DiffLine {
+                    kind,
+                    old_line: Some(i),
+                    new_line: None,
+                    text: old[i].clone(),
+                });
+                i += 1;
+            }
+            DiffKind::Insert => {
+                lines.push(DiffLine {
+                    kind,
+                    old_line: None,
+                    new_line: Some(j),
+                    text: new[j].clone(),
+                });
+                j += 1;
+            }
+        }
+    }
+    lines
+}
+
+pub fn hunks(lines: &[DiffLine], context: usize) -> Vec<Hunk> {
+    let changed: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.kind != DiffKind::Equal)
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for index in changed {
+        let start = index.saturating_sub(context);
+        let end = (index + context + 1).min(lines.len());
+        match ranges.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            let slice = &lines[start..end];
+            let old_start = slice
+                .iter()
+                .find_map(|line| line.old_line)
+                .unwrap_or_else(|| {
+                    lines[..start]
+                        .iter()
+                        .rev()
+                        .find_map(|line| line.old_line.map(|l| l + 1))
+                        .unwrap_or(0)
+                });
+            let new_start = slice
+                .iter()
+                .find_map(|line| line.new_line)
+                .unwrap_or_else(|| {
+                    lines[..start]
+                        .iter()
+                        .rev()
+                        .find_map(|line| line.new_line.map(|l| l + 1))
+                        .unwrap_or(0)
+                });
+            Hunk {
+                old_start,
+                old_len: slice.iter().filter(|l| l.old_line.is_some()).count(),
+                new_start,
+                new_len: slice.iter().filter(|l| l.new_line.is_some()).count(),
+                lines: slice.to_vec(),
+            }
+        })
+        .collect()
+}
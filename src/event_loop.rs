@@ -0,0 +1,205 @@
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use std::{
+    io,
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+enum Wakeup {
+    Input(io::Result<Event>),
+    Background,
+}
+
+/// Turns the Windows console's key events into what termi expects. AltGr
+/// arrives as Ctrl+Alt with the character it types, and an Alt+numpad code
+/// as the release of Alt carrying its character, with no press before it;
+/// both are plain typing.
+#[derive(Default)]
+struct KeyFilter {
+    // Characters pressed and not yet released, lowercased: a release only
+    // ends a press when one is pending.
+    held: Vec<char>,
+}
+
+impl KeyFilter {
+    fn apply(&mut self, event: Event) -> Event {
+        let Event::Key(mut key) = event else {
+            if let Event::FocusLost = event {
+                self.held.clear();
+            }
+            return event;
+        };
+        let KeyCode::Char(c) = key.code else {
+            return event;
+        };
+        let lower = c.to_lowercase().next().unwrap_or(c);
+        match key.kind {
+            KeyEventKind::Release if self.held.is_empty() => {
+                key.kind = KeyEventKind::Press;
+                key.modifiers.remove(KeyModifiers::ALT);
+            }
+            KeyEventKind::Release => {
+                // Shift or AltGr let go first changes the character, so an
+                // unmatched release still ends some press.
+                let index = self.held.iter().position(|&h| h == lower).unwrap_or(0);
+                self.held.remove(index);
+            }
+            _ => {
+                if !self.held.contains(&lower) {
+                    self.held.push(lower);
+                }
+                // Ctrl+Alt+letter is a shortcut; anything else it types is
+                // AltGr.
+                let altgr = KeyModifiers::CONTROL | KeyModifiers::ALT;
+                if key.modifiers.contains(altgr) && !c.is_ascii_alphanumeric() {
+                    key.modifiers.remove(altgr);
+                }
+            }
+        }
+        Event::Key(key)
+    }
+}
+
+/// Handed to background threads so they can interrupt the main loop's wait
+/// after sending it something to drain.
+#[derive(Clone)]
+pub struct Waker(Sender<Wakeup>);
+
+impl Waker {
+    pub fn wake(&self) {
+        let _ = self.0.send(Wakeup::Background);
+    }
+}
+
+/// Terminal input and background wakeups merged into one channel, so the
+/// main loop can sleep until either arrives instead of polling.
+pub struct EventLoop {
+    tx: Sender<Wakeup>,
+    rx: Receiver<Wakeup>,
+}
+
+impl EventLoop {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self { tx, rx }
+    }
+
+    /// Starts forwarding terminal input. Anything that queries the terminal
+    /// directly has to happen before this, or the reader steals the reply.
+    pub fn listen(&self) {
+        let input = self.tx.clone();
+        thread::spawn(move || {
+            let mut filter = KeyFilter::default();
+            let mut queued = None;
+            loop {
+                let mut event = queued.take().unwrap_or_else(event::read);
+                // Dragging a window edge sends a resize per step, and each one
+                // clears and repaints the whole screen, which the classic
+                // Windows console is slow at; only the last size matters.
+                while matches!(event, Ok(Event::Resize(..)))
+                    && event::poll(Duration::ZERO).unwrap_or(false)
+                {
+                    let next = event::read();
+                    if matches!(next, Ok(Event::Resize(..))) {
+                        event = next;
+                    } else {
+                        queued = Some(next);
+                        break;
+                    }
+                }
+                if cfg!(windows) {
+                    event = event.map(|event| filter.apply(event));
+                }
+                let failed = event.is_err();
+                if input.send(Wakeup::Input(event)).is_err() || failed {
+                    break;
+                }
+            }
+        });
+    }
+
+    pub fn waker(&self) -> Waker {
+        Waker(self.tx.clone())
+    }
+
+    /// Blocks until input arrives, a background thread wakes the loop, or
+    /// `timeout` passes (never, when `None`). Returns the input event, if any;
+    /// queued wakeups are coalesced so a chatty thread costs one redraw.
+    pub fn wait(&self, timeout: Option<Duration>) -> io::Result<Option<Event>> {
+        let mut next = match timeout {
+            Some(timeout) => self.rx.recv_timeout(timeout).ok(),
+            None => self.rx.recv().ok(),
+        };
+        loop {
+            match next {
+                Some(Wakeup::Input(event)) => return event.map(Some),
+                Some(Wakeup::Background) => match self.rx.try_recv() {
+                    Ok(wakeup) => next = Some(wakeup),
+                    Err(TryRecvError::Empty | TryRecvError::Disconnected) => return Ok(None),
+                },
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyEvent;
+
+    fn key(c: char, modifiers: KeyModifiers, kind: KeyEventKind) -> Event {
+        Event::Key(KeyEvent::new_with_kind(KeyCode::Char(c), modifiers, kind))
+    }
+
+    fn kinds(filter: &mut KeyFilter, events: Vec<Event>) -> Vec<KeyEventKind> {
+        events
+            .into_iter()
+            .map(|event| match filter.apply(event) {
+                Event::Key(key) => key.kind,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn typed_characters_are_pressed_once() {
+        let mut filter = KeyFilter::default();
+        let none = KeyModifiers::NONE;
+        let events = vec![
+            key('a', none, KeyEventKind::Press),
+            key('b', none, KeyEventKind::Press),
+            key('a', none, KeyEventKind::Release),
+            key('b', none, KeyEventKind::Release),
+            key('A', KeyModifiers::SHIFT, KeyEventKind::Press),
+            key('a', none, KeyEventKind::Release),
+        ];
+        let presses = kinds(&mut filter, events)
+            .into_iter()
+            .filter(|kind| *kind == KeyEventKind::Press)
+            .count();
+        assert_eq!(presses, 3);
+    }
+
+    #[test]
+    fn alt_code_release_becomes_a_press() {
+        let mut filter = KeyFilter::default();
+        let event = filter.apply(key('é', KeyModifiers::ALT, KeyEventKind::Release));
+        assert_eq!(event, key('é', KeyModifiers::NONE, KeyEventKind::Press));
+    }
+
+    #[test]
+    fn altgr_types_but_ctrl_alt_letter_stays_a_shortcut() {
+        let mut filter = KeyFilter::default();
+        let altgr = KeyModifiers::CONTROL | KeyModifiers::ALT;
+        assert_eq!(
+            filter.apply(key('@', altgr, KeyEventKind::Press)),
+            key('@', KeyModifiers::NONE, KeyEventKind::Press)
+        );
+        assert_eq!(
+            filter.apply(key('q', altgr, KeyEventKind::Press)),
+            key('q', altgr, KeyEventKind::Press)
+        );
+    }
+}
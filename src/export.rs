@@ -0,0 +1,137 @@
+use crate::{capabilities, get_token_attribute, get_token_color, TokenType};
+use crossterm::style::{Attribute, Color, Stylize};
+use std::fmt::Write;
+
+/// A line of text with the syntax tokens covering it.
+pub type HighlightedLine<'a> = (&'a [char], Vec<(usize, usize, TokenType)>);
+
+// Page colors match a dark terminal, which is what the token colors are
+// picked for.
+const BACKGROUND: &str = "#1e1e1e";
+const FOREGROUND: &str = "#e5e5e5";
+
+fn css_color(color: Color) -> String {
+    let index = match color {
+        Color::Black => 0,
+        Color::DarkRed => 1,
+        Color::DarkGreen => 2,
+        Color::DarkYellow => 3,
+        Color::DarkBlue => 4,
+        Color::DarkMagenta => 5,
+        Color::DarkCyan => 6,
+        Color::Grey => 7,
+        Color::DarkGrey => 8,
+        Color::Red => 9,
+        Color::Green => 10,
+        Color::Yellow => 11,
+        Color::Blue => 12,
+        Color::Magenta => 13,
+        Color::Cyan => 14,
+        Color::White => 15,
+        Color::AnsiValue(index) => index,
+        Color::Rgb { r, g, b } => return format!("#{:02x}{:02x}{:02x}", r, g, b),
+        Color::Reset => return FOREGROUND.to_string(),
+    };
+    let (r, g, b) = capabilities::index_to_rgb(index);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn css_attribute(attribute: Attribute) -> &'static str {
+    match attribute {
+        Attribute::Bold => "font-weight:bold",
+        Attribute::Italic => "font-style:italic",
+        Attribute::Dim => "opacity:0.7",
+        _ => "",
+    }
+}
+
+fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Splits a line into styled runs, filling the gaps between tokens with
+/// plain text.
+fn runs(line: &HighlightedLine) -> Vec<(String, TokenType)> {
+    let (chars, tokens) = line;
+    let mut runs = Vec::with_capacity(tokens.len() * 2);
+    let mut pos = 0;
+    for &(start, end, token_type) in tokens {
+        let (start, end) = (start.min(chars.len()), end.min(chars.len()));
+        if start > pos {
+            runs.push((chars[pos..start].iter().collect(), TokenType::Normal));
+        }
+        if end > start.max(pos) {
+            runs.push((chars[start.max(pos)..end].iter().collect(), token_type));
+        }
+        pos = pos.max(end);
+    }
+    if pos < chars.len() {
+        runs.push((chars[pos..].iter().collect(), TokenType::Normal));
+    }
+    runs
+}
+
+/// The colored `<pre>` block alone, for pasting into other documents.
+pub fn html_fragment(lines: &[HighlightedLine]) -> String {
+    let mut out = format!(
+        "<pre style=\"background:{};color:{};padding:8px;font-family:monospace\"><code>",
+        BACKGROUND, FOREGROUND
+    );
+    for line in lines {
+        for (text, token_type) in runs(line) {
+            if token_type == TokenType::Normal {
+                escape_html(&text, &mut out);
+                continue;
+            }
+            let mut style = format!("color:{}", css_color(get_token_color(token_type)));
+            if let Some(attribute) = get_token_attribute(token_type) {
+                let _ = write!(style, ";{}", css_attribute(attribute));
+            }
+            let _ = write!(out, "<span style=\"{}\">", style);
+            escape_html(&text, &mut out);
+            out.push_str("</span>");
+        }
+        out.push('\n');
+    }
+    out.push_str("</code></pre>");
+    out
+}
+
+pub fn html_document(title: &str, lines: &[HighlightedLine]) -> String {
+    let mut escaped_title = String::new();
+    escape_html(title, &mut escaped_title);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body style=\"background:{};margin:0\">\n{}\n</body>\n</html>\n",
+        escaped_title,
+        BACKGROUND,
+        html_fragment(lines)
+    )
+}
+
+/// The lines with terminal color codes, as the editor shows them.
+pub fn ansi(lines: &[HighlightedLine]) -> String {
+    let mut out = String::new();
+    for line in lines {
+        for (text, token_type) in runs(line) {
+            if token_type == TokenType::Normal {
+                out.push_str(&text);
+                continue;
+            }
+            let mut styled = text.with(get_token_color(token_type));
+            if let Some(attribute) = get_token_attribute(token_type) {
+                styled = styled.attribute(attribute);
+            }
+            let _ = write!(out, "{}", styled);
+        }
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,57 @@
+use std::{
+    fs::{self, File, TryLockError},
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Marks a file as being edited by this process. The OS lock goes away with
+/// the handle, so a crashed instance never leaves a stale lock behind.
+pub struct FileLock {
+    _file: File,
+}
+
+pub enum Acquired {
+    Locked(FileLock),
+    /// Another termi holds the file; its process id, when it could be read.
+    HeldElsewhere(Option<u32>),
+}
+
+fn lock_path(path: &Path) -> PathBuf {
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    crate::cache_dir().join("locks").join(format!(
+        "{:016x}.lock",
+        crate::local_history::fnv1a(&canonical.to_string_lossy())
+    ))
+}
+
+pub fn acquire(path: &Path) -> io::Result<Acquired> {
+    let lock_path = lock_path(path);
+    if let Some(parent) = lock_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // No truncate: the holder's pid must survive a failed attempt.
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)?;
+    match file.try_lock() {
+        Ok(()) => {
+            file.set_len(0)?;
+            file.rewind()?;
+            write!(file, "{}", process::id())?;
+            Ok(Acquired::Locked(FileLock { _file: file }))
+        }
+        Err(TryLockError::WouldBlock) => {
+            let mut pid = String::new();
+            let pid = file
+                .read_to_string(&mut pid)
+                .ok()
+                .and_then(|_| pid.trim().parse().ok());
+            Ok(Acquired::HeldElsewhere(pid))
+        }
+        Err(TryLockError::Error(err)) => Err(err),
+    }
+}
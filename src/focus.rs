@@ -0,0 +1,185 @@
+// Words that open a function in the languages termi highlights.
+const FUNCTION_KEYWORDS: &[&str] = &["fn", "def", "function", "func", "fun", "sub"];
+
+fn indent(line: &[char]) -> Option<usize> {
+    let width = line.iter().take_while(|c| c.is_whitespace()).count();
+    (width < line.len()).then_some(width)
+}
+
+// Words that start a statement or a type rather than a function, even
+// when a parenthesis follows.
+const NOT_FUNCTIONS: &[&str] = &[
+    "if",
+    "else",
+    "for",
+    "while",
+    "switch",
+    "match",
+    "return",
+    "catch",
+    "do",
+    "new",
+    "throw",
+    "sizeof",
+    "elif",
+    "with",
+    "await",
+    "typeof",
+    "delete",
+    "class",
+    "struct",
+    "enum",
+    "union",
+    "interface",
+    "impl",
+    "trait",
+    "namespace",
+];
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+// Identifiers in `text` directly followed by one of `openers`, with their
+// byte offsets.
+fn names_before<'a>(text: &'a str, openers: &'a [char]) -> impl Iterator<Item = (usize, &'a str)> {
+    text.match_indices(openers).filter_map(move |(end, _)| {
+        let start = text[..end]
+            .rfind(|c: char| !is_ident_char(c))
+            .map_or(0, |i| i + 1);
+        let name = &text[start..end];
+        name.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '$')
+            .then_some((start, name))
+    })
+}
+
+/// The name of the function or method the line declares, if it looks like
+/// a declaration: a function keyword followed by a name, `name = (...) =>`,
+/// or C-style `type name(...)` that is not a statement.
+pub fn function_name(line: &[char]) -> Option<String> {
+    let text: String = line.iter().collect();
+    let text = text.trim();
+    if text.starts_with("//") || text.starts_with('*') || text.starts_with("/*") {
+        return None;
+    }
+
+    let keyword = text
+        .split(|c: char| !is_ident_char(c))
+        .find(|word| FUNCTION_KEYWORDS.contains(word));
+    if let Some(keyword) = keyword {
+        // Offset of the keyword within `text`, which it borrows from.
+        let end = keyword.as_ptr() as usize - text.as_ptr() as usize + keyword.len();
+        return names_before(&text[end..], &['(', '<'])
+            .map(|(_, name)| name)
+            .find(|name| !FUNCTION_KEYWORDS.contains(name))
+            .map(String::from);
+    }
+
+    if let Some((left, right)) = text.split_once('=') {
+        let right = right.trim_start();
+        let is_function =
+            right.contains("=>") || right.starts_with("function") || right.starts_with("async");
+        if left.ends_with(['=', '!', '<', '>']) || !is_function {
+            return None;
+        }
+        let name = left.trim_end().rsplit(|c: char| !is_ident_char(c)).next()?;
+        return (!name.is_empty()).then(|| name.to_string());
+    }
+
+    if text.ends_with([';', ',']) {
+        return None;
+    }
+    let (start, name) = names_before(text, &['(']).next()?;
+    let prefix = &text[..start];
+    let declares = prefix
+        .chars()
+        .all(|c| is_ident_char(c) || " *&<>:,[]".contains(c))
+        && (!prefix.trim().is_empty() || text.ends_with('{'));
+    let first_word = text.split(|c: char| !is_ident_char(c)).next().unwrap_or("");
+    (declares && !NOT_FUNCTIONS.contains(&name) && !NOT_FUNCTIONS.contains(&first_word))
+        .then(|| name.to_string())
+}
+
+fn opens_function(line: &[char]) -> bool {
+    function_name(line).is_some()
+}
+
+// A closing bracket or `end` on its own ends the block above it.
+fn is_closer(line: &[char]) -> bool {
+    let text: String = line.iter().collect();
+    let text = text.trim();
+    text.starts_with(['}', ')', ']']) || text == "end" || text.starts_with("end ")
+}
+
+/// The function around line `y`, found by indentation: the nearest line
+/// above that opens a function and is indented less than the code around
+/// the cursor, down to where the indentation drops back (its closing
+/// bracket included). Outside a function, the top-level block.
+pub fn code_block(lines: &[Vec<char>], y: usize) -> (usize, usize) {
+    let Some(here) = (y..lines.len())
+        .chain((0..y).rev())
+        .find_map(|i| indent(&lines[i]))
+    else {
+        return (y, y);
+    };
+
+    // On a closing bracket, the block it closes.
+    let here = if lines.get(y).is_some_and(|line| is_closer(line)) {
+        here + 1
+    } else {
+        here
+    };
+    let header = (0..=y).rev().find(|&i| {
+        indent(&lines[i]).is_some_and(|width| width < here || (i == y && width == here))
+            && opens_function(&lines[i])
+    });
+    let start = header.unwrap_or_else(|| {
+        (0..=y)
+            .rev()
+            .find(|&i| indent(&lines[i]) == Some(0) && !is_closer(&lines[i]))
+            .unwrap_or(0)
+    });
+    let base = indent(&lines[start]).unwrap_or(0);
+
+    let mut end = start;
+    for (i, line) in lines.iter().enumerate().skip(start + 1) {
+        match indent(line) {
+            None => continue,
+            Some(width) if width > base => end = i,
+            // A brace on its own line under the header opens the body.
+            Some(_) if end == start && line.iter().collect::<String>().trim() == "{" => end = i,
+            Some(_) => {
+                if is_closer(line) {
+                    end = i;
+                }
+                break;
+            }
+        }
+    }
+    (start, end.max(y.min(lines.len().saturating_sub(1))))
+}
+
+/// The run of lines around `y` indented at least as deep as line `y`,
+/// trimmed to its first and last non-blank line. A blank line `y` takes the
+/// indentation of the next line with text.
+pub fn indent_block(lines: &[Vec<char>], y: usize) -> (usize, usize) {
+    let Some(base) = (y..lines.len())
+        .chain((0..y).rev())
+        .find_map(|i| indent(&lines[i]))
+    else {
+        return (y, y);
+    };
+    // The last line with text before the indentation drops below `base`.
+    let edge = |steps: &mut dyn Iterator<Item = usize>| {
+        let mut edge = y;
+        for i in steps {
+            match indent(&lines[i]) {
+                None => continue,
+                Some(width) if width >= base => edge = i,
+                Some(_) => break,
+            }
+        }
+        edge
+    };
+    (edge(&mut (0..y).rev()), edge(&mut (y + 1..lines.len())))
+}
@@ -0,0 +1,123 @@
+const MATCH_SCORE: i64 = 16;
+const CONSECUTIVE_BONUS: i64 = 24;
+const BOUNDARY_BONUS: i64 = 20;
+const PREFIX_BONUS: i64 = 32;
+const GAP_PENALTY: i64 = 2;
+
+fn is_boundary(previous: Option<char>, current: char) -> bool {
+    match previous {
+        None => true,
+        Some(prev) => {
+            matches!(prev, '/' | '\\' | '_' | '-' | '.' | ' ' | ':')
+                || (prev.is_lowercase() && current.is_uppercase())
+        }
+    }
+}
+
+/// Scores `candidate` against `query` as a case-insensitive subsequence match.
+/// Returns `None` when the query characters do not all appear in order.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let mut total = 0;
+    let mut query_index = 0;
+    let mut previous: Option<char> = None;
+    let mut last_match: Option<usize> = None;
+
+    for (index, c) in candidate.chars().enumerate() {
+        if query_index < query.len() && c.to_lowercase().eq(std::iter::once(query[query_index])) {
+            total += MATCH_SCORE;
+            if index == 0 {
+                total += PREFIX_BONUS;
+            }
+            if is_boundary(previous, c) {
+                total += BOUNDARY_BONUS;
+            }
+            match last_match {
+                Some(last) if last + 1 == index => total += CONSECUTIVE_BONUS,
+                Some(last) => total -= GAP_PENALTY * (index - last - 1).min(8) as i64,
+                None => {}
+            }
+            last_match = Some(index);
+            query_index += 1;
+        }
+        previous = Some(c);
+    }
+
+    if query_index < query.len() {
+        return None;
+    }
+    // Prefer shorter candidates when everything else is equal.
+    Some(total - candidate.chars().count() as i64 / 4)
+}
+
+/// Character indices in `candidate` matched by `query`, using the same greedy
+/// walk as [`score`]. Empty when the query does not match.
+pub fn match_positions(query: &str, candidate: &str) -> Vec<usize> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    let mut positions = Vec::with_capacity(query.len());
+    for (index, c) in candidate.chars().enumerate() {
+        if positions.len() < query.len()
+            && c.to_lowercase().eq(std::iter::once(query[positions.len()]))
+        {
+            positions.push(index);
+        }
+    }
+    if positions.len() < query.len() {
+        positions.clear();
+    }
+    positions
+}
+
+/// Returns the indices of `candidates` matching `query`, best match first.
+pub fn rank<'a>(query: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<usize> {
+    let mut scored: Vec<(usize, i64)> = candidates
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, candidate)| score(query, candidate).map(|s| (index, s)))
+        .collect();
+    scored.sort_by_key(|&(index, score)| (std::cmp::Reverse(score), index));
+    scored.into_iter().map(|(index, _)| index).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_are_case_insensitive_subsequences() {
+        assert!(score("mr", "main.rs").is_some());
+        assert!(score("MAIN", "main.rs").is_some());
+        assert!(score("rm", "main.rs").is_none());
+        assert_eq!(score("  ", "anything"), Some(0));
+    }
+
+    #[test]
+    fn positions_follow_the_greedy_walk() {
+        assert_eq!(match_positions("mrs", "main.rs"), vec![0, 5, 6]);
+        assert_eq!(match_positions("x", "main.rs"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn prefixes_and_word_starts_rank_first() {
+        assert_eq!(rank("src", ["lib/asrc.rs", "src/lib.rs"]), vec![1, 0]);
+        assert_eq!(rank("fb", ["xfxb", "foo_bar"]), vec![1, 0]);
+        assert_eq!(rank("readme", ["src/main.rs", "README.md"]), vec![1]);
+    }
+
+    #[test]
+    fn shorter_candidates_win_ties() {
+        assert_eq!(rank("ab", ["abc_long_name", "abc"]), vec![1, 0]);
+    }
+}
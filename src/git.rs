@@ -0,0 +1,226 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+fn run(dir: &Path, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .output()?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        Err(io::Error::other(if message.is_empty() {
+            format!("git {} failed", args.join(" "))
+        } else {
+            message
+        }))
+    }
+}
+
+pub fn repo_root(path: &Path) -> Option<PathBuf> {
+    let dir = if path.is_dir() {
+        path.to_path_buf()
+    } else {
+        path.parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .map(|p| p.to_path_buf())
+            .unwrap_or_else(|| PathBuf::from("."))
+    };
+    let root = run(&dir, &["rev-parse", "--show-toplevel"]).ok()?;
+    Some(PathBuf::from(root.trim()))
+}
+
+pub fn relative_path(root: &Path, path: &Path) -> Option<String> {
+    let absolute = std::fs::canonicalize(path).ok()?;
+    let root = std::fs::canonicalize(root).ok()?;
+    let relative = absolute.strip_prefix(&root).ok()?;
+    Some(relative.to_string_lossy().replace('\\', "/"))
+}
+
+pub fn show_file(root: &Path, revision: &str, relative: &str) -> io::Result<String> {
+    run(root, &["show", &format!("{}:{}", revision, relative)])
+}
+
+pub struct Commit {
+    pub hash: String,
+    pub author: String,
+    pub date: String,
+    pub subject: String,
+    /// The file's path in this commit, which differs from today's before a
+    /// rename.
+    pub path: String,
+}
+
+impl Commit {
+    pub fn short_hash(&self) -> &str {
+        &self.hash[..self.hash.len().min(8)]
+    }
+}
+
+pub fn file_log(root: &Path, relative: &str) -> io::Result<Vec<Commit>> {
+    let output = run(
+        root,
+        &[
+            "log",
+            "--follow",
+            "--name-only",
+            "--date=short",
+            "--format=%x1e%H%x1f%an%x1f%ad%x1f%s",
+            "--",
+            relative,
+        ],
+    )?;
+
+    // Each record is the header line, a blank line and the file's path.
+    let mut path = relative.to_string();
+    Ok(output
+        .split('\u{1e}')
+        .filter_map(|record| {
+            let mut lines = record.lines();
+            let mut fields = lines.next()?.split('\u{1f}');
+            let commit = Commit {
+                hash: fields.next()?.to_string(),
+                author: fields.next()?.to_string(),
+                date: fields.next()?.to_string(),
+                subject: fields.next().unwrap_or_default().to_string(),
+                // Merges list no file; they keep the path of the commit
+                // after them.
+                path: match lines.rfind(|line| !line.trim().is_empty()) {
+                    Some(name) => name.trim().to_string(),
+                    None => path.clone(),
+                },
+            };
+            path = commit.path.clone();
+            Some(commit)
+        })
+        .collect())
+}
+
+pub fn current_branch(root: &Path) -> Option<String> {
+    let branch = run(root, &["rev-parse", "--abbrev-ref", "HEAD"]).ok()?;
+    let branch = branch.trim();
+    if branch == "HEAD" {
+        let hash = run(root, &["rev-parse", "--short", "HEAD"]).ok()?;
+        Some(format!("({})", hash.trim()))
+    } else {
+        Some(branch.to_string())
+    }
+}
+
+pub fn local_branches(root: &Path) -> io::Result<Vec<String>> {
+    let output = run(
+        root,
+        &[
+            "for-each-ref",
+            "--sort=-committerdate",
+            "--format=%(refname:short)",
+            "refs/heads",
+        ],
+    )?;
+    Ok(output
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+pub fn has_uncommitted_changes(root: &Path) -> io::Result<bool> {
+    let output = run(root, &["status", "--porcelain", "--untracked-files=no"])?;
+    Ok(!output.trim().is_empty())
+}
+
+pub fn checkout(root: &Path, branch: &str) -> io::Result<()> {
+    run(root, &["checkout", branch]).map(|_| ())
+}
+
+pub struct BlameLine {
+    pub hash: String,
+    pub author: String,
+    pub time: u64,
+    pub summary: String,
+    pub text: String,
+}
+
+impl BlameLine {
+    pub fn is_uncommitted(&self) -> bool {
+        self.hash.bytes().all(|b| b == b'0')
+    }
+}
+
+pub fn blame(root: &Path, relative: &str) -> io::Result<Vec<BlameLine>> {
+    let output = run(root, &["blame", "--porcelain", "--", relative])?;
+    let mut commits: HashMap<String, (String, u64, String)> = HashMap::new();
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for line in output.lines() {
+        if let Some(text) = line.strip_prefix('\t') {
+            let (author, time, summary) = commits.get(&current).cloned().unwrap_or_default();
+            lines.push(BlameLine {
+                hash: current.clone(),
+                author,
+                time,
+                summary,
+                text: text.to_string(),
+            });
+        } else if let Some(author) = line.strip_prefix("author ") {
+            commits.entry(current.clone()).or_default().0 = author.to_string();
+        } else if let Some(time) = line.strip_prefix("author-time ") {
+            commits.entry(current.clone()).or_default().1 = time.parse().unwrap_or(0);
+        } else if let Some(summary) = line.strip_prefix("summary ") {
+            commits.entry(current.clone()).or_default().2 = summary.to_string();
+        } else if let Some(hash) = line.split(' ').next() {
+            if hash.len() == 40 && hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                current = hash.to_string();
+            }
+        }
+    }
+
+    Ok(lines)
+}
+
+/// Turns an `origin` remote (ssh or https) into the web URL of the repository.
+pub fn remote_web_url(root: &Path) -> Option<String> {
+    let remote = run(root, &["remote", "get-url", "origin"]).ok()?;
+    let remote = remote.trim().trim_end_matches('/');
+    let remote = remote.strip_suffix(".git").unwrap_or(remote);
+
+    let (host, path) = if let Some(rest) = remote
+        .strip_prefix("https://")
+        .or_else(|| remote.strip_prefix("http://"))
+        .or_else(|| remote.strip_prefix("ssh://"))
+        .or_else(|| remote.strip_prefix("git://"))
+    {
+        let (host, path) = rest.split_once('/')?;
+        (host.rsplit('@').next()?, path)
+    } else {
+        // scp-like syntax: git@github.com:owner/repo
+        let (host, path) = remote.split_once(':')?;
+        (host.rsplit('@').next()?, path)
+    };
+    let host = host.split(':').next()?;
+    Some(format!("https://{}/{}", host, path.trim_start_matches('/')))
+}
+
+pub fn commit_url(web_url: &str, hash: &str) -> String {
+    if web_url.contains("gitlab") {
+        format!("{}/-/commit/{}", web_url, hash)
+    } else if web_url.contains("bitbucket") {
+        format!("{}/commits/{}", web_url, hash)
+    } else {
+        format!("{}/commit/{}", web_url, hash)
+    }
+}
+
+pub fn user_name(dir: &Path) -> Option<String> {
+    let name = run(dir, &["config", "user.name"]).ok()?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
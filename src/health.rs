@@ -0,0 +1,274 @@
+use unicode_width::UnicodeWidthChar;
+
+// Tabs in indentation are expanded to this many columns, matching what
+// the editor's own indent inserts.
+const TAB_WIDTH: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fix {
+    LineEndings,
+    Indentation,
+    TrailingWhitespace,
+    GoToLongestLine,
+}
+
+/// What the health check found in a buffer.
+pub struct Report {
+    /// Line endings of the saved file; the buffer itself only knows `\n`.
+    /// A lone `\r` ends a line for old Mac files but stays inside the
+    /// buffer's line.
+    pub crlf_lines: usize,
+    pub lf_lines: usize,
+    pub cr_lines: usize,
+    pub tab_indented: usize,
+    pub space_indented: usize,
+    /// Lines whose indentation has both tabs and spaces.
+    pub mixed_indented: usize,
+    pub trailing_whitespace: usize,
+    /// Line index and display width of the longest line.
+    pub longest: (usize, usize),
+}
+
+pub struct Row {
+    pub label: String,
+    pub hint: String,
+    pub fix: Option<Fix>,
+}
+
+fn leading_whitespace(line: &[char]) -> &[char] {
+    let width = line
+        .iter()
+        .take_while(|c| **c == ' ' || **c == '\t')
+        .count();
+    &line[..width]
+}
+
+/// Columns the line takes on screen: wide characters such as CJK take
+/// two, and tabs run to the next tab stop.
+pub fn display_width(line: &[char]) -> usize {
+    line.iter().fold(0, |width, &c| match c {
+        '\t' => (width / TAB_WIDTH + 1) * TAB_WIDTH,
+        c => width + c.width().unwrap_or(0),
+    })
+}
+
+pub fn check(lines: &[Vec<char>], saved: Option<&str>) -> Report {
+    let (crlf_lines, lf_lines, cr_lines) = saved.map_or((0, 0, 0), |text| {
+        let crlf = text.matches("\r\n").count();
+        (
+            crlf,
+            text.matches('\n').count() - crlf,
+            text.matches('\r').count() - crlf,
+        )
+    });
+    let mut report = Report {
+        crlf_lines,
+        lf_lines,
+        cr_lines,
+        tab_indented: 0,
+        space_indented: 0,
+        mixed_indented: 0,
+        trailing_whitespace: 0,
+        longest: (0, 0),
+    };
+    for (y, line) in lines.iter().enumerate() {
+        let indent = leading_whitespace(line);
+        match (indent.contains(&'\t'), indent.contains(&' ')) {
+            (true, true) => report.mixed_indented += 1,
+            (true, false) => report.tab_indented += 1,
+            (false, true) => report.space_indented += 1,
+            (false, false) => {}
+        }
+        if line.last().is_some_and(|c| c.is_whitespace()) {
+            report.trailing_whitespace += 1;
+        }
+        let width = display_width(line);
+        if width > report.longest.1 {
+            report.longest = (y, width);
+        }
+    }
+    report
+}
+
+impl Report {
+    pub fn rows(&self) -> Vec<Row> {
+        let mut rows = Vec::new();
+        let kinds = [
+            (self.lf_lines, "LF"),
+            (self.crlf_lines, "CRLF"),
+            (self.cr_lines, "CR"),
+        ];
+        let used: Vec<_> = kinds.iter().filter(|(count, _)| *count > 0).collect();
+        rows.push(match used.as_slice() {
+            [] | [(_, "LF")] => Row {
+                label: "Line endings: LF".into(),
+                hint: "OK".into(),
+                fix: None,
+            },
+            [(_, kind)] => Row {
+                label: format!("Line endings: {}", kind),
+                hint: "Enter: save as LF".into(),
+                fix: Some(Fix::LineEndings),
+            },
+            mixed => Row {
+                label: format!(
+                    "Line endings: mixed, {}",
+                    mixed
+                        .iter()
+                        .map(|(count, kind)| format!("{} {}", count, kind))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                hint: "Enter: normalize to LF".into(),
+                fix: Some(Fix::LineEndings),
+            },
+        });
+
+        let tabs = self.tab_indented + self.mixed_indented;
+        let mixed = self.mixed_indented > 0 || (tabs > 0 && self.space_indented > 0);
+        rows.push(Row {
+            label: format!(
+                "Indentation: spaces {}, tabs {}, mixed {}",
+                self.space_indented, self.tab_indented, self.mixed_indented
+            ),
+            hint: if mixed {
+                "Enter: tabs to spaces".into()
+            } else {
+                "OK".into()
+            },
+            fix: mixed.then_some(Fix::Indentation),
+        });
+
+        rows.push(Row {
+            label: format!("Trailing whitespace: {} lines", self.trailing_whitespace),
+            hint: if self.trailing_whitespace > 0 {
+                "Enter: strip".into()
+            } else {
+                "OK".into()
+            },
+            fix: (self.trailing_whitespace > 0).then_some(Fix::TrailingWhitespace),
+        });
+
+        let (y, length) = self.longest;
+        rows.push(Row {
+            label: format!("Longest line: {} columns (line {})", length, y + 1),
+            hint: "Enter: go to it".into(),
+            fix: Some(Fix::GoToLongestLine),
+        });
+        rows
+    }
+}
+
+/// The lines with each lone `\r` turned into a line break, since saving
+/// only writes `\n` between the buffer's lines. None when there is none.
+pub fn split_carriage_returns(lines: &[Vec<char>]) -> Option<Vec<Vec<char>>> {
+    if !lines.iter().any(|line| line.contains(&'\r')) {
+        return None;
+    }
+    Some(
+        lines
+            .iter()
+            .flat_map(|line| line.split(|&c| c == '\r').map(<[char]>::to_vec))
+            .collect(),
+    )
+}
+
+/// Expands tabs in the line's indentation to spaces. Returns whether the
+/// line changed.
+pub fn expand_indent(line: &mut Vec<char>) -> bool {
+    let indent = leading_whitespace(line).to_vec();
+    if !indent.contains(&'\t') {
+        return false;
+    }
+    let mut expanded = Vec::new();
+    for &c in &indent {
+        if c == '\t' {
+            let stop = (expanded.len() / TAB_WIDTH + 1) * TAB_WIDTH;
+            expanded.resize(stop, ' ');
+        } else {
+            expanded.push(c);
+        }
+    }
+    let rest = line.split_off(indent.len());
+    *line = expanded;
+    line.extend(rest);
+    true
+}
+
+/// Removes whitespace at the end of the line. Returns whether the line
+/// changed.
+pub fn strip_trailing(line: &mut Vec<char>) -> bool {
+    let length = line.len();
+    while line.last().is_some_and(|c| c.is_whitespace()) {
+        line.pop();
+    }
+    line.len() != length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<Vec<char>> {
+        text.lines().map(|line| line.chars().collect()).collect()
+    }
+
+    fn line_endings(report: &Report) -> (String, Option<Fix>) {
+        let row = report.rows().remove(0);
+        (row.label, row.fix)
+    }
+
+    #[test]
+    fn wide_characters_count_twice() {
+        assert_eq!(display_width(&"日本語".chars().collect::<Vec<_>>()), 6);
+        assert_eq!(display_width(&"\tab".chars().collect::<Vec<_>>()), 6);
+        let report = check(&lines("abcdefg\n漢字漢字"), None);
+        assert_eq!(report.longest, (1, 8));
+    }
+
+    #[test]
+    fn uniform_line_endings() {
+        let text = "a\nb\n";
+        let report = check(&lines(text), Some(text));
+        assert_eq!(line_endings(&report), ("Line endings: LF".into(), None));
+
+        let text = "a\r\nb\r\n";
+        let report = check(&lines(text), Some(text));
+        assert_eq!(
+            line_endings(&report),
+            ("Line endings: CRLF".into(), Some(Fix::LineEndings))
+        );
+    }
+
+    #[test]
+    fn mixed_line_endings_are_offered_a_fix() {
+        let text = "a\r\nb\nc\rd\n";
+        let report = check(&lines(text), Some(text));
+        assert_eq!(
+            line_endings(&report),
+            (
+                "Line endings: mixed, 2 LF, 1 CRLF, 1 CR".into(),
+                Some(Fix::LineEndings)
+            )
+        );
+    }
+
+    #[test]
+    fn lone_carriage_returns_become_lines() {
+        assert_eq!(split_carriage_returns(&lines("a\nb")), None);
+        assert_eq!(
+            split_carriage_returns(&lines("a\rb\nc")),
+            Some(lines("a\nb\nc"))
+        );
+    }
+
+    #[test]
+    fn indentation_and_trailing_fixes() {
+        let mut line: Vec<char> = "\t  x\t".chars().collect();
+        assert!(expand_indent(&mut line));
+        assert!(strip_trailing(&mut line));
+        assert_eq!(line.iter().collect::<String>(), "      x");
+        assert!(!expand_indent(&mut line));
+        assert!(!strip_trailing(&mut line));
+    }
+}
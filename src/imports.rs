@@ -0,0 +1,581 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+// Directories never searched for definitions.
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target", "build", "dist", "__pycache__"];
+// The project search stops after reading this many files.
+const MAX_FILES: usize = 5_000;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Style {
+    /// `use a::b;`
+    Rust,
+    /// `import a` and `from a import b`
+    Python,
+    /// `import { a } from './b';`, for JavaScript and TypeScript.
+    Script,
+    /// `#include "a.h"`, for C and C++.
+    C,
+    /// `import a.b.C;`
+    Java,
+}
+
+impl Style {
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Style::Rust => &["rs"],
+            Style::Python => &["py", "pyw"],
+            Style::Script => &["js", "jsx", "mjs", "cjs", "ts", "tsx", "mts", "cts"],
+            Style::C => &["c", "h", "cpp", "cc", "cxx", "hpp", "hh", "hxx"],
+            Style::Java => &["java"],
+        }
+    }
+}
+
+pub fn style(path: &Path) -> Option<Style> {
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    [
+        Style::Rust,
+        Style::Python,
+        Style::Script,
+        Style::C,
+        Style::Java,
+    ]
+    .into_iter()
+    .find(|style| style.extensions().contains(&ext.as_str()))
+}
+
+/// Whether the line starts an import statement in this style.
+pub fn is_import(line: &str, style: Style) -> bool {
+    let line = line.trim_start();
+    match style {
+        Style::Rust => line.starts_with("use ") || line.starts_with("pub use "),
+        Style::Python => line.starts_with("import ") || line.starts_with("from "),
+        Style::Script | Style::Java => line.starts_with("import "),
+        Style::C => line.starts_with("#include"),
+    }
+}
+
+// Lines before the imports that they must stay below.
+fn is_preamble(line: &str, style: Style) -> bool {
+    let line = line.trim();
+    line.is_empty()
+        || line.starts_with("//")
+        || line.starts_with("/*")
+        || line.starts_with('*')
+        || match style {
+            Style::Rust => line.starts_with("#![") || line.starts_with("//!"),
+            Style::Python => line.starts_with('#'),
+            Style::Script => line.starts_with("'use ") || line.starts_with("\"use "),
+            Style::C => {
+                line == "#pragma once"
+                    || line.starts_with("#ifndef ")
+                    || line.starts_with("#define ")
+            }
+            Style::Java => line.starts_with("package "),
+        }
+}
+
+// The first line after the preamble; a Python module docstring is part of
+// it.
+fn preamble_end(lines: &[Vec<char>], style: Style) -> usize {
+    let mut y = 0;
+    while y < lines.len() {
+        let line: String = lines[y].iter().collect();
+        let quote = ["\"\"\"", "'''"]
+            .into_iter()
+            .find(|quote| style == Style::Python && line.trim_start().starts_with(quote));
+        if let Some(quote) = quote {
+            let rest = &line.trim_start()[3..];
+            y += 1;
+            if !rest.contains(quote) {
+                while y < lines.len() && !lines[y].iter().collect::<String>().contains(quote) {
+                    y += 1;
+                }
+                y += 1;
+            }
+        } else if is_preamble(&line, style) {
+            y += 1;
+        } else {
+            break;
+        }
+    }
+    y.min(lines.len())
+}
+
+// Imports inside a function or a nested module are indented; they are
+// not the file's.
+fn is_top_level_import(line: &str, style: Style) -> bool {
+    !line.starts_with(char::is_whitespace) && is_import(line, style)
+}
+
+/// The first and last line of the import block at the top of the file,
+/// right after the preamble. Blank lines and continuation lines inside it
+/// belong to it.
+pub fn block(lines: &[Vec<char>], style: Style) -> Option<(usize, usize)> {
+    let text = |y: usize| lines[y].iter().collect::<String>();
+    let start = preamble_end(lines, style);
+    if start >= lines.len() || !is_top_level_import(&text(start), style) {
+        return None;
+    }
+    let mut end = start;
+    let mut open = 0i32;
+    for y in start..lines.len() {
+        let line = text(y);
+        if open > 0 || is_top_level_import(&line, style) {
+            end = y;
+        } else if !line.trim().is_empty() {
+            break;
+        }
+        open += line.matches(['{', '(']).count() as i32 - line.matches(['}', ')']).count() as i32;
+    }
+    Some((start, end))
+}
+
+/// The line a new import statement goes on: in sorted order within the
+/// import block, or below the file's preamble when there is none. The flag
+/// says whether a blank line should follow to separate it from the code.
+pub fn insertion_line(lines: &[Vec<char>], style: Style, statement: &str) -> (usize, bool) {
+    if let Some((start, end)) = block(lines, style) {
+        let y = (start..=end)
+            .find(|&y| {
+                let line: String = lines[y].iter().collect();
+                is_top_level_import(&line, style) && line.trim() > statement
+            })
+            .unwrap_or(end + 1);
+        return (y, false);
+    }
+    let mut y = preamble_end(lines, style);
+    // Back up over blank lines so the import sits right after the preamble.
+    while y > 0 && lines[y - 1].iter().all(|c| c.is_whitespace()) {
+        y -= 1;
+    }
+    let followed_by_code = lines
+        .get(y)
+        .is_some_and(|line| line.iter().any(|c| !c.is_whitespace()));
+    (y, followed_by_code)
+}
+
+/// The import block sorted and without duplicates, as the lines that
+/// replace it. Groups separated by blank lines are sorted on their own and
+/// stay in order; a statement spanning several lines moves as one. Python's
+/// `from __future__` imports stay first, and C includes are only sorted
+/// when blank lines split them into groups, since a header may rely on the
+/// ones included before it.
+pub fn organize(lines: &[Vec<char>], style: Style) -> Option<((usize, usize), Vec<String>)> {
+    let (start, end) = block(lines, style)?;
+    let mut groups: Vec<Vec<String>> = vec![Vec::new()];
+    let mut open = 0i32;
+    for line in &lines[start..=end] {
+        let line: String = line.iter().collect();
+        let group = groups.last_mut()?;
+        match group.last_mut() {
+            Some(statement) if open > 0 => {
+                statement.push('\n');
+                statement.push_str(&line);
+            }
+            _ if line.trim().is_empty() => {
+                if !group.is_empty() {
+                    groups.push(Vec::new());
+                }
+            }
+            _ => group.push(line.clone()),
+        }
+        open += line.matches(['{', '(']).count() as i32 - line.matches(['}', ')']).count() as i32;
+    }
+
+    groups.retain(|group| !group.is_empty());
+    let sorted = style != Style::C || groups.len() > 1;
+    let is_future = |statement: &str| statement.trim_start().starts_with("from __future__ ");
+    let mut seen = HashSet::new();
+    let mut organized = Vec::new();
+    for mut group in groups {
+        group.retain(|statement| seen.insert(statement.split_whitespace().collect::<String>()));
+        if sorted {
+            group.sort_by(|a, b| (!is_future(a), a.trim()).cmp(&(!is_future(b), b.trim())));
+        }
+        if !organized.is_empty() && !group.is_empty() {
+            organized.push(String::new());
+        }
+        organized.extend(
+            group
+                .iter()
+                .flat_map(|statement| statement.split('\n'))
+                .map(String::from),
+        );
+    }
+    Some(((start, end), organized))
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+// Whether `text` starts with `ident` as a whole word.
+fn starts_with_ident(text: &str, ident: &str) -> bool {
+    text.strip_prefix(ident)
+        .is_some_and(|rest| !rest.starts_with(is_ident_char))
+}
+
+fn strip_any<'a>(text: &'a str, prefixes: &[&str]) -> &'a str {
+    prefixes
+        .iter()
+        .find_map(|prefix| text.strip_prefix(prefix))
+        .unwrap_or(text)
+}
+
+fn defines_rust(line: &str, ident: &str) -> bool {
+    let Some(rest) = line.trim_start().strip_prefix("pub") else {
+        return false;
+    };
+    let rest = strip_any(rest, &["(crate) ", " "]);
+    let rest = strip_any(rest, &["async ", "unsafe "]);
+    [
+        "const fn ",
+        "fn ",
+        "struct ",
+        "enum ",
+        "trait ",
+        "type ",
+        "const ",
+        "static ",
+        "union ",
+        "mod ",
+    ]
+    .iter()
+    .any(|keyword| {
+        rest.strip_prefix(keyword)
+            .is_some_and(|name| starts_with_ident(name, ident))
+    })
+}
+
+fn defines_python(line: &str, ident: &str) -> bool {
+    let rest = strip_any(line, &["async "]);
+    ["def ", "class "].iter().any(|keyword| {
+        rest.strip_prefix(keyword)
+            .is_some_and(|name| starts_with_ident(name, ident))
+    }) || line.strip_prefix(ident).is_some_and(|rest| {
+        rest.trim_start().starts_with('=') && !rest.trim_start().starts_with("==")
+    })
+}
+
+// Returns whether the export is the default one.
+fn defines_script(line: &str, ident: &str) -> Option<bool> {
+    let rest = line.strip_prefix("export ")?;
+    let (default, rest) = match rest.strip_prefix("default ") {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let rest = strip_any(rest, &["async ", "abstract ", "declare "]);
+    [
+        "function ",
+        "function* ",
+        "class ",
+        "const ",
+        "let ",
+        "var ",
+        "interface ",
+        "type ",
+        "enum ",
+    ]
+    .iter()
+    .any(|keyword| {
+        rest.strip_prefix(keyword)
+            .is_some_and(|name| starts_with_ident(name, ident))
+    })
+    .then_some(default)
+}
+
+fn defines_c(line: &str, ident: &str) -> bool {
+    let line = line.trim();
+    if let Some(name) = line.strip_prefix("#define ") {
+        return starts_with_ident(name, ident);
+    }
+    if line.starts_with("//") || line.starts_with('*') || line.starts_with("return ") {
+        return false;
+    }
+    let Some(at) = line
+        .match_indices(ident)
+        .map(|(at, _)| at)
+        .find(|&at| !line[..at].ends_with(is_ident_char) && starts_with_ident(&line[at..], ident))
+    else {
+        return false;
+    };
+    let before = line[..at].trim_end();
+    let after = line[at + ident.len()..].trim_start();
+    // `struct x {`, `class x;`, `typedef ... x;`, or a prototype `int x(...);`
+    ["struct", "class", "enum", "union"]
+        .iter()
+        .any(|keyword| before.ends_with(keyword))
+        || (line.starts_with("typedef ") && after.starts_with(';'))
+        || (!before.is_empty() && after.starts_with('(') && line.ends_with(';'))
+}
+
+/// `to` as seen from the directory `from`, with forward slashes.
+fn relative(from: &Path, to: &Path) -> String {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let shared = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut parts: Vec<String> = vec!["..".to_string(); from.len() - shared];
+    parts.extend(
+        to[shared..]
+            .iter()
+            .map(|part| part.as_os_str().to_string_lossy().into_owned()),
+    );
+    parts.join("/")
+}
+
+fn rust_module(file: &Path) -> Option<String> {
+    let src = file.ancestors().find(|dir| dir.ends_with("src"))?;
+    let mut parts: Vec<String> = file
+        .strip_prefix(src)
+        .ok()?
+        .with_extension("")
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    if matches!(
+        parts.last().map(String::as_str),
+        Some("mod" | "lib" | "main")
+    ) {
+        parts.pop();
+    }
+    parts.insert(0, "crate".to_string());
+    Some(parts.join("::"))
+}
+
+fn python_module(root: &Path, file: &Path) -> Option<String> {
+    let relative = file.strip_prefix(root).ok()?.with_extension("");
+    let mut parts: Vec<String> = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    if parts.first().is_some_and(|part| part == "src") {
+        parts.remove(0);
+    }
+    if parts.last().is_some_and(|part| part == "__init__") {
+        parts.pop();
+    }
+    (!parts.is_empty()).then(|| parts.join("."))
+}
+
+fn script_specifier(current: &Path, file: &Path) -> String {
+    let file = if file.file_stem().is_some_and(|stem| stem == "index") {
+        file.parent().unwrap_or(file).to_path_buf()
+    } else {
+        file.with_extension("")
+    };
+    let dir = current.parent().unwrap_or(Path::new(""));
+    let path = relative(dir, &file);
+    if path.starts_with("..") {
+        path
+    } else {
+        format!("./{}", path)
+    }
+}
+
+// The import statement that brings `ident` from `file` into `current`, if
+// `file` defines it.
+fn statement_for(
+    root: &Path,
+    current: &Path,
+    file: &Path,
+    text: &str,
+    ident: &str,
+    style: Style,
+) -> Option<String> {
+    match style {
+        Style::Rust => {
+            text.lines()
+                .any(|line| defines_rust(line, ident))
+                .then_some(())?;
+            // Only items of the crate the current file belongs to.
+            let crate_of = |path: &Path| {
+                path.ancestors()
+                    .find(|dir| dir.ends_with("src"))
+                    .map(Path::to_path_buf)
+            };
+            if crate_of(file) != crate_of(current) {
+                return None;
+            }
+            Some(format!("use {}::{};", rust_module(file)?, ident))
+        }
+        Style::Python => {
+            text.lines()
+                .any(|line| defines_python(line, ident))
+                .then_some(())?;
+            Some(format!(
+                "from {} import {}",
+                python_module(root, file)?,
+                ident
+            ))
+        }
+        Style::Script => {
+            let default = text.lines().find_map(|line| defines_script(line, ident))?;
+            let specifier = script_specifier(current, file);
+            Some(if default {
+                format!("import {} from '{}';", ident, specifier)
+            } else {
+                format!("import {{ {} }} from '{}';", ident, specifier)
+            })
+        }
+        Style::C => {
+            let ext = file.extension()?.to_string_lossy().to_lowercase();
+            if !["h", "hpp", "hh", "hxx"].contains(&ext.as_str()) {
+                return None;
+            }
+            text.lines()
+                .any(|line| defines_c(line, ident))
+                .then_some(())?;
+            let dir = current.parent().unwrap_or(Path::new(""));
+            Some(format!("#include \"{}\"", relative(dir, file)))
+        }
+        Style::Java => {
+            if file.file_stem()? != ident {
+                return None;
+            }
+            let package = text.lines().find_map(|line| {
+                line.trim()
+                    .strip_prefix("package ")
+                    .map(|rest| rest.trim_end_matches(';').trim().to_string())
+            })?;
+            Some(format!("import {}.{};", package, ident))
+        }
+    }
+}
+
+fn collect_files(dir: &Path, style: Style, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if files.len() >= MAX_FILES {
+            return;
+        }
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+            if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_str()) {
+                collect_files(&path, style, files);
+            }
+        } else if path.extension().is_some_and(|ext| {
+            style
+                .extensions()
+                .contains(&ext.to_string_lossy().to_lowercase().as_str())
+        }) {
+            files.push(path);
+        }
+    }
+}
+
+/// Import statements for every definition of `ident` found in the project
+/// under `root`, nearest to `current` first.
+pub fn candidates(root: &Path, current: &Path, ident: &str, style: Style) -> Vec<String> {
+    let current = fs::canonicalize(current).unwrap_or_else(|_| current.to_path_buf());
+    let mut files = Vec::new();
+    collect_files(root, style, &mut files);
+    let shared = |file: &PathBuf| {
+        file.components()
+            .zip(current.components())
+            .take_while(|(a, b)| a == b)
+            .count()
+    };
+    files.sort_by_key(|file| std::cmp::Reverse(shared(file)));
+
+    let mut statements: Vec<String> = Vec::new();
+    for file in files {
+        if file == current {
+            continue;
+        }
+        let Ok(text) = fs::read_to_string(&file) else {
+            continue;
+        };
+        if !text.contains(ident) {
+            continue;
+        }
+        if let Some(statement) = statement_for(root, &current, &file, &text, ident, style) {
+            if !statements.contains(&statement) {
+                statements.push(statement);
+            }
+        }
+    }
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<Vec<char>> {
+        text.lines().map(|line| line.chars().collect()).collect()
+    }
+
+    #[test]
+    fn block_follows_the_preamble() {
+        let file = lines("//! Docs\n\nuse b::c;\nuse a::{\n    d,\n};\n\nfn main() {}");
+        assert_eq!(block(&file, Style::Rust), Some((2, 5)));
+        let file = lines("\"\"\"Module\ndocs.\"\"\"\nimport os\n\nx = 1");
+        assert_eq!(block(&file, Style::Python), Some((2, 2)));
+    }
+
+    #[test]
+    fn nested_imports_are_not_the_block() {
+        let file = lines("fn main() {}\n\n#[cfg(test)]\nmod tests {\n    use super::*;\n}");
+        assert_eq!(block(&file, Style::Rust), None);
+        assert!(organize(&file, Style::Rust).is_none());
+        assert_eq!(insertion_line(&file, Style::Rust, "use a::b;"), (0, true));
+
+        let file = lines("def f():\n    import os\n    return os");
+        assert_eq!(block(&file, Style::Python), None);
+    }
+
+    #[test]
+    fn no_imports() {
+        let file = lines("// Header\n\nint main(void) {}");
+        assert_eq!(block(&file, Style::C), None);
+        assert_eq!(
+            insertion_line(&file, Style::C, "#include \"a.h\""),
+            (1, false)
+        );
+        assert_eq!(insertion_line(&[], Style::Rust, "use a::b;"), (0, false));
+    }
+
+    #[test]
+    fn insertion_keeps_sorted_order() {
+        let file = lines("use a::b;\nuse c::d;\n\nfn main() {}");
+        assert_eq!(insertion_line(&file, Style::Rust, "use b::c;"), (1, false));
+        assert_eq!(insertion_line(&file, Style::Rust, "use d::e;"), (2, false));
+    }
+
+    #[test]
+    fn organize_sorts_groups_and_drops_duplicates() {
+        let file = lines("use c;\nuse a;\nuse c;\n\nuse b;\n\nfn main() {}");
+        let (range, organized) = organize(&file, Style::Rust).unwrap();
+        assert_eq!(range, (0, 4));
+        assert_eq!(organized, ["use a;", "use c;", "", "use b;"]);
+    }
+
+    #[test]
+    fn organize_keeps_future_imports_first() {
+        let file = lines("import sys\nfrom __future__ import annotations\nimport abc");
+        let (_, organized) = organize(&file, Style::Python).unwrap();
+        assert_eq!(
+            organized,
+            [
+                "from __future__ import annotations",
+                "import abc",
+                "import sys"
+            ]
+        );
+    }
+
+    #[test]
+    fn organize_leaves_a_single_include_group_in_order() {
+        let file = lines("#include \"b.h\"\n#include \"a.h\"");
+        let (_, organized) = organize(&file, Style::C).unwrap();
+        assert_eq!(organized, ["#include \"b.h\"", "#include \"a.h\""]);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::event_loop::Waker;
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+// A second `termi path` hands the path to the running instance over a
+// loopback socket. The port and a random token live in this file, readable
+// only by the user, so other local users cannot drive the editor.
+fn instance_file() -> PathBuf {
+    crate::cache_dir().join("instance")
+}
+
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Asks a running instance to open `path`. Returns false when there is none
+/// (or it did not answer), in which case this process starts normally. With
+/// `wait`, returns only once that instance has closed the file or exited.
+pub fn forward(path: &Path, wait: bool) -> bool {
+    let Ok(contents) = fs::read_to_string(instance_file()) else {
+        return false;
+    };
+    let Some((port, token)) = contents.trim().split_once(' ') else {
+        return false;
+    };
+    let Ok(port) = port.parse::<u16>() else {
+        return false;
+    };
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+    let send = || -> io::Result<bool> {
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))?;
+        stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+        writeln!(
+            stream,
+            "{}\n{}\n{}",
+            token,
+            path.to_string_lossy(),
+            if wait { "wait" } else { "open" }
+        )?;
+        let mut reader = BufReader::new(stream);
+        let mut reply = String::new();
+        reader.read_line(&mut reply)?;
+        if reply.trim() != "ok" {
+            return Ok(false);
+        }
+        if wait {
+            eprintln!("Waiting for the file to be closed in termi...");
+            reader.get_ref().set_read_timeout(None)?;
+            // "closed", or the end of the stream when the editor exits.
+            let _ = reader.read_line(&mut reply);
+        }
+        Ok(true)
+    };
+    send().unwrap_or(false)
+}
+
+/// A path handed over by a later invocation.
+pub struct Request {
+    pub path: PathBuf,
+    /// Set for `termi --wait`; drop it once the file is closed.
+    pub waiter: Option<Waiter>,
+}
+
+/// The connection of a `termi --wait` invocation. Dropping it, also when
+/// the editor exits, lets that invocation return.
+pub struct Waiter(TcpStream);
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        let _ = self.0.write_all(b"closed\n");
+    }
+}
+
+/// Removes the instance file when the serving editor exits.
+pub struct InstanceServer {
+    contents: String,
+}
+
+impl Drop for InstanceServer {
+    fn drop(&mut self) {
+        // A newer instance may have taken over after a crash; leave its file.
+        let file = instance_file();
+        if fs::read_to_string(&file).is_ok_and(|contents| contents == self.contents) {
+            let _ = fs::remove_file(file);
+        }
+    }
+}
+
+/// Starts accepting paths from later invocations. They arrive on the
+/// returned channel, and `waker` interrupts the main loop for each.
+pub fn serve(waker: Waker) -> io::Result<(InstanceServer, Receiver<Request>)> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let port = listener.local_addr()?.port();
+    let token = new_token();
+    let contents = format!("{} {}", port, token);
+
+    let file = instance_file();
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_private(&file, &contents)?;
+
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            // A client that connects and says nothing must not hold up the
+            // next one.
+            let (tx, waker, token) = (tx.clone(), waker.clone(), token.clone());
+            thread::spawn(move || {
+                let Some((path, wait)) = read_request(&stream, &token) else {
+                    return;
+                };
+                if (&stream).write_all(b"ok\n").is_err() {
+                    return;
+                }
+                let waiter = wait.then_some(Waiter(stream));
+                if tx.send(Request { path, waiter }).is_ok() {
+                    waker.wake();
+                }
+            });
+        }
+    });
+
+    Ok((InstanceServer { contents }, rx))
+}
+
+/// 128 bits from the operating system's random number generator, in hex.
+pub fn new_token() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("the system random number generator is unavailable");
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Compares a token a client sent with ours in time that does not depend
+/// on where they first differ.
+pub fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+// The token, the path and whether the client waits for the file to close.
+fn read_request(stream: &TcpStream, token: &str) -> Option<(PathBuf, bool)> {
+    stream.set_read_timeout(Some(REPLY_TIMEOUT)).ok()?;
+    let mut lines = BufReader::new(stream).lines();
+    if lines.next()?.ok()? != token {
+        return None;
+    }
+    let path = lines.next()?.ok()?;
+    let wait = lines.next()?.ok()? == "wait";
+    (!path.is_empty()).then(|| (PathBuf::from(path), wait))
+}
+
+pub fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    // Recreate rather than truncate so the permissions below always apply.
+    let _ = fs::remove_file(path);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(contents.as_bytes())
+}
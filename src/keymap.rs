@@ -0,0 +1,303 @@
+use crate::PaletteCommand;
+use crossterm::event::{KeyCode, KeyModifiers};
+
+// How `[keys]` names the command palette itself.
+const PALETTE: &str = "Command Palette";
+
+/// What a bound key does: any palette command, or the palette itself.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Palette,
+    Command(PaletteCommand),
+}
+
+/// Key bindings laid over termi's own. A key a preset does not bind keeps
+/// its built-in meaning.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Preset {
+    #[default]
+    Termi,
+    VsCode,
+    Vim,
+    Nano,
+}
+
+impl Preset {
+    pub const ALL: &[Preset] = &[Preset::Termi, Preset::VsCode, Preset::Vim, Preset::Nano];
+
+    /// The value of `keymap` in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Termi => "termi",
+            Preset::VsCode => "vscode",
+            Preset::Vim => "vim",
+            Preset::Nano => "nano",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Preset> {
+        Preset::ALL
+            .iter()
+            .copied()
+            .find(|preset| preset.name() == name.to_lowercase())
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Preset::Termi => "termi default",
+            Preset::VsCode => "VS Code-like",
+            Preset::Vim => "vim-like",
+            Preset::Nano => "nano-like",
+        }
+    }
+
+    pub fn summary(self) -> &'static str {
+        match self {
+            Preset::Termi => "Ctrl+P palette, Ctrl+O tree",
+            Preset::VsCode => "Ctrl+Shift+P palette, Ctrl+B sidebar",
+            Preset::Vim => "Ctrl+R redo, Ctrl+] symbol, F1 tutor",
+            Preset::Nano => "Ctrl+O save, Ctrl+W find, Ctrl+X exit",
+        }
+    }
+
+    /// The keys this preset adds, by their labels, over termi's own.
+    pub fn bindings(self) -> &'static [(&'static str, Action)] {
+        match self {
+            Preset::Termi => &[],
+            Preset::VsCode => &[
+                ("Ctrl+Shift+P", Action::Palette),
+                ("F1", Action::Palette),
+                ("Ctrl+B", Action::Command(PaletteCommand::ToggleTree)),
+                ("Ctrl+`", Action::Command(PaletteCommand::ToggleTerminal)),
+                ("Ctrl+Shift+O", Action::Command(PaletteCommand::Outline)),
+                ("Ctrl+T", Action::Command(PaletteCommand::GoToSymbol)),
+                ("Ctrl+Shift+Z", Action::Command(PaletteCommand::Redo)),
+                ("Ctrl+F4", Action::Command(PaletteCommand::CloseBuffer)),
+            ],
+            // termi has no modes, so only vim's Ctrl and function keys carry
+            // over.
+            Preset::Vim => &[
+                ("Ctrl+R", Action::Command(PaletteCommand::Redo)),
+                ("Ctrl+]", Action::Command(PaletteCommand::GoToSymbol)),
+                // Ctrl+^, the alternate file; terminals send it as Ctrl+6.
+                (
+                    "Ctrl+6",
+                    Action::Command(PaletteCommand::ReopenClosedBuffer),
+                ),
+                ("Alt+:", Action::Palette),
+                ("F1", Action::Command(PaletteCommand::Tutorial)),
+            ],
+            Preset::Nano => &[
+                ("Ctrl+O", Action::Command(PaletteCommand::Save)),
+                ("Ctrl+X", Action::Command(PaletteCommand::Quit)),
+                ("Ctrl+W", Action::Command(PaletteCommand::Find)),
+                ("Alt+G", Action::Command(PaletteCommand::GoToLine)),
+                ("Alt+U", Action::Command(PaletteCommand::Undo)),
+                ("Alt+E", Action::Command(PaletteCommand::Redo)),
+                ("Alt+]", Action::Command(PaletteCommand::MatchingPair)),
+                ("Ctrl+T", Action::Command(PaletteCommand::ToggleTerminal)),
+                ("Ctrl+G", Action::Command(PaletteCommand::Tutorial)),
+            ],
+        }
+    }
+}
+
+/// What `bindings` maps the key to.
+pub fn lookup<S: AsRef<str>>(
+    bindings: &[(S, Action)],
+    code: KeyCode,
+    modifiers: KeyModifiers,
+) -> Option<Action> {
+    let key = normalize(code, modifiers);
+    bindings
+        .iter()
+        .find(|(label, _)| parse_key(label.as_ref()) == Some(key))
+        .map(|(_, action)| *action)
+}
+
+/// The label of the key `bindings` gives `command`, if any.
+pub fn label<S: AsRef<str>>(bindings: &[(S, Action)], command: PaletteCommand) -> Option<&str> {
+    bindings
+        .iter()
+        .find(|(_, action)| *action == Action::Command(command))
+        .map(|(label, _)| label.as_ref())
+}
+
+/// Whether `bindings` take the key labelled `label`.
+pub fn shadows<S: AsRef<str>>(bindings: &[(S, Action)], label: &str) -> bool {
+    parse_key(label).is_some_and(|(code, modifiers)| lookup(bindings, code, modifiers).is_some())
+}
+
+/// The action a `[keys]` entry names: a command palette title, or the
+/// palette itself.
+pub fn action_named(name: &str) -> Option<Action> {
+    if name.eq_ignore_ascii_case(PALETTE) {
+        return Some(Action::Palette);
+    }
+    PaletteCommand::ALL
+        .iter()
+        .find(|command| command.title().eq_ignore_ascii_case(name))
+        .map(|&command| Action::Command(command))
+}
+
+fn action_name(action: Action) -> &'static str {
+    match action {
+        Action::Palette => PALETTE,
+        Action::Command(command) => command.title(),
+    }
+}
+
+/// termi's own keys as a `[keys]` table, followed by what each preset adds,
+/// for `termi --dump-keys`.
+pub fn default_table() -> String {
+    let quote = |text: &str| toml::Value::String(text.to_string()).to_string();
+    let mut out = String::from(
+        "# Each entry binds a key to a command palette entry, or to \"Command Palette\".\n\
+         # [keys] in config.toml goes over the keymap preset, which goes over these.\n\
+         # Copy and paste, selection, word movement and completion have no palette\n\
+         # entry, so their keys (see the README) are not listed.\n\
+         [keys]\n",
+    );
+    out.push_str(&format!("{} = {}\n", quote("Ctrl+P"), quote(PALETTE)));
+    for command in PaletteCommand::ALL {
+        if !command.keys().is_empty() {
+            out.push_str(&format!(
+                "{} = {}\n",
+                quote(command.keys()),
+                quote(command.title())
+            ));
+        }
+    }
+    for preset in &Preset::ALL[1..] {
+        out.push_str(&format!("\n# keymap = {} adds:\n", quote(preset.name())));
+        for &(label, action) in preset.bindings() {
+            out.push_str(&format!(
+                "# {} = {}\n",
+                quote(label),
+                quote(action_name(action))
+            ));
+        }
+    }
+    out
+}
+
+// Letters compare lowercase with Shift as a modifier; for other characters
+// Shift is already part of the character.
+fn normalize(code: KeyCode, modifiers: KeyModifiers) -> (KeyCode, KeyModifiers) {
+    match code {
+        KeyCode::Char(c) if c.is_ascii_uppercase() => (
+            KeyCode::Char(c.to_ascii_lowercase()),
+            modifiers | KeyModifiers::SHIFT,
+        ),
+        KeyCode::Char(c) if !c.is_ascii_alphabetic() => (code, modifiers - KeyModifiers::SHIFT),
+        _ => (code, modifiers),
+    }
+}
+
+/// Reads a label like `Ctrl+Shift+P`, `Alt+Up` or `F4`.
+pub fn parse_key(label: &str) -> Option<(KeyCode, KeyModifiers)> {
+    let (modifier_names, key) = label.rsplit_once('+').unwrap_or(("", label));
+    let mut modifiers = KeyModifiers::NONE;
+    for name in modifier_names.split('+').filter(|name| !name.is_empty()) {
+        modifiers |= match name.to_lowercase().as_str() {
+            "ctrl" => KeyModifiers::CONTROL,
+            "alt" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => return None,
+        };
+    }
+    let code = match key.to_lowercase().as_str() {
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "enter" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "esc" => KeyCode::Esc,
+        "del" => KeyCode::Delete,
+        "space" => KeyCode::Char(' '),
+        name if name.len() > 1 && name.starts_with('f') => KeyCode::F(name[1..].parse().ok()?),
+        _ => {
+            let mut chars = key.chars();
+            let c = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+            // Labels spell letters in capitals; Shift is always explicit.
+            KeyCode::Char(c.to_ascii_lowercase())
+        }
+    };
+    Some(normalize(code, modifiers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_parse_with_letters_lowercased() {
+        let ctrl_shift = KeyModifiers::CONTROL | KeyModifiers::SHIFT;
+        assert_eq!(
+            parse_key("Ctrl+Shift+P"),
+            Some((KeyCode::Char('p'), ctrl_shift))
+        );
+        assert_eq!(parse_key("alt+up"), Some((KeyCode::Up, KeyModifiers::ALT)));
+        assert_eq!(parse_key("F12"), Some((KeyCode::F(12), KeyModifiers::NONE)));
+        assert_eq!(parse_key("Ctrl++"), None);
+        assert_eq!(parse_key("Hyper+A"), None);
+        assert_eq!(parse_key("Ctrl+AB"), None);
+    }
+
+    #[test]
+    fn lookup_matches_what_the_terminal_sends() {
+        let bindings = Preset::VsCode.bindings();
+        let palette = lookup(
+            bindings,
+            KeyCode::Char('P'),
+            KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+        );
+        assert!(palette == Some(Action::Palette));
+        assert!(lookup(bindings, KeyCode::Char('p'), KeyModifiers::CONTROL).is_none());
+
+        // Shift is part of `:` itself.
+        let colon = lookup(
+            Preset::Vim.bindings(),
+            KeyCode::Char(':'),
+            KeyModifiers::ALT | KeyModifiers::SHIFT,
+        );
+        assert!(colon == Some(Action::Palette));
+    }
+
+    #[test]
+    fn presets_shadow_only_their_own_keys() {
+        let nano = Preset::Nano.bindings();
+        assert!(shadows(nano, "Ctrl+O"));
+        assert!(!shadows(nano, "Ctrl+P"));
+        assert!(!shadows(Preset::Termi.bindings(), "Ctrl+O"));
+        assert_eq!(label(nano, PaletteCommand::Save), Some("Ctrl+O"));
+        assert_eq!(label(nano, PaletteCommand::GoToSymbol), None);
+    }
+
+    #[test]
+    fn actions_are_named_by_palette_title() {
+        assert!(action_named("command palette") == Some(Action::Palette));
+        assert!(action_named("file: save") == Some(Action::Command(PaletteCommand::Save)));
+        assert!(action_named("Save").is_none());
+        assert!(Preset::from_name("VIM") == Some(Preset::Vim));
+    }
+
+    #[test]
+    fn default_table_binds_every_label_it_lists() {
+        let table: toml::Value = default_table().parse().unwrap();
+        let keys = table["keys"].as_table().unwrap();
+        assert!(keys.len() > 1);
+        for (label, name) in keys {
+            assert!(parse_key(label).is_some(), "{label}");
+            assert!(action_named(name.as_str().unwrap()).is_some(), "{name}");
+        }
+    }
+}
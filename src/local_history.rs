@@ -0,0 +1,225 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const MAX_SNAPSHOTS_PER_FILE: usize = 50;
+const MAX_BYTES_PER_FILE: u64 = 8 * 1024 * 1024;
+// Limits for the history of all files together; the oldest snapshots go
+// first.
+const MAX_SNAPSHOTS: usize = 5_000;
+const MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+// Snapshots and bytes in the whole history as this process last counted
+// them, so a save only walks the history when it may be over its limits.
+static TOTALS: Mutex<Option<(usize, u64)>> = Mutex::new(None);
+
+pub struct Snapshot {
+    pub timestamp_ms: u64,
+    pub size: u64,
+    path: PathBuf,
+}
+
+impl Snapshot {
+    pub fn read(&self) -> io::Result<String> {
+        fs::read_to_string(&self.path)
+    }
+}
+
+pub fn fnv1a(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn history_root() -> PathBuf {
+    crate::cache_dir().join("history")
+}
+
+fn history_dir(path: &Path) -> PathBuf {
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    history_root().join(format!("{:016x}", fnv1a(&canonical.to_string_lossy())))
+}
+
+pub fn list(path: &Path) -> Vec<Snapshot> {
+    list_dir(&history_dir(path))
+}
+
+fn list_dir(dir: &Path) -> Vec<Snapshot> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut snapshots: Vec<Snapshot> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let entry_path = entry.path();
+            if entry_path.extension().and_then(|ext| ext.to_str()) != Some("snapshot") {
+                return None;
+            }
+            let timestamp_ms = entry_path.file_stem()?.to_str()?.parse().ok()?;
+            let size = entry.metadata().ok()?.len();
+            Some(Snapshot {
+                timestamp_ms,
+                size,
+                path: entry_path,
+            })
+        })
+        .collect();
+
+    snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.timestamp_ms));
+    snapshots
+}
+
+pub fn record(path: &Path, contents: &str) -> io::Result<()> {
+    let dir = history_dir(path);
+    fs::create_dir_all(&dir)?;
+
+    let existing = list(path);
+    if let Some(latest) = existing.first() {
+        if latest.read().is_ok_and(|previous| previous == contents) {
+            return Ok(());
+        }
+    }
+
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    fs::write(
+        dir.join("source.txt"),
+        canonical.to_string_lossy().as_bytes(),
+    )?;
+
+    let timestamp_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    fs::write(dir.join(format!("{}.snapshot", timestamp_ms)), contents)?;
+
+    let mut total = contents.len() as u64;
+    let (mut removed, mut removed_bytes) = (0, 0);
+    for (kept, snapshot) in existing.iter().enumerate() {
+        total += snapshot.size;
+        if (kept + 1 >= MAX_SNAPSHOTS_PER_FILE || total > MAX_BYTES_PER_FILE)
+            && fs::remove_file(&snapshot.path).is_ok()
+        {
+            removed += 1;
+            removed_bytes += snapshot.size;
+        }
+    }
+
+    let mut totals = TOTALS.lock().unwrap_or_else(PoisonError::into_inner);
+    *totals = Some(match *totals {
+        Some((count, bytes)) => {
+            let count = (count + 1).saturating_sub(removed);
+            let bytes = (bytes + contents.len() as u64).saturating_sub(removed_bytes);
+            if count > MAX_SNAPSHOTS || bytes > MAX_BYTES {
+                prune()
+            } else {
+                (count, bytes)
+            }
+        }
+        None => prune(),
+    });
+
+    Ok(())
+}
+
+// Drops the oldest snapshots of any file once the whole history is over
+// its limits, along with the folders of files left without any, and
+// returns what is left. Trimming goes a tenth below the limits so the next
+// saves do not have to walk the history again.
+fn prune() -> (usize, u64) {
+    let Ok(entries) = fs::read_dir(history_root()) else {
+        return (0, 0);
+    };
+    let dirs: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|dir| dir.is_dir())
+        .collect();
+    let mut snapshots: Vec<Snapshot> = dirs.iter().flat_map(|dir| list_dir(dir)).collect();
+    snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.timestamp_ms));
+
+    let all_bytes: u64 = snapshots.iter().map(|snapshot| snapshot.size).sum();
+    if snapshots.len() <= MAX_SNAPSHOTS && all_bytes <= MAX_BYTES {
+        return (snapshots.len(), all_bytes);
+    }
+
+    // Newest first; once one goes, every older one goes too.
+    let (mut kept, mut total, mut full) = (0, 0, false);
+    for snapshot in &snapshots {
+        full = full || kept >= MAX_SNAPSHOTS / 10 * 9 || total + snapshot.size > MAX_BYTES / 10 * 9;
+        if full {
+            let _ = fs::remove_file(&snapshot.path);
+        } else {
+            kept += 1;
+            total += snapshot.size;
+        }
+    }
+    for dir in dirs {
+        if list_dir(&dir).is_empty() {
+            let _ = fs::remove_dir_all(&dir);
+        }
+    }
+    (kept, total)
+}
+
+/// `YYYY-MM-DD HH:MM:SS UTC`.
+pub fn format_timestamp(timestamp_ms: u64) -> String {
+    let secs = timestamp_ms / 1000;
+    let days = (secs / 86_400) as i64;
+    let time_of_day = secs % 86_400;
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        (time_of_day % 3600) / 60,
+        time_of_day % 60
+    )
+}
+
+pub fn format_age(timestamp_ms: u64) -> String {
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let secs = now_ms.saturating_sub(timestamp_ms) / 1000;
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86_399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86_400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_are_marked_utc() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(
+            format_timestamp(1_709_210_096_789),
+            "2024-02-29 12:34:56 UTC"
+        );
+    }
+}
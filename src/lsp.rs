@@ -0,0 +1,678 @@
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use crate::event_loop::Waker;
+use crate::Language;
+
+pub struct LspServer {
+    pub command: &'static str,
+    pub args: &'static [&'static str],
+    pub language_id: &'static str,
+}
+
+pub fn server_for(lang: &Language) -> Option<LspServer> {
+    let (command, args, language_id): (&'static str, &'static [&'static str], &'static str) =
+        match lang {
+            Language::Rust => ("rust-analyzer", &[], "rust"),
+            Language::Python => ("pylsp", &[], "python"),
+            Language::JavaScript => ("typescript-language-server", &["--stdio"], "javascript"),
+            Language::C => ("clangd", &[], "c"),
+            Language::Cpp => ("clangd", &[], "cpp"),
+            Language::Java => ("jdtls", &[], "java"),
+            Language::None => return None,
+        };
+    Some(LspServer {
+        command,
+        args,
+        language_id,
+    })
+}
+
+pub fn find_project_root(path: &Path) -> PathBuf {
+    const MARKERS: &[&str] = &[
+        ".git",
+        "Cargo.toml",
+        "package.json",
+        "pyproject.toml",
+        "setup.py",
+        "compile_commands.json",
+        "pom.xml",
+    ];
+
+    let start = if path.is_dir() {
+        path.to_path_buf()
+    } else {
+        // A bare file name has an empty parent.
+        path.parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .map(|p| p.to_path_buf())
+            .unwrap_or_else(|| PathBuf::from("."))
+    };
+    let start = std::fs::canonicalize(&start).unwrap_or(start);
+
+    let mut dir = Some(start.as_path());
+    while let Some(current) = dir {
+        if MARKERS.iter().any(|marker| current.join(marker).exists()) {
+            return current.to_path_buf();
+        }
+        dir = current.parent();
+    }
+    start
+}
+
+pub fn path_to_uri(path: &Path) -> String {
+    let absolute = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut raw = absolute.to_string_lossy().replace('\\', "/");
+    if let Some(stripped) = raw.strip_prefix("//?/") {
+        raw = stripped.to_string();
+    }
+    if !raw.starts_with('/') {
+        raw.insert(0, '/');
+    }
+
+    let mut uri = String::from("file://");
+    for byte in raw.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[derive(Clone)]
+pub struct TextEdit {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub new_text: String,
+}
+
+pub struct CodeAction {
+    pub title: String,
+    pub edits: Vec<TextEdit>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SemanticKind {
+    Function,
+    Type,
+    Parameter,
+    Macro,
+}
+
+pub struct CompletionItem {
+    pub label: String,
+    pub insert_text: String,
+}
+
+/// A decoded semantic token; `start` and `length` are in UTF-16 code units.
+pub struct SemanticToken {
+    pub line: usize,
+    pub start: usize,
+    pub length: usize,
+    pub kind: SemanticKind,
+}
+
+pub enum LspEvent {
+    Diagnostics {
+        uri: String,
+        diagnostics: Vec<Value>,
+    },
+    CodeActions {
+        /// The id `code_actions` returned for the request.
+        id: u64,
+        uri: String,
+        actions: Vec<CodeAction>,
+    },
+    SemanticTokens {
+        uri: String,
+        tokens: Vec<SemanticToken>,
+    },
+    Completions {
+        uri: String,
+        items: Vec<CompletionItem>,
+    },
+    Exited,
+}
+
+enum PendingRequest {
+    Initialize,
+    CodeAction {
+        uri: String,
+    },
+    /// `version` is the document version the tokens were asked for.
+    SemanticTokens {
+        uri: String,
+        version: i64,
+    },
+    Completion {
+        uri: String,
+    },
+}
+
+pub struct LspClient {
+    child: Child,
+    writer: ChildStdin,
+    rx: Receiver<Value>,
+    next_id: u64,
+    pending: HashMap<u64, PendingRequest>,
+    initialized: bool,
+    queued: Vec<Value>,
+    versions: HashMap<String, i64>,
+    semantic_legend: Option<Vec<Option<SemanticKind>>>,
+}
+
+impl LspClient {
+    pub fn start(server: &LspServer, root: &Path, waker: Waker) -> io::Result<Self> {
+        let mut child = Command::new(server.command)
+            .args(server.args)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let writer = child
+            .stdin
+            .take()
+            .ok_or_else(|| io::Error::other("language server has no stdin"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| io::Error::other("language server has no stdout"))?;
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            while let Some(message) = read_message(&mut reader) {
+                if tx.send(message).is_err() {
+                    break;
+                }
+                waker.wake();
+            }
+            // The dropped sender tells `poll` the server exited.
+            drop(tx);
+            waker.wake();
+        });
+
+        let mut client = Self {
+            child,
+            writer,
+            rx,
+            next_id: 1,
+            pending: HashMap::new(),
+            initialized: false,
+            queued: Vec::new(),
+            versions: HashMap::new(),
+            semantic_legend: None,
+        };
+
+        let root_uri = path_to_uri(root);
+        // Only rust-analyzer knows this option: it runs clippy instead of
+        // `cargo check` on save.
+        let options = if server.command == "rust-analyzer" {
+            json!({ "check": { "command": "clippy" } })
+        } else {
+            Value::Null
+        };
+        client.request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": root_uri,
+                "workspaceFolders": [{ "uri": root_uri, "name": "termi" }],
+                "capabilities": {
+                    "textDocument": {
+                        "synchronization": { "didSave": true },
+                        "publishDiagnostics": {},
+                        "completion": { "completionItem": { "snippetSupport": false } },
+                        "semanticTokens": {
+                            "requests": { "full": true },
+                            "tokenTypes": [
+                                "namespace", "type", "class", "enum", "interface", "struct",
+                                "typeParameter", "parameter", "variable", "property",
+                                "enumMember", "function", "method", "macro", "keyword",
+                                "comment", "string", "number", "operator"
+                            ],
+                            "tokenModifiers": [],
+                            "formats": ["relative"]
+                        },
+                        "codeAction": {
+                            "codeActionLiteralSupport": {
+                                "codeActionKind": {
+                                    "valueSet": [
+                                        "quickfix",
+                                        "refactor",
+                                        "refactor.extract",
+                                        "refactor.inline",
+                                        "refactor.rewrite",
+                                        "source",
+                                        "source.organizeImports"
+                                    ]
+                                }
+                            }
+                        }
+                    },
+                    "workspace": { "workspaceEdit": { "documentChanges": true } }
+                },
+                "initializationOptions": options
+            }),
+            PendingRequest::Initialize,
+        )?;
+
+        Ok(client)
+    }
+
+    fn write_message(&mut self, message: &Value) -> io::Result<()> {
+        let body = message.to_string();
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.writer.flush()
+    }
+
+    fn send(&mut self, message: Value) -> io::Result<()> {
+        if self.initialized {
+            self.write_message(&message)
+        } else {
+            self.queued.push(message);
+            Ok(())
+        }
+    }
+
+    fn request(&mut self, method: &str, params: Value, kind: PendingRequest) -> io::Result<u64> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if matches!(kind, PendingRequest::Initialize) {
+            self.write_message(&message)?;
+        } else {
+            self.send(message)?;
+        }
+        self.pending.insert(id, kind);
+        Ok(id)
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    pub fn did_open(&mut self, path: &Path, language_id: &str, text: &str) -> io::Result<()> {
+        let uri = path_to_uri(path);
+        if self.versions.contains_key(&uri) {
+            return self.did_change(path, text);
+        }
+        self.versions.insert(uri.clone(), 1);
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": language_id,
+                    "version": 1,
+                    "text": text
+                }
+            }),
+        )
+    }
+
+    pub fn did_change(&mut self, path: &Path, text: &str) -> io::Result<()> {
+        let uri = path_to_uri(path);
+        let version = {
+            let version = self.versions.entry(uri.clone()).or_insert(0);
+            *version += 1;
+            *version
+        };
+        self.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri, "version": version },
+                "contentChanges": [{ "text": text }]
+            }),
+        )
+    }
+
+    pub fn did_save(&mut self, path: &Path) -> io::Result<()> {
+        self.notify(
+            "textDocument/didSave",
+            json!({ "textDocument": { "uri": path_to_uri(path) } }),
+        )
+    }
+
+    pub fn code_actions(
+        &mut self,
+        path: &Path,
+        start: (usize, usize),
+        end: (usize, usize),
+        diagnostics: Vec<Value>,
+        only: &[&str],
+    ) -> io::Result<u64> {
+        let uri = path_to_uri(path);
+        let mut context = json!({ "diagnostics": diagnostics });
+        if !only.is_empty() {
+            context["only"] = json!(only);
+        }
+        self.request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": uri },
+                "range": {
+                    "start": { "line": start.0, "character": start.1 },
+                    "end": { "line": end.0, "character": end.1 }
+                },
+                "context": context
+            }),
+            PendingRequest::CodeAction { uri },
+        )
+    }
+
+    pub fn completion(&mut self, path: &Path, position: (usize, usize)) -> io::Result<()> {
+        let uri = path_to_uri(path);
+        self.request(
+            "textDocument/completion",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": position.0, "character": position.1 }
+            }),
+            PendingRequest::Completion { uri },
+        )
+        .map(drop)
+    }
+
+    /// Known only once the server has answered `initialize`; until then this
+    /// returns true so requests made early are queued rather than dropped.
+    pub fn supports_semantic_tokens(&self) -> bool {
+        !self.initialized || self.semantic_legend.is_some()
+    }
+
+    pub fn semantic_tokens(&mut self, path: &Path) -> io::Result<()> {
+        if !self.supports_semantic_tokens() {
+            return Ok(());
+        }
+        let uri = path_to_uri(path);
+        let version = self.versions.get(&uri).copied().unwrap_or(0);
+        self.request(
+            "textDocument/semanticTokens/full",
+            json!({ "textDocument": { "uri": uri } }),
+            PendingRequest::SemanticTokens { uri, version },
+        )
+        .map(drop)
+    }
+
+    pub fn poll(&mut self) -> Vec<LspEvent> {
+        let mut events = Vec::new();
+
+        loop {
+            let message = match self.rx.try_recv() {
+                Ok(message) => message,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    events.push(LspEvent::Exited);
+                    break;
+                }
+            };
+
+            let method = message.get("method").and_then(Value::as_str);
+            let id = message.get("id").cloned();
+
+            match (method, id) {
+                (Some(method), Some(id)) => self.answer_server_request(method, id, &message),
+                (Some("textDocument/publishDiagnostics"), None) => {
+                    let params = &message["params"];
+                    let uri = params["uri"].as_str().unwrap_or_default().to_string();
+                    let diagnostics = params["diagnostics"]
+                        .as_array()
+                        .cloned()
+                        .unwrap_or_default();
+                    events.push(LspEvent::Diagnostics { uri, diagnostics });
+                }
+                (None, Some(id)) => {
+                    let Some((id, kind)) = id
+                        .as_u64()
+                        .and_then(|id| Some((id, self.pending.remove(&id)?)))
+                    else {
+                        continue;
+                    };
+                    let result = message.get("result").cloned().unwrap_or(Value::Null);
+                    match kind {
+                        PendingRequest::Initialize => {
+                            self.initialized = true;
+                            self.semantic_legend = parse_semantic_legend(&result);
+                            let _ = self.write_message(
+                                &json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+                            );
+                            for queued in std::mem::take(&mut self.queued) {
+                                let _ = self.write_message(&queued);
+                            }
+                        }
+                        PendingRequest::CodeAction { uri } => {
+                            let actions = parse_code_actions(&result, &uri);
+                            events.push(LspEvent::CodeActions { id, uri, actions });
+                        }
+                        PendingRequest::SemanticTokens { uri, version } => {
+                            // Tokens for text that has changed since would
+                            // colour the wrong ranges.
+                            if self.versions.get(&uri).copied().unwrap_or(0) != version {
+                                continue;
+                            }
+                            let tokens = self
+                                .semantic_legend
+                                .as_deref()
+                                .map(|legend| decode_semantic_tokens(&result, legend))
+                                .unwrap_or_default();
+                            events.push(LspEvent::SemanticTokens { uri, tokens });
+                        }
+                        PendingRequest::Completion { uri } => {
+                            let items = parse_completion_items(&result);
+                            events.push(LspEvent::Completions { uri, items });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        events
+    }
+
+    fn answer_server_request(&mut self, method: &str, id: Value, message: &Value) {
+        let result = match method {
+            "workspace/configuration" => {
+                let count = message["params"]["items"]
+                    .as_array()
+                    .map(|items| items.len())
+                    .unwrap_or(0);
+                Value::Array(vec![Value::Null; count])
+            }
+            _ => Value::Null,
+        };
+        let _ = self.write_message(&json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+    }
+
+    pub fn shutdown(&mut self) {
+        let _ = self.write_message(&json!({ "jsonrpc": "2.0", "id": 0, "method": "shutdown" }));
+        let _ = self.write_message(&json!({ "jsonrpc": "2.0", "method": "exit" }));
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    loop {
+        let mut content_length = None;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).ok()? == 0 {
+                return None;
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse::<usize>().ok();
+                }
+            }
+        }
+
+        let Some(length) = content_length else {
+            continue;
+        };
+        let mut body = vec![0_u8; length];
+        reader.read_exact(&mut body).ok()?;
+        if let Ok(message) = serde_json::from_slice(&body) {
+            return Some(message);
+        }
+    }
+}
+
+fn parse_text_edit(edit: &Value) -> Option<TextEdit> {
+    let range = edit.get("range")?;
+    let position = |pos: &Value| -> Option<(usize, usize)> {
+        Some((
+            pos.get("line")?.as_u64()? as usize,
+            pos.get("character")?.as_u64()? as usize,
+        ))
+    };
+    Some(TextEdit {
+        start: position(range.get("start")?)?,
+        end: position(range.get("end")?)?,
+        new_text: edit.get("newText")?.as_str()?.to_string(),
+    })
+}
+
+pub fn workspace_edits_for(edit: &Value, uri: &str) -> Vec<TextEdit> {
+    let mut edits = Vec::new();
+
+    if let Some(changes) = edit.get("changes").and_then(|c| c.get(uri)) {
+        edits.extend(
+            changes
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(parse_text_edit),
+        );
+    }
+
+    for change in edit
+        .get("documentChanges")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        if change["textDocument"]["uri"].as_str() == Some(uri) {
+            edits.extend(
+                change["edits"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(parse_text_edit),
+            );
+        }
+    }
+
+    edits
+}
+
+fn parse_code_actions(result: &Value, uri: &str) -> Vec<CodeAction> {
+    result
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|action| {
+            let title = action.get("title")?.as_str()?.to_string();
+            let edits = workspace_edits_for(action.get("edit")?, uri);
+            if edits.is_empty() {
+                None
+            } else {
+                Some(CodeAction { title, edits })
+            }
+        })
+        .collect()
+}
+
+fn parse_semantic_legend(initialize_result: &Value) -> Option<Vec<Option<SemanticKind>>> {
+    let provider = initialize_result["capabilities"].get("semanticTokensProvider")?;
+    if matches!(provider.get("full"), None | Some(Value::Bool(false))) {
+        return None;
+    }
+    let types = provider["legend"]["tokenTypes"].as_array()?;
+    Some(
+        types
+            .iter()
+            .map(|name| match name.as_str()? {
+                "function" | "method" => Some(SemanticKind::Function),
+                "type" | "class" | "enum" | "interface" | "struct" | "typeParameter"
+                | "typeAlias" | "builtinType" => Some(SemanticKind::Type),
+                "parameter" => Some(SemanticKind::Parameter),
+                "macro" => Some(SemanticKind::Macro),
+                _ => None,
+            })
+            .collect(),
+    )
+}
+
+fn decode_semantic_tokens(result: &Value, legend: &[Option<SemanticKind>]) -> Vec<SemanticToken> {
+    let data: Vec<usize> = result["data"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|n| n.as_u64().map(|n| n as usize))
+        .collect();
+
+    let mut tokens = Vec::new();
+    let (mut line, mut start) = (0, 0);
+    for chunk in data.chunks_exact(5) {
+        let (delta_line, delta_start, length, kind) = (chunk[0], chunk[1], chunk[2], chunk[3]);
+        if delta_line > 0 {
+            line += delta_line;
+            start = delta_start;
+        } else {
+            start += delta_start;
+        }
+        if let Some(kind) = legend.get(kind).copied().flatten() {
+            tokens.push(SemanticToken {
+                line,
+                start,
+                length,
+                kind,
+            });
+        }
+    }
+    tokens
+}
+
+fn parse_completion_items(result: &Value) -> Vec<CompletionItem> {
+    let items = match result {
+        Value::Array(items) => items,
+        _ => match result["items"].as_array() {
+            Some(items) => items,
+            None => return vec![],
+        },
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            let label = item.get("label")?.as_str()?.trim().to_string();
+            let insert_text = item["textEdit"]["newText"]
+                .as_str()
+                .or_else(|| item["insertText"].as_str())
+                .unwrap_or(&label)
+                .to_string();
+            Some(CompletionItem { label, insert_text })
+        })
+        .collect()
+}
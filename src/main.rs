@@ -1,8 +1,42 @@
+mod capabilities;
+mod config;
+mod counterpart;
+mod coverage;
+mod diff;
+mod event_loop;
+mod export;
+mod file_lock;
+mod focus;
+mod fuzzy;
+mod git;
+mod health;
+mod imports;
+mod instance;
+mod keymap;
+mod local_history;
+mod lsp;
+mod markdown;
+mod minified;
+mod positions;
+mod prose;
+mod replay;
+mod screen;
+mod session;
+mod share;
+mod symbols;
+mod tags;
+mod templates;
+mod tutor;
+mod version;
+
+use capabilities::{Capabilities, ColorSupport, Glyphs};
+use config::QuitConfirm;
 use crossterm::{
     cursor,
     event::{
-        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
-        KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+        DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+        KeyModifiers, KeyboardEnhancementFlags, MouseButton, MouseEvent, MouseEventKind,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
     },
     execute,
     style::{Attribute, Color, SetAttribute, SetForegroundColor},
@@ -12,13 +46,21 @@ use discord_rich_presence::{
     activity::{Activity, Timestamps},
     DiscordIpc, DiscordIpcClient,
 };
+use event_loop::{EventLoop, Waker};
+use file_lock::FileLock;
+use lsp::{LspClient, LspEvent};
 use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
+use session::SessionEvent;
 use std::{
     collections::{HashMap, HashSet},
     env, fs,
     io::{self, Read, Write},
     path::{Path, PathBuf},
-    sync::mpsc::{self, Receiver, TryRecvError},
+    process::ExitCode,
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        OnceLock,
+    },
     thread,
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
@@ -30,6 +72,15 @@ const LINE_NUM_WIDTH: u16 = 6;
 const TERMINAL_SCROLL_STEP: usize = 3;
 const TERMINAL_SCROLLBACK_LEN: usize = 5000;
 const RECENT_ITEM_LIMIT: usize = 6;
+const OUTPUT_PANEL_HEIGHT: u16 = 10;
+const PALETTE_VISIBLE_ITEMS: usize = 12;
+const SCROLL_FRAME: Duration = Duration::from_millis(16);
+const WORD_FREQUENCY_CAP: u32 = 10;
+const WORD_RECENCY_WINDOW: u64 = 64;
+// Byte order mark, as some Windows tools write at the start of UTF-8 files.
+const BOM: char = '\u{feff}';
+// Columns one level of indentation takes, unless a buffer sets its own.
+const INDENT_WIDTH: usize = 4;
 const DASHBOARD_LOGO: &[&str] = &[
     " _______                  _ ",
     "|__   __|                (_)",
@@ -39,6 +90,27 @@ const DASHBOARD_LOGO: &[&str] = &[
     "   |_|\\___|_|  |_| |_| |_|_|",
 ];
 
+struct JumpTarget {
+    pos: (usize, usize),
+    screen: (u16, u16),
+    label: String,
+}
+
+// Home-row keys first, so the nearest matches are the easiest to type.
+const JUMP_LABEL_KEYS: &str = "asdfghjklqwertyuiopzxcvbnm";
+
+/// One letter per target while they last, otherwise two for every target.
+fn jump_labels(count: usize) -> Vec<String> {
+    let keys: Vec<char> = JUMP_LABEL_KEYS.chars().collect();
+    if count <= keys.len() {
+        return keys.iter().take(count).map(|c| c.to_string()).collect();
+    }
+    keys.iter()
+        .flat_map(|&a| keys.iter().map(move |&b| format!("{}{}", a, b)))
+        .take(count)
+        .collect()
+}
+
 #[derive(Clone)]
 struct FileNode {
     name: String,
@@ -48,6 +120,7 @@ struct FileNode {
     depth: usize,
 }
 
+#[derive(Clone, Copy)]
 enum EditorMode {
     Dashboard,
     Normal,
@@ -59,6 +132,27 @@ enum EditorMode {
     Terminal,
     GoToLine,
     Autocomplete,
+    QuickFix,
+    Palette,
+    Timeline,
+    Diff,
+    DiffPickFile,
+    DiffRevision,
+    GitLog,
+    RevisionView,
+    NotificationLog,
+    BranchPicker,
+    Outline,
+    SymbolPicker,
+    QuickJump,
+    Health,
+    LocalOptions,
+    BranchCheckoutConfirm,
+    Blame,
+    TemplatePicker,
+    ScaffoldPicker,
+    ScaffoldDirectory,
+    KeymapPicker,
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -67,6 +161,18 @@ enum TokenType {
     String,
     Comment,
     Number,
+    Attribute,
+    Lifetime,
+    Macro,
+    Escape,
+    FormatSpec,
+    Function,
+    Type,
+    Parameter,
+    DocComment,
+    DocCode,
+    DocBold,
+    DocItalic,
     Normal,
 }
 
@@ -81,12 +187,580 @@ enum Language {
     None,
 }
 
+impl Language {
+    const ALL: &'static [Language] = &[
+        Language::Rust,
+        Language::JavaScript,
+        Language::Python,
+        Language::C,
+        Language::Cpp,
+        Language::Java,
+        Language::None,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Language::Rust => "Rust",
+            Language::JavaScript => "JavaScript",
+            Language::Python => "Python",
+            Language::C => "C",
+            Language::Cpp => "C++",
+            Language::Java => "Java",
+            Language::None => "Text",
+        }
+    }
+}
+
+/// Settings changed for one buffer from "Buffer: Local Options"; `None`
+/// follows the global setting.
+#[derive(Clone, Default)]
+struct LocalOptions {
+    wrap: Option<bool>,
+    indent_width: Option<usize>,
+    language: Option<Language>,
+    read_only: Option<ReadOnly>,
+}
+
+/// Why a buffer refuses edits.
+#[derive(Clone, Copy, PartialEq)]
+enum ReadOnly {
+    /// Turned on from Buffer: Local Options.
+    Chosen,
+    /// Another termi holds the file's edit lock.
+    Locked,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CompletionSource {
+    Buffer,
+    Keyword,
+    Dictionary,
+    Snippet,
+    Lsp,
+    Path,
+}
+
+#[derive(Clone)]
+struct Completion {
+    label: String,
+    insert_text: String,
+    source: CompletionSource,
+    /// Column on the cursor line where the replaced text starts.
+    start: usize,
+}
+
+// How often and when (in `word_usage_clock` ticks) a word was used this session.
+#[derive(Clone, Copy, Default)]
+struct WordUsage {
+    count: u32,
+    last_used: u64,
+}
+
+// Semantic spans for one line, kept with the text they were computed for so an
+// edited line falls back to syntactic highlighting until the next refresh.
+struct SemanticLine {
+    text: Vec<char>,
+    spans: Vec<(usize, usize, TokenType)>,
+}
+
 struct TerminalSession {
     master: Box<dyn MasterPty + Send>,
     writer: Box<dyn Write + Send>,
     child: Box<dyn Child + Send>,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum TestStatus {
+    Running,
+    Passed,
+    Failed,
+}
+
+struct TestTarget {
+    // Results are kept under this name, so they stay with the test when
+    // lines above it change.
+    name: String,
+    program: String,
+    args: Vec<String>,
+    cwd: PathBuf,
+}
+
+enum JobEvent {
+    Output(String),
+    /// A line the command wrote to stderr.
+    Error(String),
+    Finished(bool),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum GitAction {
+    Stash,
+    StashPop,
+    Pull,
+    Push,
+}
+
+impl GitAction {
+    fn args(self) -> &'static [&'static str] {
+        match self {
+            GitAction::Stash => &["stash", "push"],
+            GitAction::StashPop => &["stash", "pop"],
+            GitAction::Pull => &["pull", "--progress"],
+            GitAction::Push => &["push", "--progress"],
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            GitAction::Stash => "stash",
+            GitAction::StashPop => "stash pop",
+            GitAction::Pull => "pull",
+            GitAction::Push => "push",
+        }
+    }
+}
+
+enum JobKind {
+    Test { path: PathBuf, name: String },
+    Git { action: GitAction },
+    Scaffold { root: PathBuf },
+    Share { upload: share::Upload },
+    Evaluate { replace: Option<EvaluatedText> },
+    // `manual` checks also report being up to date, and failures.
+    UpdateCheck { manual: bool },
+}
+
+/// Text handed to an evaluator whose result should take its place, provided
+/// it still reads the same when the evaluator finishes.
+struct EvaluatedText {
+    start: (usize, usize),
+    end: (usize, usize),
+    text: String,
+}
+
+struct Job {
+    kind: JobKind,
+    rx: Receiver<JobEvent>,
+}
+
+/// A search of the project for where `ident` is defined, for an import in
+/// `path`; the import statements that would bring it in arrive on `rx`.
+struct ImportScan {
+    path: PathBuf,
+    ident: String,
+    rx: Receiver<Vec<String>>,
+}
+
+/// Verdict on what has been typed into a prompt so far, shown next to it
+/// and redone on every keystroke. Enter is refused while it is `Invalid`.
+enum PromptCheck {
+    Valid(String),
+    Invalid(String),
+}
+
+impl PromptCheck {
+    fn color(&self) -> Color {
+        match self {
+            PromptCheck::Valid(_) => Color::Green,
+            PromptCheck::Invalid(_) => Color::Red,
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            PromptCheck::Valid(message) | PromptCheck::Invalid(message) => message,
+        }
+    }
+}
+
+struct OutputPanel {
+    title: String,
+    lines: Vec<String>,
+    scroll: usize,
+    status: Option<TestStatus>,
+}
+
+const MAX_NOTIFICATIONS: usize = 500;
+// The symbol picker lists at most this many matches.
+const MAX_SYMBOL_RESULTS: usize = 200;
+// Closed files beyond this many can no longer be reopened with Ctrl+Shift+T.
+const MAX_CLOSED_BUFFERS: usize = 20;
+
+struct Notification {
+    timestamp_ms: u64,
+    message: String,
+}
+
+enum DiffRow {
+    Header(usize),
+    Line(usize, usize),
+}
+
+struct DiffView {
+    title: String,
+    old: Vec<String>,
+    hunks: Vec<diff::Hunk>,
+    rows: Vec<DiffRow>,
+    scroll: usize,
+    selected: usize,
+}
+
+impl DiffView {
+    fn new(title: String, old: Vec<String>) -> Self {
+        Self {
+            title,
+            old,
+            hunks: vec![],
+            rows: vec![],
+            scroll: 0,
+            selected: 0,
+        }
+    }
+
+    fn set_hunks(&mut self, hunks: Vec<diff::Hunk>) {
+        self.rows.clear();
+        for (hunk_index, hunk) in hunks.iter().enumerate() {
+            self.rows.push(DiffRow::Header(hunk_index));
+            self.rows
+                .extend((0..hunk.lines.len()).map(|line| DiffRow::Line(hunk_index, line)));
+        }
+        self.hunks = hunks;
+        self.selected = self.selected.min(self.hunks.len().saturating_sub(1));
+        self.scroll = self.header_row(self.selected);
+    }
+
+    fn header_row(&self, hunk: usize) -> usize {
+        self.rows
+            .iter()
+            .position(|row| matches!(row, DiffRow::Header(h) if *h == hunk))
+            .unwrap_or(0)
+    }
+
+    fn hunk_at_row(&self, row: usize) -> usize {
+        match self.rows.get(row) {
+            Some(DiffRow::Header(hunk)) | Some(DiffRow::Line(hunk, _)) => *hunk,
+            None => self.selected,
+        }
+    }
+}
+
+struct BlameView {
+    title: String,
+    root: PathBuf,
+    lines: Vec<git::BlameLine>,
+    cursor: usize,
+    scroll: usize,
+}
+
+struct RevisionView {
+    title: String,
+    lines: Vec<String>,
+    scroll: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PaletteCommand {
+    Save,
+    Find,
+    GoToLine,
+    ToggleTree,
+    ToggleTerminal,
+    SelectAll,
+    Undo,
+    Redo,
+    QuickFix,
+    RunTestAtCursor,
+    ToggleOutputPanel,
+    ReloadCoverage,
+    ToggleCoverage,
+    LocalHistory,
+    DiffWithSaved,
+    DiffWithHead,
+    DiffWithRevision,
+    DiffWithTreeFile,
+    DiffWithClipboard,
+    FileHistory,
+    GitStash,
+    GitStashPop,
+    GitPull,
+    GitPush,
+    NotificationLog,
+    SwitchBranch,
+    Blame,
+    NewProject,
+    MatchingPair,
+    SaveAndQuit,
+    Quit,
+    Detach,
+    EditAnyway,
+    ExportHtml,
+    CopyWithFormatting,
+    Share,
+    TogglePreview,
+    ToggleTypewriter,
+    ToggleFocusMode,
+    ToggleSoftWrap,
+    PrettyPrint,
+    FormatTable,
+    ReflowParagraph,
+    JustifyParagraph,
+    Outline,
+    QuickJump,
+    BlockStart,
+    BlockEnd,
+    HealthCheck,
+    SwitchHeaderSource,
+    AddImport,
+    OrganizeImports,
+    PreviousFunction,
+    NextFunction,
+    Evaluate,
+    EvaluateAndReplace,
+    LocalOptions,
+    CloseBuffer,
+    ReopenClosedBuffer,
+    CloseOtherBuffers,
+    CloseAllBuffers,
+    TogglePin,
+    GoToSymbol,
+    Tutorial,
+    KeymapPreset,
+    About,
+    CheckForUpdates,
+}
+
+impl PaletteCommand {
+    const ALL: &'static [PaletteCommand] = &[
+        PaletteCommand::Save,
+        PaletteCommand::Find,
+        PaletteCommand::GoToLine,
+        PaletteCommand::ToggleTree,
+        PaletteCommand::ToggleTerminal,
+        PaletteCommand::SelectAll,
+        PaletteCommand::Undo,
+        PaletteCommand::Redo,
+        PaletteCommand::QuickFix,
+        PaletteCommand::RunTestAtCursor,
+        PaletteCommand::ToggleOutputPanel,
+        PaletteCommand::ReloadCoverage,
+        PaletteCommand::ToggleCoverage,
+        PaletteCommand::LocalHistory,
+        PaletteCommand::DiffWithSaved,
+        PaletteCommand::DiffWithHead,
+        PaletteCommand::DiffWithRevision,
+        PaletteCommand::DiffWithTreeFile,
+        PaletteCommand::DiffWithClipboard,
+        PaletteCommand::FileHistory,
+        PaletteCommand::GitStash,
+        PaletteCommand::GitStashPop,
+        PaletteCommand::GitPull,
+        PaletteCommand::GitPush,
+        PaletteCommand::NotificationLog,
+        PaletteCommand::SwitchBranch,
+        PaletteCommand::Blame,
+        PaletteCommand::NewProject,
+        PaletteCommand::MatchingPair,
+        PaletteCommand::SaveAndQuit,
+        PaletteCommand::Quit,
+        PaletteCommand::Detach,
+        PaletteCommand::EditAnyway,
+        PaletteCommand::ExportHtml,
+        PaletteCommand::CopyWithFormatting,
+        PaletteCommand::Share,
+        PaletteCommand::TogglePreview,
+        PaletteCommand::ToggleTypewriter,
+        PaletteCommand::ToggleFocusMode,
+        PaletteCommand::ToggleSoftWrap,
+        PaletteCommand::PrettyPrint,
+        PaletteCommand::FormatTable,
+        PaletteCommand::ReflowParagraph,
+        PaletteCommand::JustifyParagraph,
+        PaletteCommand::Outline,
+        PaletteCommand::QuickJump,
+        PaletteCommand::BlockStart,
+        PaletteCommand::BlockEnd,
+        PaletteCommand::HealthCheck,
+        PaletteCommand::SwitchHeaderSource,
+        PaletteCommand::AddImport,
+        PaletteCommand::OrganizeImports,
+        PaletteCommand::PreviousFunction,
+        PaletteCommand::NextFunction,
+        PaletteCommand::Evaluate,
+        PaletteCommand::EvaluateAndReplace,
+        PaletteCommand::LocalOptions,
+        PaletteCommand::CloseBuffer,
+        PaletteCommand::ReopenClosedBuffer,
+        PaletteCommand::CloseOtherBuffers,
+        PaletteCommand::CloseAllBuffers,
+        PaletteCommand::TogglePin,
+        PaletteCommand::GoToSymbol,
+        PaletteCommand::Tutorial,
+        PaletteCommand::KeymapPreset,
+        PaletteCommand::About,
+        PaletteCommand::CheckForUpdates,
+    ];
+
+    fn title(&self) -> &'static str {
+        match self {
+            PaletteCommand::Save => "File: Save",
+            PaletteCommand::Find => "Edit: Find",
+            PaletteCommand::GoToLine => "Go to Line",
+            PaletteCommand::ToggleTree => "View: Toggle File Tree",
+            PaletteCommand::ToggleTerminal => "View: Toggle Terminal",
+            PaletteCommand::SelectAll => "Edit: Select All",
+            PaletteCommand::Undo => "Edit: Undo",
+            PaletteCommand::Redo => "Edit: Redo",
+            PaletteCommand::QuickFix => "Code: Quick Fix",
+            PaletteCommand::RunTestAtCursor => "Test: Run Test Under Cursor",
+            PaletteCommand::ToggleOutputPanel => "View: Toggle Output Panel",
+            PaletteCommand::ReloadCoverage => "Coverage: Reload Report",
+            PaletteCommand::ToggleCoverage => "Coverage: Toggle Overlay",
+            PaletteCommand::LocalHistory => "File: Open Local History",
+            PaletteCommand::DiffWithSaved => "Diff: Compare With Saved Version",
+            PaletteCommand::DiffWithHead => "Diff: Compare With Git HEAD",
+            PaletteCommand::DiffWithRevision => "Diff: Compare With Revision...",
+            PaletteCommand::DiffWithTreeFile => "Diff: Compare With File From Tree...",
+            PaletteCommand::DiffWithClipboard => "Diff: Compare With Clipboard",
+            PaletteCommand::FileHistory => "Git: Show File History",
+            PaletteCommand::GitStash => "Git: Stash Changes",
+            PaletteCommand::GitStashPop => "Git: Pop Stash",
+            PaletteCommand::GitPull => "Git: Pull",
+            PaletteCommand::GitPush => "Git: Push",
+            PaletteCommand::NotificationLog => "View: Notification Log",
+            PaletteCommand::SwitchBranch => "Git: Switch Branch...",
+            PaletteCommand::Blame => "Git: Blame File",
+            PaletteCommand::NewProject => "Project: New Project...",
+            PaletteCommand::MatchingPair => "Go to Matching Bracket/Tag",
+            PaletteCommand::SaveAndQuit => "File: Save and Quit (:wq)",
+            PaletteCommand::Quit => "File: Quit (:q)",
+            PaletteCommand::Detach => "Session: Detach",
+            PaletteCommand::EditAnyway => "File: Edit Anyway (ignore other termi)",
+            PaletteCommand::ExportHtml => "File: Export as HTML",
+            PaletteCommand::CopyWithFormatting => "Edit: Copy with Formatting",
+            PaletteCommand::Share => "Share: Upload Selection or File",
+            PaletteCommand::TogglePreview => "View: Toggle Markdown Preview",
+            PaletteCommand::ToggleTypewriter => "View: Toggle Typewriter Scrolling",
+            PaletteCommand::ToggleFocusMode => "View: Toggle Focus Mode",
+            PaletteCommand::ToggleSoftWrap => "View: Toggle Soft Wrap",
+            PaletteCommand::PrettyPrint => "Edit: Pretty-print JSON/JS/CSS",
+            PaletteCommand::FormatTable => "Markdown: Format Table",
+            PaletteCommand::ReflowParagraph => "Prose: Reflow Paragraph",
+            PaletteCommand::JustifyParagraph => "Prose: Justify Paragraph",
+            PaletteCommand::Outline => "Go: Outline of File",
+            PaletteCommand::QuickJump => "Go: Quick Jump to Character",
+            PaletteCommand::BlockStart => "Go: Start of Indented Block",
+            PaletteCommand::BlockEnd => "Go: End of Indented Block",
+            PaletteCommand::HealthCheck => "File: Check Buffer Health",
+            PaletteCommand::SwitchHeaderSource => "Code: Switch Header/Source",
+            PaletteCommand::AddImport => "Code: Add Import for Symbol",
+            PaletteCommand::OrganizeImports => "Code: Organize Imports",
+            PaletteCommand::PreviousFunction => "Go: Previous Function",
+            PaletteCommand::NextFunction => "Go: Next Function",
+            PaletteCommand::Evaluate => "Edit: Evaluate Selection",
+            PaletteCommand::EvaluateAndReplace => "Edit: Evaluate and Replace Selection",
+            PaletteCommand::LocalOptions => "Buffer: Local Options...",
+            PaletteCommand::CloseBuffer => "File: Close Buffer",
+            PaletteCommand::ReopenClosedBuffer => "File: Reopen Closed Buffer",
+            PaletteCommand::CloseOtherBuffers => "File: Close Other Buffers",
+            PaletteCommand::CloseAllBuffers => "File: Close All Buffers",
+            PaletteCommand::TogglePin => "Buffer: Toggle Pin",
+            PaletteCommand::GoToSymbol => "Go: Symbol in Project...",
+            PaletteCommand::Tutorial => "Help: Tutorial",
+            PaletteCommand::KeymapPreset => "Preferences: Keybinding Preset...",
+            PaletteCommand::About => "Help: About termi",
+            PaletteCommand::CheckForUpdates => "Help: Check for Updates",
+        }
+    }
+
+    fn keys(&self) -> &'static str {
+        match self {
+            PaletteCommand::Save => "Ctrl+S",
+            PaletteCommand::Find => "Ctrl+F",
+            PaletteCommand::GoToLine => "Ctrl+G",
+            PaletteCommand::ToggleTree => "Ctrl+O",
+            PaletteCommand::ToggleTerminal => "Ctrl+1",
+            PaletteCommand::SelectAll => "Ctrl+A",
+            PaletteCommand::Undo => "Ctrl+Z",
+            PaletteCommand::Redo => "Ctrl+Y",
+            PaletteCommand::QuickFix => "Ctrl+.",
+            PaletteCommand::RunTestAtCursor => "F5",
+            PaletteCommand::ToggleOutputPanel => "F6",
+            PaletteCommand::ReloadCoverage | PaletteCommand::ToggleCoverage => "",
+            PaletteCommand::LocalHistory => "F7",
+            PaletteCommand::DiffWithSaved
+            | PaletteCommand::DiffWithHead
+            | PaletteCommand::DiffWithRevision
+            | PaletteCommand::DiffWithTreeFile
+            | PaletteCommand::DiffWithClipboard
+            | PaletteCommand::FileHistory
+            | PaletteCommand::GitStash
+            | PaletteCommand::GitStashPop
+            | PaletteCommand::GitPull
+            | PaletteCommand::GitPush
+            | PaletteCommand::Blame
+            | PaletteCommand::NewProject => "",
+            PaletteCommand::SwitchBranch => "Ctrl+B",
+            PaletteCommand::MatchingPair => "Ctrl+]",
+            PaletteCommand::Outline => "F4",
+            PaletteCommand::QuickJump => "Alt+J",
+            PaletteCommand::BlockStart => "Alt+Up",
+            PaletteCommand::BlockEnd => "Alt+Down",
+            PaletteCommand::SwitchHeaderSource => "Alt+O",
+            PaletteCommand::PreviousFunction => "Ctrl+Up",
+            PaletteCommand::NextFunction => "Ctrl+Down",
+            PaletteCommand::CloseBuffer => "Ctrl+W",
+            PaletteCommand::ReopenClosedBuffer => "Ctrl+Shift+T",
+            PaletteCommand::ReflowParagraph => "Alt+Q",
+            PaletteCommand::NotificationLog => "F9",
+            PaletteCommand::SaveAndQuit
+            | PaletteCommand::Detach
+            | PaletteCommand::EditAnyway
+            | PaletteCommand::ExportHtml
+            | PaletteCommand::CopyWithFormatting
+            | PaletteCommand::Share
+            | PaletteCommand::TogglePreview
+            | PaletteCommand::ToggleTypewriter
+            | PaletteCommand::ToggleFocusMode
+            | PaletteCommand::ToggleSoftWrap
+            | PaletteCommand::PrettyPrint
+            | PaletteCommand::FormatTable
+            | PaletteCommand::JustifyParagraph
+            | PaletteCommand::HealthCheck
+            | PaletteCommand::AddImport
+            | PaletteCommand::OrganizeImports
+            | PaletteCommand::Evaluate
+            | PaletteCommand::EvaluateAndReplace
+            | PaletteCommand::LocalOptions
+            | PaletteCommand::CloseOtherBuffers
+            | PaletteCommand::CloseAllBuffers
+            | PaletteCommand::TogglePin
+            | PaletteCommand::GoToSymbol
+            | PaletteCommand::Tutorial
+            | PaletteCommand::KeymapPreset
+            | PaletteCommand::About
+            | PaletteCommand::CheckForUpdates => "",
+            PaletteCommand::Quit => "Ctrl+Q",
+        }
+    }
+
+    /// Commands that change the buffer, which a read-only one refuses.
+    fn edits_buffer(&self) -> bool {
+        matches!(
+            self,
+            PaletteCommand::Undo
+                | PaletteCommand::Redo
+                | PaletteCommand::QuickFix
+                | PaletteCommand::PrettyPrint
+                | PaletteCommand::FormatTable
+                | PaletteCommand::ReflowParagraph
+                | PaletteCommand::JustifyParagraph
+                | PaletteCommand::AddImport
+                | PaletteCommand::OrganizeImports
+                | PaletteCommand::EvaluateAndReplace
+        )
+    }
+}
+
 #[derive(Clone)]
 enum DashboardAction {
     OpenWorkspace(PathBuf),
@@ -95,7 +769,7 @@ enum DashboardAction {
     RecentFile(PathBuf),
 }
 
-fn detect_language(path: &PathBuf) -> Language {
+fn detect_language(path: &Path) -> Language {
     if let Some(ext) = path.extension() {
         match ext.to_string_lossy().to_lowercase().as_str() {
             "rs" => Language::Rust,
@@ -111,14 +785,14 @@ fn detect_language(path: &PathBuf) -> Language {
     }
 }
 
-fn get_keywords(lang: &Language) -> Vec<&'static str> {
+fn get_keywords(lang: &Language) -> &'static [&'static str] {
     match lang {
-        Language::Rust => vec![
+        Language::Rust => &[
             "fn", "let", "mut", "const", "struct", "enum", "impl", "trait", "use", "mod", "pub",
             "if", "else", "match", "for", "while", "loop", "return", "break", "continue", "true",
             "false", "self", "Self", "super", "as", "impl", "dyn", "unsafe",
         ],
-        Language::JavaScript => vec![
+        Language::JavaScript => &[
             "function",
             "const",
             "let",
@@ -144,17 +818,17 @@ fn get_keywords(lang: &Language) -> Vec<&'static str> {
             "typeof",
             "instanceof",
         ],
-        Language::Python => vec![
+        Language::Python => &[
             "def", "class", "if", "else", "elif", "for", "while", "return", "import", "from", "as",
             "try", "except", "finally", "with", "lambda", "True", "False", "None", "and", "or",
             "not", "in", "is",
         ],
-        Language::C | Language::Cpp => vec![
+        Language::C | Language::Cpp => &[
             "int", "char", "float", "double", "void", "struct", "enum", "if", "else", "for",
             "while", "return", "break", "continue", "switch", "case", "default", "typedef",
             "static", "const", "extern", "volatile", "goto",
         ],
-        Language::Java => vec![
+        Language::Java => &[
             "class",
             "interface",
             "public",
@@ -178,27 +852,386 @@ fn get_keywords(lang: &Language) -> Vec<&'static str> {
             "import",
             "package",
         ],
-        Language::None => vec![],
+        Language::None => &[],
     }
 }
 
+/// Keyword lookup used by the tokenizer; each language's set is built on first use.
+fn keyword_set(lang: &Language) -> &'static HashSet<&'static str> {
+    static RUST: OnceLock<HashSet<&str>> = OnceLock::new();
+    static JAVASCRIPT: OnceLock<HashSet<&str>> = OnceLock::new();
+    static PYTHON: OnceLock<HashSet<&str>> = OnceLock::new();
+    static C: OnceLock<HashSet<&str>> = OnceLock::new();
+    static JAVA: OnceLock<HashSet<&str>> = OnceLock::new();
+    static NONE: OnceLock<HashSet<&str>> = OnceLock::new();
+
+    let set = match lang {
+        Language::Rust => &RUST,
+        Language::JavaScript => &JAVASCRIPT,
+        Language::Python => &PYTHON,
+        Language::C | Language::Cpp => &C,
+        Language::Java => &JAVA,
+        Language::None => &NONE,
+    };
+    set.get_or_init(|| get_keywords(lang).iter().copied().collect())
+}
+
+fn syntax_tokens(line: &[char], lang: &Language) -> Vec<(usize, usize, TokenType)> {
+    // Minified code would stall every frame; show it plain.
+    if *lang == Language::None || line.len() > minified::LONG_LINE {
+        return vec![(0, line.len(), TokenType::Normal)];
+    }
+    tokenize_line(line, lang, keyword_set(lang))
+}
+
 fn get_token_color(token_type: TokenType) -> Color {
     match token_type {
         TokenType::Keyword => Color::Cyan,
         TokenType::String => Color::Green,
         TokenType::Comment => Color::DarkGrey,
         TokenType::Number => Color::Yellow,
+        TokenType::Attribute => Color::DarkYellow,
+        TokenType::Lifetime => Color::Magenta,
+        TokenType::Macro => Color::Blue,
+        TokenType::Escape => Color::DarkMagenta,
+        TokenType::FormatSpec => Color::DarkCyan,
+        TokenType::Function => Color::Yellow,
+        TokenType::Type => Color::DarkGreen,
+        TokenType::Parameter => Color::Grey,
+        TokenType::DocComment | TokenType::DocBold | TokenType::DocItalic => Color::Grey,
+        TokenType::DocCode => Color::DarkYellow,
         TokenType::Normal => Color::White,
     }
 }
 
-fn tokenize_line(line: &str, lang: &Language, keywords: &[&str]) -> Vec<(usize, usize, TokenType)> {
+fn get_token_attribute(token_type: TokenType) -> Option<Attribute> {
+    match token_type {
+        TokenType::DocBold => Some(Attribute::Bold),
+        TokenType::DocItalic => Some(Attribute::Italic),
+        TokenType::DocCode => Some(Attribute::Dim),
+        _ => None,
+    }
+}
+
+// Returns the end of a raw (byte) string such as `r#"…"#` starting at `i`.
+fn rust_raw_string_end(chars: &[char], i: usize) -> Option<usize> {
+    let mut j = i;
+    if chars.get(j) == Some(&'b') {
+        j += 1;
+    }
+    if chars.get(j) != Some(&'r') {
+        return None;
+    }
+    j += 1;
+    let hashes = chars[j..].iter().take_while(|&&c| c == '#').count();
+    j += hashes;
+    if chars.get(j) != Some(&'"') {
+        return None;
+    }
+    j += 1;
+    while j < chars.len() {
+        if chars[j] == '"'
+            && chars[j + 1..]
+                .iter()
+                .take(hashes)
+                .filter(|&&c| c == '#')
+                .count()
+                == hashes
+        {
+            return Some(j + 1 + hashes);
+        }
+        j += 1;
+    }
+    Some(chars.len())
+}
+
+// Tells a char literal (`'a'`, `'\n'`, `'\u{1F600}'`) apart from a lifetime
+// (`'a`, `'static`) and returns the end of the literal.
+fn rust_char_literal_end(chars: &[char], i: usize) -> Option<usize> {
+    match chars.get(i + 1)? {
+        '\\' => {
+            let close = chars.get(i + 3..)?.iter().position(|&c| c == '\'')?;
+            Some(i + 3 + close + 1)
+        }
+        _ if chars.get(i + 2) == Some(&'\'') => Some(i + 3),
+        _ => None,
+    }
+}
+
+fn escape_end(chars: &[char], i: usize, end: usize) -> usize {
+    let hex_run = |from: usize, max: usize| {
+        from + chars[from.min(end)..end]
+            .iter()
+            .take(max)
+            .take_while(|c| c.is_ascii_hexdigit())
+            .count()
+    };
+    match chars.get(i + 1) {
+        Some('u') if chars.get(i + 2) == Some(&'{') => chars[i + 2..end]
+            .iter()
+            .position(|&c| c == '}')
+            .map(|p| i + 2 + p + 1)
+            .unwrap_or(end),
+        Some('u') => hex_run(i + 2, 4),
+        Some('x') => hex_run(i + 2, 2),
+        Some(_) => i + 2,
+        None => i + 1,
+    }
+    .min(end)
+}
+
+fn printf_spec_end(chars: &[char], i: usize, end: usize) -> Option<usize> {
+    let mut j = i + 1;
+    if chars.get(j) == Some(&'%') {
+        return Some(j + 1);
+    }
+    while j < end && matches!(chars[j], '-' | '+' | ' ' | '#' | '0') {
+        j += 1;
+    }
+    while j < end && (chars[j].is_ascii_digit() || chars[j] == '*' || chars[j] == '.') {
+        j += 1;
+    }
+    while j < end && matches!(chars[j], 'h' | 'l' | 'L' | 'z' | 'j' | 't') {
+        j += 1;
+    }
+    (j < end && "diouxXeEfFgGcspaAn".contains(chars[j])).then_some(j + 1)
+}
+
+// Sub-token pass: splits string tokens so escape sequences and format
+// placeholders (`{}`, `{name:>8}`, `%5.2f`) get their own colors.
+fn split_string_tokens(
+    chars: &[char],
+    tokens: Vec<(usize, usize, TokenType)>,
+    lang: &Language,
+) -> Vec<(usize, usize, TokenType)> {
+    let braces = matches!(lang, Language::Rust | Language::Python);
+    let printf = matches!(
+        lang,
+        Language::C | Language::Cpp | Language::Java | Language::Python
+    );
+    let mut result = Vec::with_capacity(tokens.len());
+
+    for (start, end, token_type) in tokens {
+        let end = end.min(chars.len());
+        // Rust raw strings have no escapes, but may still hold placeholders.
+        let raw = lang == &Language::Rust && rust_raw_string_end(chars, start).is_some();
+        if token_type != TokenType::String || end <= start + 1 {
+            result.push((start, end, token_type));
+            continue;
+        }
+
+        let mut plain_start = start;
+        let mut i = start + 1;
+        while i + 1 < end {
+            let sub = match chars[i] {
+                '\\' if !raw => Some((escape_end(chars, i, end), TokenType::Escape)),
+                '{' | '}' if braces && chars[i + 1] == chars[i] => Some((i + 2, TokenType::Escape)),
+                '{' if braces => chars[i + 1..end]
+                    .iter()
+                    .position(|&c| matches!(c, '}' | '{' | '"'))
+                    .filter(|&p| chars[i + 1 + p] == '}')
+                    .map(|p| (i + p + 2, TokenType::FormatSpec)),
+                '%' if printf => {
+                    printf_spec_end(chars, i, end).map(|sub_end| (sub_end, TokenType::FormatSpec))
+                }
+                _ => None,
+            };
+            match sub {
+                Some((sub_end, sub_type)) => {
+                    if plain_start < i {
+                        result.push((plain_start, i, TokenType::String));
+                    }
+                    result.push((i, sub_end, sub_type));
+                    plain_start = sub_end;
+                    i = sub_end;
+                }
+                None => i += 1,
+            }
+        }
+        if plain_start < end {
+            result.push((plain_start, end, TokenType::String));
+        }
+    }
+
+    result
+}
+
+// Recolors plain identifiers that fall inside a language server span; everything
+// the syntactic pass already classified (strings, comments, keywords) wins.
+fn merge_semantic_tokens(
+    tokens: Vec<(usize, usize, TokenType)>,
+    spans: &[(usize, usize, TokenType)],
+) -> Vec<(usize, usize, TokenType)> {
+    tokens
+        .into_iter()
+        .map(|(start, end, token_type)| {
+            let semantic = spans
+                .iter()
+                .find(|&&(s, e, _)| s <= start && end <= e)
+                .map(|&(_, _, t)| t);
+            match semantic {
+                Some(t) if token_type == TokenType::Normal => (start, end, t),
+                _ => (start, end, token_type),
+            }
+        })
+        .collect()
+}
+
+fn doc_span_end(chars: &[char], from: usize, end: usize, delim: &[char]) -> Option<usize> {
+    let n = delim.len();
+    (from + 1..=end.saturating_sub(n))
+        .find(|&j| chars[j..j + n] == *delim)
+        .map(|j| j + n)
+}
+
+// Sub-token pass: light markdown inside doc comments, so `code` spans,
+// **bold** and *italic* / _italic_ text stand out from the comment body.
+fn split_doc_comments(
+    chars: &[char],
+    tokens: Vec<(usize, usize, TokenType)>,
+) -> Vec<(usize, usize, TokenType)> {
+    let mut result = Vec::with_capacity(tokens.len());
+
+    for (start, end, token_type) in tokens {
+        let end = end.min(chars.len());
+        if token_type != TokenType::DocComment {
+            result.push((start, end, token_type));
+            continue;
+        }
+
+        let mut plain_start = start;
+        let mut i = start;
+        while i < end {
+            let word_start = i == start || !chars[i - 1].is_alphanumeric();
+            let sub = match chars[i] {
+                '`' => doc_span_end(chars, i, end, &['`']).map(|e| (e, TokenType::DocCode)),
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    doc_span_end(chars, i + 1, end, &['*', '*']).map(|e| (e, TokenType::DocBold))
+                }
+                '*' | '_' if word_start && chars.get(i + 1).is_some_and(|c| !c.is_whitespace()) => {
+                    doc_span_end(chars, i, end, &[chars[i]])
+                        .filter(|&e| chars.get(e).is_none_or(|c| !c.is_alphanumeric()))
+                        .map(|e| (e, TokenType::DocItalic))
+                }
+                _ => None,
+            };
+            match sub {
+                Some((sub_end, sub_type)) => {
+                    if plain_start < i {
+                        result.push((plain_start, i, TokenType::DocComment));
+                    }
+                    result.push((i, sub_end, sub_type));
+                    plain_start = sub_end;
+                    i = sub_end;
+                }
+                None => i += 1,
+            }
+        }
+        if plain_start < end {
+            result.push((plain_start, end, TokenType::DocComment));
+        }
+    }
+
+    result
+}
+
+fn tokenize_line(
+    chars: &[char],
+    lang: &Language,
+    keywords: &HashSet<&str>,
+) -> Vec<(usize, usize, TokenType)> {
     let mut tokens = Vec::new();
     let mut i = 0;
-    let chars: Vec<char> = line.chars().collect();
+    let mut word = String::new();
     let len = chars.len();
+    let is_rust = lang == &Language::Rust;
 
     while i < len {
+        let at_word_start =
+            i == 0 || !(chars[i - 1].is_ascii_alphanumeric() || chars[i - 1] == '_');
+
+        if is_rust && at_word_start {
+            if let Some(end) = rust_raw_string_end(chars, i) {
+                tokens.push((i, end, TokenType::String));
+                i = end;
+                continue;
+            }
+            if chars[i] == 'b' && matches!(chars.get(i + 1), Some('"') | Some('\'')) {
+                // Byte strings and byte chars share the regular literal rules.
+                tokens.push((i, i + 1, TokenType::String));
+                i += 1;
+                if chars[i] == '\'' {
+                    let end = rust_char_literal_end(chars, i).unwrap_or(i + 1);
+                    tokens.push((i, end, TokenType::String));
+                    i = end;
+                }
+                continue;
+            }
+        }
+
+        if is_rust && chars[i] == '\'' {
+            if let Some(end) = rust_char_literal_end(chars, i) {
+                tokens.push((i, end, TokenType::String));
+                i = end;
+                continue;
+            }
+            let start = i;
+            i += 1;
+            while i < len && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((start, i, TokenType::Lifetime));
+            continue;
+        }
+
+        if is_rust
+            && chars[i] == '#'
+            && (chars.get(i + 1) == Some(&'[')
+                || (chars.get(i + 1) == Some(&'!') && chars.get(i + 2) == Some(&'[')))
+        {
+            let start = i;
+            let mut depth = 0;
+            let mut in_string = false;
+            while i < len {
+                match chars[i] {
+                    '\\' if in_string => i += 1,
+                    '"' => in_string = !in_string,
+                    '[' if !in_string => depth += 1,
+                    ']' if !in_string => {
+                        depth -= 1;
+                        if depth == 0 {
+                            i += 1;
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+            let end = i.min(len);
+            tokens.push((start, end, TokenType::Attribute));
+            i = end;
+            continue;
+        }
+
+        if lang == &Language::Python
+            && (chars[i] == '"' || chars[i] == '\'')
+            && chars.get(i + 1) == Some(&chars[i])
+            && chars.get(i + 2) == Some(&chars[i])
+        {
+            // A triple-quoted string opening the line is treated as a docstring.
+            let start = i;
+            let delim = [chars[i]; 3];
+            i = doc_span_end(chars, i + 2, len, &delim).unwrap_or(len);
+            let token_type = if chars[..start].iter().all(|c| c.is_whitespace()) {
+                TokenType::DocComment
+            } else {
+                TokenType::String
+            };
+            tokens.push((start, i, token_type));
+            continue;
+        }
+
         if chars[i] == '"' || chars[i] == '\'' {
             let quote = chars[i];
             let start = i;
@@ -226,7 +1259,14 @@ fn tokenize_line(line: &str, lang: &Language, keywords: &[&str]) -> Vec<(usize,
             && chars[i] == '/'
             && chars[i + 1] == '/'
         {
-            tokens.push((i, len, TokenType::Comment));
+            let doc =
+                matches!(chars.get(i + 2), Some('/') | Some('!')) && chars.get(i + 3) != Some(&'/');
+            let token_type = if doc {
+                TokenType::DocComment
+            } else {
+                TokenType::Comment
+            };
+            tokens.push((i, len, token_type));
             break;
         }
         if lang == &Language::Python && i < len && chars[i] == '#' {
@@ -255,8 +1295,14 @@ fn tokenize_line(line: &str, lang: &Language, keywords: &[&str]) -> Vec<(usize,
             while i < len && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
                 i += 1;
             }
-            let word: String = chars[start..i].iter().collect();
-            let token_type = if keywords.iter().any(|&kw| kw == word) {
+            word.clear();
+            word.extend(&chars[start..i]);
+            if is_rust && chars.get(i) == Some(&'!') && chars.get(i + 1) != Some(&'=') {
+                tokens.push((start, i + 1, TokenType::Macro));
+                i += 1;
+                continue;
+            }
+            let token_type = if keywords.contains(word.as_str()) {
                 TokenType::Keyword
             } else {
                 TokenType::Normal
@@ -268,11 +1314,11 @@ fn tokenize_line(line: &str, lang: &Language, keywords: &[&str]) -> Vec<(usize,
         i += 1;
     }
 
-    tokens
+    split_doc_comments(chars, split_string_tokens(chars, tokens, lang))
 }
 
 fn to_io_error<E: std::fmt::Display>(err: E) -> io::Error {
-    io::Error::new(io::ErrorKind::Other, err.to_string())
+    io::Error::other(err.to_string())
 }
 
 fn terminal_shell_program() -> String {
@@ -296,10 +1342,217 @@ fn terminal_pty_size(rows: u16, cols: u16) -> PtySize {
     }
 }
 
+fn spawn_command_job(
+    program: &str,
+    args: &[String],
+    cwd: &Path,
+    waker: &Waker,
+) -> io::Result<Receiver<JobEvent>> {
+    let mut child = std::process::Command::new(program)
+        .args(args)
+        .current_dir(cwd)
+        // git asks for credentials on the terminal, not stdin, and nothing
+        // would ever answer; fail instead of hanging the job.
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut readers = Vec::new();
+
+    if let Some(stdout) = child.stdout.take() {
+        let (tx, waker) = (tx.clone(), waker.clone());
+        readers.push(thread::spawn(move || {
+            send_output_lines(stdout, JobEvent::Output, tx, waker)
+        }));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        let (tx, waker) = (tx.clone(), waker.clone());
+        readers.push(thread::spawn(move || {
+            send_output_lines(stderr, JobEvent::Error, tx, waker)
+        }));
+    }
+
+    let waker = waker.clone();
+    thread::spawn(move || {
+        for reader in readers {
+            let _ = reader.join();
+        }
+        let success = child.wait().map(|status| status.success()).unwrap_or(false);
+        let _ = tx.send(JobEvent::Finished(success));
+        waker.wake();
+    });
+
+    Ok(rx)
+}
+
+/// Builds `expression` into a program that prints it with rustc, so Rust
+/// literals like `0xFFu32` and `u64::MAX` mean what they do in Rust, and
+/// runs it. Only the program's stdout is output; compiler errors and what
+/// it writes to stderr are errors.
+fn spawn_rust_evaluation(
+    rustc: &str,
+    args: &[String],
+    expression: &str,
+    waker: &Waker,
+) -> io::Result<Receiver<JobEvent>> {
+    let dir = std::env::temp_dir().join(format!("termi-eval-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    let source = dir.join("eval.rs");
+    let program = dir.join(format!("eval{}", std::env::consts::EXE_SUFFIX));
+    fs::write(
+        &source,
+        format!(
+            "fn main() {{ println!(\"{{:?}}\", {{ {} }}); }}\n",
+            expression
+        ),
+    )?;
+    let mut build = std::process::Command::new(rustc);
+    build
+        .args(args)
+        .arg("-o")
+        .arg(&program)
+        .arg(&source)
+        .stdin(std::process::Stdio::null());
+
+    let (tx, rx) = mpsc::channel();
+    let (rustc, waker) = (rustc.to_string(), waker.clone());
+    thread::spawn(move || {
+        let send = |event: fn(String) -> JobEvent, bytes: &[u8]| {
+            for line in String::from_utf8_lossy(bytes).lines() {
+                let _ = tx.send(event(line.to_string()));
+            }
+        };
+        let success = match build.output() {
+            Ok(built) if built.status.success() => {
+                match std::process::Command::new(&program)
+                    .stdin(std::process::Stdio::null())
+                    .output()
+                {
+                    Ok(run) => {
+                        send(JobEvent::Output, &run.stdout);
+                        send(JobEvent::Error, &run.stderr);
+                        run.status.success()
+                    }
+                    Err(err) => {
+                        send(JobEvent::Error, err.to_string().as_bytes());
+                        false
+                    }
+                }
+            }
+            // The first error says what is wrong; the rest is rustc's
+            // summary of it.
+            Ok(built) => {
+                let stderr = String::from_utf8_lossy(&built.stderr);
+                let error = stderr.lines().find(|line| line.starts_with("error"));
+                send(JobEvent::Error, error.unwrap_or(&stderr).as_bytes());
+                false
+            }
+            Err(err) => {
+                send(JobEvent::Error, format!("{}: {}", rustc, err).as_bytes());
+                false
+            }
+        };
+        let _ = fs::remove_dir_all(&dir);
+        let _ = tx.send(JobEvent::Finished(success));
+        waker.wake();
+    });
+    Ok(rx)
+}
+
+// Progress meters redraw their line with carriage returns, so those end a
+// line as well; a Windows line ending still counts once.
+fn send_output_lines(
+    output: impl Read,
+    event: fn(String) -> JobEvent,
+    tx: mpsc::Sender<JobEvent>,
+    waker: Waker,
+) {
+    let mut line = Vec::new();
+    let mut after_return = false;
+    let send = |line: &mut Vec<u8>| {
+        let text = String::from_utf8_lossy(line).into_owned();
+        line.clear();
+        let sent = tx.send(event(text)).is_ok();
+        waker.wake();
+        sent
+    };
+    for byte in io::BufReader::new(output).bytes().map_while(Result::ok) {
+        match byte {
+            b'\n' if after_return => {}
+            b'\r' | b'\n' => {
+                if !send(&mut line) {
+                    return;
+                }
+            }
+            _ => line.push(byte),
+        }
+        after_return = byte == b'\r';
+    }
+    if !line.is_empty() {
+        send(&mut line);
+    }
+}
+
+fn open_in_browser(url: &str) -> io::Result<()> {
+    if replaying() {
+        return Err(io::Error::other("not in a replay"));
+    }
+    let mut command = if cfg!(windows) {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", "", url]);
+        command
+    } else if cfg!(target_os = "macos") {
+        let mut command = std::process::Command::new("open");
+        command.arg(url);
+        command
+    } else {
+        let mut command = std::process::Command::new("xdg-open");
+        command.arg(url);
+        command
+    };
+    command
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .map(|_| ())
+}
+
+fn python_program() -> &'static str {
+    if cfg!(windows) {
+        "python"
+    } else {
+        "python3"
+    }
+}
+
+fn npx_program() -> &'static str {
+    if cfg!(windows) {
+        "npx.cmd"
+    } else {
+        "npx"
+    }
+}
+
+fn first_string_literal(text: &str) -> Option<String> {
+    let start = text.find(['"', '\'', '`'])?;
+    let quote = text[start..].chars().next()?;
+    let rest = &text[start + 1..];
+    let end = rest.find(quote)?;
+    Some(rest[..end].to_string())
+}
+
 fn normalize_recent_path(path: &Path) -> PathBuf {
     fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
 }
 
+fn same_file(a: &Path, b: &Path) -> bool {
+    normalize_recent_path(a) == normalize_recent_path(b)
+}
+
 fn dashboard_state_path() -> PathBuf {
     #[cfg(windows)]
     {
@@ -326,11 +1579,50 @@ fn dashboard_state_path() -> PathBuf {
     PathBuf::from("dashboard_state.txt")
 }
 
-fn push_recent_path(list: &mut Vec<PathBuf>, path: &Path) {
-    let normalized = normalize_recent_path(path);
-    list.retain(|entry| entry != &normalized);
-    list.insert(0, normalized);
-    list.truncate(RECENT_ITEM_LIMIT);
+fn config_path() -> PathBuf {
+    dashboard_state_path().with_file_name("config.toml")
+}
+
+// Set for `--record` and `--replay`, so both start from the same empty cache.
+static CACHE_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+// Set for `--replay`, which works in a scratch copy of the tree and must not
+// reach anything outside it: no Discord, language servers, network or
+// config writes.
+static REPLAYING: OnceLock<()> = OnceLock::new();
+
+fn replaying() -> bool {
+    REPLAYING.get().is_some()
+}
+
+fn cache_dir() -> PathBuf {
+    if let Some(dir) = CACHE_DIR_OVERRIDE.get() {
+        return dir.clone();
+    }
+
+    #[cfg(windows)]
+    {
+        if let Ok(local_appdata) = env::var("LOCALAPPDATA") {
+            return PathBuf::from(local_appdata).join("Termi").join("cache");
+        }
+    }
+
+    if let Ok(xdg_cache) = env::var("XDG_CACHE_HOME") {
+        return PathBuf::from(xdg_cache).join("termi");
+    }
+
+    if let Ok(home) = env::var("HOME") {
+        return PathBuf::from(home).join(".cache").join("termi");
+    }
+
+    PathBuf::from(".termi-cache")
+}
+
+fn push_recent_path(list: &mut Vec<PathBuf>, path: &Path) {
+    let normalized = normalize_recent_path(path);
+    list.retain(|entry| entry != &normalized);
+    list.insert(0, normalized);
+    list.truncate(RECENT_ITEM_LIMIT);
 }
 
 fn shorten_text_middle(text: &str, max_chars: usize) -> String {
@@ -370,6 +1662,25 @@ struct Editor {
     show_tree: bool,
 
     show_line_numbers: bool,
+    // Rendered Markdown beside the text, for Markdown buffers.
+    show_preview: bool,
+    // Keep the cursor line centered vertically.
+    typewriter: bool,
+    // Dim everything but the paragraph or function being edited.
+    focus_mode: bool,
+    // Long lines continue on the next screen rows instead of scrolling
+    // sideways. `wrap_row` is the row of line `scroll_y` at the top.
+    soft_wrap: bool,
+    wrap_row: usize,
+    // Overrides for the current buffer; those of other files wait in
+    // `buffer_options` until they are opened again.
+    local_options: LocalOptions,
+    buffer_options: HashMap<PathBuf, LocalOptions>,
+    local_options_index: usize,
+    keymap_index: usize,
+    // Where the preset picker goes back to; it also opens on first run,
+    // over the dashboard.
+    keymap_return: EditorMode,
 
     mode: EditorMode,
     dashboard_selection: usize,
@@ -390,6 +1701,8 @@ struct Editor {
     language: Language,
 
     cursor_locked: bool,
+    // Where an animated scroll is heading; `scroll_y` is the frame being drawn.
+    scroll_target: Option<usize>,
 
     delete_target: Option<PathBuf>,
 
@@ -414,6 +1727,9 @@ struct Editor {
     goto_line_input: Vec<char>,
 
     matched_bracket: Option<(usize, usize)>,
+    matched_tag: Option<(tags::TagSpan, tags::TagSpan)>,
+    // The scanned tags, with the file and edit count they were scanned at.
+    tag_index: Option<(Option<PathBuf>, u64, tags::TagIndex)>,
 
     last_scroll_y: usize,
     last_scroll_x: usize,
@@ -421,33 +1737,158 @@ struct Editor {
     needs_full_redraw: bool,
 
     quit_confirm: bool,
+    should_quit: bool,
+    // Running as `termi --server`; detaching drops the client that asked.
+    serving_session: bool,
+    detach_requested: bool,
 
     dirty_files: HashSet<PathBuf>,
+    // Edit locks this instance holds. A file whose lock another instance
+    // held opens with `ReadOnly::Locked` in its local options.
+    file_locks: HashMap<PathBuf, FileLock>,
+    // Files that started with a BOM; it is written back on save.
+    bom_files: HashSet<PathBuf>,
+    lock_warning: Option<(PathBuf, Option<u32>)>,
+    // Length of the overlong line in a just-opened minified file.
+    minified_warning: Option<usize>,
+    // A file over `large_file_size` waiting for the user to pick how to
+    // open it, and its size; files opened in large-file mode.
+    large_file_warning: Option<(PathBuf, u64)>,
+    large_files: HashSet<PathBuf>,
 
     file_buffers: HashMap<PathBuf, Vec<Vec<char>>>,
-
-    autocomplete_suggestions: Vec<String>,
+    // Files with a buffer, most recently shown first.
+    buffer_order: Vec<PathBuf>,
+    // Closed files and where their cursor was, most recently closed last.
+    // Files that Close Buffer and Close All leave open.
+    pinned_files: HashSet<PathBuf>,
+    closed_buffers: Vec<(PathBuf, positions::Position)>,
+
+    autocomplete_suggestions: Vec<Completion>,
     autocomplete_index: usize,
     autocomplete_prefix: String,
-
+    word_usage: HashMap<PathBuf, HashMap<String, WordUsage>>,
+    ghost_completion: Option<(usize, usize, Completion)>,
+    word_usage_clock: u64,
+    autocomplete_lsp_request: Option<(usize, usize)>,
+
+    // Wakes the main loop when a background thread has queued output.
+    waker: Waker,
+    // Size of the terminal being drawn to, as (cols, rows). It is the local
+    // terminal's, or the attached client's when running as a session server.
+    screen_size: (u16, u16),
+    // Paths sent by later invocations in single-instance mode.
+    instance_rx: Option<Receiver<instance::Request>>,
+    // `termi --wait` invocations, each waiting for its file to be closed.
+    instance_waiters: Vec<(PathBuf, instance::Waiter)>,
+    glyphs: &'static Glyphs,
     discord_client: Option<DiscordIpcClient>,
     discord_start_time: i64,
     discord_enabled: bool,
+
+    lsp_clients: HashMap<&'static str, LspClient>,
+    lsp_unavailable: HashSet<&'static str>,
+    lsp_diagnostics: HashMap<String, Vec<serde_json::Value>>,
+    lsp_needs_sync: bool,
+    // Counts edits to the buffer, so what is derived from it knows when to
+    // be recomputed.
+    buffer_edits: u64,
+    semantic_tokens: HashMap<PathBuf, HashMap<usize, SemanticLine>>,
+
+    quick_fixes: Vec<lsp::CodeAction>,
+    quick_fix_index: usize,
+    // The code action request the quick fix list waits for.
+    quick_fix_request: Option<u64>,
+    // The code action request looking for an import, and the symbol.
+    import_request: Option<(u64, String)>,
+    // The project search for a symbol's definition, run when the language
+    // server has no import to offer.
+    import_scan: Option<ImportScan>,
+    // The code action request for organizing imports.
+    organize_request: Option<u64>,
+
+    jobs: Vec<Job>,
+    // Reply from the running share upload, read once it finishes.
+    share_response: Vec<String>,
+    evaluation_output: Vec<String>,
+    // What the evaluator wrote to stderr, kept out of its result.
+    evaluation_errors: Vec<String>,
+    update_response: Vec<String>,
+    test_results: HashMap<PathBuf, HashMap<String, TestStatus>>,
+    output_panel: Option<OutputPanel>,
+    show_output_panel: bool,
+
+    palette_query: Vec<char>,
+    palette_index: usize,
+
+    coverage: HashMap<PathBuf, coverage::LineHits>,
+    show_coverage: bool,
+
+    timeline_entries: Vec<local_history::Snapshot>,
+    timeline_index: usize,
+    timeline_preview: Vec<String>,
+
+    diff_view: Option<DiffView>,
+    diff_revision_input: Vec<char>,
+
+    git_log_entries: Vec<git::Commit>,
+    git_log_index: usize,
+    git_log_scroll: usize,
+    revision_view: Option<RevisionView>,
+
+    notifications: Vec<Notification>,
+    notification_scroll: usize,
+
+    git_branch: Option<String>,
+    branch_list: Vec<String>,
+    branch_query: Vec<char>,
+    branch_index: usize,
+    outline: Vec<markdown::Heading>,
+    outline_query: Vec<char>,
+    outline_index: usize,
+    // Loaded on first use; written back on exit.
+    symbol_cache: Option<symbols::Cache>,
+    project_symbols: Vec<(PathBuf, symbols::Symbol)>,
+    symbol_query: Vec<char>,
+    symbol_index: usize,
+    // The tutorial document and the lesson it is on, while one runs.
+    tutor: Option<(PathBuf, usize)>,
+    // `--safe`: default settings, and no language servers or Discord.
+    safe_mode: bool,
+    health: Option<health::Report>,
+    health_index: usize,
+    // Quick jump: the character looked for, then its labeled matches on
+    // screen and the label typed so far.
+    jump_char: Option<char>,
+    jump_targets: Vec<JumpTarget>,
+    jump_input: String,
+    branch_pending: Option<String>,
+
+    blame_view: Option<BlameView>,
+
+    template_choices: Vec<&'static templates::Template>,
+    template_index: usize,
+
+    config: config::Config,
+    scaffold_index: usize,
+    scaffold_dir_input: Vec<char>,
 }
 
 impl Editor {
-    fn new() -> Self {
-        Self::new_with_options(".", true)
-    }
-
-    fn new_with_path(initial_path: &str) -> Self {
-        Self::new_with_options(initial_path, false)
-    }
-
-    fn new_with_options(initial_path: &str, show_dashboard: bool) -> Self {
+    fn new_with_options(
+        initial_path: &str,
+        show_dashboard: bool,
+        safe_mode: bool,
+        waker: Waker,
+    ) -> Self {
         let (cols, rows) = terminal::size().unwrap_or((80, 24));
         let dashboard_workspace = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
         let (recent_projects, recent_files) = Self::load_dashboard_state();
+        let (config, config_error) = if safe_mode {
+            (config::Config::default(), None)
+        } else {
+            config::load()
+        };
         let mut e = Self {
             buffer: vec![vec![]],
             cursor_x: 0,
@@ -463,6 +1904,16 @@ impl Editor {
             tree_scroll: 0,
             show_tree: false,
             show_line_numbers: true,
+            show_preview: false,
+            typewriter: config.scrolling.typewriter,
+            focus_mode: false,
+            soft_wrap: false,
+            local_options: LocalOptions::default(),
+            buffer_options: HashMap::new(),
+            local_options_index: 0,
+            keymap_index: 0,
+            keymap_return: EditorMode::Normal,
+            wrap_row: 0,
             mode: if show_dashboard {
                 EditorMode::Dashboard
             } else {
@@ -482,6 +1933,7 @@ impl Editor {
             history_limit: 100,
             language: Language::None,
             cursor_locked: false,
+            scroll_target: None,
             delete_target: None,
             rename_target: None,
             rename_name: vec![],
@@ -500,25 +1952,115 @@ impl Editor {
             terminal_parser: TerminalParser::new(rows.max(1), cols.max(1), TERMINAL_SCROLLBACK_LEN),
             goto_line_input: vec![],
             matched_bracket: None,
+            matched_tag: None,
+            tag_index: None,
             last_scroll_y: 0,
             last_scroll_x: 0,
             last_tree_scroll: 0,
             needs_full_redraw: true,
             quit_confirm: false,
+            should_quit: false,
+            serving_session: false,
+            detach_requested: false,
             dirty_files: HashSet::new(),
+            file_locks: HashMap::new(),
+            bom_files: HashSet::new(),
+            lock_warning: None,
+            minified_warning: None,
+            large_file_warning: None,
+            large_files: HashSet::new(),
             file_buffers: HashMap::new(),
+            buffer_order: vec![],
+            closed_buffers: vec![],
+            pinned_files: HashSet::new(),
             autocomplete_suggestions: vec![],
             autocomplete_index: 0,
             autocomplete_prefix: String::new(),
+            word_usage: HashMap::new(),
+            ghost_completion: None,
+            word_usage_clock: 0,
+            autocomplete_lsp_request: None,
+            waker,
+            screen_size: (cols, rows),
+            instance_rx: None,
+            instance_waiters: Vec::new(),
+            glyphs: &capabilities::UNICODE_GLYPHS,
             discord_client: None,
             discord_start_time: SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_secs() as i64,
-            discord_enabled: true,
+            discord_enabled: !safe_mode && !replaying(),
+            lsp_clients: HashMap::new(),
+            lsp_unavailable: HashSet::new(),
+            lsp_diagnostics: HashMap::new(),
+            lsp_needs_sync: false,
+            buffer_edits: 0,
+            semantic_tokens: HashMap::new(),
+            quick_fixes: vec![],
+            quick_fix_index: 0,
+            quick_fix_request: None,
+            import_request: None,
+            import_scan: None,
+            organize_request: None,
+            jobs: vec![],
+            share_response: vec![],
+            update_response: vec![],
+            evaluation_output: vec![],
+            evaluation_errors: vec![],
+            test_results: HashMap::new(),
+            output_panel: None,
+            show_output_panel: false,
+            palette_query: vec![],
+            palette_index: 0,
+            coverage: HashMap::new(),
+            show_coverage: false,
+            timeline_entries: vec![],
+            timeline_index: 0,
+            timeline_preview: vec![],
+            diff_view: None,
+            diff_revision_input: vec![],
+            git_log_entries: vec![],
+            git_log_index: 0,
+            git_log_scroll: 0,
+            revision_view: None,
+            notifications: vec![],
+            notification_scroll: 0,
+            git_branch: None,
+            branch_list: vec![],
+            branch_query: vec![],
+            branch_index: 0,
+            outline: vec![],
+            outline_query: vec![],
+            outline_index: 0,
+            symbol_cache: None,
+            project_symbols: vec![],
+            symbol_query: vec![],
+            symbol_index: 0,
+            tutor: None,
+            safe_mode,
+            health: None,
+            health_index: 0,
+            jump_char: None,
+            jump_targets: vec![],
+            jump_input: String::new(),
+            branch_pending: None,
+            blame_view: None,
+            template_choices: vec![],
+            template_index: 0,
+            config,
+            scaffold_index: 0,
+            scaffold_dir_input: vec![],
         };
 
-        e.init_discord();
+        if safe_mode {
+            e.status = "Safe mode: default settings, no language servers or Discord".into();
+        } else if !replaying() {
+            e.init_discord();
+        }
+        if let Some(err) = config_error {
+            e.status = format!("Config error: {}", err);
+        }
 
         let path = PathBuf::from(initial_path);
         if show_dashboard {
@@ -582,6 +2124,9 @@ impl Editor {
     }
 
     fn save_dashboard_state(&self) {
+        if replaying() {
+            return;
+        }
         let state_path = dashboard_state_path();
         if let Some(parent) = state_path.parent() {
             if fs::create_dir_all(parent).is_err() {
@@ -666,13 +2211,6 @@ impl Editor {
         self.status = Self::default_status();
     }
 
-    fn enter_dashboard(&mut self) {
-        self.mode = EditorMode::Dashboard;
-        self.clamp_dashboard_selection();
-        self.needs_full_redraw = true;
-        self.dirty = true;
-    }
-
     fn leave_dashboard(&mut self) {
         self.mode = EditorMode::Normal;
         self.restore_default_status();
@@ -704,8 +2242,13 @@ impl Editor {
         self.cursor_y = 0;
         self.scroll_x = 0;
         self.scroll_y = 0;
+        self.scroll_target = None;
+        if let Some(path) = self.file_path.take() {
+            self.buffer_options
+                .insert(path, std::mem::take(&mut self.local_options));
+        }
+        self.local_options = LocalOptions::default();
         self.file_name = None;
-        self.file_path = None;
         self.language = Language::None;
         self.history = vec![vec![vec![]]];
         self.history_index = 0;
@@ -742,6 +2285,52 @@ impl Editor {
         }
     }
 
+    fn drain_instance_requests(&mut self) {
+        // Wait for open dialogs and views to close rather than yank them away.
+        if !matches!(self.mode, EditorMode::Normal | EditorMode::Dashboard) {
+            return;
+        }
+        let Some(rx) = self.instance_rx.as_ref() else {
+            return;
+        };
+        let requests: Vec<instance::Request> = rx.try_iter().collect();
+        for request in requests {
+            self.open_forwarded_path(&request.path);
+            // A folder, or a file that did not open, has nothing to wait for.
+            if let Some(waiter) = request.waiter {
+                if self
+                    .file_path
+                    .as_deref()
+                    .is_some_and(|open| same_file(open, &request.path))
+                {
+                    self.instance_waiters.push((request.path, waiter));
+                }
+            }
+        }
+    }
+
+    fn open_forwarded_path(&mut self, path: &Path) {
+        if path.is_dir() {
+            self.open_workspace(path);
+            return;
+        }
+        if !path.is_file() {
+            self.status = format!("Not found: {}", path.to_string_lossy());
+            self.needs_full_redraw = true;
+            return;
+        }
+        if matches!(self.mode, EditorMode::Dashboard) {
+            if let Some(parent) = path.parent() {
+                self.open_workspace(parent);
+            }
+        }
+        match self.open_file(&path.to_path_buf()) {
+            Ok(()) => self.status = format!("Opened {}", path.to_string_lossy()),
+            Err(err) => self.status = format!("Could not open {}: {}", path.to_string_lossy(), err),
+        }
+        self.needs_full_redraw = true;
+    }
+
     fn init_discord(&mut self) {
         const DISCORD_APP_ID: &str = "1457025246568906804";
 
@@ -772,15 +2361,7 @@ impl Editor {
         };
 
         let (details, state) = if let Some(ref file_name) = self.file_name {
-            let lang_name = match self.language {
-                Language::Rust => "Rust",
-                Language::JavaScript => "JavaScript",
-                Language::Python => "Python",
-                Language::C => "C",
-                Language::Cpp => "C++",
-                Language::Java => "Java",
-                Language::None => "Text",
-            };
+            let lang_name = self.language.name();
 
             let line_count = self.buffer.len();
             (
@@ -806,3701 +2387,10614 @@ impl Editor {
         self.discord_client = None;
     }
 
-    fn load_root(&mut self, dir: &str) {
-        self.tree.clear();
-        self.load_dir(PathBuf::from(dir), 0);
-        self.tree_scroll = 0;
-        self.tree_cursor = 0;
-        self.needs_full_redraw = true;
+    fn is_markdown(&self) -> bool {
+        self.file_path
+            .as_deref()
+            .is_some_and(markdown::is_markdown_path)
     }
 
-    fn load_dir(&mut self, path: PathBuf, depth: usize) {
-        if let Ok(entries) = fs::read_dir(path) {
-            for e in entries.flatten() {
-                let meta = e.metadata().unwrap();
-                self.tree.push(FileNode {
-                    name: e.file_name().to_string_lossy().into(),
-                    path: e.path(),
-                    is_dir: meta.is_dir(),
-                    expanded: false,
-                    depth,
-                });
-            }
+    /// Columns on the right taken by the Markdown preview; 0 when it is off
+    /// or the buffer isn't Markdown.
+    fn preview_width(&self, cols: u16) -> u16 {
+        if !self.show_preview || !self.is_markdown() {
+            return 0;
         }
+        let tree_offset = if self.show_tree { TREE_WIDTH } else { 0 };
+        let line_num_offset = if self.show_line_numbers {
+            LINE_NUM_WIDTH
+        } else {
+            0
+        };
+        cols.saturating_sub(tree_offset + line_num_offset) / 2
     }
 
-    fn toggle_dir(&mut self, idx: usize) {
-        if !self.tree[idx].is_dir {
-            return;
+    fn toggle_preview(&mut self) {
+        if !self.is_markdown() {
+            self.status = "The preview is for Markdown files".into();
+        } else {
+            self.show_preview = !self.show_preview;
+            self.scroll_x = 0;
         }
+        self.needs_full_redraw = true;
+    }
 
-        if self.tree[idx].expanded {
-            let d = self.tree[idx].depth;
-            self.tree[idx].expanded = false;
-            let start_idx = idx + 1;
-            while start_idx < self.tree.len() && self.tree[start_idx].depth > d {
-                self.tree.remove(start_idx);
-            }
-            self.needs_full_redraw = true;
+    fn toggle_typewriter(&mut self) {
+        self.typewriter = !self.typewriter;
+        self.cursor_locked = false;
+        self.status = if self.typewriter {
+            "Typewriter scrolling on".into()
         } else {
-            self.tree[idx].expanded = true;
-            let path = self.tree[idx].path.clone();
-            let depth = self.tree[idx].depth + 1;
-            let mut insert = idx + 1;
+            "Typewriter scrolling off".into()
+        };
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-            if let Ok(entries) = fs::read_dir(path) {
-                for e in entries.flatten() {
-                    let meta = e.metadata().unwrap();
-                    self.tree.insert(
-                        insert,
-                        FileNode {
-                            name: e.file_name().to_string_lossy().into(),
-                            path: e.path(),
-                            is_dir: meta.is_dir(),
-                            expanded: false,
-                            depth,
-                        },
-                    );
-                    insert += 1;
-                }
-            }
-            self.needs_full_redraw = true;
-        }
+    fn toggle_focus_mode(&mut self) {
+        self.focus_mode = !self.focus_mode;
+        self.status = if self.focus_mode {
+            "Focus mode on".into()
+        } else {
+            "Focus mode off".into()
+        };
+        self.needs_full_redraw = true;
+        self.dirty = true;
     }
 
-    fn open_file(&mut self, path: &PathBuf) -> io::Result<()> {
-        if let Some(old_path) = &self.file_path {
-            self.file_buffers
-                .insert(old_path.clone(), self.buffer.clone());
+    fn toggle_soft_wrap(&mut self) {
+        self.soft_wrap = !self.soft_wrap;
+        self.wrap_row = 0;
+        self.scroll_x = 0;
+        self.cursor_locked = false;
+        self.status = if self.soft_wrap {
+            "Soft wrap on".into()
+        } else {
+            "Soft wrap off".into()
+        };
+        if self.local_options.wrap.is_some() {
+            self.status
+                .push_str(" - this buffer keeps its own setting (Buffer: Local Options)");
         }
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        if let Some(cached_buffer) = self.file_buffers.get(path) {
-            self.buffer = cached_buffer.clone();
+    /// Columns of text on screen, between the gutter and the preview.
+    fn text_area_width(&self) -> usize {
+        let (cols, _) = self.screen_size;
+        let tree_offset = if self.show_tree { TREE_WIDTH } else { 0 };
+        let line_num_offset = if self.show_line_numbers {
+            LINE_NUM_WIDTH
         } else {
-            let mut s = String::new();
-            fs::File::open(path)?.read_to_string(&mut s)?;
-            self.buffer = s.lines().map(|l| l.chars().collect()).collect();
-            if self.buffer.is_empty() {
-                self.buffer.push(vec![]);
+            0
+        };
+        cols.saturating_sub(tree_offset + line_num_offset + self.preview_width(cols)) as usize
+    }
+
+    /// Width of the text area while soft wrap is on.
+    fn soft_wrap_width(&self) -> Option<usize> {
+        let width = self.text_area_width();
+        (self.wraps() && width > 0).then_some(width)
+    }
+
+    /// Soft wrap as the current buffer has it.
+    fn wraps(&self) -> bool {
+        self.local_options.wrap.unwrap_or(self.soft_wrap)
+    }
+
+    /// The screen row after (or before) `(line, row)` in the wrapped view.
+    fn wrap_step(
+        &self,
+        (y, row): (usize, usize),
+        up: bool,
+        width: usize,
+    ) -> Option<(usize, usize)> {
+        if up {
+            match (row, y) {
+                (0, 0) => None,
+                (0, _) => Some((y - 1, wrapped_rows(&self.buffer[y - 1], width) - 1)),
+                _ => Some((y, row - 1)),
             }
-            self.file_buffers.insert(path.clone(), self.buffer.clone());
+        } else if row + 1 < wrapped_rows(&self.buffer[y], width) {
+            Some((y, row + 1))
+        } else {
+            (y + 1 < self.buffer.len()).then_some((y + 1, 0))
+        }
+    }
+
+    /// Screen rows from `from` down to `to`, counting no further than
+    /// `limit`.
+    fn wrapped_distance(
+        &self,
+        mut from: (usize, usize),
+        to: (usize, usize),
+        width: usize,
+        limit: usize,
+    ) -> usize {
+        let mut rows = 0;
+        while from < to && rows < limit {
+            match self.wrap_step(from, false, width) {
+                Some(next) => from = next,
+                None => break,
+            }
+            rows += 1;
         }
+        rows
+    }
 
-        self.file_path = Some(path.clone());
-        self.file_name = Some(path.file_name().unwrap().to_string_lossy().into());
-        self.language = detect_language(path);
-        self.cursor_x = 0;
-        self.cursor_y = 0;
-        self.scroll_y = 0;
-        self.scroll_x = 0;
-        self.needs_full_redraw = true;
-        self.dirty = false;
-        self.dirty_files.remove(path);
-        self.update_bracket_matching();
-        self.save_history_state();
-        self.record_recent_file(path);
-        self.update_discord_presence();
-        Ok(())
+    /// Where the cursor sits in the text area, as (column, row).
+    fn cursor_screen_pos(&self, max_lines: usize) -> (usize, usize) {
+        match self.soft_wrap_width() {
+            Some(width) => {
+                let top = (self.scroll_y, self.wrap_row);
+                let cursor = (self.cursor_y, self.cursor_x / width);
+                let row = if cursor < top {
+                    max_lines
+                } else {
+                    self.wrapped_distance(top, cursor, width, max_lines)
+                };
+                (self.cursor_x % width, row)
+            }
+            None => (
+                self.cursor_x.saturating_sub(self.scroll_x),
+                self.cursor_y.saturating_sub(self.scroll_y),
+            ),
+        }
+    }
+
+    /// The buffer position shown at a text-area row and column; the column
+    /// is not clamped to the line.
+    fn text_position(&self, row: usize, column: usize) -> (usize, usize) {
+        match self.soft_wrap_width() {
+            Some(width) => {
+                let mut pos = (self.scroll_y, self.wrap_row);
+                for _ in 0..row {
+                    match self.wrap_step(pos, false, width) {
+                        Some(next) => pos = next,
+                        // Past the last line.
+                        None => return (self.buffer.len(), 0),
+                    }
+                }
+                (pos.0, pos.1 * width + column)
+            }
+            None => (self.scroll_y + row, self.scroll_x + column),
+        }
     }
 
-    fn mark_file_dirty(&mut self) {
-        self.dirty = true;
-        self.needs_full_redraw = true;
-        if let Some(path) = &self.file_path {
-            self.dirty_files.insert(path.clone());
+    /// Where Up or Down lands in the wrapped view: the same column one
+    /// screen row away.
+    fn wrapped_move(&self, width: usize, up: bool) -> Option<(usize, usize)> {
+        let column = self.cursor_x % width;
+        let (y, row) = self.wrap_step((self.cursor_y, self.cursor_x / width), up, width)?;
+        Some((y, (row * width + column).min(self.buffer[y].len())))
+    }
+
+    fn pretty_print(&mut self) {
+        let Some(kind) = self.file_path.as_deref().and_then(minified::kind) else {
+            self.status = "Pretty-printing works on JSON, JavaScript and CSS files".into();
+            self.dirty = true;
+            return;
+        };
+        let text = minified::pretty_print(&self.buffer_text(), kind);
+        let lines: Vec<Vec<char>> = text.lines().map(|l| l.chars().collect()).collect();
+        if lines.is_empty() || lines == self.buffer {
+            return;
         }
+        self.save_history_state();
+        self.buffer = lines;
+        self.clear_selection();
+        self.cursor_y = 0;
+        self.cursor_x = 0;
+        self.scroll_x = 0;
+        self.wrap_row = 0;
+        self.status = format!("Pretty-printed into {} lines", self.buffer.len());
+        self.mark_file_dirty();
     }
 
-    fn get_word_boundaries(&self, y: usize, x: usize) -> Option<(usize, usize)> {
-        if y >= self.buffer.len() {
+    /// Lines left undimmed in focus mode: the paragraph in prose, the
+    /// function in code.
+    fn focus_range(&self) -> Option<(usize, usize)> {
+        if !self.focus_mode {
             return None;
         }
-        let line = &self.buffer[y];
-        if x > line.len() {
-            return None;
+        if self.is_prose_buffer() {
+            return Some(
+                prose::paragraph_bounds(&self.buffer, self.cursor_y)
+                    .unwrap_or((self.cursor_y, self.cursor_y)),
+            );
         }
+        Some(focus::code_block(&self.buffer, self.cursor_y))
+    }
 
-        let is_special_char = |c: char| -> bool {
-            matches!(
-                c,
-                '.' | ','
-                    | '['
-                    | ']'
-                    | '{'
-                    | '}'
-                    | '$'
-                    | '('
-                    | ')'
-                    | ';'
-                    | ':'
-                    | '!'
-                    | '?'
-                    | '@'
-                    | '#'
-                    | '%'
-                    | '^'
-                    | '&'
-                    | '*'
-                    | '+'
-                    | '-'
-                    | '='
-                    | '/'
-                    | '\\'
-                    | '|'
-                    | '<'
-                    | '>'
-                    | '`'
-                    | '\''
-                    | '"'
-            )
-        };
+    /// The Markdown table around the cursor, as a line range.
+    fn table_at_cursor(&self) -> Option<(usize, usize)> {
+        if !self.is_markdown() {
+            return None;
+        }
+        markdown::table_bounds(&self.buffer, self.cursor_y)
+    }
 
-        if line.is_empty() {
-            return Some((0, 0));
+    /// Rewrites the table in place, keeping the cursor in the same cell.
+    fn reformat_table(&mut self, start: usize, end: usize) {
+        let cell = markdown::cell_at(&self.buffer[self.cursor_y], self.cursor_x);
+        let formatted = markdown::format_table(&self.buffer[start..=end]);
+        if formatted[..] != self.buffer[start..=end] {
+            self.buffer.splice(start..=end, formatted);
+            self.mark_file_dirty();
         }
+        let line = &self.buffer[self.cursor_y];
+        self.cursor_x = markdown::cell_start(line, cell).unwrap_or(line.len());
+    }
 
-        let mut start = x.min(line.len());
-        let mut end = x.min(line.len());
+    fn reflow_paragraph(&mut self, justify: bool) {
+        if !self.is_prose_buffer() {
+            self.status = "Reflow is for Markdown and text files".into();
+            self.dirty = true;
+            return;
+        }
+        let Some((start, end)) = prose::paragraph_bounds(&self.buffer, self.cursor_y) else {
+            self.status = "No paragraph at the cursor".into();
+            self.dirty = true;
+            return;
+        };
+        let reflowed = prose::reflow(&self.buffer[start..=end], self.config.text_width, justify);
+        if reflowed[..] == self.buffer[start..=end] {
+            return;
+        }
+        self.save_history_state();
+        let last = start + reflowed.len() - 1;
+        self.buffer.splice(start..=end, reflowed);
+        if self.is_selecting {
+            self.is_selecting = false;
+            self.selection_start = None;
+            self.selection_end = None;
+        }
+        // Land after the paragraph, ready for the next one.
+        self.cursor_y = last;
+        self.cursor_x = self.buffer[last].len();
+        self.cursor_locked = false;
+        self.mark_file_dirty();
+    }
 
-        if start >= line.len() || line[start] == ' ' || line[start] == '\t' {
-            while start > 0 && (line[start - 1] == ' ' || line[start - 1] == '\t') {
-                start -= 1;
-            }
-            if start > 0 {
-                start -= 1;
-                while start > 0 && !(line[start - 1] == ' ' || line[start - 1] == '\t') {
-                    if is_special_char(line[start - 1]) {
-                        break;
-                    }
-                    start -= 1;
-                }
-            }
-            end = start;
-            while end < line.len() && !(line[end] == ' ' || line[end] == '\t') {
-                if is_special_char(line[end]) {
-                    end += 1;
-                    break;
-                }
-                end += 1;
+    fn format_table(&mut self) {
+        let Some((start, end)) = self.table_at_cursor() else {
+            self.status = "Not inside a Markdown table".into();
+            return;
+        };
+        self.save_history_state();
+        self.reformat_table(start, end);
+    }
+
+    /// Tab inside a Markdown table: tidy it and move to the next cell,
+    /// adding a row past the last one. False when not in a table.
+    fn table_next_cell(&mut self, backwards: bool) -> bool {
+        let Some((start, end)) = self.table_at_cursor() else {
+            return false;
+        };
+        self.save_history_state();
+        self.reformat_table(start, end);
+
+        let mut y = self.cursor_y;
+        let mut cell = markdown::cell_at(&self.buffer[y], self.cursor_x);
+        let is_row = |line: &Vec<char>| !markdown::is_separator_row(line);
+        if backwards {
+            if cell > 0 {
+                cell -= 1;
+            } else if let Some(prev) = (start..y).rev().find(|&i| is_row(&self.buffer[i])) {
+                y = prev;
+                cell = markdown::cell_count(&self.buffer[y]).saturating_sub(1);
             }
+        } else if cell + 1 < markdown::cell_count(&self.buffer[y]) {
+            cell += 1;
         } else {
-            let c = line[start];
-            if is_special_char(c) {
-                end = start + 1;
-            } else {
-                while start > 0 && !(line[start - 1] == ' ' || line[start - 1] == '\t') {
-                    let prev = line[start - 1];
-                    if is_special_char(prev) {
-                        break;
-                    }
-                    start -= 1;
-                }
-                while end < line.len() && !(line[end] == ' ' || line[end] == '\t') {
-                    if is_special_char(line[end]) {
-                        end += 1;
-                        break;
-                    }
-                    end += 1;
+            y = match (y + 1..=end).find(|&i| is_row(&self.buffer[i])) {
+                Some(next) => next,
+                None => {
+                    let row = markdown::empty_row(&self.buffer[end]);
+                    self.buffer.insert(end + 1, row);
+                    self.mark_file_dirty();
+                    end + 1
                 }
-            }
+            };
+            cell = 0;
+        }
+        self.cursor_y = y;
+        self.cursor_x = markdown::cell_start(&self.buffer[y], cell).unwrap_or(0);
+        self.cursor_locked = false;
+        self.needs_full_redraw = true;
+        true
+    }
+
+    fn start_outline(&mut self) {
+        let Some(path) = self
+            .file_path
+            .clone()
+            .filter(|path| symbols::is_indexed(path))
+        else {
+            self.status = "The outline is for Markdown and code files".into();
+            self.dirty = true;
+            return;
+        };
+        // Functions and types all sit at the top level of a code outline.
+        self.outline = symbols::extract(&path, &self.buffer)
+            .into_iter()
+            .map(|symbol| markdown::Heading {
+                y: symbol.y,
+                level: match symbol.kind {
+                    symbols::Kind::Heading(level) => level,
+                    _ => 1,
+                },
+                text: symbol.name,
+            })
+            .collect();
+        if self.outline.is_empty() {
+            self.status = if self.is_markdown() {
+                "No headings in this file".into()
+            } else {
+                "No functions or types in this file".into()
+            };
+            self.dirty = true;
+            return;
         }
+        self.outline_query.clear();
+        // Start on the section the cursor is in.
+        self.outline_index = self
+            .outline
+            .iter()
+            .rposition(|heading| heading.y <= self.cursor_y)
+            .unwrap_or(0);
+        self.mode = EditorMode::Outline;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        Some((start, end))
+    fn cancel_outline(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.outline.clear();
+        self.outline_query.clear();
+        self.outline_index = 0;
+        self.needs_full_redraw = true;
+        self.dirty = true;
     }
 
-    fn select_word_at(&mut self, y: usize, x: usize) {
-        if let Some((start, end)) = self.get_word_boundaries(y, x) {
-            self.cursor_y = y;
-            self.cursor_x = start;
-            self.start_selection();
-            self.cursor_x = end;
-            self.update_selection_end();
-            self.needs_full_redraw = true;
+    /// Indexes into `outline`, in document order while the query is empty
+    /// so the nesting stays readable.
+    fn outline_matches(&self) -> Vec<usize> {
+        if self.outline_query.is_empty() {
+            return (0..self.outline.len()).collect();
         }
+        let query: String = self.outline_query.iter().collect();
+        fuzzy::rank(&query, self.outline.iter().map(|h| h.text.as_str()))
     }
 
-    fn select_line_at(&mut self, y: usize) {
-        if y < self.buffer.len() {
-            self.cursor_y = y;
-            self.cursor_x = 0;
-            self.start_selection();
-            self.cursor_x = self.buffer[y].len();
-            self.update_selection_end();
-            self.needs_full_redraw = true;
+    fn next_outline_item(&mut self) {
+        let count = self.outline_matches().len();
+        if count > 0 {
+            self.outline_index = (self.outline_index + 1) % count;
+            self.dirty = true;
         }
     }
 
-    fn handle_mouse_click(&mut self, col: u16, row: u16, rows: u16, _cols: u16, shift: bool) {
-        let tree_offset = if self.show_tree { TREE_WIDTH } else { 0 };
-        let line_num_offset = if self.show_line_numbers {
-            LINE_NUM_WIDTH
-        } else {
-            0
-        };
-        let text_offset = tree_offset + line_num_offset;
+    fn prev_outline_item(&mut self) {
+        let count = self.outline_matches().len();
+        if count > 0 {
+            self.outline_index = self.outline_index.checked_sub(1).unwrap_or(count - 1);
+            self.dirty = true;
+        }
+    }
 
-        if col < text_offset {
-            return;
+    fn confirm_outline(&mut self) {
+        let target = self
+            .outline_matches()
+            .get(self.outline_index)
+            .map(|&i| self.outline[i].y);
+        self.cancel_outline();
+        if let Some(y) = target {
+            self.jump_to_line(y);
         }
+    }
 
-        let max_lines = rows - STATUS_HEIGHT;
-        if row >= max_lines {
+    /// Lists the functions, types and headings of every file in the
+    /// project. Files unchanged since they were last seen come from the
+    /// symbol cache.
+    fn start_symbol_picker(&mut self) {
+        let root = self.project_root();
+        let cache = self.symbol_cache.get_or_insert_with(symbols::Cache::load);
+        self.project_symbols = cache.project(&root);
+        let _ = cache.save();
+        if self.project_symbols.is_empty() {
+            self.status = "No symbols found in the project".into();
+            self.dirty = true;
             return;
         }
+        self.symbol_query.clear();
+        self.symbol_index = 0;
+        self.mode = EditorMode::SymbolPicker;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        let clicked_y = self.scroll_y + row as usize;
-        if clicked_y < self.buffer.len() {
-            let clicked_x_screen = (col - text_offset) as usize;
-            let clicked_x = self.scroll_x + clicked_x_screen;
-            let clicked_pos = (clicked_y, clicked_x.min(self.buffer[clicked_y].len()));
-
-            let now = Instant::now();
-            let is_double_click = if let (Some(last_time), Some(last_pos)) =
-                (self.last_mouse_click_time, self.last_mouse_click_pos)
-            {
-                last_pos == clicked_pos
-                    && now.duration_since(last_time) < Duration::from_millis(500)
-            } else {
-                false
-            };
-
-            self.cursor_y = clicked_y;
-            if let Some(line) = self.buffer.get(clicked_y) {
-                self.cursor_x = clicked_x.min(line.len());
-            } else {
-                self.cursor_x = 0;
-            }
-
-            if is_double_click {
-                self.select_word_at(clicked_y, self.cursor_x);
-            } else if shift {
-                if !self.is_selecting {
-                    self.start_selection();
-                }
-                self.update_selection_end();
-            } else {
-                self.is_selecting = false;
-                self.selection_start = None;
-                self.selection_end = None;
-                self.mouse_dragging = true;
-                self.mouse_drag_start_pos = Some((self.cursor_y, self.cursor_x));
-            }
-
-            self.last_mouse_click_time = Some(now);
-            self.last_mouse_click_pos = Some(clicked_pos);
-            self.needs_full_redraw = true;
-        }
+    fn cancel_symbol_picker(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.project_symbols.clear();
+        self.symbol_query.clear();
+        self.symbol_index = 0;
+        self.needs_full_redraw = true;
+        self.dirty = true;
     }
 
-    fn handle_mouse_drag(&mut self, col: u16, row: u16, rows: u16, _cols: u16) {
-        let tree_offset = if self.show_tree { TREE_WIDTH } else { 0 };
-        let line_num_offset = if self.show_line_numbers {
-            LINE_NUM_WIDTH
+    /// Indexes into `project_symbols`, best match first.
+    fn symbol_matches(&self) -> Vec<usize> {
+        let mut matches = if self.symbol_query.is_empty() {
+            (0..self.project_symbols.len()).collect()
         } else {
-            0
+            let query: String = self.symbol_query.iter().collect();
+            fuzzy::rank(
+                &query,
+                self.project_symbols
+                    .iter()
+                    .map(|(_, symbol)| symbol.name.as_str()),
+            )
         };
-        let text_offset = tree_offset + line_num_offset;
+        matches.truncate(MAX_SYMBOL_RESULTS);
+        matches
+    }
 
-        if col < text_offset {
+    fn confirm_symbol_picker(&mut self) {
+        let target = self.symbol_matches().get(self.symbol_index).map(|&i| {
+            (
+                self.project_symbols[i].0.clone(),
+                self.project_symbols[i].1.y,
+            )
+        });
+        self.cancel_symbol_picker();
+        let Some((path, y)) = target else {
             return;
+        };
+        let open = self
+            .file_path
+            .as_ref()
+            .is_some_and(|current| fs::canonicalize(current).is_ok_and(|c| c == path));
+        if !open {
+            if let Err(err) = self.open_file(&path) {
+                self.status = format!("Could not open {}: {}", path.to_string_lossy(), err);
+                return;
+            }
         }
-
-        let max_lines = rows - STATUS_HEIGHT;
-        if row >= max_lines {
-            return;
+        if self.file_path.is_some() {
+            self.jump_to_line(y);
         }
+    }
 
-        if self.mouse_dragging {
-            let clicked_y = self.scroll_y + row as usize;
-            if clicked_y < self.buffer.len() {
-                let clicked_x_screen = (col - text_offset) as usize;
-                let clicked_x = self.scroll_x + clicked_x_screen;
-
-                if !self.is_selecting {
-                    if let Some(start_pos) = self.mouse_drag_start_pos {
-                        self.selection_start = Some(start_pos);
-                        self.selection_end = Some(start_pos);
-                        self.is_selecting = true;
-                    } else {
-                        self.cursor_y = clicked_y;
-                        if let Some(line) = self.buffer.get(clicked_y) {
-                            self.cursor_x = clicked_x.min(line.len());
-                        } else {
-                            self.cursor_x = 0;
-                        }
-                        self.start_selection();
-                    }
-                }
+    fn save_symbol_cache(&mut self) {
+        if let Some(cache) = self.symbol_cache.as_mut() {
+            let _ = cache.save();
+        }
+    }
 
-                self.cursor_y = clicked_y;
-                if let Some(line) = self.buffer.get(clicked_y) {
-                    self.cursor_x = clicked_x.min(line.len());
-                } else {
-                    self.cursor_x = 0;
-                }
-                self.update_selection_end();
-                self.needs_full_redraw = true;
+    /// Opens a fresh copy of the tutorial and starts watching for the
+    /// first lesson's goal.
+    fn start_tutor(&mut self) {
+        let path = match tutor::write_document() {
+            Ok(path) => path,
+            Err(err) => {
+                self.status = format!("Cannot write the tutorial: {}", err);
+                self.dirty = true;
+                return;
             }
+        };
+        // A copy still open from an earlier run would shadow the fresh one.
+        self.file_buffers.remove(&path);
+        self.dirty_files.remove(&path);
+        if let Err(err) = self.load_file(&path) {
+            self.status = format!("Cannot open the tutorial: {}", err);
+            self.dirty = true;
+            return;
         }
+        self.mode = EditorMode::Normal;
+        self.show_tree = false;
+        self.cursor_x = 0;
+        self.cursor_y = 0;
+        self.scroll_y = 0;
+        self.tutor = Some((path, 0));
+        let lesson = &tutor::LESSONS[0];
+        self.status = format!("Lesson 1, {}: {}", lesson.title, lesson.hint);
+        self.needs_full_redraw = true;
     }
 
-    fn handle_mouse_release(&mut self) {
-        self.mouse_dragging = false;
+    /// Whether a typed character would land in the buffer or the integrated
+    /// terminal, so a recording anonymizes it.
+    fn types_text(&self) -> bool {
+        if matches!(self.mode, EditorMode::Terminal) {
+            return true;
+        }
+        matches!(self.mode, EditorMode::Normal | EditorMode::Autocomplete)
+            && self.lock_warning.is_none()
+            && self.large_file_warning.is_none()
+            && self.minified_warning.is_none()
     }
 
-    fn handle_mouse_scroll(&mut self, rows: u16, up: bool) {
-        let max_lines = rows as usize - STATUS_HEIGHT as usize;
-        let max_scroll_y = self.buffer.len().saturating_sub(max_lines);
-
-        self.cursor_locked = true;
-
-        const SCROLL_STEP: usize = 3;
-
-        if up {
-            if self.scroll_y > 0 {
-                self.scroll_y = self.scroll_y.saturating_sub(SCROLL_STEP);
-                self.dirty = true;
-            }
-        } else {
-            if self.scroll_y < max_scroll_y {
-                self.scroll_y = (self.scroll_y + SCROLL_STEP).min(max_scroll_y);
-                self.dirty = true;
+    /// The lesson the tutorial is on, while its document is shown.
+    fn tutor_mark(&self) -> String {
+        match &self.tutor {
+            Some((path, lesson)) if self.file_path.as_ref() == Some(path) => {
+                format!(" [Lesson {}/{}]", lesson + 1, tutor::LESSONS.len())
             }
+            _ => String::new(),
         }
     }
 
-    fn save(&mut self) -> io::Result<()> {
-        if let Some(path) = &self.file_path {
-            let txt = self
-                .buffer
-                .iter()
-                .map(|l| l.iter().collect::<String>())
-                .collect::<Vec<_>>()
-                .join("\n");
-            fs::write(path, txt)?;
-            self.status = "Saved".into();
-            self.needs_full_redraw = true;
-            self.dirty = false;
-            self.dirty_files.remove(path);
-            self.file_buffers.insert(path.clone(), self.buffer.clone());
+    /// Moves the tutorial on past every lesson whose goal is met.
+    fn check_tutor(&mut self) {
+        let Some((path, current)) = &self.tutor else {
+            return;
+        };
+        if self.file_path.as_ref() != Some(path) {
+            return;
         }
-        Ok(())
-    }
-
-    fn ensure_cursor_visible(&mut self, rows: u16, cols: u16) {
-        let max_lines = rows as usize - STATUS_HEIGHT as usize;
-        let tree_offset = if self.show_tree { TREE_WIDTH } else { 0 };
-        let line_num_offset = if self.show_line_numbers {
-            LINE_NUM_WIDTH
-        } else {
-            0
+        let state = tutor::State {
+            lines: &self.buffer,
+            cursor_y: self.cursor_y,
+            selecting: self.selection_start.is_some() && self.selection_start != self.selection_end,
+            palette: matches!(self.mode, EditorMode::Palette),
+            modified: self.dirty_files.contains(path),
         };
-        let text_offset = tree_offset + line_num_offset;
-        let available_width = (cols - text_offset) as usize;
-
-        if self.cursor_y < self.scroll_y {
-            self.scroll_y = self.cursor_y;
-        } else if max_lines > 0 && self.cursor_y >= self.scroll_y + max_lines {
-            self.scroll_y = self.cursor_y - max_lines + 1;
+        let mut next = *current;
+        while tutor::LESSONS
+            .get(next)
+            .is_some_and(|lesson| lesson.done(next, &state))
+        {
+            next += 1;
         }
-
-        if available_width > 0 {
-            if self.cursor_x < self.scroll_x {
-                self.scroll_x = self.cursor_x;
-            } else if self.cursor_x >= self.scroll_x + available_width {
-                self.scroll_x = self.cursor_x - available_width + 1;
-            }
+        if next == *current {
+            return;
         }
+        self.status = match tutor::LESSONS.get(next) {
+            Some(lesson) => format!(
+                "Lesson {} done. Lesson {}, {}: {}",
+                next,
+                next + 1,
+                lesson.title,
+                lesson.hint
+            ),
+            None => "Tutorial complete! Ctrl+Q quits; termi --tutor starts it over".into(),
+        };
+        self.tutor = tutor::LESSONS.get(next).map(|_| (path.clone(), next));
+        self.dirty = true;
     }
 
-    fn left(&mut self) {
-        if self.cursor_x > 0 {
-            if self.is_selecting {
-                self.update_selection_end();
-            } else {
-                self.clear_selection();
-            }
-            self.cursor_x -= 1;
-            self.cursor_locked = false;
-            self.update_bracket_matching();
+    fn switch_header_source(&mut self) {
+        let Some(path) = self.file_path.clone() else {
+            return;
+        };
+        if !counterpart::is_c_family(&path) {
+            self.status = "Not a C/C++ file".into();
             self.dirty = true;
+            return;
         }
-    }
-    fn right(&mut self) {
-        if self.cursor_x < self.buffer[self.cursor_y].len() {
-            if !self.is_selecting {
-                self.clear_selection();
+        let root = lsp::find_project_root(&path);
+        match counterpart::find(&path, &root) {
+            Some(other) => {
+                if let Err(err) = self.open_file(&other) {
+                    self.status = format!("Could not open {}: {}", other.to_string_lossy(), err);
+                }
             }
-            self.cursor_x += 1;
-            if self.is_selecting {
-                self.update_selection_end();
+            None => {
+                self.status = format!(
+                    "No header/source found for {}",
+                    self.file_name.as_deref().unwrap_or_default()
+                );
             }
-            self.cursor_locked = false;
-            self.update_bracket_matching();
-            self.dirty = true;
         }
+        self.needs_full_redraw = true;
+        self.dirty = true;
     }
-    fn up(&mut self) {
-        if self.cursor_y > 0 {
-            if self.is_selecting {
-                self.update_selection_end();
-            } else {
-                self.clear_selection();
-            }
-            self.cursor_y -= 1;
-            self.cursor_x = self.cursor_x.min(self.buffer[self.cursor_y].len());
-            self.cursor_locked = false;
-            self.update_bracket_matching();
-            self.dirty = true;
-        }
+
+    fn start_health_check(&mut self) {
+        let saved = self
+            .file_path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok());
+        self.health = Some(health::check(&self.buffer, saved.as_deref()));
+        self.mode = EditorMode::Health;
+        self.needs_full_redraw = true;
+        self.dirty = true;
     }
-    fn down(&mut self) {
-        if self.cursor_y + 1 < self.buffer.len() {
-            if self.is_selecting {
-                self.update_selection_end();
-            } else {
-                self.clear_selection();
+
+    fn cancel_health_check(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.health = None;
+        self.health_index = 0;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn health_rows(&self) -> Vec<health::Row> {
+        self.health
+            .as_ref()
+            .map(health::Report::rows)
+            .unwrap_or_default()
+    }
+
+    /// Applies the fix on the selected row, then checks again so the
+    /// numbers reflect it.
+    fn apply_health_fix(&mut self) {
+        let Some(fix) = self
+            .health_rows()
+            .get(self.health_index)
+            .and_then(|row| row.fix)
+        else {
+            return;
+        };
+        if fix != health::Fix::GoToLongestLine && self.is_read_only() {
+            self.refuse_read_only_edit();
+            return;
+        }
+        match fix {
+            health::Fix::GoToLongestLine => {
+                let y = self.health.as_ref().map_or(0, |report| report.longest.0);
+                self.cancel_health_check();
+                self.jump_to_line(y);
+                return;
+            }
+            // Saving always writes `\n` line endings; lone `\r`s are inside
+            // the buffer's lines and are split there first.
+            health::Fix::LineEndings => {
+                if let Some(lines) = health::split_carriage_returns(&self.buffer) {
+                    self.save_history_state();
+                    self.buffer = lines;
+                    self.cursor_y = self.cursor_y.min(self.buffer.len() - 1);
+                    self.cursor_x = self.cursor_x.min(self.buffer[self.cursor_y].len());
+                    self.mark_file_dirty();
+                }
+                if self.save().is_ok() {
+                    self.status = "Saved with LF line endings".into();
+                }
+            }
+            health::Fix::Indentation | health::Fix::TrailingWhitespace => {
+                self.save_history_state();
+                let mut changed = 0;
+                for line in &mut self.buffer {
+                    let fixed = if fix == health::Fix::Indentation {
+                        health::expand_indent(line)
+                    } else {
+                        health::strip_trailing(line)
+                    };
+                    changed += fixed as usize;
+                }
+                self.cursor_x = self.cursor_x.min(self.buffer[self.cursor_y].len());
+                self.mark_file_dirty();
+                self.status = format!("Fixed {} lines", changed);
             }
-            self.cursor_y += 1;
-            self.cursor_x = self.cursor_x.min(self.buffer[self.cursor_y].len());
-            self.cursor_locked = false;
-            self.update_bracket_matching();
-            self.dirty = true;
         }
+        self.start_health_check();
     }
 
-    fn start_selection(&mut self) {
-        self.is_selecting = true;
-        self.selection_start = Some((self.cursor_y, self.cursor_x));
-        self.selection_end = Some((self.cursor_y, self.cursor_x));
+    fn start_local_options(&mut self) {
+        self.local_options_index = 0;
+        self.mode = EditorMode::LocalOptions;
+        self.needs_full_redraw = true;
         self.dirty = true;
     }
 
-    fn update_selection_end(&mut self) {
-        if self.is_selecting {
-            self.selection_end = Some((self.cursor_y, self.cursor_x));
-            self.dirty = true;
-        }
+    fn cancel_local_options(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.needs_full_redraw = true;
+        self.dirty = true;
     }
 
-    fn clear_selection(&mut self) {
-        if !self.is_selecting {
-            self.selection_start = None;
-            self.selection_end = None;
+    fn local_option_rows(&self) -> Vec<(String, String)> {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        let source = |local: bool| {
+            if local {
+                "this buffer".to_string()
+            } else {
+                "global".to_string()
+            }
+        };
+        let options = &self.local_options;
+        vec![
+            (
+                format!("Soft wrap: {}", on_off(self.wraps())),
+                source(options.wrap.is_some()),
+            ),
+            (
+                format!("Tab width: {}", self.indent_width()),
+                source(options.indent_width.is_some()),
+            ),
+            (
+                format!("Language: {}", self.language.name()),
+                if options.language.is_some() {
+                    "this buffer".to_string()
+                } else {
+                    "detected".to_string()
+                },
+            ),
+            (
+                format!("Read-only: {}", on_off(self.is_read_only())),
+                match options.read_only {
+                    Some(ReadOnly::Locked) => "another termi".to_string(),
+                    Some(ReadOnly::Chosen) => "this buffer".to_string(),
+                    None => "global".to_string(),
+                },
+            ),
+        ]
+    }
+
+    /// The language the file name implies; large-file mode skips
+    /// highlighting, and with it the language server and keyword
+    /// completion.
+    fn detected_language(&self) -> Language {
+        match &self.file_path {
+            Some(path) if !self.large_files.contains(path) => detect_language(path),
+            _ => Language::None,
         }
     }
 
-    fn get_selected_text(&self) -> Option<String> {
-        if let (Some(start), Some(end)) = (self.selection_start, self.selection_end) {
-            let (start_y, start_x) = start;
-            let (end_y, end_x) = end;
+    fn apply_local_language(&mut self) {
+        self.language = match &self.local_options.language {
+            Some(language) if !self.is_large_file() => language.clone(),
+            _ => self.detected_language(),
+        };
+    }
 
-            if start_y == end_y && start_x == end_x {
-                return None;
+    /// Steps the selected option to its next value. A value equal to the
+    /// global one drops the override.
+    fn change_local_option(&mut self) {
+        match self.local_options_index {
+            0 => {
+                let wrap = !self.wraps();
+                self.local_options.wrap = (wrap != self.soft_wrap).then_some(wrap);
+                self.wrap_row = 0;
+                self.scroll_x = 0;
+                self.cursor_locked = false;
             }
-
-            let (actual_start_y, actual_start_x, actual_end_y, actual_end_x) =
-                if (start_y, start_x) < (end_y, end_x) {
-                    (start_y, start_x, end_y, end_x)
-                } else {
-                    (end_y, end_x, start_y, start_x)
+            1 => {
+                let width = match self.indent_width() {
+                    2 => 4,
+                    4 => 8,
+                    _ => 2,
                 };
-
-            let mut result = String::new();
-
-            if actual_start_y == actual_end_y {
-                if let Some(line) = self.buffer.get(actual_start_y) {
-                    let selected: String = line
-                        .iter()
-                        .skip(actual_start_x)
-                        .take(actual_end_x - actual_start_x)
-                        .collect();
-                    result.push_str(&selected);
-                }
-            } else {
-                for y in actual_start_y..=actual_end_y {
-                    if let Some(line) = self.buffer.get(y) {
-                        if y == actual_start_y {
-                            let selected: String = line.iter().skip(actual_start_x).collect();
-                            result.push_str(&selected);
-                        } else if y == actual_end_y {
-                            let selected: String = line.iter().take(actual_end_x).collect();
-                            result.push_str(&selected);
-                        } else {
-                            let selected: String = line.iter().collect();
-                            result.push_str(&selected);
-                        }
-                        if y < actual_end_y {
-                            result.push('\n');
-                        }
-                    }
+                self.local_options.indent_width = (width != INDENT_WIDTH).then_some(width);
+            }
+            2 => {
+                if self.is_large_file() {
+                    self.status = "Highlighting is off in large-file mode".into();
+                    self.dirty = true;
+                    return;
                 }
+                let next = Language::ALL
+                    .iter()
+                    .position(|language| *language == self.language)
+                    .map_or(0, |i| (i + 1) % Language::ALL.len());
+                let language = Language::ALL[next].clone();
+                self.local_options.language =
+                    (language != self.detected_language()).then_some(language);
+                self.apply_local_language();
             }
+            _ => match self.local_options.read_only {
+                Some(ReadOnly::Locked) => self.edit_anyway(),
+                Some(ReadOnly::Chosen) => self.local_options.read_only = None,
+                None => self.local_options.read_only = Some(ReadOnly::Chosen),
+            },
+        }
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-            if result.is_empty() {
-                None
-            } else {
-                Some(result)
+    fn reset_local_option(&mut self) {
+        match self.local_options_index {
+            0 => self.local_options.wrap = None,
+            1 => self.local_options.indent_width = None,
+            2 => {
+                self.local_options.language = None;
+                self.apply_local_language();
+            }
+            // A lock held elsewhere is not an override to drop.
+            _ => {
+                if self.local_options.read_only == Some(ReadOnly::Chosen) {
+                    self.local_options.read_only = None;
+                }
             }
-        } else {
-            None
         }
+        self.needs_full_redraw = true;
+        self.dirty = true;
     }
 
-    fn select_all(&mut self) {
-        if self.buffer.is_empty() {
-            return;
+    /// Status bar marks for the options this buffer overrides; read-only
+    /// shows as `[RO]` with the rest.
+    fn local_option_marks(&self) -> String {
+        let options = &self.local_options;
+        let mut marks = String::new();
+        if let Some(wrap) = options.wrap {
+            marks.push_str(if wrap { " [wrap]" } else { " [nowrap]" });
+        }
+        if let Some(width) = options.indent_width {
+            marks.push_str(&format!(" [tab {}]", width));
+        }
+        if let Some(language) = &options.language {
+            marks.push_str(&format!(" [{}]", language.name()));
         }
+        marks
+    }
 
-        self.selection_start = Some((0, 0));
+    fn jump_to_line(&mut self, y: usize) {
+        if self.is_selecting {
+            self.is_selecting = false;
+            self.selection_start = None;
+            self.selection_end = None;
+        }
+        self.cursor_y = y.min(self.buffer.len().saturating_sub(1));
+        self.cursor_x = 0;
+        self.cursor_locked = false;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        let last_line = self.buffer.len() - 1;
-        let last_col = self.buffer[last_line].len();
-        self.selection_end = Some((last_line, last_col));
+    fn start_quick_jump(&mut self) {
+        self.jump_char = None;
+        self.jump_targets.clear();
+        self.jump_input.clear();
+        self.mode = EditorMode::QuickJump;
+        self.dirty = true;
+    }
 
-        self.cursor_y = last_line;
-        self.cursor_x = last_col;
-        self.is_selecting = true;
+    fn cancel_quick_jump(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.jump_char = None;
+        self.jump_targets.clear();
+        self.jump_input.clear();
         self.needs_full_redraw = true;
         self.dirty = true;
     }
 
-    fn copy_selection(&mut self) {
-        if let Some(text) = self.get_selected_text() {
-            self.clipboard = Some(text.clone());
-            if let Ok(mut clipboard) = arboard::Clipboard::new() {
-                let _ = clipboard.set_text(&text);
+    /// Labels every visible occurrence of `c`, nearest to the cursor first.
+    /// A lowercase letter also matches its uppercase form.
+    fn label_jump_targets(&mut self, c: char) {
+        let matches = |other: char| {
+            other == c || (c.is_lowercase() && other.to_lowercase().eq(c.to_lowercase()))
+        };
+        let width = self.text_area_width();
+        let max_lines = self.text_rows(self.screen_size.1) as usize;
+        let mut targets = Vec::new();
+        for row in 0..max_lines {
+            let (y, row_start) = self.text_position(row, 0);
+            let Some(line) = self.buffer.get(y) else {
+                break;
+            };
+            let end = (row_start + width).min(line.len());
+            for (x, &other) in line.iter().enumerate().take(end).skip(row_start) {
+                if matches(other) && (y, x) != (self.cursor_y, self.cursor_x) {
+                    targets.push(((y, x), ((x - row_start) as u16, row as u16)));
+                }
             }
-            self.status = "Copied".into();
+        }
+        let cursor_row = self.cursor_screen_pos(max_lines).1 as isize;
+        targets.sort_by_key(|&((y, x), (_, row))| {
+            (
+                (row as isize - cursor_row).abs(),
+                y.abs_diff(self.cursor_y),
+                x.abs_diff(self.cursor_x),
+            )
+        });
+        let labels = jump_labels(targets.len());
+        self.jump_targets = targets
+            .into_iter()
+            .zip(labels)
+            .map(|((pos, screen), label)| JumpTarget { pos, screen, label })
+            .collect();
+    }
+
+    fn quick_jump_key(&mut self, c: char) {
+        if self.jump_char.is_none() {
+            self.jump_char = Some(c);
+            self.label_jump_targets(c);
+            match self.jump_targets.len() {
+                0 => {
+                    self.cancel_quick_jump();
+                    self.status = format!("No '{}' on screen", c);
+                }
+                1 => {
+                    let pos = self.jump_targets[0].pos;
+                    self.finish_quick_jump(pos);
+                }
+                _ => self.needs_full_redraw = true,
+            }
+            return;
+        }
+        self.jump_input.push(c);
+        let input = &self.jump_input;
+        if let Some(target) = self.jump_targets.iter().find(|t| &t.label == input) {
+            let pos = target.pos;
+            self.finish_quick_jump(pos);
+        } else if self
+            .jump_targets
+            .iter()
+            .any(|t| t.label.starts_with(input.as_str()))
+        {
+            self.needs_full_redraw = true;
             self.dirty = true;
+        } else {
+            self.cancel_quick_jump();
         }
     }
 
-    fn paste(&mut self) {
-        let clipboard_text = if let Some(ref internal_text) = self.clipboard {
-            Some(internal_text.clone())
+    fn finish_quick_jump(&mut self, (y, x): (usize, usize)) {
+        self.cancel_quick_jump();
+        if self.is_selecting {
+            self.is_selecting = false;
+            self.selection_start = None;
+            self.selection_end = None;
+        }
+        self.cursor_y = y;
+        self.cursor_x = x;
+        self.cursor_locked = false;
+        self.update_bracket_matching();
+    }
+
+    fn jump_to_heading(&mut self, forward: bool) {
+        let headings = markdown::headings(&self.buffer);
+        let target = if forward {
+            headings.iter().find(|h| h.y > self.cursor_y)
         } else {
-            if let Ok(mut clipboard) = arboard::Clipboard::new() {
-                clipboard.get_text().ok()
-            } else {
-                None
+            headings.iter().rev().find(|h| h.y < self.cursor_y)
+        };
+        match target {
+            Some(heading) => self.jump_to_line(heading.y),
+            None => {
+                self.status = if forward {
+                    "No heading below".into()
+                } else {
+                    "No heading above".into()
+                };
+                self.dirty = true;
             }
+        }
+    }
+
+    // Moves to the first or last line of the indented block around the
+    // cursor. From there, a second press steps out: up to the line that
+    // opens the block, or down past its end.
+    fn jump_to_block_edge(&mut self, forward: bool) {
+        let (start, end) = focus::indent_block(&self.buffer, self.cursor_y);
+        let has_text = |y: &usize| self.buffer[*y].iter().any(|c| !c.is_whitespace());
+        let target = if forward && self.cursor_y < end {
+            Some(end)
+        } else if forward {
+            (end + 1..self.buffer.len()).find(has_text)
+        } else if self.cursor_y > start {
+            Some(start)
+        } else {
+            (0..start).rev().find(has_text)
+        };
+        let Some(y) = target else {
+            self.status = if forward {
+                "End of file".into()
+            } else {
+                "Start of file".into()
+            };
+            self.dirty = true;
+            return;
         };
+        self.jump_to_line(y);
+        self.cursor_x = self.buffer[y]
+            .iter()
+            .take_while(|c| c.is_whitespace())
+            .count();
+        self.update_bracket_matching();
+    }
 
-        if let Some(clipboard_text) = clipboard_text {
-            self.save_history_state();
-            self.clear_selection();
+    fn jump_to_function(&mut self, forward: bool) {
+        let is_function = |y: &usize| focus::function_name(&self.buffer[*y]).is_some();
+        let target = if forward {
+            (self.cursor_y + 1..self.buffer.len()).find(is_function)
+        } else {
+            (0..self.cursor_y).rev().find(is_function)
+        };
+        let Some(y) = target else {
+            self.status = if forward {
+                "No function below".into()
+            } else {
+                "No function above".into()
+            };
+            self.dirty = true;
+            return;
+        };
+        self.jump_to_line(y);
+        self.cursor_x = self.buffer[y]
+            .iter()
+            .take_while(|c| c.is_whitespace())
+            .count();
+        self.update_bracket_matching();
+    }
 
-            let normalized_text = clipboard_text.replace("\r\n", "\n").replace('\r', "\n");
+    /// " | in name()" for the innermost function around the cursor, looked
+    /// for within a few thousand lines above it.
+    fn function_context(&self) -> String {
+        const SEARCH_LINES: usize = 5_000;
+        if self.is_prose_buffer() || self.is_large_file() {
+            return String::new();
+        }
+        let cursor_y = self.cursor_y.min(self.buffer.len() - 1);
+        (cursor_y.saturating_sub(SEARCH_LINES)..=cursor_y)
+            .rev()
+            .filter_map(|y| Some((y, focus::function_name(&self.buffer[y])?)))
+            .find(|&(y, _)| focus::code_block(&self.buffer, y).1 >= cursor_y)
+            .map(|(_, name)| format!(" | in {}()", name))
+            .unwrap_or_default()
+    }
 
-            let lines: Vec<&str> = normalized_text.split('\n').collect();
+    fn text_rows(&self, rows: u16) -> u16 {
+        let panel_height = if self.show_output_panel && self.output_panel.is_some() {
+            OUTPUT_PANEL_HEIGHT.min(rows.saturating_sub(STATUS_HEIGHT) / 2)
+        } else {
+            0
+        };
+        rows.saturating_sub(STATUS_HEIGHT + panel_height)
+    }
 
-            if lines.len() == 1 {
-                let chars: Vec<char> = lines[0].chars().collect();
-                for &c in &chars {
-                    self.buffer[self.cursor_y].insert(self.cursor_x, c);
-                    self.cursor_x += 1;
-                }
-            } else {
-                let rest = self.buffer[self.cursor_y].split_off(self.cursor_x);
+    fn tree_up(&mut self) {
+        if self.tree_cursor > 0 {
+            self.tree_cursor -= 1;
+            if self.tree_cursor < self.tree_scroll {
+                self.tree_scroll = self.tree_cursor;
+            }
+            self.dirty = true;
+        }
+    }
 
-                let first_chars: Vec<char> = lines[0].chars().collect();
-                for &c in &first_chars {
-                    self.buffer[self.cursor_y].push(c);
-                    self.cursor_x += 1;
-                }
+    fn tree_down(&mut self) {
+        if self.tree_cursor + 1 < self.tree.len() {
+            self.tree_cursor += 1;
+            let (_, rows) = self.screen_size;
+            let max_tree_lines = self.text_rows(rows) as usize;
+            if self.tree_cursor >= self.tree_scroll + max_tree_lines {
+                self.tree_scroll = self.tree_cursor - max_tree_lines + 1;
+            }
+            self.dirty = true;
+        }
+    }
 
-                for line in lines.iter().skip(1).take(lines.len() - 1) {
-                    let line_chars: Vec<char> = line.chars().collect();
-                    self.buffer.insert(self.cursor_y + 1, line_chars);
-                    self.cursor_y += 1;
-                    self.cursor_x = self.buffer[self.cursor_y].len();
-                }
+    fn load_root(&mut self, dir: &str) {
+        self.tree.clear();
+        self.load_dir(PathBuf::from(dir), 0);
+        self.tree_scroll = 0;
+        self.tree_cursor = 0;
+        self.needs_full_redraw = true;
+    }
 
-                if let Some(last_line) = lines.last() {
-                    let mut new_last_line: Vec<char> = last_line.chars().collect();
-                    new_last_line.extend(rest);
-                    self.buffer.insert(self.cursor_y + 1, new_last_line);
-                    self.cursor_y += 1;
-                    self.cursor_x = lines.last().unwrap().chars().count();
-                }
+    fn load_dir(&mut self, path: PathBuf, depth: usize) {
+        if let Ok(entries) = fs::read_dir(path) {
+            for e in entries.flatten() {
+                let meta = e.metadata().unwrap();
+                self.tree.push(FileNode {
+                    name: e.file_name().to_string_lossy().into(),
+                    path: e.path(),
+                    is_dir: meta.is_dir(),
+                    expanded: false,
+                    depth,
+                });
             }
-
-            self.mark_file_dirty();
         }
     }
 
-    fn save_history_state(&mut self) {
-        self.history.truncate(self.history_index + 1);
+    fn toggle_dir(&mut self, idx: usize) {
+        if !self.tree[idx].is_dir {
+            return;
+        }
 
-        let snapshot = self.buffer.iter().map(|line| line.clone()).collect();
-        self.history.push(snapshot);
-        self.history_index += 1;
+        if self.tree[idx].expanded {
+            let d = self.tree[idx].depth;
+            self.tree[idx].expanded = false;
+            let start_idx = idx + 1;
+            while start_idx < self.tree.len() && self.tree[start_idx].depth > d {
+                self.tree.remove(start_idx);
+            }
+            self.needs_full_redraw = true;
+        } else {
+            self.tree[idx].expanded = true;
+            let path = self.tree[idx].path.clone();
+            let depth = self.tree[idx].depth + 1;
+            let mut insert = idx + 1;
 
-        if self.history.len() > self.history_limit {
-            self.history.remove(0);
-            self.history_index -= 1;
+            if let Ok(entries) = fs::read_dir(path) {
+                for e in entries.flatten() {
+                    let meta = e.metadata().unwrap();
+                    self.tree.insert(
+                        insert,
+                        FileNode {
+                            name: e.file_name().to_string_lossy().into(),
+                            path: e.path(),
+                            is_dir: meta.is_dir(),
+                            expanded: false,
+                            depth,
+                        },
+                    );
+                    insert += 1;
+                }
+            }
+            self.needs_full_redraw = true;
         }
     }
 
-    fn undo(&mut self) {
-        if self.history_index > 0 {
-            self.history_index -= 1;
-            if let Some(old_state) = self.history.get(self.history_index) {
-                self.buffer = old_state.iter().map(|line| line.clone()).collect();
-                if self.cursor_y >= self.buffer.len() {
-                    self.cursor_y = self.buffer.len().saturating_sub(1);
-                }
-                if let Some(line) = self.buffer.get(self.cursor_y) {
-                    self.cursor_x = self.cursor_x.min(line.len());
-                }
+    /// Opens a file, unless it is over the large-file threshold: then the
+    /// user is asked first and `load_file` runs once they choose.
+    fn open_file(&mut self, path: &PathBuf) -> io::Result<()> {
+        if !self.file_buffers.contains_key(path) {
+            let size = fs::metadata(path)?.len();
+            if size > self.config.large_file_size {
+                self.large_file_warning = Some((path.clone(), size));
                 self.needs_full_redraw = true;
-                self.dirty = true;
+                return Ok(());
             }
         }
+        self.load_file(path)
     }
 
-    fn redo(&mut self) {
-        if self.history_index + 1 < self.history.len() {
-            self.history_index += 1;
-            if let Some(new_state) = self.history.get(self.history_index) {
-                self.buffer = new_state.iter().map(|line| line.clone()).collect();
-                if self.cursor_y >= self.buffer.len() {
-                    self.cursor_y = self.buffer.len().saturating_sub(1);
+    fn load_file(&mut self, path: &PathBuf) -> io::Result<()> {
+        self.remember_position();
+        if let Some(old_path) = &self.file_path {
+            self.file_buffers
+                .insert(old_path.clone(), self.buffer.clone());
+            self.buffer_options
+                .insert(old_path.clone(), std::mem::take(&mut self.local_options));
+        }
+        self.local_options = self.buffer_options.remove(path).unwrap_or_default();
+
+        if let Some(cached_buffer) = self.file_buffers.get(path) {
+            // A cached buffer keeps its unsaved edits, and its dirty mark.
+            self.buffer = cached_buffer.clone();
+        } else {
+            let mut s = String::new();
+            fs::File::open(path)?.read_to_string(&mut s)?;
+            let s = match s.strip_prefix(BOM) {
+                Some(rest) => {
+                    self.bom_files.insert(path.clone());
+                    rest
                 }
-                if let Some(line) = self.buffer.get(self.cursor_y) {
-                    self.cursor_x = self.cursor_x.min(line.len());
+                None => {
+                    self.bom_files.remove(path);
+                    &s
                 }
-                self.needs_full_redraw = true;
-                self.dirty = true;
+            };
+            self.buffer = s.lines().map(|l| l.chars().collect()).collect();
+            if self.buffer.is_empty() {
+                self.buffer.push(vec![]);
             }
+            self.file_buffers.insert(path.clone(), self.buffer.clone());
+            self.dirty_files.remove(path);
+            self.lock_file(path);
+            self.minified_warning = minified::longest_line(&self.buffer);
         }
-    }
 
-    fn start_search(&mut self) {
-        self.mode = EditorMode::Search;
-        self.search_query.clear();
-        self.search_results.clear();
-        self.current_search_index = 0;
-        self.status = "Search: ".into();
+        self.file_path = Some(path.clone());
+        self.file_name = Some(path.file_name().unwrap().to_string_lossy().into());
+        self.buffer_order.retain(|open| open != path);
+        self.buffer_order.insert(0, path.clone());
+        self.apply_local_language();
+        self.cursor_x = 0;
+        self.cursor_y = 0;
+        self.scroll_y = 0;
+        self.scroll_target = None;
+        self.scroll_x = 0;
+        self.wrap_row = 0;
+        if self.config.restore_cursor {
+            self.restore_position(path);
+        }
         self.needs_full_redraw = true;
-        self.dirty = true;
+        self.dirty = false;
+        self.update_bracket_matching();
+        self.save_history_state();
+        self.record_recent_file(path);
+        self.update_discord_presence();
+        self.lsp_open_current();
+        self.refresh_git_branch();
+        Ok(())
     }
 
-    fn cancel_search(&mut self) {
-        self.mode = EditorMode::Normal;
-        self.search_query.clear();
-        self.search_results.clear();
-        self.status =
-            "Ctrl+O Tree | Ctrl+S Save | Ctrl+F Find | Ctrl+Z Undo | Ctrl+Y Redo | Ctrl+Q Quit"
-                .into();
-        self.needs_full_redraw = true;
-        self.dirty = true;
+    fn current_position(&self) -> positions::Position {
+        positions::Position {
+            y: self.cursor_y,
+            x: self.cursor_x,
+            scroll_y: self.scroll_y,
+        }
     }
 
-    fn start_create_file(&mut self) {
-        if !self.show_tree || self.tree.is_empty() {
+    fn remember_position(&self) {
+        let Some(path) = &self.file_path else {
             return;
+        };
+        if self.config.restore_cursor {
+            let _ = positions::remember(path, self.current_position());
         }
+    }
 
-        let selected_node = &self.tree[self.tree_cursor];
-        let parent_path = if selected_node.is_dir {
-            selected_node.path.clone()
-        } else {
-            selected_node
-                .path
-                .parent()
-                .unwrap_or(&PathBuf::from("."))
-                .to_path_buf()
-        };
+    fn restore_position(&mut self, path: &Path) {
+        if let Some(position) = positions::load(path) {
+            self.apply_position(position);
+        }
+    }
 
-        self.mode = EditorMode::CreateFile;
-        self.create_name.clear();
-        self.create_parent_path = Some(parent_path);
-        self.status = "New file name: ".into();
-        self.needs_full_redraw = true;
-        self.dirty = true;
+    // The file may have changed since, so the position is clamped to it.
+    fn apply_position(&mut self, position: positions::Position) {
+        self.cursor_y = position.y.min(self.buffer.len().saturating_sub(1));
+        self.cursor_x = position.x.min(self.buffer[self.cursor_y].len());
+        self.scroll_y = position.scroll_y.min(self.cursor_y);
     }
 
-    fn start_create_dir(&mut self) {
-        if !self.show_tree || self.tree.is_empty() {
+    /// Drops the current file's buffer and shows the one used before it.
+    /// Files with unsaved changes have to be saved first, and pinned ones
+    /// unpinned.
+    fn close_buffer(&mut self) {
+        let Some(path) = self.file_path.clone() else {
+            self.status = "No file to close".into();
+            self.dirty = true;
+            return;
+        };
+        if self.pinned_files.contains(&path) {
+            self.status = "Pinned - unpin it (Buffer: Toggle Pin) to close".into();
+            self.dirty = true;
+            return;
+        }
+        if self.dirty_files.contains(&path) {
+            self.status = "Unsaved changes - save (Ctrl+S) before closing".into();
+            self.dirty = true;
             return;
         }
 
-        let selected_node = &self.tree[self.tree_cursor];
-        let parent_path = if selected_node.is_dir {
-            selected_node.path.clone()
-        } else {
-            selected_node
-                .path
-                .parent()
-                .unwrap_or(&PathBuf::from("."))
-                .to_path_buf()
-        };
+        self.remember_position();
+        self.forget_buffer(&path, self.current_position());
+        self.show_next_buffer();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        self.status = format!("Closed {} (Ctrl+Shift+T reopens it)", name);
+    }
 
-        self.mode = EditorMode::CreateDir;
-        self.create_name.clear();
-        self.create_parent_path = Some(parent_path);
-        self.status = "New folder name: ".into();
+    /// Closes every buffer without unsaved changes that is not pinned; with
+    /// `keep_current`, the current one stays open too.
+    fn close_all_buffers(&mut self, keep_current: bool) {
+        let current = self.file_path.clone();
+        let (mut closed, mut kept) = (0, 0);
+        // Least recent first, so Ctrl+Shift+T brings back the most recent.
+        for path in self.buffer_order.clone().iter().rev() {
+            if keep_current && current.as_ref() == Some(path) {
+                continue;
+            }
+            if self.pinned_files.contains(path) || self.dirty_files.contains(path) {
+                kept += 1;
+                continue;
+            }
+            let position = if current.as_ref() == Some(path) {
+                self.remember_position();
+                self.current_position()
+            } else {
+                positions::load(path).unwrap_or(positions::Position {
+                    y: 0,
+                    x: 0,
+                    scroll_y: 0,
+                })
+            };
+            self.forget_buffer(path, position);
+            closed += 1;
+        }
+        if current.is_some() && self.file_path.is_none() {
+            self.show_next_buffer();
+        }
+        self.status = format!(
+            "Closed {} file{}",
+            closed,
+            if closed == 1 { "" } else { "s" }
+        );
+        if kept > 0 {
+            self.status
+                .push_str(&format!(", kept {} pinned or unsaved", kept));
+        }
         self.needs_full_redraw = true;
         self.dirty = true;
     }
 
-    fn cancel_create(&mut self) {
-        self.mode = EditorMode::Normal;
-        self.create_name.clear();
-        self.create_parent_path = None;
-        self.status =
-            "Ctrl+O Tree | Ctrl+S Save | Ctrl+F Find | Ctrl+Z Undo | Ctrl+Y Redo | Ctrl+Q Quit"
-                .into();
+    /// Drops everything held for a file and adds it to the files
+    /// Ctrl+Shift+T can reopen.
+    fn forget_buffer(&mut self, path: &Path, position: positions::Position) {
+        self.closed_buffers.retain(|(closed, _)| closed != path);
+        self.closed_buffers.push((path.to_path_buf(), position));
+        if self.closed_buffers.len() > MAX_CLOSED_BUFFERS {
+            self.closed_buffers.remove(0);
+        }
+
+        self.file_buffers.remove(path);
+        self.buffer_order.retain(|open| open != path);
+        self.instance_waiters
+            .retain(|(waiting, _)| !same_file(waiting, path));
+        self.buffer_options.remove(path);
+        self.file_locks.remove(path);
+        self.large_files.remove(path);
+        if self.file_path.as_deref() == Some(path) {
+            // Nothing of the closed file should be stashed on the way out.
+            self.file_path = None;
+            self.local_options = LocalOptions::default();
+        }
+    }
+
+    fn show_next_buffer(&mut self) {
+        match self.buffer_order.first().cloned() {
+            Some(next) if self.load_file(&next).is_ok() => {}
+            _ => self.new_empty_buffer(),
+        }
         self.needs_full_redraw = true;
         self.dirty = true;
     }
 
-    fn create_file_or_dir(&mut self) -> io::Result<()> {
-        if self.create_name.is_empty() {
-            return Ok(());
-        }
+    fn toggle_pin(&mut self) {
+        let Some(path) = self.file_path.clone() else {
+            self.status = "Only files can be pinned".into();
+            self.dirty = true;
+            return;
+        };
+        self.status = if self.pinned_files.remove(&path) {
+            "Unpinned".into()
+        } else {
+            self.pinned_files.insert(path);
+            "Pinned: closing all buffers keeps this one".into()
+        };
+        self.dirty = true;
+    }
 
-        let name: String = self.create_name.iter().collect();
-        let parent_path = self.create_parent_path.clone();
-        if let Some(parent) = parent_path {
-            let new_path = parent.join(&name);
+    fn is_pinned(&self) -> bool {
+        self.file_path
+            .as_ref()
+            .is_some_and(|path| self.pinned_files.contains(path))
+    }
 
-            match self.mode {
-                EditorMode::CreateFile => {
-                    fs::File::create(&new_path)?;
-                    let _ = self.open_file(&new_path);
-                }
-                EditorMode::CreateDir => {
-                    fs::create_dir(&new_path)?;
-                }
-                _ => {}
-            }
-
-            if parent.to_string_lossy() == "." {
-                self.load_root(".");
-            } else {
-                self.reload_tree_at_parent(&parent);
-            }
-            self.needs_full_redraw = true;
+    /// Opens the most recently closed file again, with the cursor where it
+    /// was.
+    fn reopen_closed_buffer(&mut self) {
+        let Some((path, position)) = self.closed_buffers.pop() else {
+            self.status = "No closed files to reopen".into();
+            self.dirty = true;
+            return;
+        };
+        if let Err(err) = self.open_file(&path) {
+            self.status = format!("Could not reopen {}: {}", path.to_string_lossy(), err);
+        } else if self.file_path.as_ref() == Some(&path) {
+            self.apply_position(position);
+            self.cursor_locked = false;
+            self.status = format!(
+                "Reopened {}",
+                path.file_name().unwrap_or_default().to_string_lossy()
+            );
         }
-
-        self.cancel_create();
-        Ok(())
+        self.needs_full_redraw = true;
+        self.dirty = true;
     }
 
-    fn reload_tree_at_parent(&mut self, parent: &std::path::Path) {
-        for (i, node) in self.tree.iter().enumerate() {
-            if node.path == *parent && node.is_dir {
-                if node.expanded {
-                    let depth = node.depth;
-                    let remove_start = i + 1;
-                    while remove_start < self.tree.len() && self.tree[remove_start].depth > depth {
-                        self.tree.remove(remove_start);
-                    }
-
-                    if let Ok(entries) = fs::read_dir(parent) {
-                        let mut insert_pos = i + 1;
-                        for e in entries.flatten() {
-                            let meta = e.metadata().unwrap();
-                            self.tree.insert(
-                                insert_pos,
-                                FileNode {
-                                    name: e.file_name().to_string_lossy().into(),
-                                    path: e.path(),
-                                    is_dir: meta.is_dir(),
-                                    expanded: false,
-                                    depth: depth + 1,
-                                },
-                            );
-                            insert_pos += 1;
-                        }
-                    }
-                } else {
-                    self.toggle_dir(i);
-                }
-                break;
+    /// Takes the edit lock for a file loaded from disk. If another instance
+    /// holds it, the file stays read-only unless the user chooses otherwise.
+    fn lock_file(&mut self, path: &Path) {
+        if self.file_locks.contains_key(path)
+            || self.local_options.read_only == Some(ReadOnly::Locked)
+        {
+            return;
+        }
+        match file_lock::acquire(path) {
+            Ok(file_lock::Acquired::Locked(lock)) => {
+                self.file_locks.insert(path.to_path_buf(), lock);
             }
+            Ok(file_lock::Acquired::HeldElsewhere(pid)) => {
+                self.local_options.read_only = Some(ReadOnly::Locked);
+                self.lock_warning = Some((path.to_path_buf(), pid));
+            }
+            // Without the cache directory there is nothing to coordinate
+            // through; edit as before.
+            Err(_) => {}
         }
-        self.dirty = true;
     }
 
-    fn start_delete(&mut self) {
-        if !self.show_tree || self.tree.is_empty() {
-            return;
+    fn open_large_file(&mut self, path: &PathBuf, large_mode: bool) {
+        if large_mode {
+            self.large_files.insert(path.clone());
+        }
+        if let Err(err) = self.load_file(path) {
+            self.status = format!("Could not open {}: {}", path.to_string_lossy(), err);
         }
-
-        let selected_node = &self.tree[self.tree_cursor];
-        self.delete_target = Some(selected_node.path.clone());
-        self.mode = EditorMode::DeleteConfirm;
-        let item_type = if selected_node.is_dir {
-            "folder"
-        } else {
-            "file"
-        };
-        self.status = format!("Delete {}? (Y/N)", item_type);
         self.needs_full_redraw = true;
-        self.dirty = true;
     }
 
-    fn confirm_delete(&mut self) -> io::Result<()> {
-        let target = if let Some(t) = &self.delete_target {
-            Some(t.clone())
-        } else {
-            None
-        };
+    fn is_large_file(&self) -> bool {
+        self.file_path
+            .as_ref()
+            .is_some_and(|path| self.large_files.contains(path))
+    }
 
-        if let Some(target) = target {
-            let is_dir = target.is_dir();
-            let parent = target
-                .parent()
-                .map(|p| p.to_path_buf())
-                .unwrap_or_else(|| PathBuf::from("."));
+    fn has_bom(&self) -> bool {
+        self.file_path
+            .as_ref()
+            .is_some_and(|path| self.bom_files.contains(path))
+    }
 
-            if is_dir {
-                fs::remove_dir_all(&target)?;
-            } else {
-                fs::remove_file(&target)?;
-            }
+    fn is_read_only(&self) -> bool {
+        self.local_options.read_only.is_some()
+    }
 
-            if let Some(current_path) = &self.file_path {
-                if current_path == &target {
-                    self.buffer = vec![vec![]];
-                    self.file_path = None;
-                    self.file_name = None;
-                    self.language = Language::None;
-                }
+    /// Says why the buffer refused an edit; edit keys and commands check
+    /// `is_read_only` before they change anything.
+    fn refuse_read_only_edit(&mut self) {
+        self.status = match self.local_options.read_only {
+            Some(ReadOnly::Locked) => {
+                "Read-only: another termi is editing this file (File: Edit Anyway to override)"
             }
+            _ => "Read-only buffer (Buffer: Local Options to change)",
+        }
+        .into();
+        self.needs_full_redraw = true;
+    }
 
-            if parent.to_string_lossy() == "." {
-                self.load_root(".");
-            } else {
-                self.reload_tree_at_parent(&parent);
-                for (i, node) in self.tree.iter().enumerate() {
-                    if node.path == parent {
-                        self.tree_cursor = i;
-                        break;
-                    }
-                }
-            }
+    fn edit_anyway(&mut self) {
+        if self.local_options.read_only == Some(ReadOnly::Locked) {
+            self.local_options.read_only = None;
+            self.status =
+                "Editing anyway: saving may overwrite the other instance's changes".into();
+        } else {
+            self.status = "This file is not read-only".into();
         }
+        self.needs_full_redraw = true;
+    }
 
-        self.cancel_delete();
-        Ok(())
+    fn has_unsaved_changes(&self) -> bool {
+        !self.dirty_files.is_empty()
+            || (self.file_path.is_none() && self.buffer.iter().any(|line| !line.is_empty()))
     }
 
-    fn cancel_delete(&mut self) {
-        self.mode = EditorMode::Normal;
-        self.delete_target = None;
-        self.status =
-            "Ctrl+O Tree | Ctrl+S Save | Ctrl+F Find | Ctrl+Z Undo | Ctrl+Y Redo | Ctrl+Q Quit"
-                .into();
+    /// Quits, or asks first as `confirm_quit` demands; a second request
+    /// while asking goes through.
+    fn request_quit(&mut self) {
+        let confirm = match self.config.confirm_quit {
+            QuitConfirm::Always => true,
+            QuitConfirm::Unsaved => self.has_unsaved_changes(),
+            QuitConfirm::Never => false,
+        };
+        if self.quit_confirm || !confirm {
+            self.should_quit = true;
+            return;
+        }
+        self.quit_confirm = true;
+        self.status = if self.has_unsaved_changes() {
+            "File not saved! Press Ctrl+Q again to quit, any other key to cancel".into()
+        } else {
+            "Press Ctrl+Q again to quit, any other key to cancel".into()
+        };
         self.needs_full_redraw = true;
-        self.dirty = true;
     }
 
-    fn start_rename(&mut self) {
-        if !self.show_tree || self.tree.is_empty() {
+    fn save_and_quit(&mut self) {
+        if self.file_path.is_none() {
+            self.status = "Nothing to save: this buffer has no file".into();
+            return;
+        }
+        if let Err(err) = self.save() {
+            self.status = format!("Save failed: {}", err);
             return;
         }
+        // Other files may still hold edits; those go through the usual check.
+        if self.has_unsaved_changes() {
+            self.request_quit();
+        } else {
+            self.should_quit = true;
+        }
+    }
 
-        let selected_node = &self.tree[self.tree_cursor];
-        self.rename_target = Some(selected_node.path.clone());
-        self.rename_name = selected_node.name.chars().collect();
-        self.mode = EditorMode::Rename;
-        self.status = "Rename: ".into();
-        self.needs_full_redraw = true;
-        self.dirty = true;
+    fn request_detach(&mut self) {
+        if self.serving_session {
+            self.detach_requested = true;
+        } else {
+            self.status = "Not a session: start one with `termi --server`".into();
+        }
     }
 
-    fn cancel_rename(&mut self) {
-        self.mode = EditorMode::Normal;
-        self.rename_target = None;
-        self.rename_name.clear();
-        self.status =
-            "Ctrl+O Tree | Ctrl+S Save | Ctrl+F Find | Ctrl+Z Undo | Ctrl+Y Redo | Ctrl+Q Quit"
-                .into();
-        self.needs_full_redraw = true;
+    fn mark_file_dirty(&mut self) {
         self.dirty = true;
+        self.needs_full_redraw = true;
+        self.lsp_needs_sync = true;
+        self.buffer_edits += 1;
+        if let Some(path) = &self.file_path {
+            self.dirty_files.insert(path.clone());
+        }
     }
 
-    fn confirm_rename(&mut self) -> io::Result<()> {
-        if self.rename_name.is_empty() {
-            return Ok(());
+    fn get_word_boundaries(&self, y: usize, x: usize) -> Option<(usize, usize)> {
+        if y >= self.buffer.len() {
+            return None;
+        }
+        let line = &self.buffer[y];
+        if x > line.len() {
+            return None;
         }
 
-        let target = if let Some(t) = &self.rename_target {
-            Some(t.clone())
-        } else {
-            None
+        let is_special_char = |c: char| -> bool {
+            matches!(
+                c,
+                '.' | ','
+                    | '['
+                    | ']'
+                    | '{'
+                    | '}'
+                    | '$'
+                    | '('
+                    | ')'
+                    | ';'
+                    | ':'
+                    | '!'
+                    | '?'
+                    | '@'
+                    | '#'
+                    | '%'
+                    | '^'
+                    | '&'
+                    | '*'
+                    | '+'
+                    | '-'
+                    | '='
+                    | '/'
+                    | '\\'
+                    | '|'
+                    | '<'
+                    | '>'
+                    | '`'
+                    | '\''
+                    | '"'
+            )
         };
 
-        let new_name: String = self.rename_name.iter().collect();
-        if let Some(target) = target {
-            let parent = target
-                .parent()
-                .map(|p| p.to_path_buf())
-                .unwrap_or_else(|| PathBuf::from("."));
-            let new_path = parent.join(&new_name);
+        if line.is_empty() {
+            return Some((0, 0));
+        }
 
-            fs::rename(&target, &new_path)?;
+        let mut start = x.min(line.len());
+        let mut end = x.min(line.len());
 
-            if let Some(current_path) = &self.file_path {
-                if current_path == &target {
-                    self.file_path = Some(new_path.clone());
-                    self.file_name = Some(new_name.clone());
-                }
+        if start >= line.len() || line[start] == ' ' || line[start] == '\t' {
+            while start > 0 && (line[start - 1] == ' ' || line[start - 1] == '\t') {
+                start -= 1;
             }
-
-            if parent.to_string_lossy() == "." {
-                self.load_root(".");
-                for (i, node) in self.tree.iter().enumerate() {
-                    if node.path == new_path {
-                        self.tree_cursor = i;
+            if start > 0 {
+                start -= 1;
+                while start > 0 && !(line[start - 1] == ' ' || line[start - 1] == '\t') {
+                    if is_special_char(line[start - 1]) {
                         break;
                     }
+                    start -= 1;
                 }
+            }
+            end = start;
+            while end < line.len() && !(line[end] == ' ' || line[end] == '\t') {
+                if is_special_char(line[end]) {
+                    end += 1;
+                    break;
+                }
+                end += 1;
+            }
+        } else {
+            let c = line[start];
+            if is_special_char(c) {
+                end = start + 1;
             } else {
-                self.reload_tree_at_parent(&parent);
-                for (i, node) in self.tree.iter().enumerate() {
-                    if node.path == new_path {
-                        self.tree_cursor = i;
+                while start > 0 && !(line[start - 1] == ' ' || line[start - 1] == '\t') {
+                    let prev = line[start - 1];
+                    if is_special_char(prev) {
                         break;
                     }
+                    start -= 1;
                 }
-            }
-            self.needs_full_redraw = true;
-        }
+                while end < line.len() && !(line[end] == ' ' || line[end] == '\t') {
+                    if is_special_char(line[end]) {
+                        end += 1;
+                        break;
+                    }
+                    end += 1;
+                }
+            }
+        }
 
-        self.cancel_rename();
-        Ok(())
+        Some((start, end))
     }
 
-    fn update_search(&mut self) {
-        if self.search_query.is_empty() {
-            self.search_results.clear();
+    fn select_word_at(&mut self, y: usize, x: usize) {
+        if let Some((start, end)) = self.get_word_boundaries(y, x) {
+            self.cursor_y = y;
+            self.cursor_x = start;
+            self.start_selection();
+            self.cursor_x = end;
+            self.update_selection_end();
+            self.needs_full_redraw = true;
+        }
+    }
+
+    fn handle_mouse_click(&mut self, col: u16, row: u16, rows: u16, cols: u16, shift: bool) {
+        let tree_offset = if self.show_tree { TREE_WIDTH } else { 0 };
+        let line_num_offset = if self.show_line_numbers {
+            LINE_NUM_WIDTH
+        } else {
+            0
+        };
+        let text_offset = tree_offset + line_num_offset;
+
+        if col < text_offset || col >= cols - self.preview_width(cols) {
             return;
         }
 
-        let query: String = self.search_query.iter().collect();
-        self.search_results.clear();
+        let max_lines = self.text_rows(rows);
+        if row >= max_lines {
+            return;
+        }
 
-        for (y, line) in self.buffer.iter().enumerate() {
-            let line_str: String = line.iter().collect();
-            let mut start = 0;
-            while let Some(pos) = line_str[start..].find(&query) {
-                let absolute_pos = start + pos;
-                self.search_results.push((y, absolute_pos));
-                start = absolute_pos + 1;
+        let (clicked_y, clicked_x) = self.text_position(row as usize, (col - text_offset) as usize);
+        if clicked_y < self.buffer.len() {
+            let clicked_pos = (clicked_y, clicked_x.min(self.buffer[clicked_y].len()));
+
+            let now = Instant::now();
+            let is_double_click = if let (Some(last_time), Some(last_pos)) =
+                (self.last_mouse_click_time, self.last_mouse_click_pos)
+            {
+                last_pos == clicked_pos
+                    && now.duration_since(last_time) < Duration::from_millis(500)
+            } else {
+                false
+            };
+
+            self.cursor_y = clicked_y;
+            if let Some(line) = self.buffer.get(clicked_y) {
+                self.cursor_x = clicked_x.min(line.len());
+            } else {
+                self.cursor_x = 0;
             }
-        }
 
-        if !self.search_results.is_empty() {
-            self.current_search_index = 0;
-            self.jump_to_search_result(0);
+            if is_double_click {
+                self.select_word_at(clicked_y, self.cursor_x);
+            } else if shift {
+                if !self.is_selecting {
+                    self.start_selection();
+                }
+                self.update_selection_end();
+            } else {
+                self.is_selecting = false;
+                self.selection_start = None;
+                self.selection_end = None;
+                self.mouse_dragging = true;
+                self.mouse_drag_start_pos = Some((self.cursor_y, self.cursor_x));
+            }
+
+            self.last_mouse_click_time = Some(now);
+            self.last_mouse_click_pos = Some(clicked_pos);
+            self.needs_full_redraw = true;
         }
     }
 
-    fn jump_to_search_result(&mut self, index: usize) {
-        if let Some(&(y, x)) = self.search_results.get(index) {
-            self.cursor_y = y;
-            self.cursor_x = x;
-            self.current_search_index = index;
-            self.dirty = true;
+    fn handle_mouse_drag(&mut self, col: u16, row: u16, rows: u16, cols: u16) {
+        let tree_offset = if self.show_tree { TREE_WIDTH } else { 0 };
+        let line_num_offset = if self.show_line_numbers {
+            LINE_NUM_WIDTH
+        } else {
+            0
+        };
+        let text_offset = tree_offset + line_num_offset;
+
+        if col < text_offset {
+            return;
         }
-    }
 
-    fn next_search_result(&mut self) {
-        if !self.search_results.is_empty() {
-            self.current_search_index = (self.current_search_index + 1) % self.search_results.len();
-            self.jump_to_search_result(self.current_search_index);
+        let max_lines = self.text_rows(rows);
+        if row >= max_lines {
+            return;
         }
-    }
 
-    fn insert(&mut self, c: char) {
-        self.save_history_state();
+        // The preview is not part of the text; drags into it stop at its edge.
+        let text_end = cols - self.preview_width(cols);
+        let col = col.min(text_end.saturating_sub(1));
 
-        let closing = match c {
-            '(' => Some(')'),
-            '[' => Some(']'),
-            '{' => Some('}'),
-            '"' => Some('"'),
-            '\'' => Some('\''),
-            _ => None,
-        };
+        if self.mouse_dragging {
+            let (clicked_y, row_start) = self.text_position(row as usize, 0);
+            if clicked_y < self.buffer.len() {
+                let clicked_x_screen = (col - text_offset) as usize;
+                // Dragging onto either edge column reaches one past it, so the
+                // view scrolls horizontally to follow the selection.
+                let clicked_x = if col + 1 >= text_end {
+                    row_start + clicked_x_screen + 1
+                } else if clicked_x_screen == 0 {
+                    row_start.saturating_sub(1)
+                } else {
+                    row_start + clicked_x_screen
+                };
 
-        self.buffer[self.cursor_y].insert(self.cursor_x, c);
-        self.cursor_x += 1;
+                if !self.is_selecting {
+                    if let Some(start_pos) = self.mouse_drag_start_pos {
+                        self.selection_start = Some(start_pos);
+                        self.selection_end = Some(start_pos);
+                        self.is_selecting = true;
+                    } else {
+                        self.cursor_y = clicked_y;
+                        if let Some(line) = self.buffer.get(clicked_y) {
+                            self.cursor_x = clicked_x.min(line.len());
+                        } else {
+                            self.cursor_x = 0;
+                        }
+                        self.start_selection();
+                    }
+                }
 
-        if let Some(close) = closing {
-            self.buffer[self.cursor_y].insert(self.cursor_x, close);
+                self.cursor_y = clicked_y;
+                if let Some(line) = self.buffer.get(clicked_y) {
+                    self.cursor_x = clicked_x.min(line.len());
+                } else {
+                    self.cursor_x = 0;
+                }
+                self.update_selection_end();
+                self.cursor_locked = false;
+                self.needs_full_redraw = true;
+            }
         }
-
-        self.cursor_locked = false;
-        self.mark_file_dirty();
     }
 
-    fn backspace(&mut self) {
-        if self.cursor_x > 0 {
-            self.save_history_state();
-            self.cursor_x -= 1;
-            self.buffer[self.cursor_y].remove(self.cursor_x);
-            self.cursor_locked = false;
-            self.mark_file_dirty();
-        } else if self.cursor_y > 0 {
-            self.save_history_state();
-            let current_line = self.buffer.remove(self.cursor_y);
-            self.cursor_y -= 1;
-            self.cursor_x = self.buffer[self.cursor_y].len();
-            self.buffer[self.cursor_y].extend(current_line);
-            self.cursor_locked = false;
-            self.update_bracket_matching();
-            self.mark_file_dirty();
-        }
+    fn handle_mouse_release(&mut self) {
+        self.mouse_dragging = false;
     }
 
-    fn delete(&mut self) {
-        if self.cursor_x < self.buffer[self.cursor_y].len() {
-            self.save_history_state();
-            self.buffer[self.cursor_y].remove(self.cursor_x);
-            self.cursor_locked = false;
-            self.mark_file_dirty();
-        } else if self.cursor_x == self.buffer[self.cursor_y].len()
-            && self.cursor_y + 1 < self.buffer.len()
-        {
-            self.save_history_state();
-            let next_line = self.buffer.remove(self.cursor_y + 1);
-            self.buffer[self.cursor_y].extend(next_line);
-            self.cursor_locked = false;
-            self.update_bracket_matching();
-            self.mark_file_dirty();
+    fn max_scroll_y(&self, max_lines: usize) -> usize {
+        // Centering the last lines needs room below them.
+        if self.config.scrolling.past_end || self.typewriter {
+            self.buffer.len().saturating_sub(1)
+        } else {
+            self.buffer.len().saturating_sub(max_lines)
         }
     }
 
-    fn newline(&mut self) {
-        self.save_history_state();
-        let rest = self.buffer[self.cursor_y].split_off(self.cursor_x);
+    fn handle_mouse_scroll(&mut self, rows: u16, up: bool) {
+        let max_lines = self.text_rows(rows) as usize;
+        let max_scroll_y = self.max_scroll_y(max_lines);
 
-        let indent_level = self.calculate_indent_level();
+        self.cursor_locked = true;
 
-        self.buffer.insert(self.cursor_y + 1, rest);
-        self.cursor_y += 1;
-        self.cursor_x = 0;
+        const SCROLL_STEP: usize = 3;
 
-        if indent_level > 0 {
-            let indent = self.get_indent_string(indent_level);
-            for c in indent.chars() {
-                self.buffer[self.cursor_y].insert(0, c);
-                self.cursor_x += 1;
+        if let Some(width) = self.soft_wrap_width() {
+            let mut top = (self.scroll_y, self.wrap_row);
+            for _ in 0..SCROLL_STEP {
+                match self.wrap_step(top, up, width) {
+                    Some(next) => top = next,
+                    None => break,
+                }
             }
+            (self.scroll_y, self.wrap_row) = top;
+            self.needs_full_redraw = true;
+            self.dirty = true;
+            return;
         }
 
-        self.cursor_locked = false;
-        self.update_bracket_matching();
-        self.mark_file_dirty();
+        let from = self.scroll_target.unwrap_or(self.scroll_y);
+        if up {
+            self.scroll_to(from.saturating_sub(SCROLL_STEP));
+        } else {
+            self.scroll_to((from + SCROLL_STEP).min(max_scroll_y.max(from)));
+        }
     }
 
-    fn calculate_indent_level(&self) -> usize {
-        if self.cursor_y == 0 {
-            return 0;
+    fn scroll_to(&mut self, y: usize) {
+        if self.config.scrolling.smooth {
+            self.scroll_target = (y != self.scroll_y).then_some(y);
+        } else {
+            self.scroll_y = y;
         }
+        self.dirty = true;
+    }
 
-        let prev_line = &self.buffer[self.cursor_y - 1];
+    /// Advances an animated scroll by one frame; covers a third of the
+    /// remaining distance so long jumps ease out.
+    fn step_scroll_animation(&mut self) {
+        let Some(target) = self.scroll_target else {
+            return;
+        };
+        let distance = target.abs_diff(self.scroll_y);
+        let step = distance.div_ceil(3).max(1);
+        if target > self.scroll_y {
+            self.scroll_y += step;
+        } else {
+            self.scroll_y -= step;
+        }
+        if self.scroll_y == target {
+            self.scroll_target = None;
+        }
+        self.dirty = true;
+    }
 
-        let mut prev_indent = 0;
-        for c in prev_line.iter() {
-            if *c == ' ' {
-                prev_indent += 1;
-            } else if *c == '\t' {
-                prev_indent += 4;
-            } else {
-                break;
-            }
+    fn page_move(&mut self, rows: u16, down: bool) {
+        let max_lines = self.text_rows(rows) as usize;
+        let page = max_lines.saturating_sub(1).max(1);
+        if self.is_selecting {
+            self.update_selection_end();
+        } else {
+            self.clear_selection();
         }
 
-        let prev_line_str: String = prev_line.iter().collect();
-        let trimmed_prev: String = prev_line_str.trim_start().to_string();
+        let from = self.scroll_target.unwrap_or(self.scroll_y);
+        let last = self.buffer.len().saturating_sub(1);
+        if down {
+            self.cursor_y = (self.cursor_y + page).min(last);
+            self.scroll_to((from + page).min(self.max_scroll_y(max_lines).max(from)));
+        } else {
+            self.cursor_y = self.cursor_y.saturating_sub(page);
+            self.scroll_to(from.saturating_sub(page));
+        }
+        self.cursor_x = self.cursor_x.min(self.buffer[self.cursor_y].len());
+        if self.is_selecting {
+            self.update_selection_end();
+        }
+        self.cursor_locked = false;
+        self.update_bracket_matching();
+    }
 
-        let increase_indent = match self.language {
-            Language::Python => {
-                trimmed_prev.ends_with(':')
-                    || trimmed_prev.starts_with("if ")
-                    || trimmed_prev.starts_with("elif ")
-                    || trimmed_prev.starts_with("else ")
-                    || trimmed_prev.starts_with("for ")
-                    || trimmed_prev.starts_with("while ")
-                    || trimmed_prev.starts_with("def ")
-                    || trimmed_prev.starts_with("class ")
-                    || trimmed_prev.starts_with("try:")
-                    || trimmed_prev.starts_with("except ")
-                    || trimmed_prev.starts_with("finally:")
-                    || trimmed_prev.starts_with("with ")
-                    || trimmed_prev.starts_with("async ")
-                    || trimmed_prev == "else:"
-                    || trimmed_prev == "elif:"
-                    || trimmed_prev == "except:"
-                    || trimmed_prev == "finally:"
-                    || trimmed_prev == "try:"
+    fn buffer_text(&self) -> String {
+        self.buffer
+            .iter()
+            .map(|l| l.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn save(&mut self) -> io::Result<()> {
+        match self.local_options.read_only {
+            Some(ReadOnly::Chosen) => {
+                self.status = "Not saved: this buffer is read-only".into();
+                self.needs_full_redraw = true;
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "buffer is read-only",
+                ));
             }
-            Language::Rust
-            | Language::JavaScript
-            | Language::C
-            | Language::Cpp
-            | Language::Java => {
-                trimmed_prev.ends_with(" {")
-                    || trimmed_prev.ends_with("{")
-                    || (trimmed_prev.starts_with("if ") && !trimmed_prev.contains(";"))
-                    || (trimmed_prev.starts_with("for ") && !trimmed_prev.contains(";"))
-                    || (trimmed_prev.starts_with("while ") && !trimmed_prev.contains(";"))
-                    || trimmed_prev.starts_with("fn ")
-                    || trimmed_prev.starts_with("impl ")
-                    || trimmed_prev.starts_with("trait ")
-                    || trimmed_prev.starts_with("struct ")
-                    || trimmed_prev.starts_with("enum ")
-                    || trimmed_prev.starts_with("match ")
-                    || trimmed_prev.starts_with("unsafe ")
-                    || trimmed_prev.starts_with("loop ")
-                    || trimmed_prev.starts_with("function ")
-                    || trimmed_prev.starts_with("class ")
-                    || trimmed_prev.ends_with("=>")
-                    || trimmed_prev.ends_with("else {")
-                    || trimmed_prev.ends_with("try {")
-                    || trimmed_prev.ends_with("catch {")
+            Some(ReadOnly::Locked) => {
+                self.status =
+                    "Not saved: read-only while another termi is editing this file".into();
+                self.needs_full_redraw = true;
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "file is being edited in another termi",
+                ));
             }
-            Language::None => false,
-        };
+            None => {}
+        }
+        if let Some(path) = self.file_path.clone() {
+            if self.config.organize_imports_on_save {
+                self.organize_imports_locally();
+            }
+            let text = self.buffer_text();
+            if self.bom_files.contains(&path) && self.config.keep_bom {
+                fs::write(&path, format!("{}{}", BOM, text))?;
+            } else {
+                fs::write(&path, &text)?;
+            }
+            let _ = local_history::record(&path, &text);
+            self.symbol_cache
+                .get_or_insert_with(symbols::Cache::load)
+                .update(&path, &self.buffer);
+            self.status = "Saved".into();
+            self.needs_full_redraw = true;
+            self.dirty = false;
+            self.dirty_files.remove(&path);
+            self.file_buffers.insert(path.clone(), self.buffer.clone());
+            self.lsp_sync_current();
+            if let Some(client) = self.current_lsp_client() {
+                let _ = client.did_save(&path);
+            }
+            self.request_semantic_tokens();
+        }
+        Ok(())
+    }
 
-        if increase_indent {
-            prev_indent + 4
+    fn ensure_cursor_visible(&mut self, rows: u16, cols: u16) {
+        let max_lines = self.text_rows(rows) as usize;
+        if let Some(width) = self.soft_wrap_width() {
+            self.ensure_cursor_visible_wrapped(max_lines, width);
+            return;
+        }
+        let tree_offset = if self.show_tree { TREE_WIDTH } else { 0 };
+        let line_num_offset = if self.show_line_numbers {
+            LINE_NUM_WIDTH
         } else {
-            prev_indent
+            0
+        };
+        let text_offset = tree_offset + line_num_offset;
+        let available_width = (cols - text_offset - self.preview_width(cols)) as usize;
+
+        // Margins shrink on small viewports so the cursor can still move.
+        let margin = self
+            .config
+            .scrolling
+            .margin
+            .min(max_lines.saturating_sub(1) / 2);
+        // During an animation the target, not the current frame, must keep
+        // the cursor in view.
+        let mut scroll_y = self.scroll_target.unwrap_or(self.scroll_y);
+        if self.typewriter {
+            scroll_y = self.cursor_y.saturating_sub(max_lines / 2);
+        } else if self.cursor_y < scroll_y + margin {
+            scroll_y = self.cursor_y.saturating_sub(margin);
+        } else if max_lines > 0 && self.cursor_y + margin >= scroll_y + max_lines {
+            scroll_y = self.cursor_y + margin + 1 - max_lines;
+        }
+        scroll_y = scroll_y.min(self.max_scroll_y(max_lines));
+        match self.scroll_target {
+            Some(_) => self.scroll_target = Some(scroll_y),
+            None => self.scroll_y = scroll_y,
         }
-    }
 
-    fn get_indent_string(&self, level: usize) -> String {
-        " ".repeat(level)
+        if available_width > 0 {
+            let margin = self
+                .config
+                .scrolling
+                .horizontal_margin
+                .min(available_width.saturating_sub(1) / 2);
+            if self.cursor_x < self.scroll_x + margin {
+                self.scroll_x = self.cursor_x.saturating_sub(margin);
+            } else if self.cursor_x + margin >= self.scroll_x + available_width {
+                self.scroll_x = self.cursor_x + margin + 1 - available_width;
+            }
+        }
     }
 
-    fn indent(&mut self) {
-        self.save_history_state();
-        let indent = self.get_indent_string(4);
+    fn ensure_cursor_visible_wrapped(&mut self, max_lines: usize, width: usize) {
+        self.scroll_x = 0;
+        self.scroll_target = None;
+        let cursor = (self.cursor_y, self.cursor_x / width);
+        let mut top = (self.scroll_y, self.wrap_row);
+        if cursor < top {
+            top = cursor;
+        } else if self.wrapped_distance(top, cursor, width, max_lines) >= max_lines {
+            top = cursor;
+            for _ in 1..max_lines {
+                match self.wrap_step(top, true, width) {
+                    Some(previous) => top = previous,
+                    None => break,
+                }
+            }
+        }
+        if top != (self.scroll_y, self.wrap_row) {
+            (self.scroll_y, self.wrap_row) = top;
+            self.needs_full_redraw = true;
+        }
+    }
 
-        if self.cursor_x == 0
-            || self.buffer[self.cursor_y]
-                .iter()
-                .take(self.cursor_x)
-                .all(|&c| c == ' ' || c == '\t')
-        {
-            for c in indent.chars() {
-                self.buffer[self.cursor_y].insert(self.cursor_x, c);
-                self.cursor_x += 1;
+    fn left(&mut self) {
+        if self.cursor_x > 0 {
+            if self.is_selecting {
+                self.update_selection_end();
+            } else {
+                self.clear_selection();
             }
-        } else {
-            for c in indent.chars() {
-                self.buffer[self.cursor_y].insert(self.cursor_x, c);
-                self.cursor_x += 1;
+            self.cursor_x -= 1;
+            self.cursor_locked = false;
+            self.update_bracket_matching();
+            self.dirty = true;
+        }
+    }
+    fn right(&mut self) {
+        if self.cursor_x < self.buffer[self.cursor_y].len() {
+            if !self.is_selecting {
+                self.clear_selection();
+            }
+            self.cursor_x += 1;
+            if self.is_selecting {
+                self.update_selection_end();
             }
+            self.cursor_locked = false;
+            self.update_bracket_matching();
+            self.dirty = true;
         }
-
-        self.cursor_locked = false;
-        self.needs_full_redraw = true;
-        self.dirty = true;
     }
-
-    fn unindent(&mut self) {
-        self.save_history_state();
-        let line = &mut self.buffer[self.cursor_y];
-
-        if line.is_empty() {
-            return;
+    fn up(&mut self) {
+        let target = match self.soft_wrap_width() {
+            Some(width) => self.wrapped_move(width, true),
+            None => (self.cursor_y > 0).then(|| {
+                let y = self.cursor_y - 1;
+                (y, self.cursor_x.min(self.buffer[y].len()))
+            }),
+        };
+        if let Some((y, x)) = target {
+            if self.is_selecting {
+                self.update_selection_end();
+            } else {
+                self.clear_selection();
+            }
+            self.cursor_y = y;
+            self.cursor_x = x;
+            self.cursor_locked = false;
+            self.update_bracket_matching();
+            self.dirty = true;
         }
-
-        let mut removed = 0;
-
-        while removed < line.len() && removed < 4 {
-            if line[0] == ' ' {
-                line.remove(0);
-                removed += 1;
-                if self.cursor_x > 0 {
-                    self.cursor_x -= 1;
-                }
-            } else if line[0] == '\t' {
-                line.remove(0);
-                removed += 4;
-                if self.cursor_x > 0 {
-                    self.cursor_x -= 1;
-                }
+    }
+    fn down(&mut self) {
+        let target = match self.soft_wrap_width() {
+            Some(width) => self.wrapped_move(width, false),
+            None => (self.cursor_y + 1 < self.buffer.len()).then(|| {
+                let y = self.cursor_y + 1;
+                (y, self.cursor_x.min(self.buffer[y].len()))
+            }),
+        };
+        if let Some((y, x)) = target {
+            if self.is_selecting {
+                self.update_selection_end();
             } else {
-                break;
+                self.clear_selection();
             }
+            self.cursor_y = y;
+            self.cursor_x = x;
+            self.cursor_locked = false;
+            self.update_bracket_matching();
+            self.dirty = true;
         }
+    }
 
+    // First press goes to the first non-blank character, a second to column 0.
+    fn line_home(&mut self) {
+        if !self.is_selecting {
+            self.clear_selection();
+        }
+        let line = &self.buffer[self.cursor_y];
+        let indent = line.iter().take_while(|c| c.is_whitespace()).count();
+        self.cursor_x = if self.cursor_x == indent { 0 } else { indent };
+        if self.is_selecting {
+            self.update_selection_end();
+        }
         self.cursor_locked = false;
-        self.needs_full_redraw = true;
+        self.update_bracket_matching();
         self.dirty = true;
     }
 
-    fn ensure_terminal_session(&mut self) -> io::Result<()> {
-        if self.terminal_session.is_some() {
-            return Ok(());
+    fn line_end(&mut self) {
+        if !self.is_selecting {
+            self.clear_selection();
         }
+        self.cursor_x = self.buffer[self.cursor_y].len();
+        if self.is_selecting {
+            self.update_selection_end();
+        }
+        self.cursor_locked = false;
+        self.update_bracket_matching();
+        self.dirty = true;
+    }
 
-        let (cols, rows) = terminal::size().unwrap_or((80, 24));
-        let pty_system = native_pty_system();
-        let pair = pty_system
-            .openpty(terminal_pty_size(rows, cols))
-            .map_err(to_io_error)?;
-        let shell = terminal_shell_program();
-        let cmd = CommandBuilder::new(shell);
-        let child = pair.slave.spawn_command(cmd).map_err(to_io_error)?;
-        let mut reader = pair.master.try_clone_reader().map_err(to_io_error)?;
-        let writer = pair.master.take_writer().map_err(to_io_error)?;
-        let (tx, rx) = mpsc::channel();
-
-        thread::spawn(move || {
-            let mut buffer = [0_u8; 4096];
-            loop {
-                match reader.read(&mut buffer) {
-                    Ok(0) => {
-                        let _ = tx.send("\n[Shell exited]\n".to_string());
-                        break;
-                    }
-                    Ok(n) => {
-                        let chunk = String::from_utf8_lossy(&buffer[..n]).into_owned();
-                        if tx.send(chunk).is_err() {
-                            break;
-                        }
-                    }
-                    Err(err) => {
-                        let _ = tx.send(format!("\n[Terminal read error: {}]\n", err));
-                        break;
-                    }
-                }
-            }
-        });
-
-        self.terminal_output_rx = Some(rx);
-        self.terminal_session = Some(TerminalSession {
-            master: pair.master,
-            writer,
-            child,
-        });
-        self.terminal_scroll = 0;
-        self.needs_full_redraw = true;
+    fn start_selection(&mut self) {
+        self.is_selecting = true;
+        self.selection_start = Some((self.cursor_y, self.cursor_x));
+        self.selection_end = Some((self.cursor_y, self.cursor_x));
         self.dirty = true;
-        Ok(())
     }
 
-    fn resize_terminal_session(&mut self, rows: u16, cols: u16) {
-        if let Some(session) = self.terminal_session.as_mut() {
-            let _ = session.master.resize(terminal_pty_size(rows, cols));
+    fn update_selection_end(&mut self) {
+        if self.is_selecting {
+            self.selection_end = Some((self.cursor_y, self.cursor_x));
+            self.dirty = true;
         }
-        self.terminal_parser
-            .screen_mut()
-            .set_size(rows.max(1), cols.max(1));
-        self.sync_terminal_scrollback();
     }
 
-    fn sync_terminal_scrollback(&mut self) {
-        self.terminal_parser
-            .screen_mut()
-            .set_scrollback(self.terminal_scroll);
-        self.terminal_scroll = self.terminal_parser.screen().scrollback();
+    fn clear_selection(&mut self) {
+        if !self.is_selecting {
+            self.selection_start = None;
+            self.selection_end = None;
+        }
     }
 
-    fn process_terminal_bytes(&mut self, bytes: &[u8]) {
-        self.terminal_parser.process(bytes);
-        self.sync_terminal_scrollback();
-    }
+    fn get_selected_text(&self) -> Option<String> {
+        if let (Some(start), Some(end)) = (self.selection_start, self.selection_end) {
+            let (start_y, start_x) = start;
+            let (end_y, end_x) = end;
 
-    fn append_terminal_message(&mut self, message: &str) {
-        self.process_terminal_bytes(message.as_bytes());
-    }
+            if start_y == end_y && start_x == end_x {
+                return None;
+            }
 
-    fn scroll_terminal(&mut self, delta: isize) {
-        if delta.is_negative() {
-            self.terminal_scroll = self.terminal_scroll.saturating_sub(delta.unsigned_abs());
-        } else {
-            self.terminal_scroll = self.terminal_scroll.saturating_add(delta as usize);
-        }
-        self.sync_terminal_scrollback();
-        self.needs_full_redraw = true;
-        self.dirty = true;
-    }
+            let (actual_start_y, actual_start_x, actual_end_y, actual_end_x) =
+                if (start_y, start_x) < (end_y, end_x) {
+                    (start_y, start_x, end_y, end_x)
+                } else {
+                    (end_y, end_x, start_y, start_x)
+                };
 
-    fn reset_terminal_scrollback(&mut self) {
-        if self.terminal_scroll != 0 {
-            self.terminal_scroll = 0;
-            self.sync_terminal_scrollback();
+            let mut result = String::new();
+
+            if actual_start_y == actual_end_y {
+                if let Some(line) = self.buffer.get(actual_start_y) {
+                    let selected: String = line
+                        .iter()
+                        .skip(actual_start_x)
+                        .take(actual_end_x - actual_start_x)
+                        .collect();
+                    result.push_str(&selected);
+                }
+            } else {
+                for y in actual_start_y..=actual_end_y {
+                    if let Some(line) = self.buffer.get(y) {
+                        if y == actual_start_y {
+                            let selected: String = line.iter().skip(actual_start_x).collect();
+                            result.push_str(&selected);
+                        } else if y == actual_end_y {
+                            let selected: String = line.iter().take(actual_end_x).collect();
+                            result.push_str(&selected);
+                        } else {
+                            let selected: String = line.iter().collect();
+                            result.push_str(&selected);
+                        }
+                        if y < actual_end_y {
+                            result.push('\n');
+                        }
+                    }
+                }
+            }
+
+            if result.is_empty() {
+                None
+            } else {
+                Some(result)
+            }
+        } else {
+            None
         }
     }
 
-    fn write_terminal_bytes(&mut self, bytes: &[u8]) {
-        if let Err(err) = self.ensure_terminal_session() {
-            self.append_terminal_message(&format!("\r\n[Failed to start shell: {}]\r\n", err));
-            self.needs_full_redraw = true;
-            self.dirty = true;
+    fn select_all(&mut self) {
+        if self.buffer.is_empty() {
             return;
         }
 
-        self.reset_terminal_scrollback();
-
-        if let Some(session) = self.terminal_session.as_mut() {
-            let result = session
-                .writer
-                .write_all(bytes)
-                .and_then(|_| session.writer.flush());
+        self.selection_start = Some((0, 0));
 
-            if result.is_err() {
-                self.append_terminal_message("\r\n[Failed to write to shell]\r\n");
-                self.terminal_output_rx = None;
-                self.terminal_session = None;
-            }
-        }
+        let last_line = self.buffer.len() - 1;
+        let last_col = self.buffer[last_line].len();
+        self.selection_end = Some((last_line, last_col));
 
+        self.cursor_y = last_line;
+        self.cursor_x = last_col;
+        self.is_selecting = true;
         self.needs_full_redraw = true;
         self.dirty = true;
     }
 
-    fn terminal_key_bytes(&self, code: KeyCode, modifiers: KeyModifiers) -> Option<Vec<u8>> {
-        let application_cursor = self.terminal_parser.screen().application_cursor();
-        let alt = modifiers.contains(KeyModifiers::ALT);
-        let control = modifiers.contains(KeyModifiers::CONTROL);
-
-        let mut bytes = match code {
-            KeyCode::Enter => vec![b'\r'],
-            KeyCode::Backspace => vec![0x7f],
-            KeyCode::Tab => vec![b'\t'],
-            KeyCode::BackTab => b"\x1b[Z".to_vec(),
-            KeyCode::Esc => vec![0x1b],
-            KeyCode::Left => {
-                if application_cursor {
-                    b"\x1bOD".to_vec()
-                } else {
-                    b"\x1b[D".to_vec()
-                }
-            }
-            KeyCode::Right => {
-                if application_cursor {
-                    b"\x1bOC".to_vec()
-                } else {
-                    b"\x1b[C".to_vec()
-                }
-            }
-            KeyCode::Up => {
-                if application_cursor {
-                    b"\x1bOA".to_vec()
-                } else {
-                    b"\x1b[A".to_vec()
-                }
+    fn copy_selection(&mut self) {
+        if let Some(text) = self.get_selected_text() {
+            self.clipboard = Some(text.clone());
+            if let Ok(mut clipboard) = arboard::Clipboard::new() {
+                let _ = clipboard.set_text(&text);
             }
-            KeyCode::Down => {
-                if application_cursor {
-                    b"\x1bOB".to_vec()
+            self.status = "Copied".into();
+            self.dirty = true;
+        }
+    }
+
+    /// Copies the selection, or the whole buffer, as syntax-colored HTML so
+    /// it keeps its colors when pasted into documents and chats. Plain text
+    /// goes along for targets that don't take HTML.
+    fn copy_with_formatting(&mut self) {
+        let last = self.buffer.len() - 1;
+        let (start, end) = match (self.selection_start, self.selection_end) {
+            (Some(a), Some(b)) if a != b => (a.min(b), a.max(b)),
+            _ => ((0, 0), (last, self.buffer[last].len())),
+        };
+        let tokens: Vec<_> = (start.0..=end.0).map(|y| self.line_tokens(y)).collect();
+        let lines: Vec<export::HighlightedLine> = (start.0..=end.0)
+            .zip(tokens)
+            .map(|(y, tokens)| {
+                let line = &self.buffer[y];
+                let from = if y == start.0 {
+                    start.1.min(line.len())
                 } else {
-                    b"\x1b[B".to_vec()
-                }
-            }
-            KeyCode::Home => b"\x1b[H".to_vec(),
-            KeyCode::End => b"\x1b[F".to_vec(),
-            KeyCode::Delete => b"\x1b[3~".to_vec(),
-            KeyCode::Insert => b"\x1b[2~".to_vec(),
-            KeyCode::PageUp => b"\x1b[5~".to_vec(),
-            KeyCode::PageDown => b"\x1b[6~".to_vec(),
-            KeyCode::Char(c) => {
-                if control && !alt {
-                    let lower = c.to_ascii_lowercase();
-                    let control_byte = match lower {
-                        'a'..='z' => lower as u8 - b'a' + 1,
-                        ' ' | '@' => 0,
-                        '[' => 27,
-                        '\\' => 28,
-                        ']' => 29,
-                        '^' => 30,
-                        '_' => 31,
-                        _ => return None,
-                    };
-                    vec![control_byte]
+                    0
+                };
+                let to = if y == end.0 {
+                    end.1.min(line.len())
                 } else {
-                    c.to_string().into_bytes()
-                }
+                    line.len()
+                };
+                let tokens = tokens
+                    .into_iter()
+                    .filter_map(|(token_start, token_end, token_type)| {
+                        let (token_start, token_end) = (token_start.max(from), token_end.min(to));
+                        (token_start < token_end)
+                            .then(|| (token_start - from, token_end - from, token_type))
+                    })
+                    .collect();
+                (&line[from..to], tokens)
+            })
+            .collect();
+
+        let text = lines
+            .iter()
+            .map(|(chars, _)| chars.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let html = export::html_fragment(&lines);
+        let copied = arboard::Clipboard::new()
+            .and_then(|mut clipboard| clipboard.set_html(html, Some(text.clone())));
+        self.status = match copied {
+            Ok(()) => "Copied with formatting".into(),
+            Err(err) => format!("Copy failed: {}", err),
+        };
+        self.clipboard = Some(text);
+        self.dirty = true;
+    }
+
+    fn paste(&mut self) {
+        let clipboard_text = if let Some(ref internal_text) = self.clipboard {
+            Some(internal_text.clone())
+        } else {
+            if let Ok(mut clipboard) = arboard::Clipboard::new() {
+                clipboard.get_text().ok()
+            } else {
+                None
             }
-            _ => return None,
         };
 
-        if alt && !control && matches!(code, KeyCode::Char(_)) {
-            bytes.insert(0, 0x1b);
-        }
+        if let Some(clipboard_text) = clipboard_text {
+            self.save_history_state();
+            self.clear_selection();
 
-        Some(bytes)
-    }
+            let normalized_text = clipboard_text.replace("\r\n", "\n").replace('\r', "\n");
 
-    fn handle_terminal_key_event(&mut self, code: KeyCode, modifiers: KeyModifiers) {
-        match (code, modifiers) {
-            (KeyCode::Char('1'), KeyModifiers::CONTROL) => {
-                self.toggle_terminal();
-            }
-            (KeyCode::PageUp, m) if m.contains(KeyModifiers::SHIFT) => {
-                self.scroll_terminal(
-                    terminal::size()
-                        .map(|(_, rows)| rows as isize)
-                        .unwrap_or(24),
-                );
-            }
-            (KeyCode::PageDown, m) if m.contains(KeyModifiers::SHIFT) => {
-                self.scroll_terminal(
-                    -terminal::size()
-                        .map(|(_, rows)| rows as isize)
-                        .unwrap_or(24),
-                );
-            }
-            _ => {
-                if let Some(bytes) = self.terminal_key_bytes(code, modifiers) {
-                    self.write_terminal_bytes(&bytes);
+            let lines: Vec<&str> = normalized_text.split('\n').collect();
+
+            if lines.len() == 1 {
+                let chars: Vec<char> = lines[0].chars().collect();
+                for &c in &chars {
+                    self.buffer[self.cursor_y].insert(self.cursor_x, c);
+                    self.cursor_x += 1;
                 }
-            }
-        }
-    }
+            } else {
+                let rest = self.buffer[self.cursor_y].split_off(self.cursor_x);
 
-    fn drain_terminal_output(&mut self) {
-        let mut disconnected = false;
-        let mut chunks = Vec::new();
+                let first_chars: Vec<char> = lines[0].chars().collect();
+                for &c in &first_chars {
+                    self.buffer[self.cursor_y].push(c);
+                    self.cursor_x += 1;
+                }
 
-        if let Some(rx) = self.terminal_output_rx.as_ref() {
-            loop {
-                match rx.try_recv() {
-                    Ok(chunk) => chunks.push(chunk),
-                    Err(TryRecvError::Empty) => break,
-                    Err(TryRecvError::Disconnected) => {
-                        disconnected = true;
-                        break;
-                    }
+                for line in lines.iter().skip(1).take(lines.len() - 1) {
+                    let line_chars: Vec<char> = line.chars().collect();
+                    self.buffer.insert(self.cursor_y + 1, line_chars);
+                    self.cursor_y += 1;
+                    self.cursor_x = self.buffer[self.cursor_y].len();
+                }
+
+                if let Some(last_line) = lines.last() {
+                    let mut new_last_line: Vec<char> = last_line.chars().collect();
+                    new_last_line.extend(rest);
+                    self.buffer.insert(self.cursor_y + 1, new_last_line);
+                    self.cursor_y += 1;
+                    self.cursor_x = lines.last().unwrap().chars().count();
                 }
             }
-        }
 
-        if chunks.is_empty() && !disconnected {
-            return;
-        }
-
-        for chunk in chunks {
-            self.process_terminal_bytes(chunk.as_bytes());
+            self.mark_file_dirty();
         }
+    }
 
-        if disconnected {
-            self.terminal_output_rx = None;
-            self.terminal_session = None;
+    fn save_history_state(&mut self) {
+        // Snapshots of a huge buffer would eat memory fast.
+        if self.is_large_file() {
+            self.history.clear();
+            self.history_index = 0;
+            return;
         }
+        self.history.truncate(self.history_index + 1);
 
-        self.needs_full_redraw = true;
-        self.dirty = true;
-    }
+        let snapshot = self.buffer.to_vec();
+        self.history.push(snapshot);
+        self.history_index += 1;
 
-    fn close_terminal_session(&mut self) {
-        self.terminal_output_rx = None;
-        if let Some(mut session) = self.terminal_session.take() {
-            let _ = session.child.kill();
-            let _ = session.child.wait();
+        if self.history.len() > self.history_limit {
+            self.history.remove(0);
+            self.history_index -= 1;
         }
     }
 
-    fn interrupt_terminal(&mut self) {
-        self.write_terminal_bytes(&[3]);
+    fn undo(&mut self) {
+        if self.is_large_file() {
+            self.status = "Undo is off in large-file mode".into();
+            self.dirty = true;
+            return;
+        }
+        if self.history_index > 0 {
+            self.history_index -= 1;
+            if let Some(old_state) = self.history.get(self.history_index) {
+                self.buffer = old_state.to_vec();
+                self.lsp_needs_sync = true;
+                self.buffer_edits += 1;
+                if self.cursor_y >= self.buffer.len() {
+                    self.cursor_y = self.buffer.len().saturating_sub(1);
+                }
+                if let Some(line) = self.buffer.get(self.cursor_y) {
+                    self.cursor_x = self.cursor_x.min(line.len());
+                }
+                self.needs_full_redraw = true;
+                self.dirty = true;
+            }
+        }
     }
 
-    fn toggle_terminal(&mut self) {
-        self.terminal_show = !self.terminal_show;
-        if self.terminal_show {
-            self.mode = EditorMode::Terminal;
-            if let Err(err) = self.ensure_terminal_session() {
-                self.append_terminal_message(&format!("\r\n[Failed to start shell: {}]\r\n", err));
-            } else {
-                let (cols, rows) = terminal::size().unwrap_or((80, 24));
-                self.resize_terminal_session(rows, cols);
+    fn redo(&mut self) {
+        if self.history_index + 1 < self.history.len() {
+            self.history_index += 1;
+            if let Some(new_state) = self.history.get(self.history_index) {
+                self.buffer = new_state.to_vec();
+                self.lsp_needs_sync = true;
+                self.buffer_edits += 1;
+                if self.cursor_y >= self.buffer.len() {
+                    self.cursor_y = self.buffer.len().saturating_sub(1);
+                }
+                if let Some(line) = self.buffer.get(self.cursor_y) {
+                    self.cursor_x = self.cursor_x.min(line.len());
+                }
+                self.needs_full_redraw = true;
+                self.dirty = true;
             }
-        } else {
-            self.mode = EditorMode::Normal;
         }
-        self.needs_full_redraw = true;
-        self.dirty = true;
     }
 
-    fn start_goto_line(&mut self) {
-        self.mode = EditorMode::GoToLine;
-        self.goto_line_input.clear();
-        self.status = "Go to line: ".into();
+    fn start_search(&mut self) {
+        self.mode = EditorMode::Search;
+        self.search_query.clear();
+        self.search_results.clear();
+        self.current_search_index = 0;
+        self.status = "Search: ".into();
         self.needs_full_redraw = true;
         self.dirty = true;
     }
 
-    fn cancel_goto_line(&mut self) {
+    fn cancel_search(&mut self) {
         self.mode = EditorMode::Normal;
-        self.goto_line_input.clear();
-        self.status = "Ctrl+O Tree | Ctrl+N File | Ctrl+M Folder | F2 Rename | Del Delete | Ctrl+S Save | Ctrl+F Find | Shift+Arrow Select | Ctrl+C Copy | Ctrl+V Paste | Ctrl+1 Terminal | Ctrl+Q Quit".into();
+        self.search_query.clear();
+        self.search_results.clear();
+        self.status =
+            "Ctrl+O Tree | Ctrl+S Save | Ctrl+F Find | Ctrl+Z Undo | Ctrl+Y Redo | Ctrl+Q Quit"
+                .into();
         self.needs_full_redraw = true;
         self.dirty = true;
     }
 
-    fn confirm_goto_line(&mut self) {
-        if self.goto_line_input.is_empty() {
+    fn start_create_file(&mut self) {
+        if !self.show_tree || self.tree.is_empty() {
             return;
         }
 
-        let line_str: String = self.goto_line_input.iter().collect();
-        if let Ok(line_num) = line_str.parse::<usize>() {
-            if line_num > 0 && line_num <= self.buffer.len() {
-                self.cursor_y = line_num - 1;
-                if let Some(line) = self.buffer.get(self.cursor_y) {
-                    self.cursor_x = self.cursor_x.min(line.len());
-                } else {
-                    self.cursor_x = 0;
-                }
-                self.cursor_locked = false;
-                self.dirty = true;
-            }
-        }
-        self.cancel_goto_line();
-    }
+        let selected_node = &self.tree[self.tree_cursor];
+        let parent_path = if selected_node.is_dir {
+            selected_node.path.clone()
+        } else {
+            selected_node
+                .path
+                .parent()
+                .unwrap_or(&PathBuf::from("."))
+                .to_path_buf()
+        };
 
-    fn find_matching_bracket(&mut self, y: usize, x: usize) -> Option<(usize, usize)> {
-        if y >= self.buffer.len() {
-            return None;
-        }
+        self.mode = EditorMode::CreateFile;
+        self.create_name.clear();
+        self.create_parent_path = Some(parent_path);
+        self.status = "New file name: ".into();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        let line = &self.buffer[y];
-        if x >= line.len() {
-            return None;
+    fn start_create_dir(&mut self) {
+        if !self.show_tree || self.tree.is_empty() {
+            return;
         }
 
-        let char_at = line[x];
-
-        let (open, close, forward) = match char_at {
-            '(' => ('(', ')', true),
-            ')' => (')', '(', false),
-            '[' => ('[', ']', true),
-            ']' => (']', '[', false),
-            '{' => ('{', '}', true),
-            '}' => ('}', '{', false),
-            _ => return None,
+        let selected_node = &self.tree[self.tree_cursor];
+        let parent_path = if selected_node.is_dir {
+            selected_node.path.clone()
+        } else {
+            selected_node
+                .path
+                .parent()
+                .unwrap_or(&PathBuf::from("."))
+                .to_path_buf()
         };
 
-        let mut depth = 0;
-        let mut current_y = y;
-        let mut current_x = if forward { x + 1 } else { x.saturating_sub(1) };
+        self.mode = EditorMode::CreateDir;
+        self.create_name.clear();
+        self.create_parent_path = Some(parent_path);
+        self.status = "New folder name: ".into();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        loop {
-            if current_y >= self.buffer.len() {
-                break;
-            }
+    fn cancel_create(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.create_name.clear();
+        self.create_parent_path = None;
+        self.status =
+            "Ctrl+O Tree | Ctrl+S Save | Ctrl+F Find | Ctrl+Z Undo | Ctrl+Y Redo | Ctrl+Q Quit"
+                .into();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-            let line = &self.buffer[current_y];
+    fn create_file_or_dir(&mut self) -> io::Result<()> {
+        if self.create_name.is_empty() || self.prompt_refused() {
+            return Ok(());
+        }
 
-            while (forward && current_x < line.len()) || (!forward && current_x > 0) {
-                let c = if forward {
-                    line[current_x]
-                } else {
-                    line[current_x - 1]
-                };
+        let name: String = self.create_name.iter().collect();
+        let parent_path = self.create_parent_path.clone();
+        let mut created_file = false;
+        if let Some(parent) = parent_path {
+            let new_path = parent.join(&name);
 
-                if c == open {
-                    depth += 1;
-                } else if c == close {
-                    if depth == 0 {
-                        return Some((current_y, if forward { current_x } else { current_x - 1 }));
+            match self.mode {
+                EditorMode::CreateFile => {
+                    let header = self.file_header_for(&new_path).unwrap_or_default();
+                    fs::write(&new_path, &header)?;
+                    created_file = self.open_file(&new_path).is_ok();
+                    if created_file && !header.is_empty() {
+                        self.cursor_y = self.buffer.len() - 1;
+                        self.cursor_x = self.buffer[self.cursor_y].len();
                     }
-                    depth -= 1;
                 }
-
-                if forward {
-                    current_x += 1;
-                } else {
-                    current_x = current_x.saturating_sub(1);
+                EditorMode::CreateDir => {
+                    fs::create_dir(&new_path)?;
                 }
+                _ => {}
             }
 
-            if forward {
-                current_y += 1;
-                current_x = 0;
+            if parent.to_string_lossy() == "." {
+                self.load_root(".");
             } else {
-                if current_y == 0 {
-                    break;
+                self.reload_tree_at_parent(&parent);
+            }
+            self.needs_full_redraw = true;
+        }
+
+        self.cancel_create();
+        if created_file {
+            self.start_template_picker();
+        }
+        Ok(())
+    }
+
+    /// Checks the input of the open prompt: names that would overwrite
+    /// something and lines past the end are refused.
+    fn prompt_check(&self) -> Option<PromptCheck> {
+        match self.mode {
+            EditorMode::CreateFile | EditorMode::CreateDir => {
+                let name: String = self.create_name.iter().collect();
+                let path = self.create_parent_path.as_ref()?.join(name.trim_end());
+                if name.trim().is_empty() {
+                    None
+                } else if path.is_dir() {
+                    Some(PromptCheck::Invalid(
+                        "a folder with that name exists".into(),
+                    ))
+                } else if path.exists() {
+                    Some(PromptCheck::Invalid("a file with that name exists".into()))
+                } else if !path.parent().is_some_and(Path::is_dir) {
+                    Some(PromptCheck::Invalid("no such folder".into()))
+                } else if matches!(self.mode, EditorMode::CreateFile) {
+                    Some(PromptCheck::Valid("new file".into()))
+                } else {
+                    Some(PromptCheck::Valid("new folder".into()))
                 }
-                current_y -= 1;
-                current_x = self.buffer[current_y].len();
             }
+            EditorMode::Rename => {
+                let target = self.rename_target.as_ref()?;
+                let name: String = self.rename_name.iter().collect();
+                if name.trim().is_empty() || target.file_name()? == name.as_str() {
+                    return None;
+                }
+                let new_path = target.with_file_name(&name);
+                // On case-insensitive file systems the "other" file may be
+                // the target itself with different capitals.
+                let same_file = fs::canonicalize(&new_path)
+                    .is_ok_and(|path| fs::canonicalize(target).is_ok_and(|t| t == path));
+                if new_path.exists() && !same_file {
+                    Some(PromptCheck::Invalid(format!("{} already exists", name)))
+                } else if !new_path.parent().is_some_and(Path::is_dir) {
+                    Some(PromptCheck::Invalid("no such folder".into()))
+                } else {
+                    Some(PromptCheck::Valid("available".into()))
+                }
+            }
+            EditorMode::GoToLine => {
+                let input: String = self.goto_line_input.iter().collect();
+                let last = self.buffer.len();
+                match input.parse::<usize>() {
+                    Ok(line) if (1..=last).contains(&line) => {
+                        let text: String = self.buffer[line - 1].iter().collect();
+                        let text = text.trim();
+                        Some(PromptCheck::Valid(if text.is_empty() {
+                            "blank line".into()
+                        } else {
+                            text.chars().take(40).collect()
+                        }))
+                    }
+                    Ok(_) => Some(PromptCheck::Invalid(format!("out of range (1-{})", last))),
+                    Err(_) if input.is_empty() => None,
+                    Err(_) => Some(PromptCheck::Invalid("not a line number".into())),
+                }
+            }
+            _ => None,
         }
+    }
 
-        None
+    fn prompt_refused(&mut self) -> bool {
+        let refused = matches!(self.prompt_check(), Some(PromptCheck::Invalid(_)));
+        self.dirty |= refused;
+        refused
     }
 
-    fn update_bracket_matching(&mut self) {
-        self.matched_bracket = None;
+    fn start_scaffold_picker(&mut self) {
+        self.scaffold_index = 0;
+        self.mode = EditorMode::ScaffoldPicker;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        if self.cursor_y >= self.buffer.len() {
+    fn cancel_scaffold(&mut self) {
+        self.mode = if self.file_path.is_none() && !self.show_tree {
+            EditorMode::Dashboard
+        } else {
+            EditorMode::Normal
+        };
+        self.scaffold_dir_input.clear();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn confirm_scaffold_picker(&mut self) {
+        if self.scaffold_index >= self.config.scaffolders.len() {
             return;
         }
+        let mut base = self.dashboard_workspace.to_string_lossy().into_owned();
+        if !base.ends_with(std::path::MAIN_SEPARATOR) {
+            base.push(std::path::MAIN_SEPARATOR);
+        }
+        self.scaffold_dir_input = base.chars().collect();
+        self.mode = EditorMode::ScaffoldDirectory;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        let line = &self.buffer[self.cursor_y];
-        if self.cursor_x >= line.len() {
+    fn confirm_scaffold_directory(&mut self) {
+        let input: String = self.scaffold_dir_input.iter().collect();
+        let input = input.trim();
+        if input.is_empty() {
             return;
         }
-
-        if let Some(matched) = self.find_matching_bracket(self.cursor_y, self.cursor_x) {
-            self.matched_bracket = Some(matched);
+        let target = match (input.strip_prefix("~/"), env::var("HOME")) {
+            (Some(rest), Ok(home)) => PathBuf::from(home).join(rest),
+            _ => PathBuf::from(input),
+        };
+        let target = if target.is_relative() {
+            self.dashboard_workspace.join(target)
         } else {
-            if self.cursor_x > 0 {
-                if let Some(matched) = self.find_matching_bracket(self.cursor_y, self.cursor_x - 1)
-                {
-                    self.matched_bracket = Some(matched);
-                }
-            }
-        }
+            target
+        };
+        self.cancel_scaffold();
+        self.run_scaffolder(target);
     }
 
-    fn word_left(&mut self) {
-        if self.cursor_x == 0 {
-            if self.cursor_y > 0 {
-                self.cursor_y -= 1;
-                self.cursor_x = self.buffer[self.cursor_y].len();
-                self.cursor_locked = false;
-                self.dirty = true;
-            }
+    fn run_scaffolder(&mut self, target: PathBuf) {
+        let Some(scaffolder) = self.config.scaffolders.get(self.scaffold_index) else {
             return;
-        }
-
-        let line = &self.buffer[self.cursor_y];
-        let mut x = self.cursor_x;
-
-        while x > 0 && (line[x - 1] == ' ' || line[x - 1] == '\t') {
-            x -= 1;
-        }
-
-        if x == 0 {
-            self.cursor_x = x;
-            self.cursor_locked = false;
-            self.update_bracket_matching();
+        };
+        let (Some(name), Some(parent)) = (
+            target.file_name().map(|n| n.to_string_lossy().into_owned()),
+            target.parent().map(Path::to_path_buf),
+        ) else {
+            self.status = "Choose a directory for the new project".into();
+            self.dirty = true;
+            return;
+        };
+        if fs::read_dir(&target).is_ok_and(|mut entries| entries.next().is_some()) {
+            self.status = format!(
+                "{} already exists and is not empty",
+                target.to_string_lossy()
+            );
             self.dirty = true;
             return;
         }
 
-        let is_special_char = |c: char| -> bool {
-            matches!(
-                c,
-                '.' | ','
-                    | '['
-                    | ']'
-                    | '{'
-                    | '}'
-                    | '$'
-                    | '('
-                    | ')'
-                    | ';'
-                    | ':'
-                    | '!'
-                    | '?'
-                    | '@'
-                    | '#'
-                    | '%'
-                    | '^'
-                    | '&'
-                    | '*'
-                    | '+'
-                    | '-'
-                    | '='
-                    | '/'
-                    | '\\'
-                    | '|'
-                    | '<'
-                    | '>'
-                    | '`'
-                    | '\''
-                    | '"'
-            )
+        // Tools like `cargo new` refuse a directory that exists, even an
+        // empty one; their init form fills it in place.
+        let (program, args, cwd) = match &scaffolder.init {
+            Some((program, args)) if target.is_dir() => (program, args, target.clone()),
+            _ if scaffolder.in_dir => (&scaffolder.program, &scaffolder.args, target.clone()),
+            _ => (&scaffolder.program, &scaffolder.args, parent),
         };
+        if let Err(err) = fs::create_dir_all(&cwd) {
+            self.status = format!("Failed to create {}: {}", cwd.to_string_lossy(), err);
+            self.dirty = true;
+            return;
+        }
 
-        let prev_char = line[x - 1];
-
-        if is_special_char(prev_char) {
-            x -= 1;
-        } else if prev_char.is_alphanumeric() || prev_char == '_' {
-            while x > 0 {
-                let c = line[x - 1];
-                if c.is_alphanumeric() || c == '_' {
-                    x -= 1;
-                } else {
-                    break;
+        let path = target.to_string_lossy();
+        let args: Vec<String> = args
+            .iter()
+            .map(|arg| arg.replace("{name}", &name).replace("{path}", &path))
+            .collect();
+        let program = program.clone();
+        let command_line = format!("{} {}", program, args.join(" "));
+
+        match spawn_command_job(&program, &args, &cwd, &self.waker) {
+            Ok(rx) => {
+                self.jobs.push(Job {
+                    kind: JobKind::Scaffold {
+                        root: target.clone(),
+                    },
+                    rx,
+                });
+                if !self.terminal_show {
+                    self.toggle_terminal();
                 }
+                self.append_terminal_message(&format!(
+                    "\r\n[{}] $ {}\r\n",
+                    cwd.to_string_lossy(),
+                    command_line
+                ));
+                self.status = format!("Running {}...", command_line);
+            }
+            Err(err) => {
+                self.status = format!("Failed to run {}: {}", program, err);
             }
         }
-
-        self.cursor_x = x;
-        self.cursor_locked = false;
-        self.update_bracket_matching();
+        self.needs_full_redraw = true;
         self.dirty = true;
     }
 
-    fn word_right(&mut self) {
-        let line = &self.buffer[self.cursor_y];
-
-        if self.cursor_x >= line.len() {
-            if self.cursor_y + 1 < self.buffer.len() {
-                self.cursor_y += 1;
-                self.cursor_x = 0;
-                self.cursor_locked = false;
-                self.update_bracket_matching();
-                self.dirty = true;
-            }
+    fn start_template_picker(&mut self) {
+        let Some(name) = self.file_name.clone() else {
+            return;
+        };
+        let choices = templates::for_file_name(&name);
+        if choices.is_empty() {
             return;
         }
+        self.template_choices = choices;
+        self.template_index = 0;
+        self.mode = EditorMode::TemplatePicker;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        let mut x = self.cursor_x;
-
-        while x < line.len() && (line[x] == ' ' || line[x] == '\t') {
-            x += 1;
-        }
+    fn cancel_template_picker(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.template_choices.clear();
+        self.template_index = 0;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        if x >= line.len() {
-            self.cursor_x = x;
-            self.cursor_locked = false;
-            self.update_bracket_matching();
-            self.dirty = true;
+    fn template_vars(&self, path: &Path) -> Vec<(&'static str, String)> {
+        let dir = path
+            .parent()
+            .map(|parent| fs::canonicalize(parent).unwrap_or_else(|_| parent.to_path_buf()))
+            .unwrap_or_else(|| PathBuf::from("."));
+        let name = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "project".into());
+        let file = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let author = self
+            .config
+            .header_author
+            .clone()
+            .or_else(|| git::user_name(&dir))
+            .or_else(|| env::var("USER").ok())
+            .or_else(|| env::var("USERNAME").ok())
+            .unwrap_or_default();
+        let license = self.config.header_license.clone().unwrap_or_default();
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let date = local_history::format_timestamp(now_ms)[..10].to_string();
+        let year = date[..4].to_string();
+        vec![
+            ("name", name),
+            ("file", file),
+            ("author", author),
+            ("license", license),
+            ("year", year),
+            ("date", date),
+        ]
+    }
+
+    fn file_header_for(&self, path: &Path) -> Option<String> {
+        let ext = path.extension()?.to_string_lossy().to_lowercase();
+        let template = self.config.headers.get(&ext)?;
+        let vars = self.template_vars(path);
+        let vars: Vec<(&str, &str)> = vars.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        Some(templates::fill(template, &vars))
+    }
+
+    // Index 0 in the picker is "Empty file", so templates start at 1.
+    fn confirm_template_picker(&mut self) {
+        let template = self
+            .template_index
+            .checked_sub(1)
+            .and_then(|index| self.template_choices.get(index).copied());
+        self.cancel_template_picker();
+        let Some(template) = template else {
             return;
-        }
+        };
 
-        let is_special_char = |c: char| -> bool {
-            matches!(
-                c,
-                '.' | ','
-                    | '['
-                    | ']'
-                    | '{'
-                    | '}'
-                    | '$'
-                    | '('
-                    | ')'
-                    | ';'
-                    | ':'
-                    | '!'
-                    | '?'
-                    | '@'
-                    | '#'
-                    | '%'
-                    | '^'
-                    | '&'
-                    | '*'
-                    | '+'
-                    | '-'
-                    | '='
-                    | '/'
-                    | '\\'
-                    | '|'
-                    | '<'
-                    | '>'
-                    | '`'
-                    | '\''
-                    | '"'
-            )
+        let Some(path) = self.file_path.clone() else {
+            return;
         };
+        let vars = self.template_vars(&path);
+        let vars: Vec<(&str, &str)> = vars.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        let contents = template.render(&vars);
 
-        let current_char = line[x];
+        self.save_history_state();
+        self.buffer = contents.lines().map(|l| l.chars().collect()).collect();
+        if self.buffer.is_empty() {
+            self.buffer.push(vec![]);
+        }
+        self.cursor_y = 0;
+        self.cursor_x = 0;
+        self.mark_file_dirty();
+        self.status = format!("Filled from template: {} (unsaved)", template.label);
+    }
 
-        if is_special_char(current_char) {
-            x += 1;
-        } else if current_char.is_alphanumeric() || current_char == '_' {
-            while x < line.len() {
-                let c = line[x];
-                if c.is_alphanumeric() || c == '_' {
-                    x += 1;
+    fn reload_tree_at_parent(&mut self, parent: &std::path::Path) {
+        for (i, node) in self.tree.iter().enumerate() {
+            if node.path == *parent && node.is_dir {
+                if node.expanded {
+                    let depth = node.depth;
+                    let remove_start = i + 1;
+                    while remove_start < self.tree.len() && self.tree[remove_start].depth > depth {
+                        self.tree.remove(remove_start);
+                    }
+
+                    if let Ok(entries) = fs::read_dir(parent) {
+                        for (offset, e) in entries.flatten().enumerate() {
+                            let meta = e.metadata().unwrap();
+                            self.tree.insert(
+                                i + 1 + offset,
+                                FileNode {
+                                    name: e.file_name().to_string_lossy().into(),
+                                    path: e.path(),
+                                    is_dir: meta.is_dir(),
+                                    expanded: false,
+                                    depth: depth + 1,
+                                },
+                            );
+                        }
+                    }
                 } else {
-                    break;
+                    self.toggle_dir(i);
                 }
+                break;
             }
         }
-
-        self.cursor_x = x;
-        self.cursor_locked = false;
-        self.update_bracket_matching();
         self.dirty = true;
     }
 
-    fn delete_word_backward(&mut self) {
-        if self.cursor_x == 0 {
-            if self.cursor_y > 0 {
-                self.save_history_state();
-                let current_line = self.buffer.remove(self.cursor_y);
-                self.cursor_y -= 1;
-                self.cursor_x = self.buffer[self.cursor_y].len();
-                self.buffer[self.cursor_y].extend(current_line);
-                self.mark_file_dirty();
-            }
+    fn start_delete(&mut self) {
+        if !self.show_tree || self.tree.is_empty() {
             return;
         }
 
-        let line = &self.buffer[self.cursor_y];
-        let mut start = self.cursor_x;
-
-        let is_special_char = |c: char| -> bool {
-            matches!(
-                c,
-                '.' | ','
-                    | '['
-                    | ']'
-                    | '{'
-                    | '}'
-                    | '$'
-                    | '('
-                    | ')'
-                    | ';'
-                    | ':'
-                    | '!'
-                    | '?'
-                    | '@'
-                    | '#'
-                    | '%'
-                    | '^'
-                    | '&'
-                    | '*'
-                    | '+'
-                    | '-'
-                    | '='
-                    | '/'
-                    | '\\'
-                    | '|'
-                    | '<'
-                    | '>'
-                    | '`'
-                    | '\''
-                    | '"'
-            )
+        let selected_node = &self.tree[self.tree_cursor];
+        self.delete_target = Some(selected_node.path.clone());
+        self.mode = EditorMode::DeleteConfirm;
+        let item_type = if selected_node.is_dir {
+            "folder"
+        } else {
+            "file"
         };
+        self.status = format!("Delete {}? (Y/N)", item_type);
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        while start > 0 && (line[start - 1] == ' ' || line[start - 1] == '\t') {
-            start -= 1;
-        }
+    fn confirm_delete(&mut self) -> io::Result<()> {
+        let target = self.delete_target.clone();
 
-        if start < self.cursor_x {
-            self.save_history_state();
-            let line = &mut self.buffer[self.cursor_y];
-            line.drain(start..self.cursor_x);
-            self.cursor_x = start;
-            self.mark_file_dirty();
-            return;
-        }
+        if let Some(target) = target {
+            let is_dir = target.is_dir();
+            let parent = target
+                .parent()
+                .map(|p| p.to_path_buf())
+                .unwrap_or_else(|| PathBuf::from("."));
 
-        if start > 0 {
-            let prev_char = line[start - 1];
+            if is_dir {
+                fs::remove_dir_all(&target)?;
+            } else {
+                fs::remove_file(&target)?;
+            }
 
-            if is_special_char(prev_char) {
-                start -= 1;
-            } else if prev_char.is_alphanumeric() || prev_char == '_' {
-                while start > 0 {
-                    let c = line[start - 1];
-                    if c.is_alphanumeric() || c == '_' {
-                        start -= 1;
-                    } else {
+            if let Some(current_path) = &self.file_path {
+                if current_path == &target {
+                    self.buffer = vec![vec![]];
+                    self.file_path = None;
+                    self.file_name = None;
+                    self.language = Language::None;
+                    self.local_options = LocalOptions::default();
+                }
+            }
+            self.pinned_files.remove(&target);
+
+            if parent.to_string_lossy() == "." {
+                self.load_root(".");
+            } else {
+                self.reload_tree_at_parent(&parent);
+                for (i, node) in self.tree.iter().enumerate() {
+                    if node.path == parent {
+                        self.tree_cursor = i;
                         break;
                     }
                 }
             }
         }
 
-        if start < self.cursor_x {
-            self.save_history_state();
-            let line = &mut self.buffer[self.cursor_y];
-            line.drain(start..self.cursor_x);
-            self.cursor_x = start;
-            self.mark_file_dirty();
-        }
+        self.cancel_delete();
+        Ok(())
     }
 
-    fn delete_word_forward(&mut self) {
-        let line = &self.buffer[self.cursor_y];
+    fn cancel_delete(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.delete_target = None;
+        self.status =
+            "Ctrl+O Tree | Ctrl+S Save | Ctrl+F Find | Ctrl+Z Undo | Ctrl+Y Redo | Ctrl+Q Quit"
+                .into();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        if self.cursor_x >= line.len() {
-            if self.cursor_y + 1 < self.buffer.len() {
-                self.save_history_state();
-                let next_line = self.buffer.remove(self.cursor_y + 1);
-                self.buffer[self.cursor_y].extend(next_line);
-                self.mark_file_dirty();
-            }
+    fn start_rename(&mut self) {
+        if !self.show_tree || self.tree.is_empty() {
             return;
         }
 
-        let mut end = self.cursor_x;
+        let selected_node = &self.tree[self.tree_cursor];
+        self.rename_target = Some(selected_node.path.clone());
+        self.rename_name = selected_node.name.chars().collect();
+        self.mode = EditorMode::Rename;
+        self.status = "Rename: ".into();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        let is_special_char = |c: char| -> bool {
-            matches!(
-                c,
-                '.' | ','
-                    | '['
-                    | ']'
-                    | '{'
-                    | '}'
-                    | '$'
-                    | '('
-                    | ')'
-                    | ';'
-                    | ':'
-                    | '!'
-                    | '?'
-                    | '@'
-                    | '#'
-                    | '%'
-                    | '^'
-                    | '&'
-                    | '*'
-                    | '+'
-                    | '-'
-                    | '='
-                    | '/'
-                    | '\\'
-                    | '|'
-                    | '<'
-                    | '>'
-                    | '`'
-                    | '\''
-                    | '"'
-            )
-        };
+    fn cancel_rename(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.rename_target = None;
+        self.rename_name.clear();
+        self.status =
+            "Ctrl+O Tree | Ctrl+S Save | Ctrl+F Find | Ctrl+Z Undo | Ctrl+Y Redo | Ctrl+Q Quit"
+                .into();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        while end < line.len() && (line[end] == ' ' || line[end] == '\t') {
-            end += 1;
+    fn confirm_rename(&mut self) -> io::Result<()> {
+        if self.rename_name.is_empty() || self.prompt_refused() {
+            return Ok(());
         }
 
-        if end > self.cursor_x {
-            self.save_history_state();
-            let line = &mut self.buffer[self.cursor_y];
-            line.drain(self.cursor_x..end);
-            self.mark_file_dirty();
-            return;
-        }
+        let target = self.rename_target.clone();
 
-        if end < line.len() {
-            let current_char = line[end];
+        let new_name: String = self.rename_name.iter().collect();
+        if let Some(target) = target {
+            let parent = target
+                .parent()
+                .map(|p| p.to_path_buf())
+                .unwrap_or_else(|| PathBuf::from("."));
+            let new_path = parent.join(&new_name);
 
-            if is_special_char(current_char) {
-                end += 1;
-            } else if current_char.is_alphanumeric() || current_char == '_' {
-                while end < line.len() {
-                    let c = line[end];
-                    if c.is_alphanumeric() || c == '_' {
-                        end += 1;
-                    } else {
+            fs::rename(&target, &new_path)?;
+            if let Some(options) = self.buffer_options.remove(&target) {
+                self.buffer_options.insert(new_path.clone(), options);
+            }
+            if self.pinned_files.remove(&target) {
+                self.pinned_files.insert(new_path.clone());
+            }
+
+            if let Some(current_path) = &self.file_path {
+                if current_path == &target {
+                    self.file_path = Some(new_path.clone());
+                    self.file_name = Some(new_name.clone());
+                }
+            }
+
+            if parent.to_string_lossy() == "." {
+                self.load_root(".");
+                for (i, node) in self.tree.iter().enumerate() {
+                    if node.path == new_path {
+                        self.tree_cursor = i;
+                        break;
+                    }
+                }
+            } else {
+                self.reload_tree_at_parent(&parent);
+                for (i, node) in self.tree.iter().enumerate() {
+                    if node.path == new_path {
+                        self.tree_cursor = i;
                         break;
                     }
                 }
             }
+            self.needs_full_redraw = true;
         }
 
-        let line = &self.buffer[self.cursor_y];
-        while end < line.len() && (line[end] == ' ' || line[end] == '\t') {
-            end += 1;
-        }
-
-        if end > self.cursor_x {
-            self.save_history_state();
-            let line = &mut self.buffer[self.cursor_y];
-            line.drain(self.cursor_x..end);
-            self.mark_file_dirty();
-        }
+        self.cancel_rename();
+        Ok(())
     }
 
-    fn get_word_at_cursor(&self) -> Option<(String, usize)> {
-        if self.cursor_y >= self.buffer.len() {
-            return None;
-        }
-
-        let line = &self.buffer[self.cursor_y];
-        if self.cursor_x == 0 {
-            return None;
+    fn update_search(&mut self) {
+        if self.search_query.is_empty() {
+            self.search_results.clear();
+            return;
         }
 
-        let mut start = self.cursor_x;
+        let query: String = self.search_query.iter().collect();
+        self.search_results.clear();
 
-        while start > 0 {
-            let c = line[start - 1];
-            if c.is_alphanumeric() || c == '_' {
-                start -= 1;
-            } else {
-                break;
+        for (y, line) in self.buffer.iter().enumerate() {
+            let line_str: String = line.iter().collect();
+            let mut start = 0;
+            while let Some(pos) = line_str[start..].find(&query) {
+                let absolute_pos = start + pos;
+                self.search_results.push((y, absolute_pos));
+                start = absolute_pos + 1;
             }
         }
 
-        if start == self.cursor_x {
-            return None;
+        if !self.search_results.is_empty() {
+            self.current_search_index = 0;
+            self.jump_to_search_result(0);
         }
+    }
 
-        let word: String = line[start..self.cursor_x].iter().collect();
-        Some((word, start))
+    fn jump_to_search_result(&mut self, index: usize) {
+        if let Some(&(y, x)) = self.search_results.get(index) {
+            self.cursor_y = y;
+            self.cursor_x = x;
+            self.current_search_index = index;
+            self.dirty = true;
+        }
     }
 
-    fn collect_words_from_buffer(&self) -> Vec<String> {
-        let mut words: HashSet<String> = HashSet::new();
+    fn next_search_result(&mut self) {
+        if !self.search_results.is_empty() {
+            self.current_search_index = (self.current_search_index + 1) % self.search_results.len();
+            self.jump_to_search_result(self.current_search_index);
+        }
+    }
 
-        for line in &self.buffer {
-            let line_str: String = line.iter().collect();
-            let mut word = String::new();
-            for c in line_str.chars() {
-                if c.is_alphanumeric() || c == '_' {
-                    word.push(c);
-                } else {
-                    if word.len() >= 2 {
-                        words.insert(word.clone());
-                    }
-                    word.clear();
-                }
-            }
-            if word.len() >= 2 {
-                words.insert(word);
-            }
+    fn insert(&mut self, c: char) {
+        self.save_history_state();
+        if !(c.is_alphanumeric() || c == '_') {
+            self.record_word_before_cursor();
         }
 
-        words.into_iter().collect()
-    }
+        let closing = match c {
+            '(' => Some(')'),
+            '[' => Some(']'),
+            '{' => Some('}'),
+            // Quotes double as apostrophes in prose, so they are not paired there.
+            '"' | '\'' if self.is_prose_buffer() => None,
+            '"' => Some('"'),
+            '\'' => Some('\''),
+            _ => None,
+        };
 
-    fn start_autocomplete(&mut self) {
-        if let Some((prefix, _start)) = self.get_word_at_cursor() {
-            if prefix.is_empty() {
-                return;
-            }
+        self.buffer[self.cursor_y].insert(self.cursor_x, c);
+        self.cursor_x += 1;
 
-            let all_words = self.collect_words_from_buffer();
-            let mut suggestions: Vec<String> = all_words
-                .into_iter()
-                .filter(|w| w.starts_with(&prefix) && w != &prefix)
-                .collect();
+        if let Some(close) = closing {
+            self.buffer[self.cursor_y].insert(self.cursor_x, close);
+        }
 
-            let keywords = get_keywords(&self.language);
-            for kw in keywords {
-                if kw.starts_with(&prefix) && kw != &prefix {
-                    let kw_str = kw.to_string();
-                    if !suggestions.contains(&kw_str) {
-                        suggestions.push(kw_str);
-                    }
-                }
+        if c == '>' && self.is_markup_buffer() {
+            if let Some(tag) = tags::closing_tag_for(&self.buffer[self.cursor_y], self.cursor_x) {
+                let at = self.cursor_x;
+                self.buffer[self.cursor_y].splice(at..at, tag.chars());
             }
+        }
 
-            suggestions.sort();
+        self.cursor_locked = false;
+        self.mark_file_dirty();
+    }
 
-            if !suggestions.is_empty() {
-                self.autocomplete_prefix = prefix;
-                self.autocomplete_suggestions = suggestions;
-                self.autocomplete_index = 0;
-                self.mode = EditorMode::Autocomplete;
-                self.needs_full_redraw = true;
-                self.dirty = true;
-            }
+    fn backspace(&mut self) {
+        if self.cursor_x > 0 {
+            self.save_history_state();
+            self.cursor_x -= 1;
+            self.buffer[self.cursor_y].remove(self.cursor_x);
+            self.cursor_locked = false;
+            self.mark_file_dirty();
+        } else if self.cursor_y > 0 {
+            self.save_history_state();
+            let current_line = self.buffer.remove(self.cursor_y);
+            self.cursor_y -= 1;
+            self.cursor_x = self.buffer[self.cursor_y].len();
+            self.buffer[self.cursor_y].extend(current_line);
+            self.cursor_locked = false;
+            self.mark_file_dirty();
+            self.update_bracket_matching();
         }
     }
 
-    fn apply_autocomplete(&mut self) {
-        if self.autocomplete_suggestions.is_empty() {
-            self.cancel_autocomplete();
-            return;
+    fn delete(&mut self) {
+        if self.cursor_x < self.buffer[self.cursor_y].len() {
+            self.save_history_state();
+            self.buffer[self.cursor_y].remove(self.cursor_x);
+            self.cursor_locked = false;
+            self.mark_file_dirty();
+        } else if self.cursor_x == self.buffer[self.cursor_y].len()
+            && self.cursor_y + 1 < self.buffer.len()
+        {
+            self.save_history_state();
+            let next_line = self.buffer.remove(self.cursor_y + 1);
+            self.buffer[self.cursor_y].extend(next_line);
+            self.cursor_locked = false;
+            self.mark_file_dirty();
+            self.update_bracket_matching();
         }
+    }
 
-        let selected = &self.autocomplete_suggestions[self.autocomplete_index].clone();
+    fn newline(&mut self) {
+        self.save_history_state();
+        self.record_word_before_cursor();
+        let rest = self.buffer[self.cursor_y].split_off(self.cursor_x);
 
-        if let Some((_prefix, start)) = self.get_word_at_cursor() {
-            self.save_history_state();
+        let indent_level = self.calculate_indent_level();
 
-            let line = &mut self.buffer[self.cursor_y];
-            line.drain(start..self.cursor_x);
-            self.cursor_x = start;
+        self.buffer.insert(self.cursor_y + 1, rest);
+        self.cursor_y += 1;
+        self.cursor_x = 0;
 
-            for c in selected.chars() {
-                self.buffer[self.cursor_y].insert(self.cursor_x, c);
+        if indent_level > 0 {
+            let indent = self.get_indent_string(indent_level);
+            for c in indent.chars() {
+                self.buffer[self.cursor_y].insert(0, c);
                 self.cursor_x += 1;
             }
-
-            self.mark_file_dirty();
         }
 
-        self.cancel_autocomplete();
-    }
-
-    fn cancel_autocomplete(&mut self) {
-        self.mode = EditorMode::Normal;
-        self.autocomplete_suggestions.clear();
-        self.autocomplete_index = 0;
-        self.autocomplete_prefix.clear();
-        self.needs_full_redraw = true;
-        self.dirty = true;
+        self.cursor_locked = false;
+        self.mark_file_dirty();
+        self.update_bracket_matching();
     }
 
-    fn next_autocomplete(&mut self) {
-        if !self.autocomplete_suggestions.is_empty() {
-            self.autocomplete_index =
-                (self.autocomplete_index + 1) % self.autocomplete_suggestions.len();
-            self.dirty = true;
+    fn calculate_indent_level(&self) -> usize {
+        if self.cursor_y == 0 {
+            return 0;
         }
-    }
 
-    fn prev_autocomplete(&mut self) {
-        if !self.autocomplete_suggestions.is_empty() {
-            if self.autocomplete_index == 0 {
-                self.autocomplete_index = self.autocomplete_suggestions.len() - 1;
+        let prev_line = &self.buffer[self.cursor_y - 1];
+
+        let mut prev_indent = 0;
+        for c in prev_line.iter() {
+            if *c == ' ' {
+                prev_indent += 1;
+            } else if *c == '\t' {
+                prev_indent += self.indent_width();
             } else {
-                self.autocomplete_index -= 1;
+                break;
             }
-            self.dirty = true;
+        }
+
+        let prev_line_str: String = prev_line.iter().collect();
+        let trimmed_prev: String = prev_line_str.trim_start().to_string();
+
+        let increase_indent = match self.language {
+            Language::Python => {
+                trimmed_prev.ends_with(':')
+                    || trimmed_prev.starts_with("if ")
+                    || trimmed_prev.starts_with("elif ")
+                    || trimmed_prev.starts_with("else ")
+                    || trimmed_prev.starts_with("for ")
+                    || trimmed_prev.starts_with("while ")
+                    || trimmed_prev.starts_with("def ")
+                    || trimmed_prev.starts_with("class ")
+                    || trimmed_prev.starts_with("try:")
+                    || trimmed_prev.starts_with("except ")
+                    || trimmed_prev.starts_with("finally:")
+                    || trimmed_prev.starts_with("with ")
+                    || trimmed_prev.starts_with("async ")
+                    || trimmed_prev == "else:"
+                    || trimmed_prev == "elif:"
+                    || trimmed_prev == "except:"
+                    || trimmed_prev == "finally:"
+                    || trimmed_prev == "try:"
+            }
+            Language::Rust
+            | Language::JavaScript
+            | Language::C
+            | Language::Cpp
+            | Language::Java => {
+                trimmed_prev.ends_with(" {")
+                    || trimmed_prev.ends_with("{")
+                    || (trimmed_prev.starts_with("if ") && !trimmed_prev.contains(";"))
+                    || (trimmed_prev.starts_with("for ") && !trimmed_prev.contains(";"))
+                    || (trimmed_prev.starts_with("while ") && !trimmed_prev.contains(";"))
+                    || trimmed_prev.starts_with("fn ")
+                    || trimmed_prev.starts_with("impl ")
+                    || trimmed_prev.starts_with("trait ")
+                    || trimmed_prev.starts_with("struct ")
+                    || trimmed_prev.starts_with("enum ")
+                    || trimmed_prev.starts_with("match ")
+                    || trimmed_prev.starts_with("unsafe ")
+                    || trimmed_prev.starts_with("loop ")
+                    || trimmed_prev.starts_with("function ")
+                    || trimmed_prev.starts_with("class ")
+                    || trimmed_prev.ends_with("=>")
+                    || trimmed_prev.ends_with("else {")
+                    || trimmed_prev.ends_with("try {")
+                    || trimmed_prev.ends_with("catch {")
+            }
+            Language::None => false,
+        };
+
+        if increase_indent {
+            prev_indent + self.indent_width()
+        } else {
+            prev_indent
         }
     }
 
-    fn execute_terminal_command(&mut self) {
-        self.write_terminal_bytes(b"\r");
+    fn indent_width(&self) -> usize {
+        self.local_options.indent_width.unwrap_or(INDENT_WIDTH)
     }
-}
 
-fn dashboard_action_title(action: &DashboardAction) -> String {
-    match action {
-        DashboardAction::OpenWorkspace(_) => "Open current workspace".into(),
-        DashboardAction::NewBuffer => "Start with an empty buffer".into(),
-        DashboardAction::RecentProject(path) => {
-            let name = path.file_name().unwrap_or_default().to_string_lossy();
-            format!("Project  {}", name)
-        }
-        DashboardAction::RecentFile(path) => {
-            let name = path.file_name().unwrap_or_default().to_string_lossy();
-            format!("File     {}", name)
-        }
+    fn get_indent_string(&self, level: usize) -> String {
+        " ".repeat(level)
     }
-}
 
-fn dashboard_action_meta(action: &DashboardAction, width: usize) -> String {
-    let raw = match action {
-        DashboardAction::OpenWorkspace(path) | DashboardAction::RecentProject(path) => {
-            path.to_string_lossy().into_owned()
+    fn indent(&mut self) {
+        self.save_history_state();
+        let indent = self.get_indent_string(self.indent_width());
+
+        for c in indent.chars() {
+            self.buffer[self.cursor_y].insert(self.cursor_x, c);
+            self.cursor_x += 1;
         }
-        DashboardAction::NewBuffer => "No file selected yet".into(),
-        DashboardAction::RecentFile(path) => path
-            .parent()
-            .map(|parent| parent.to_string_lossy().into_owned())
-            .unwrap_or_else(|| path.to_string_lossy().into_owned()),
-    };
-    shorten_text_middle(&raw, width)
-}
 
-fn draw_centered_text(
-    out: &mut io::Stdout,
-    row: u16,
-    cols: u16,
-    text: &str,
-    color: Color,
-    bold: bool,
-) -> io::Result<()> {
-    let text_width = text.chars().count() as u16;
-    let x = cols.saturating_sub(text_width) / 2;
-    execute!(out, cursor::MoveTo(x, row), SetForegroundColor(color))?;
-    if bold {
-        execute!(out, SetAttribute(Attribute::Bold))?;
+        self.cursor_locked = false;
+        self.needs_full_redraw = true;
+        self.dirty = true;
     }
-    write!(out, "{}", text)?;
-    execute!(
-        out,
-        SetAttribute(Attribute::Reset),
-        SetForegroundColor(Color::Reset)
-    )?;
-    Ok(())
-}
 
-fn draw_dashboard(ed: &mut Editor, out: &mut io::Stdout, cols: u16, rows: u16) -> io::Result<()> {
-    execute!(
-        out,
-        terminal::Clear(terminal::ClearType::All),
-        cursor::Hide,
-        SetAttribute(Attribute::Reset),
-        SetForegroundColor(Color::Reset),
-        crossterm::style::SetBackgroundColor(Color::Reset)
-    )?;
+    fn unindent(&mut self) {
+        self.save_history_state();
+        let width = self.indent_width();
+        let line = &mut self.buffer[self.cursor_y];
 
-    for (i, line) in DASHBOARD_LOGO.iter().enumerate() {
-        let color = match i {
-            0 | 1 => Color::Cyan,
-            2 | 3 => Color::Blue,
-            _ => Color::White,
-        };
-        draw_centered_text(out, 1 + i as u16, cols, line, color, true)?;
-    }
+        if line.is_empty() {
+            return;
+        }
 
-    draw_centered_text(
-        out,
-        8,
-        cols,
-        "Launch pad for recent work, fast starts, and clean sessions",
-        Color::DarkGrey,
-        false,
-    )?;
+        let mut removed = 0;
 
-    let panel_width = cols.saturating_sub(6).min(86);
-    let panel_x = cols.saturating_sub(panel_width) / 2;
-    let mut row = 10;
+        while removed < line.len() && removed < width {
+            if line[0] == ' ' {
+                line.remove(0);
+                removed += 1;
+                if self.cursor_x > 0 {
+                    self.cursor_x -= 1;
+                }
+            } else if line[0] == '\t' {
+                line.remove(0);
+                removed += width;
+                if self.cursor_x > 0 {
+                    self.cursor_x -= 1;
+                }
+            } else {
+                break;
+            }
+        }
 
-    execute!(
-        out,
-        cursor::MoveTo(panel_x, row),
-        SetForegroundColor(Color::DarkGrey),
-        SetAttribute(Attribute::Bold)
-    )?;
-    write!(out, "Start")?;
-    execute!(
-        out,
-        SetAttribute(Attribute::Reset),
-        SetForegroundColor(Color::Reset)
-    )?;
-    row += 1;
+        self.cursor_locked = false;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-    let actions = ed.dashboard_actions();
-    let meta_width = panel_width.saturating_sub(8) as usize;
-    let draw_item = |out: &mut io::Stdout,
-                     row: &mut u16,
-                     index: usize,
-                     action: &DashboardAction|
-     -> io::Result<()> {
-        if *row >= rows.saturating_sub(6) {
+    fn ensure_terminal_session(&mut self) -> io::Result<()> {
+        if self.terminal_session.is_some() {
             return Ok(());
         }
 
-        execute!(out, cursor::MoveTo(panel_x, *row))?;
-        let prefix = format!("{:>2}.", index + 1);
-        let title = dashboard_action_title(action);
-        let meta = dashboard_action_meta(action, meta_width);
+        let (cols, rows) = self.screen_size;
+        let pty_system = native_pty_system();
+        let pair = pty_system
+            .openpty(terminal_pty_size(rows, cols))
+            .map_err(to_io_error)?;
+        let shell = terminal_shell_program();
+        let cmd = CommandBuilder::new(shell);
+        let child = pair.slave.spawn_command(cmd).map_err(to_io_error)?;
+        let mut reader = pair.master.try_clone_reader().map_err(to_io_error)?;
+        let writer = pair.master.take_writer().map_err(to_io_error)?;
+        let (tx, rx) = mpsc::channel();
+        let waker = self.waker.clone();
 
-        if index == ed.dashboard_selection {
-            execute!(
-                out,
-                crossterm::style::SetBackgroundColor(Color::Blue),
-                SetForegroundColor(Color::White),
-                SetAttribute(Attribute::Bold)
-            )?;
-            write!(
-                out,
-                " {} {:<width$} ",
-                prefix,
-                shorten_text_middle(&title, panel_width.saturating_sub(6) as usize),
-                width = panel_width.saturating_sub(6) as usize
-            )?;
-            execute!(
-                out,
-                crossterm::style::SetBackgroundColor(Color::Reset),
-                SetForegroundColor(Color::Reset),
-                SetAttribute(Attribute::Reset)
-            )?;
-            *row += 1;
-            if *row < rows.saturating_sub(5) {
-                execute!(
-                    out,
-                    cursor::MoveTo(panel_x + 4, *row),
-                    SetForegroundColor(Color::Grey)
-                )?;
-                write!(
-                    out,
-                    "{:<width$}",
-                    meta,
-                    width = panel_width.saturating_sub(4) as usize
-                )?;
-                execute!(out, SetForegroundColor(Color::Reset))?;
-            }
-        } else {
-            execute!(out, SetForegroundColor(Color::White))?;
-            write!(
-                out,
-                " {} {}",
-                prefix,
-                shorten_text_middle(&title, panel_width.saturating_sub(6) as usize)
-            )?;
-            *row += 1;
-            if *row < rows.saturating_sub(5) {
-                execute!(
-                    out,
-                    cursor::MoveTo(panel_x + 4, *row),
-                    SetForegroundColor(Color::DarkGrey)
-                )?;
-                write!(out, "{}", meta)?;
-                execute!(out, SetForegroundColor(Color::Reset))?;
+        thread::spawn(move || {
+            let mut buffer = [0_u8; 4096];
+            loop {
+                match reader.read(&mut buffer) {
+                    Ok(0) => {
+                        let _ = tx.send("\n[Shell exited]\n".to_string());
+                        break;
+                    }
+                    Ok(n) => {
+                        let chunk = String::from_utf8_lossy(&buffer[..n]).into_owned();
+                        if tx.send(chunk).is_err() {
+                            break;
+                        }
+                        waker.wake();
+                    }
+                    Err(err) => {
+                        let _ = tx.send(format!("\n[Terminal read error: {}]\n", err));
+                        break;
+                    }
+                }
             }
-        }
+            drop(tx);
+            waker.wake();
+        });
 
-        *row += 1;
+        self.terminal_output_rx = Some(rx);
+        self.terminal_session = Some(TerminalSession {
+            master: pair.master,
+            writer,
+            child,
+        });
+        self.terminal_scroll = 0;
+        self.needs_full_redraw = true;
+        self.dirty = true;
         Ok(())
-    };
+    }
 
-    draw_item(out, &mut row, 0, &actions[0])?;
-    draw_item(out, &mut row, 1, &actions[1])?;
+    fn resize_terminal_session(&mut self, rows: u16, cols: u16) {
+        if let Some(session) = self.terminal_session.as_mut() {
+            let _ = session.master.resize(terminal_pty_size(rows, cols));
+        }
+        self.terminal_parser
+            .screen_mut()
+            .set_size(rows.max(1), cols.max(1));
+        self.sync_terminal_scrollback();
+    }
 
-    row += 1;
-    execute!(
-        out,
-        cursor::MoveTo(panel_x, row),
-        SetForegroundColor(Color::DarkGrey),
-        SetAttribute(Attribute::Bold)
-    )?;
-    write!(out, "Recent Projects")?;
-    execute!(
-        out,
-        SetAttribute(Attribute::Reset),
-        SetForegroundColor(Color::Reset)
-    )?;
-    row += 1;
+    fn sync_terminal_scrollback(&mut self) {
+        self.terminal_parser
+            .screen_mut()
+            .set_scrollback(self.terminal_scroll);
+        self.terminal_scroll = self.terminal_parser.screen().scrollback();
+    }
 
-    if ed.recent_projects.is_empty() {
-        execute!(
-            out,
-            cursor::MoveTo(panel_x + 4, row),
-            SetForegroundColor(Color::DarkGrey)
-        )?;
-        write!(out, "No recent projects yet")?;
-        execute!(out, SetForegroundColor(Color::Reset))?;
-        row += 2;
-    } else {
-        for (offset, action) in actions
-            .iter()
-            .skip(2)
-            .take(ed.recent_projects.len())
-            .enumerate()
-        {
-            draw_item(out, &mut row, 2 + offset, action)?;
-        }
+    fn process_terminal_bytes(&mut self, bytes: &[u8]) {
+        self.terminal_parser.process(bytes);
+        self.sync_terminal_scrollback();
     }
 
-    execute!(
-        out,
-        cursor::MoveTo(panel_x, row),
-        SetForegroundColor(Color::DarkGrey),
-        SetAttribute(Attribute::Bold)
-    )?;
-    write!(out, "Recent Files")?;
-    execute!(
-        out,
-        SetAttribute(Attribute::Reset),
-        SetForegroundColor(Color::Reset)
-    )?;
-    row += 1;
+    fn append_terminal_message(&mut self, message: &str) {
+        self.process_terminal_bytes(message.as_bytes());
+    }
 
-    if ed.recent_files.is_empty() {
-        execute!(
-            out,
-            cursor::MoveTo(panel_x + 4, row),
-            SetForegroundColor(Color::DarkGrey)
-        )?;
-        write!(out, "No recent files yet")?;
-        execute!(out, SetForegroundColor(Color::Reset))?;
-    } else {
-        for (offset, action) in actions
-            .iter()
-            .skip(2 + ed.recent_projects.len())
-            .take(ed.recent_files.len())
-            .enumerate()
-        {
-            draw_item(out, &mut row, 2 + ed.recent_projects.len() + offset, action)?;
+    fn scroll_terminal(&mut self, delta: isize) {
+        if delta.is_negative() {
+            self.terminal_scroll = self.terminal_scroll.saturating_sub(delta.unsigned_abs());
+        } else {
+            self.terminal_scroll = self.terminal_scroll.saturating_add(delta as usize);
         }
+        self.sync_terminal_scrollback();
+        self.needs_full_redraw = true;
+        self.dirty = true;
     }
 
-    let shortcuts_row = rows.saturating_sub(5);
-    execute!(
-        out,
-        cursor::MoveTo(panel_x, shortcuts_row),
-        SetForegroundColor(Color::DarkGrey),
-        SetAttribute(Attribute::Bold)
-    )?;
-    write!(out, "Shortcuts")?;
-    execute!(
-        out,
-        SetAttribute(Attribute::Reset),
-        SetForegroundColor(Color::Reset)
-    )?;
-
-    execute!(
-        out,
-        cursor::MoveTo(panel_x, shortcuts_row + 1),
-        SetForegroundColor(Color::Grey)
-    )?;
-    write!(out, "Ctrl+O Tree   Ctrl+1 Terminal   Ctrl+F Search")?;
-    execute!(out, cursor::MoveTo(panel_x, shortcuts_row + 2))?;
-    write!(out, "Ctrl+N File   Ctrl+M Folder     Ctrl+G Go to line")?;
-    execute!(out, SetForegroundColor(Color::Reset))?;
+    fn reset_terminal_scrollback(&mut self) {
+        if self.terminal_scroll != 0 {
+            self.terminal_scroll = 0;
+            self.sync_terminal_scrollback();
+        }
+    }
 
-    execute!(
-        out,
-        cursor::MoveTo(0, rows - 2),
-        SetForegroundColor(Color::DarkGrey)
-    )?;
-    write!(
-        out,
-        "{:^width$}",
-        "Enter open | Up/Down navigate | O workspace | N new buffer | R refresh | Ctrl+Q quit",
-        width = cols as usize
-    )?;
-    execute!(out, SetForegroundColor(Color::Reset))?;
+    fn write_terminal_bytes(&mut self, bytes: &[u8]) {
+        if let Err(err) = self.ensure_terminal_session() {
+            self.append_terminal_message(&format!("\r\n[Failed to start shell: {}]\r\n", err));
+            self.needs_full_redraw = true;
+            self.dirty = true;
+            return;
+        }
 
-    execute!(out, cursor::MoveTo(0, rows - 1))?;
-    write!(out, "{:^width$}", "Termi Dashboard", width = cols as usize)?;
+        self.reset_terminal_scrollback();
 
-    out.flush()?;
-    ed.needs_full_redraw = false;
-    Ok(())
-}
+        if let Some(session) = self.terminal_session.as_mut() {
+            let result = session
+                .writer
+                .write_all(bytes)
+                .and_then(|_| session.writer.flush());
 
-fn draw(ed: &mut Editor, out: &mut io::Stdout) -> io::Result<()> {
-    let (cols, rows) = terminal::size()?;
+            if result.is_err() {
+                self.append_terminal_message("\r\n[Failed to write to shell]\r\n");
+                self.terminal_output_rx = None;
+                self.terminal_session = None;
+            }
+        }
 
-    if matches!(ed.mode, EditorMode::Terminal) {
-        let formatted = ed.terminal_parser.screen().contents_formatted();
-        out.write_all(&formatted)?;
-        out.flush()?;
-        return Ok(());
+        self.needs_full_redraw = true;
+        self.dirty = true;
     }
 
-    if matches!(ed.mode, EditorMode::Dashboard) {
-        return draw_dashboard(ed, out, cols, rows);
-    }
+    fn terminal_key_bytes(&self, code: KeyCode, modifiers: KeyModifiers) -> Option<Vec<u8>> {
+        let application_cursor = self.terminal_parser.screen().application_cursor();
+        let alt = modifiers.contains(KeyModifiers::ALT);
+        let control = modifiers.contains(KeyModifiers::CONTROL);
 
-    execute!(
-        out,
-        cursor::Hide,
-        SetAttribute(Attribute::Reset),
-        SetForegroundColor(Color::Reset),
-        crossterm::style::SetBackgroundColor(Color::Reset)
-    )?;
+        let mut bytes = match code {
+            KeyCode::Enter => vec![b'\r'],
+            KeyCode::Backspace => vec![0x7f],
+            KeyCode::Tab => vec![b'\t'],
+            KeyCode::BackTab => b"\x1b[Z".to_vec(),
+            KeyCode::Esc => vec![0x1b],
+            KeyCode::Left => {
+                if application_cursor {
+                    b"\x1bOD".to_vec()
+                } else {
+                    b"\x1b[D".to_vec()
+                }
+            }
+            KeyCode::Right => {
+                if application_cursor {
+                    b"\x1bOC".to_vec()
+                } else {
+                    b"\x1b[C".to_vec()
+                }
+            }
+            KeyCode::Up => {
+                if application_cursor {
+                    b"\x1bOA".to_vec()
+                } else {
+                    b"\x1b[A".to_vec()
+                }
+            }
+            KeyCode::Down => {
+                if application_cursor {
+                    b"\x1bOB".to_vec()
+                } else {
+                    b"\x1b[B".to_vec()
+                }
+            }
+            KeyCode::Home => b"\x1b[H".to_vec(),
+            KeyCode::End => b"\x1b[F".to_vec(),
+            KeyCode::Delete => b"\x1b[3~".to_vec(),
+            KeyCode::Insert => b"\x1b[2~".to_vec(),
+            KeyCode::PageUp => b"\x1b[5~".to_vec(),
+            KeyCode::PageDown => b"\x1b[6~".to_vec(),
+            KeyCode::Char(c) => {
+                if control && !alt {
+                    let lower = c.to_ascii_lowercase();
+                    let control_byte = match lower {
+                        'a'..='z' => lower as u8 - b'a' + 1,
+                        ' ' | '@' => 0,
+                        '[' => 27,
+                        '\\' => 28,
+                        ']' => 29,
+                        '^' => 30,
+                        '_' => 31,
+                        _ => return None,
+                    };
+                    vec![control_byte]
+                } else {
+                    c.to_string().into_bytes()
+                }
+            }
+            _ => return None,
+        };
 
-    let max_lines = rows - STATUS_HEIGHT;
-    let tree_offset = if ed.show_tree { TREE_WIDTH } else { 0 };
-    let line_num_offset = if ed.show_line_numbers {
-        LINE_NUM_WIDTH
-    } else {
-        0
-    };
-    let text_offset = tree_offset + line_num_offset;
+        if alt && !control && matches!(code, KeyCode::Char(_)) {
+            bytes.insert(0, 0x1b);
+        }
 
-    let scroll_changed = ed.scroll_y != ed.last_scroll_y || ed.scroll_x != ed.last_scroll_x;
-    let tree_scroll_changed =
-        ed.show_tree && (ed.tree_scroll != ed.last_tree_scroll || ed.needs_full_redraw);
-    let should_clear = ed.needs_full_redraw || scroll_changed;
+        Some(bytes)
+    }
 
-    if should_clear {
-        if scroll_changed && !ed.needs_full_redraw {
-            for y in 0..max_lines {
-                execute!(out, cursor::MoveTo(0, y))?;
-                write!(out, "\x1b[K")?;
+    fn handle_terminal_key_event(&mut self, code: KeyCode, modifiers: KeyModifiers) {
+        match (code, modifiers) {
+            (KeyCode::Char('1'), KeyModifiers::CONTROL) => {
+                self.toggle_terminal();
+            }
+            (KeyCode::PageUp, m) if m.contains(KeyModifiers::SHIFT) => {
+                self.scroll_terminal(self.screen_size.1 as isize);
+            }
+            (KeyCode::PageDown, m) if m.contains(KeyModifiers::SHIFT) => {
+                self.scroll_terminal(-(self.screen_size.1 as isize));
+            }
+            _ => {
+                if let Some(bytes) = self.terminal_key_bytes(code, modifiers) {
+                    self.write_terminal_bytes(&bytes);
+                }
             }
-        } else {
-            execute!(out, terminal::Clear(terminal::ClearType::All))?;
         }
     }
 
-    if matches!(ed.mode, EditorMode::DeleteConfirm) {
-        let dialog_y = (rows / 2) as u16;
-        let dialog_x = (cols / 2).saturating_sub(20);
-        execute!(out, cursor::MoveTo(dialog_x, dialog_y))?;
-        execute!(out, SetForegroundColor(Color::Red))?;
-        execute!(out, SetAttribute(Attribute::Bold))?;
-        write!(out, "═══════════════════════════")?;
-        execute!(out, SetAttribute(Attribute::Reset))?;
-        execute!(out, SetForegroundColor(Color::White))?;
+    fn drain_terminal_output(&mut self) {
+        let mut disconnected = false;
+        let mut chunks = Vec::new();
 
-        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 1))?;
-        if let Some(target) = &ed.delete_target {
-            let item_type = if target.is_dir() { "Folder" } else { "File" };
-            let name = target.file_name().unwrap_or_default().to_string_lossy();
-            write!(out, " Delete {}?", item_type)?;
-            execute!(out, cursor::MoveTo(dialog_x, dialog_y + 2))?;
-            write!(out, "  {}", name)?;
+        if let Some(rx) = self.terminal_output_rx.as_ref() {
+            loop {
+                match rx.try_recv() {
+                    Ok(chunk) => chunks.push(chunk),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        disconnected = true;
+                        break;
+                    }
+                }
+            }
         }
 
-        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 3))?;
-        write!(out, " Y - Yes  |  N - No")?;
-        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 4))?;
-        execute!(out, SetForegroundColor(Color::Red))?;
-        write!(out, "═══════════════════════════")?;
-        execute!(out, SetAttribute(Attribute::Reset))?;
-        execute!(out, SetForegroundColor(Color::White))?;
+        if chunks.is_empty() && !disconnected {
+            return;
+        }
+
+        for chunk in chunks {
+            self.process_terminal_bytes(chunk.as_bytes());
+        }
+
+        if disconnected {
+            self.terminal_output_rx = None;
+            self.terminal_session = None;
+        }
+
+        self.needs_full_redraw = true;
+        self.dirty = true;
     }
 
-    if matches!(ed.mode, EditorMode::Rename) {
-        let dialog_y = (rows / 2) as u16;
-        let dialog_x = (cols / 2).saturating_sub(20);
-        execute!(out, cursor::MoveTo(dialog_x, dialog_y))?;
-        execute!(out, SetForegroundColor(Color::Cyan))?;
-        execute!(out, SetAttribute(Attribute::Bold))?;
-        write!(out, "═══════════════════════════")?;
-        execute!(out, SetAttribute(Attribute::Reset))?;
-        execute!(out, SetForegroundColor(Color::White))?;
+    fn close_terminal_session(&mut self) {
+        self.terminal_output_rx = None;
+        if let Some(mut session) = self.terminal_session.take() {
+            let _ = session.child.kill();
+            let _ = session.child.wait();
+        }
+    }
 
-        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 1))?;
-        write!(out, " Rename:")?;
-        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 2))?;
-        let rename_name: String = ed.rename_name.iter().collect();
-        write!(out, "  {}", rename_name)?;
-        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 3))?;
-        write!(out, " Enter - Confirm  |  Esc - Cancel")?;
-        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 4))?;
-        execute!(out, SetForegroundColor(Color::Cyan))?;
-        write!(out, "═══════════════════════════")?;
-        execute!(out, SetAttribute(Attribute::Reset))?;
-        execute!(out, SetForegroundColor(Color::White))?;
+    fn interrupt_terminal(&mut self) {
+        self.write_terminal_bytes(&[3]);
     }
 
-    if matches!(ed.mode, EditorMode::GoToLine) {
-        let dialog_y = (rows / 2) as u16;
-        let dialog_x = (cols / 2).saturating_sub(20);
-        execute!(out, cursor::MoveTo(dialog_x, dialog_y))?;
-        execute!(out, SetForegroundColor(Color::Yellow))?;
-        execute!(out, SetAttribute(Attribute::Bold))?;
-        write!(out, "═══════════════════════════")?;
-        execute!(out, SetAttribute(Attribute::Reset))?;
-        execute!(out, SetForegroundColor(Color::White))?;
+    fn toggle_terminal(&mut self) {
+        self.terminal_show = !self.terminal_show;
+        if self.terminal_show {
+            self.mode = EditorMode::Terminal;
+            if let Err(err) = self.ensure_terminal_session() {
+                self.append_terminal_message(&format!("\r\n[Failed to start shell: {}]\r\n", err));
+            } else {
+                let (cols, rows) = self.screen_size;
+                self.resize_terminal_session(rows, cols);
+            }
+        } else {
+            self.mode = EditorMode::Normal;
+        }
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 1))?;
-        write!(out, " Go to Line:")?;
-        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 2))?;
-        let line_input: String = ed.goto_line_input.iter().collect();
-        write!(out, "  {}", line_input)?;
-        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 3))?;
-        write!(out, " Enter - Go  |  Esc - Cancel")?;
-        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 4))?;
-        execute!(out, SetForegroundColor(Color::Yellow))?;
-        write!(out, "═══════════════════════════")?;
-        execute!(out, SetAttribute(Attribute::Reset))?;
-        execute!(out, SetForegroundColor(Color::White))?;
+    fn start_goto_line(&mut self) {
+        self.mode = EditorMode::GoToLine;
+        self.goto_line_input.clear();
+        self.status = "Go to line: ".into();
+        self.needs_full_redraw = true;
+        self.dirty = true;
     }
 
-    if ed.show_tree {
-        let tree_max_scroll = ed.tree.len().saturating_sub(max_lines as usize);
-        ed.tree_scroll = ed.tree_scroll.min(tree_max_scroll);
+    fn cancel_goto_line(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.goto_line_input.clear();
+        self.status = "Ctrl+O Tree | Ctrl+N File | Ctrl+M Folder | F2 Rename | Del Delete | Ctrl+S Save | Ctrl+F Find | Shift+Arrow Select | Ctrl+C Copy | Ctrl+V Paste | Ctrl+1 Terminal | Ctrl+Q Quit".into();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        let tree_scroll_changed = ed.tree_scroll != ed.last_tree_scroll || ed.needs_full_redraw;
-        if tree_scroll_changed {
-            for y in 0..max_lines {
-                execute!(out, cursor::MoveTo(0, y))?;
-                write!(out, "{:width$}", "", width = TREE_WIDTH as usize)?;
-            }
+    fn confirm_goto_line(&mut self) {
+        if self.goto_line_input.is_empty() || self.prompt_refused() {
+            return;
         }
 
-        for (screen_i, tree_i) in (ed.tree_scroll..ed.tree.len())
-            .enumerate()
-            .take(max_lines as usize)
-        {
-            if let Some(n) = ed.tree.get(tree_i) {
-                execute!(out, cursor::MoveTo(0, screen_i as u16))?;
-                let mark = if tree_i == ed.tree_cursor { ">" } else { " " };
-                let icon = if n.is_dir { "📁" } else { "📄" };
-                let prefix = if !n.is_dir && ed.dirty_files.contains(&n.path) {
-                    "."
+        let line_str: String = self.goto_line_input.iter().collect();
+        if let Ok(line_num) = line_str.parse::<usize>() {
+            if line_num > 0 && line_num <= self.buffer.len() {
+                self.cursor_y = line_num - 1;
+                if let Some(line) = self.buffer.get(self.cursor_y) {
+                    self.cursor_x = self.cursor_x.min(line.len());
                 } else {
-                    ""
-                };
-                let name_display = format!(
-                    "{} {}{} {}{}",
-                    mark,
-                    "  ".repeat(n.depth),
-                    icon,
-                    prefix,
-                    n.name
-                );
-                let truncated: String = name_display.chars().take(TREE_WIDTH as usize).collect();
-                write!(out, "{:<width$}", truncated, width = TREE_WIDTH as usize)?;
+                    self.cursor_x = 0;
+                }
+                self.cursor_locked = false;
+                self.dirty = true;
             }
         }
+        self.cancel_goto_line();
+    }
 
-        let visible_tree_items =
-            (ed.tree.len().saturating_sub(ed.tree_scroll)).min(max_lines as usize);
-        if visible_tree_items < max_lines as usize {
-            for y in visible_tree_items..max_lines as usize {
-                execute!(out, cursor::MoveTo(0, y as u16))?;
-                write!(out, "{:width$}", "", width = TREE_WIDTH as usize)?;
-            }
+    fn find_matching_bracket(&mut self, y: usize, x: usize) -> Option<(usize, usize)> {
+        if y >= self.buffer.len() {
+            return None;
         }
 
-        ed.last_tree_scroll = ed.tree_scroll;
-    }
-
-    if ed.show_line_numbers {
-        for screen_y in 0..max_lines {
-            let buf_y = ed.scroll_y + screen_y as usize;
-            if ed.buffer.get(buf_y).is_some() {
-                execute!(out, cursor::MoveTo(tree_offset, screen_y))?;
-                let line_num = buf_y + 1;
-                let line_num_str = format!("{:>4} │", line_num);
-                write!(out, "{}", line_num_str)?;
-            }
+        let line = &self.buffer[y];
+        if x >= line.len() {
+            return None;
         }
-    }
 
-    let available_width = (cols - text_offset) as usize;
-    let keywords = get_keywords(&ed.language);
-    for screen_y in 0..max_lines {
-        let buf_y = ed.scroll_y + screen_y as usize;
-        execute!(out, cursor::MoveTo(text_offset, screen_y))?;
-        if let Some(line) = ed.buffer.get(buf_y) {
-            let s: String = line.iter().collect();
-            let line_len = s.chars().count();
+        let char_at = line[x];
 
-            let start_char_idx = ed.scroll_x.min(line_len);
-            let end_char_idx = (ed.scroll_x + available_width).min(line_len);
+        let (open, close, forward) = match char_at {
+            '(' => ('(', ')', true),
+            ')' => (')', '(', false),
+            '[' => ('[', ']', true),
+            ']' => (']', '[', false),
+            '{' => ('{', '}', true),
+            '}' => ('}', '{', false),
+            _ => return None,
+        };
 
-            if start_char_idx >= line_len {
-                write!(out, "{:width$}", "", width = available_width)?;
-            } else {
-                let visible_part: String = s
-                    .chars()
-                    .skip(start_char_idx)
-                    .take(end_char_idx - start_char_idx)
-                    .collect();
+        let mut depth = 0;
+        let mut current_y = y;
+        let mut current_x = if forward { x + 1 } else { x.saturating_sub(1) };
 
-                let tokens = if ed.language != Language::None {
-                    tokenize_line(&s, &ed.language, &keywords)
-                } else {
-                    vec![(0, s.len(), TokenType::Normal)]
-                };
+        loop {
+            if current_y >= self.buffer.len() {
+                break;
+            }
+
+            let line = &self.buffer[current_y];
 
-                let is_search_mode =
-                    !ed.search_results.is_empty() && matches!(ed.mode, EditorMode::Search);
-                let query = if is_search_mode {
-                    ed.search_query.iter().collect::<String>()
+            while (forward && current_x < line.len()) || (!forward && current_x > 0) {
+                let c = if forward {
+                    line[current_x]
                 } else {
-                    String::new()
+                    line[current_x - 1]
                 };
 
-                let (actual_start_y, actual_start_x, actual_end_y, actual_end_x) =
-                    if let (Some((sel_start_y, sel_start_x)), Some((sel_end_y, sel_end_x))) =
-                        (ed.selection_start, ed.selection_end)
-                    {
-                        if (sel_start_y, sel_start_x) < (sel_end_y, sel_end_x) {
-                            (
-                                Some(sel_start_y),
-                                Some(sel_start_x),
-                                Some(sel_end_y),
-                                Some(sel_end_x),
-                            )
-                        } else {
-                            (
-                                Some(sel_end_y),
-                                Some(sel_end_x),
-                                Some(sel_start_y),
-                                Some(sel_start_x),
-                            )
-                        }
-                    } else {
-                        (None, None, None, None)
-                    };
-
-                let is_char_selected = |char_idx: usize| -> bool {
-                    if let (Some(start_y), Some(start_x), Some(end_y), Some(end_x)) =
-                        (actual_start_y, actual_start_x, actual_end_y, actual_end_x)
-                    {
-                        if buf_y >= start_y && buf_y <= end_y {
-                            if buf_y == start_y && buf_y == end_y {
-                                char_idx >= start_x && char_idx < end_x
-                            } else if buf_y == start_y {
-                                char_idx >= start_x
-                            } else if buf_y == end_y {
-                                char_idx < end_x
-                            } else {
-                                true
-                            }
-                        } else {
-                            false
-                        }
-                    } else {
-                        false
+                if c == open {
+                    depth += 1;
+                } else if c == close {
+                    if depth == 0 {
+                        return Some((current_y, if forward { current_x } else { current_x - 1 }));
                     }
-                };
-
-                if is_search_mode || ed.language != Language::None {
-                    let mut char_idx = start_char_idx;
-                    let mut token_idx = 0;
-                    let mut output_len = 0;
+                    depth -= 1;
+                }
 
-                    while char_idx < end_char_idx && output_len < available_width {
-                        while token_idx < tokens.len() && tokens[token_idx].1 <= char_idx {
-                            token_idx += 1;
-                        }
+                if forward {
+                    current_x += 1;
+                } else {
+                    current_x = current_x.saturating_sub(1);
+                }
+            }
 
-                        let token = if token_idx < tokens.len() {
-                            tokens[token_idx]
-                        } else {
-                            (s.len(), s.len(), TokenType::Normal)
-                        };
+            if forward {
+                current_y += 1;
+                current_x = 0;
+            } else {
+                if current_y == 0 {
+                    break;
+                }
+                current_y -= 1;
+                current_x = self.buffer[current_y].len();
+            }
+        }
 
-                        let search_match = if is_search_mode {
-                            let search_str: String = s.chars().skip(char_idx).collect();
-                            search_str.find(&query).map(|p| char_idx + p)
-                        } else {
-                            None
-                        };
+        None
+    }
 
-                        if let Some(match_start) = search_match {
-                            if match_start < end_char_idx {
-                                if match_start > char_idx {
-                                    let token_type = if token.0 <= char_idx && token.1 > char_idx {
-                                        token.2
-                                    } else {
-                                        TokenType::Normal
-                                    };
+    fn is_markup_buffer(&self) -> bool {
+        self.file_path.as_deref().is_some_and(tags::is_markup_path)
+    }
 
-                                    let mut current_idx = char_idx;
-                                    while current_idx < match_start && output_len < available_width
-                                    {
-                                        let is_selected = is_char_selected(current_idx);
-                                        let segment_end = {
-                                            let mut end = current_idx + 1;
-                                            while end < match_start
-                                                && is_char_selected(end) == is_selected
-                                            {
-                                                end += 1;
-                                            }
-                                            end
-                                        };
+    fn is_prose_buffer(&self) -> bool {
+        self.file_path.as_deref().is_some_and(prose::is_prose_path)
+    }
 
-                                        execute!(
-                                            out,
-                                            SetForegroundColor(get_token_color(token_type))
-                                        )?;
-                                        if is_selected {
-                                            execute!(out, SetAttribute(Attribute::Reverse))?;
-                                        }
-
-                                        let segment_text: String = s
-                                            .chars()
-                                            .skip(current_idx)
-                                            .take(segment_end - current_idx)
-                                            .collect();
-                                        write!(out, "{}", segment_text)?;
-                                        output_len += segment_text.chars().count();
+    /// Word count and reading time for the status bar; empty for code and
+    /// for large files, which it would rescan on every frame.
+    fn prose_stats(&self) -> String {
+        if !self.is_prose_buffer() || self.is_large_file() {
+            return String::new();
+        }
+        let words = prose::word_count(&self.buffer);
+        format!(
+            " | {} word{}, {} min read",
+            words,
+            if words == 1 { "" } else { "s" },
+            prose::reading_minutes(words)
+        )
+    }
 
-                                        if is_selected {
-                                            execute!(out, SetAttribute(Attribute::Reset))?;
-                                        }
-                                        execute!(out, SetForegroundColor(Color::White))?;
+    fn jump_to_matching_pair(&mut self) {
+        self.update_bracket_matching();
+        if let Some((y, x)) = self.matched_bracket {
+            self.cursor_y = y;
+            self.cursor_x = x;
+        } else if let Some((_, other)) = self.matched_tag {
+            self.cursor_y = other.y;
+            self.cursor_x = other.name_start;
+        } else {
+            self.status = "No matching bracket or tag".into();
+            self.dirty = true;
+            return;
+        }
+        self.cursor_locked = false;
+        self.update_bracket_matching();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-                                        current_idx = segment_end;
-                                    }
-                                }
+    fn update_bracket_matching(&mut self) {
+        self.matched_bracket = None;
+        self.matched_tag = None;
+        if self.is_markup_buffer() && self.cursor_y < self.buffer.len() {
+            let stale = !matches!(
+                &self.tag_index,
+                Some((path, edits, _)) if *path == self.file_path && *edits == self.buffer_edits
+            );
+            if stale {
+                self.tag_index = Some((
+                    self.file_path.clone(),
+                    self.buffer_edits,
+                    tags::TagIndex::scan(&self.buffer),
+                ));
+            }
+            self.matched_tag = self
+                .tag_index
+                .as_ref()
+                .and_then(|(_, _, index)| index.find_matching(self.cursor_y, self.cursor_x));
+        }
 
-                                let match_end =
-                                    (match_start + query.chars().count()).min(end_char_idx);
-                                let current_result = ed.search_results[ed.current_search_index];
+        if self.cursor_y >= self.buffer.len() {
+            return;
+        }
 
-                                let mut current_idx = match_start;
-                                while current_idx < match_end && output_len < available_width {
-                                    let is_selected = is_char_selected(current_idx);
-                                    let segment_end = {
-                                        let mut end = current_idx + 1;
-                                        while end < match_end
-                                            && is_char_selected(end) == is_selected
-                                        {
-                                            end += 1;
-                                        }
-                                        end
-                                    };
+        let line = &self.buffer[self.cursor_y];
+        if self.cursor_x >= line.len() {
+            return;
+        }
 
-                                    if current_result == (buf_y, match_start) {
-                                        execute!(out, SetAttribute(Attribute::Reverse))?;
-                                    } else {
-                                        execute!(out, SetAttribute(Attribute::Bold))?;
-                                    }
-                                    execute!(out, SetForegroundColor(Color::White))?;
+        if let Some(matched) = self.find_matching_bracket(self.cursor_y, self.cursor_x) {
+            self.matched_bracket = Some(matched);
+        } else {
+            if self.cursor_x > 0 {
+                if let Some(matched) = self.find_matching_bracket(self.cursor_y, self.cursor_x - 1)
+                {
+                    self.matched_bracket = Some(matched);
+                }
+            }
+        }
+    }
 
-                                    if is_selected && current_result != (buf_y, match_start) {
-                                        execute!(out, SetAttribute(Attribute::Reverse))?;
-                                    }
+    fn word_left(&mut self) {
+        if self.cursor_x == 0 {
+            if self.cursor_y > 0 {
+                self.cursor_y -= 1;
+                self.cursor_x = self.buffer[self.cursor_y].len();
+                self.cursor_locked = false;
+                self.dirty = true;
+            }
+            return;
+        }
 
-                                    let segment_text: String = s
-                                        .chars()
-                                        .skip(current_idx)
-                                        .take(segment_end - current_idx)
-                                        .collect();
-                                    write!(out, "{}", segment_text)?;
-                                    output_len += segment_text.chars().count();
+        let line = &self.buffer[self.cursor_y];
+        let mut x = self.cursor_x;
 
-                                    execute!(out, SetAttribute(Attribute::Reset))?;
-                                    current_idx = segment_end;
-                                }
-                                char_idx = match_end;
-                            } else {
-                                break;
-                            }
-                        } else {
-                            let token_end = token.1.min(end_char_idx);
-                            if token_end > char_idx {
-                                let is_matched_bracket = if let Some((match_y, match_x)) =
-                                    ed.matched_bracket
-                                {
-                                    (buf_y == match_y && char_idx <= match_x && match_x < token_end)
-                                        || (buf_y == ed.cursor_y
-                                            && char_idx <= ed.cursor_x
-                                            && ed.cursor_x < token_end)
-                                } else {
-                                    false
-                                };
+        while x > 0 && (line[x - 1] == ' ' || line[x - 1] == '\t') {
+            x -= 1;
+        }
 
-                                let mut current_idx = char_idx;
-                                while current_idx < token_end && output_len < available_width {
-                                    let is_selected = is_char_selected(current_idx);
-                                    let segment_end = {
-                                        let mut end = current_idx + 1;
-                                        while end < token_end
-                                            && is_char_selected(end) == is_selected
-                                        {
-                                            end += 1;
-                                        }
-                                        end
-                                    };
+        if x == 0 {
+            self.cursor_x = x;
+            self.cursor_locked = false;
+            self.update_bracket_matching();
+            self.dirty = true;
+            return;
+        }
 
-                                    let bracket_in_segment = is_matched_bracket
-                                        && ((buf_y == ed.cursor_y
-                                            && current_idx <= ed.cursor_x
-                                            && ed.cursor_x < segment_end)
-                                            || (if let Some((match_y, match_x)) = ed.matched_bracket
-                                            {
-                                                buf_y == match_y
-                                                    && current_idx <= match_x
-                                                    && match_x < segment_end
-                                            } else {
-                                                false
-                                            }));
+        let is_special_char = |c: char| -> bool {
+            matches!(
+                c,
+                '.' | ','
+                    | '['
+                    | ']'
+                    | '{'
+                    | '}'
+                    | '$'
+                    | '('
+                    | ')'
+                    | ';'
+                    | ':'
+                    | '!'
+                    | '?'
+                    | '@'
+                    | '#'
+                    | '%'
+                    | '^'
+                    | '&'
+                    | '*'
+                    | '+'
+                    | '-'
+                    | '='
+                    | '/'
+                    | '\\'
+                    | '|'
+                    | '<'
+                    | '>'
+                    | '`'
+                    | '\''
+                    | '"'
+            )
+        };
 
-                                    if bracket_in_segment {
-                                        execute!(out, SetForegroundColor(Color::Yellow))?;
-                                        execute!(out, SetAttribute(Attribute::Bold))?;
-                                    } else {
-                                        execute!(
-                                            out,
-                                            SetForegroundColor(get_token_color(token.2))
-                                        )?;
-                                    }
+        let prev_char = line[x - 1];
 
-                                    if is_selected {
-                                        execute!(out, SetAttribute(Attribute::Reverse))?;
-                                    }
+        if is_special_char(prev_char) {
+            x -= 1;
+        } else if prev_char.is_alphanumeric() || prev_char == '_' {
+            while x > 0 {
+                let c = line[x - 1];
+                if c.is_alphanumeric() || c == '_' {
+                    x -= 1;
+                } else {
+                    break;
+                }
+            }
+        }
 
-                                    let segment_text: String = s
-                                        .chars()
-                                        .skip(current_idx)
-                                        .take(segment_end - current_idx)
-                                        .collect();
-                                    write!(out, "{}", segment_text)?;
-                                    output_len += segment_text.chars().count();
+        self.cursor_x = x;
+        self.cursor_locked = false;
+        self.update_bracket_matching();
+        self.dirty = true;
+    }
 
-                                    if is_selected {
-                                        execute!(out, SetAttribute(Attribute::Reset))?;
-                                    }
-                                    if bracket_in_segment {
-                                        execute!(out, SetAttribute(Attribute::Reset))?;
-                                    }
-                                    execute!(out, SetForegroundColor(Color::White))?;
+    fn word_right(&mut self) {
+        let line = &self.buffer[self.cursor_y];
 
-                                    current_idx = segment_end;
-                                }
-                                char_idx = token_end;
-                                token_idx += 1;
-                            } else {
-                                break;
-                            }
-                        }
-                    }
+        if self.cursor_x >= line.len() {
+            if self.cursor_y + 1 < self.buffer.len() {
+                self.cursor_y += 1;
+                self.cursor_x = 0;
+                self.cursor_locked = false;
+                self.update_bracket_matching();
+                self.dirty = true;
+            }
+            return;
+        }
 
-                    let remaining_width = available_width.saturating_sub(output_len);
-                    if remaining_width > 0 {
-                        write!(out, "{:width$}", "", width = remaining_width)?;
-                    }
-                } else {
-                    if let (Some((sel_start_y, sel_start_x)), Some((sel_end_y, sel_end_x))) =
-                        (ed.selection_start, ed.selection_end)
-                    {
-                        let (actual_start_y, actual_start_x, actual_end_y, actual_end_x) =
-                            if (sel_start_y, sel_start_x) < (sel_end_y, sel_end_x) {
-                                (sel_start_y, sel_start_x, sel_end_y, sel_end_x)
-                            } else {
-                                (sel_end_y, sel_end_x, sel_start_y, sel_start_x)
-                            };
+        let mut x = self.cursor_x;
 
-                        if buf_y >= actual_start_y && buf_y <= actual_end_y {
-                            let mut char_idx = start_char_idx;
-                            let mut output_len = 0;
+        while x < line.len() && (line[x] == ' ' || line[x] == '\t') {
+            x += 1;
+        }
 
-                            while char_idx < end_char_idx && output_len < available_width {
-                                let is_selected =
-                                    if buf_y == actual_start_y && buf_y == actual_end_y {
-                                        char_idx >= actual_start_x && char_idx < actual_end_x
-                                    } else if buf_y == actual_start_y {
-                                        char_idx >= actual_start_x
-                                    } else if buf_y == actual_end_y {
-                                        char_idx < actual_end_x
-                                    } else {
-                                        true
-                                    };
+        if x >= line.len() {
+            self.cursor_x = x;
+            self.cursor_locked = false;
+            self.update_bracket_matching();
+            self.dirty = true;
+            return;
+        }
 
-                                let next_pos = if is_selected {
-                                    if buf_y == actual_start_y && buf_y == actual_end_y {
-                                        actual_end_x.min(end_char_idx)
-                                    } else if buf_y == actual_start_y {
-                                        end_char_idx
-                                    } else if buf_y == actual_end_y {
-                                        actual_end_x.min(end_char_idx)
-                                    } else {
-                                        end_char_idx
-                                    }
-                                } else {
-                                    if buf_y == actual_start_y {
-                                        actual_start_x.min(end_char_idx)
-                                    } else if buf_y == actual_end_y {
-                                        actual_end_x.min(end_char_idx)
-                                    } else {
-                                        end_char_idx
-                                    }
-                                };
+        let is_special_char = |c: char| -> bool {
+            matches!(
+                c,
+                '.' | ','
+                    | '['
+                    | ']'
+                    | '{'
+                    | '}'
+                    | '$'
+                    | '('
+                    | ')'
+                    | ';'
+                    | ':'
+                    | '!'
+                    | '?'
+                    | '@'
+                    | '#'
+                    | '%'
+                    | '^'
+                    | '&'
+                    | '*'
+                    | '+'
+                    | '-'
+                    | '='
+                    | '/'
+                    | '\\'
+                    | '|'
+                    | '<'
+                    | '>'
+                    | '`'
+                    | '\''
+                    | '"'
+            )
+        };
 
-                                if next_pos > char_idx {
-                                    let is_matched_bracket =
-                                        if let Some((match_y, match_x)) = ed.matched_bracket {
-                                            (buf_y == match_y
-                                                && char_idx <= match_x
-                                                && match_x < next_pos)
-                                                || (buf_y == ed.cursor_y
-                                                    && char_idx <= ed.cursor_x
-                                                    && ed.cursor_x < next_pos)
-                                        } else {
-                                            false
-                                        };
+        let current_char = line[x];
 
-                                    if is_selected {
-                                        execute!(out, SetAttribute(Attribute::Reverse))?;
-                                    }
+        if is_special_char(current_char) {
+            x += 1;
+        } else if current_char.is_alphanumeric() || current_char == '_' {
+            while x < line.len() {
+                let c = line[x];
+                if c.is_alphanumeric() || c == '_' {
+                    x += 1;
+                } else {
+                    break;
+                }
+            }
+        }
 
-                                    if is_matched_bracket {
-                                        execute!(out, SetForegroundColor(Color::Yellow))?;
-                                        execute!(out, SetAttribute(Attribute::Bold))?;
-                                    }
+        self.cursor_x = x;
+        self.cursor_locked = false;
+        self.update_bracket_matching();
+        self.dirty = true;
+    }
 
-                                    let text: String = s
-                                        .chars()
-                                        .skip(char_idx)
-                                        .take(next_pos - char_idx)
-                                        .collect();
-                                    write!(out, "{}", text)?;
+    fn delete_word_backward(&mut self) {
+        if self.cursor_x == 0 {
+            if self.cursor_y > 0 {
+                self.save_history_state();
+                let current_line = self.buffer.remove(self.cursor_y);
+                self.cursor_y -= 1;
+                self.cursor_x = self.buffer[self.cursor_y].len();
+                self.buffer[self.cursor_y].extend(current_line);
+                self.mark_file_dirty();
+            }
+            return;
+        }
 
-                                    if is_matched_bracket {
-                                        execute!(out, SetAttribute(Attribute::Reset))?;
-                                        execute!(out, SetForegroundColor(Color::White))?;
-                                    }
+        let line = &self.buffer[self.cursor_y];
+        let mut start = self.cursor_x;
 
-                                    if is_selected {
-                                        execute!(out, SetAttribute(Attribute::Reset))?;
-                                    }
-                                    output_len += text.chars().count();
-                                    char_idx = next_pos;
-                                } else {
-                                    break;
-                                }
-                            }
+        let is_special_char = |c: char| -> bool {
+            matches!(
+                c,
+                '.' | ','
+                    | '['
+                    | ']'
+                    | '{'
+                    | '}'
+                    | '$'
+                    | '('
+                    | ')'
+                    | ';'
+                    | ':'
+                    | '!'
+                    | '?'
+                    | '@'
+                    | '#'
+                    | '%'
+                    | '^'
+                    | '&'
+                    | '*'
+                    | '+'
+                    | '-'
+                    | '='
+                    | '/'
+                    | '\\'
+                    | '|'
+                    | '<'
+                    | '>'
+                    | '`'
+                    | '\''
+                    | '"'
+            )
+        };
 
-                            let remaining_width = available_width.saturating_sub(output_len);
-                            if remaining_width > 0 {
-                                write!(out, "{:width$}", "", width = remaining_width)?;
-                            }
-                        } else {
-                            write!(out, "{:<width$}", visible_part, width = available_width)?;
-                        }
-                    } else {
-                        write!(out, "{:<width$}", visible_part, width = available_width)?;
-                    }
-                }
-            }
+        while start > 0 && (line[start - 1] == ' ' || line[start - 1] == '\t') {
+            start -= 1;
+        }
+
+        if start < self.cursor_x {
+            self.save_history_state();
+            let line = &mut self.buffer[self.cursor_y];
+            line.drain(start..self.cursor_x);
+            self.cursor_x = start;
+            self.mark_file_dirty();
+            return;
         }
-    }
 
-    if matches!(ed.mode, EditorMode::Normal) || matches!(ed.mode, EditorMode::Autocomplete) {
-        let cursor_screen_x = ed.cursor_x.saturating_sub(ed.scroll_x);
-        let cursor_screen_y = ed.cursor_y.saturating_sub(ed.scroll_y);
+        if start > 0 {
+            let prev_char = line[start - 1];
 
-        if cursor_screen_y < max_lines as usize {
-            let available_width = (cols - text_offset) as usize;
-            if cursor_screen_x < available_width {
-                execute!(
-                    out,
-                    cursor::MoveTo(text_offset + cursor_screen_x as u16, cursor_screen_y as u16),
-                    SetAttribute(Attribute::Reverse)
-                )?;
-                if let Some(line) = ed.buffer.get(ed.cursor_y) {
-                    if ed.cursor_x < line.len() {
-                        write!(out, "{}", line[ed.cursor_x])?;
+            if is_special_char(prev_char) {
+                start -= 1;
+            } else if prev_char.is_alphanumeric() || prev_char == '_' {
+                while start > 0 {
+                    let c = line[start - 1];
+                    if c.is_alphanumeric() || c == '_' {
+                        start -= 1;
                     } else {
-                        write!(out, " ")?;
+                        break;
                     }
-                } else {
-                    write!(out, " ")?;
                 }
-                execute!(out, SetAttribute(Attribute::Reset))?;
             }
         }
-    }
-
-    if matches!(ed.mode, EditorMode::Autocomplete) && !ed.autocomplete_suggestions.is_empty() {
-        let cursor_screen_x = ed.cursor_x.saturating_sub(ed.scroll_x);
-        let cursor_screen_y = ed.cursor_y.saturating_sub(ed.scroll_y);
-
-        let popup_x = text_offset + cursor_screen_x as u16;
-        let popup_y = cursor_screen_y as u16 + 1;
 
-        let max_suggestions = 8.min(ed.autocomplete_suggestions.len());
-        let max_width = ed
-            .autocomplete_suggestions
-            .iter()
-            .take(max_suggestions)
-            .map(|s| s.len())
-            .max()
-            .unwrap_or(10)
-            .max(10);
-
-        for (i, suggestion) in ed
-            .autocomplete_suggestions
-            .iter()
-            .take(max_suggestions)
-            .enumerate()
-        {
-            let y = popup_y + i as u16;
-            if y >= max_lines {
-                break;
-            }
+        if start < self.cursor_x {
+            self.save_history_state();
+            let line = &mut self.buffer[self.cursor_y];
+            line.drain(start..self.cursor_x);
+            self.cursor_x = start;
+            self.mark_file_dirty();
+        }
+    }
 
-            execute!(out, cursor::MoveTo(popup_x, y))?;
+    fn delete_word_forward(&mut self) {
+        let line = &self.buffer[self.cursor_y];
 
-            if i == ed.autocomplete_index {
-                execute!(out, crossterm::style::SetBackgroundColor(Color::Blue))?;
-                execute!(out, SetForegroundColor(Color::White))?;
-                execute!(out, SetAttribute(Attribute::Bold))?;
-                write!(out, " {:<width$} ", suggestion, width = max_width)?;
-                execute!(out, SetAttribute(Attribute::Reset))?;
-                execute!(out, crossterm::style::SetBackgroundColor(Color::Reset))?;
-            } else {
-                execute!(out, crossterm::style::SetBackgroundColor(Color::DarkGrey))?;
-                execute!(out, SetForegroundColor(Color::White))?;
-                write!(out, " {:<width$} ", suggestion, width = max_width)?;
-                execute!(out, crossterm::style::SetBackgroundColor(Color::Reset))?;
+        if self.cursor_x >= line.len() {
+            if self.cursor_y + 1 < self.buffer.len() {
+                self.save_history_state();
+                let next_line = self.buffer.remove(self.cursor_y + 1);
+                self.buffer[self.cursor_y].extend(next_line);
+                self.mark_file_dirty();
             }
+            return;
         }
-    }
 
-    execute!(out, cursor::MoveTo(0, rows - 1))?;
-    let status_text = match ed.mode {
-        EditorMode::Dashboard => "Dashboard".to_string(),
-        EditorMode::Search => {
-            let query: String = ed.search_query.iter().collect();
-            format!(
-                "Search: {} | {} results found{}",
-                query,
-                ed.search_results.len(),
-                if !ed.search_results.is_empty() {
-                    format!(
-                        " ({}/{})",
-                        ed.current_search_index + 1,
-                        ed.search_results.len()
-                    )
-                } else {
-                    String::new()
-                }
+        let mut end = self.cursor_x;
+
+        let is_special_char = |c: char| -> bool {
+            matches!(
+                c,
+                '.' | ','
+                    | '['
+                    | ']'
+                    | '{'
+                    | '}'
+                    | '$'
+                    | '('
+                    | ')'
+                    | ';'
+                    | ':'
+                    | '!'
+                    | '?'
+                    | '@'
+                    | '#'
+                    | '%'
+                    | '^'
+                    | '&'
+                    | '*'
+                    | '+'
+                    | '-'
+                    | '='
+                    | '/'
+                    | '\\'
+                    | '|'
+                    | '<'
+                    | '>'
+                    | '`'
+                    | '\''
+                    | '"'
             )
+        };
+
+        while end < line.len() && (line[end] == ' ' || line[end] == '\t') {
+            end += 1;
         }
-        EditorMode::CreateFile | EditorMode::CreateDir => {
-            let name: String = ed.create_name.iter().collect();
-            let prompt = if matches!(ed.mode, EditorMode::CreateFile) {
-                "New file name"
-            } else {
-                "New folder name"
-            };
-            format!("{}: {}", prompt, name)
+
+        if end > self.cursor_x {
+            self.save_history_state();
+            let line = &mut self.buffer[self.cursor_y];
+            line.drain(self.cursor_x..end);
+            self.mark_file_dirty();
+            return;
         }
-        EditorMode::DeleteConfirm => ed.status.clone(),
-        EditorMode::Rename => {
-            let name: String = ed.rename_name.iter().collect();
-            format!("Rename: {}", name)
+
+        if end < line.len() {
+            let current_char = line[end];
+
+            if is_special_char(current_char) {
+                end += 1;
+            } else if current_char.is_alphanumeric() || current_char == '_' {
+                while end < line.len() {
+                    let c = line[end];
+                    if c.is_alphanumeric() || c == '_' {
+                        end += 1;
+                    } else {
+                        break;
+                    }
+                }
+            }
         }
-        EditorMode::GoToLine => {
-            let line_input: String = ed.goto_line_input.iter().collect();
-            format!("Go to line: {}", line_input)
+
+        let line = &self.buffer[self.cursor_y];
+        while end < line.len() && (line[end] == ' ' || line[end] == '\t') {
+            end += 1;
         }
-        EditorMode::Terminal => "Terminal mode".to_string(),
-        EditorMode::Autocomplete => {
-            format!(
-                "Autocomplete: ↑↓ select | Tab/Enter confirm | Esc cancel | {}/{}",
-                ed.autocomplete_index + 1,
-                ed.autocomplete_suggestions.len()
-            )
+
+        if end > self.cursor_x {
+            self.save_history_state();
+            let line = &mut self.buffer[self.cursor_y];
+            line.drain(self.cursor_x..end);
+            self.mark_file_dirty();
         }
-        EditorMode::Normal => {
-            format!(
-                "[{}] Line:{} Col:{} | {}",
-                ed.file_name.as_deref().unwrap_or("New"),
-                ed.cursor_y + 1,
-                ed.cursor_x + 1,
-                ed.status
-            )
+    }
+
+    fn get_word_at_cursor(&self) -> Option<(String, usize)> {
+        if self.cursor_y >= self.buffer.len() {
+            return None;
         }
-    };
 
-    let status_text_truncated: String = status_text.chars().take(cols as usize).collect();
-    write!(
-        out,
-        "{:<width$}",
-        status_text_truncated,
-        width = cols as usize
-    )?;
+        let line = &self.buffer[self.cursor_y];
+        if self.cursor_x == 0 {
+            return None;
+        }
 
-    out.flush()?;
+        let mut start = self.cursor_x;
 
-    ed.last_scroll_y = ed.scroll_y;
-    ed.last_scroll_x = ed.scroll_x;
-    ed.needs_full_redraw = false;
+        while start > 0 {
+            let c = line[start - 1];
+            if c.is_alphanumeric() || c == '_' {
+                start -= 1;
+            } else {
+                break;
+            }
+        }
 
-    Ok(())
-}
+        if start == self.cursor_x {
+            return None;
+        }
 
-fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
+        let word: String = line[start..self.cursor_x].iter().collect();
+        Some((word, start))
+    }
 
-    let initial_path = if args.len() > 1 { &args[1] } else { "." };
+    fn collect_words_from_buffer(&self) -> Vec<String> {
+        let mut words: HashSet<String> = HashSet::new();
 
-    terminal::enable_raw_mode()?;
-    let mut out = io::stdout();
-    execute!(
-        out,
-        terminal::EnterAlternateScreen,
-        cursor::Hide,
-        EnableMouseCapture
-    )?;
+        for line in &self.buffer {
+            let line_str: String = line.iter().collect();
+            let mut word = String::new();
+            for c in line_str.chars() {
+                if c.is_alphanumeric() || c == '_' {
+                    word.push(c);
+                } else {
+                    if word.len() >= 2 {
+                        words.insert(word.clone());
+                    }
+                    word.clear();
+                }
+            }
+            if word.len() >= 2 {
+                words.insert(word);
+            }
+        }
 
-    let mut ed = Editor::new_with_options(initial_path, args.len() == 1);
+        words.into_iter().collect()
+    }
 
-    loop {
-        let (cols, rows) = terminal::size()?;
-        ed.drain_terminal_output();
+    // The `/`-containing token before the cursor, split into the directory to
+    // list and the column where the file name being typed starts.
+    fn path_prefix_at_cursor(&self) -> Option<(PathBuf, usize)> {
+        let line = self.buffer.get(self.cursor_y)?;
+        let mut start = self.cursor_x;
+        while start > 0 && !matches!(line[start - 1], ' ' | '\t' | '"' | '\'' | '(' | '<' | ',') {
+            start -= 1;
+        }
+        let token: String = line[start..self.cursor_x].iter().collect();
+        let slash = token.rfind('/')?;
+        let dir = &token[..=slash];
+
+        let base = self
+            .file_path
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| self.dashboard_workspace.clone());
+        Some((base.join(dir), start + token[..=slash].chars().count()))
+    }
 
-        if !ed.cursor_locked {
-            ed.ensure_cursor_visible(rows, cols);
+    fn path_completions(&self) -> Vec<Completion> {
+        let Some((dir, start)) = self.path_prefix_at_cursor() else {
+            return vec![];
+        };
+        let typed_hidden = self.buffer[self.cursor_y].get(start) == Some(&'.');
+        let Ok(entries) = fs::read_dir(&dir) else {
+            return vec![];
+        };
+        entries
+            .flatten()
+            .filter_map(|entry| {
+                let mut name = entry.file_name().to_string_lossy().to_string();
+                if name.starts_with('.') && !typed_hidden {
+                    return None;
+                }
+                if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    name.push('/');
+                }
+                Some(Completion {
+                    label: name.clone(),
+                    insert_text: name,
+                    source: CompletionSource::Path,
+                    start,
+                })
+            })
+            .collect()
+    }
+
+    // Filters candidates against the text typed since their start column and
+    // orders them by the configured prefix, fuzzy and recency weights.
+    fn rank_completions(&self, candidates: Vec<Completion>) -> Vec<Completion> {
+        let settings = &self.config.autocomplete;
+        let line = &self.buffer[self.cursor_y];
+        let usage = self.word_usage.get(&self.word_usage_root());
+        let mut scored: Vec<(i64, Completion)> = candidates
+            .into_iter()
+            .filter_map(|candidate| {
+                let typed: String = line[candidate.start.min(self.cursor_x)..self.cursor_x]
+                    .iter()
+                    .collect();
+                if candidate.label == typed {
+                    return None;
+                }
+                let mut score = settings.fuzzy_weight * fuzzy::score(&typed, &candidate.label)?;
+                if candidate.label.starts_with(&typed) {
+                    score += settings.prefix_weight;
+                }
+                if let Some(used) = usage.and_then(|words| words.get(&candidate.label)) {
+                    let frequency = used.count.min(WORD_FREQUENCY_CAP) as i64;
+                    score += settings.frequency_weight * frequency / WORD_FREQUENCY_CAP as i64;
+                    let age = (self.word_usage_clock - used.last_used).min(WORD_RECENCY_WINDOW);
+                    score += settings.recent_weight * (WORD_RECENCY_WINDOW - age) as i64
+                        / WORD_RECENCY_WINDOW as i64;
+                }
+                Some((score, candidate))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.label.cmp(&b.1.label)));
+
+        let mut seen = HashSet::new();
+        scored
+            .into_iter()
+            .map(|(_, candidate)| candidate)
+            .filter(|candidate| seen.insert(candidate.label.clone()))
+            .take(settings.max_results)
+            .collect()
+    }
+
+    // Usage stats are shared by every buffer in the same project.
+    fn word_usage_root(&self) -> PathBuf {
+        match &self.file_path {
+            Some(path) => lsp::find_project_root(path),
+            None => self.dashboard_workspace.clone(),
+        }
+    }
+
+    fn record_word_usage(&mut self, word: &str) {
+        self.word_usage_clock += 1;
+        let clock = self.word_usage_clock;
+        let root = self.word_usage_root();
+        let used = self
+            .word_usage
+            .entry(root)
+            .or_default()
+            .entry(word.to_string())
+            .or_default();
+        used.count += 1;
+        used.last_used = clock;
+    }
+
+    // Called before a non-word character is typed: the identifier that ends at
+    // the cursor counts as used.
+    fn record_word_before_cursor(&mut self) {
+        if let Some((word, _)) = self.get_word_at_cursor() {
+            if word.chars().count() >= 2 && !word.chars().all(|c| c.is_ascii_digit()) {
+                self.record_word_usage(&word);
+            }
         }
+    }
 
-        if ed.dirty || ed.needs_full_redraw {
-            draw(&mut ed, &mut out)?;
-            ed.dirty = false;
-            ed.needs_full_redraw = false;
+    fn show_completions(&mut self, candidates: Vec<Completion>) {
+        let suggestions = self.rank_completions(candidates);
+        if suggestions.is_empty() {
+            return;
         }
+        self.autocomplete_suggestions = suggestions;
+        self.autocomplete_index = 0;
+        self.mode = EditorMode::Autocomplete;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
 
-        if event::poll(Duration::from_millis(100))? {
-            match event::read()? {
-                Event::Resize(_, _) => {
-                    let (cols, rows) = terminal::size()?;
-                    ed.resize_terminal_session(rows, cols);
-                    ed.needs_full_redraw = true;
+    fn completion_word(&self) -> Option<(String, usize)> {
+        let min_prefix = self.config.autocomplete.min_prefix.max(1);
+        self.get_word_at_cursor()
+            .filter(|(prefix, _)| prefix.chars().count() >= min_prefix)
+    }
+
+    // Candidates from every enabled source that answers synchronously.
+    fn local_completions(&self, word: Option<&(String, usize)>) -> Vec<Completion> {
+        let settings = &self.config.autocomplete;
+        let mut candidates = vec![];
+
+        if let Some((typed, start)) = word {
+            let candidate = |label: &str, insert_text: &str, source| Completion {
+                label: label.to_string(),
+                insert_text: insert_text.to_string(),
+                source,
+                start: *start,
+            };
+            if settings.buffer_words {
+                for word in self.collect_words_from_buffer() {
+                    candidates.push(candidate(&word, &word, CompletionSource::Buffer));
                 }
-                Event::Mouse(MouseEvent {
-                    kind,
-                    column,
-                    row,
-                    modifiers,
-                    ..
-                }) => {
-                    let (cols, rows) = terminal::size()?;
-                    if matches!(ed.mode, EditorMode::Terminal) {
-                        match kind {
-                            MouseEventKind::ScrollUp => {
-                                ed.scroll_terminal(TERMINAL_SCROLL_STEP as isize);
-                            }
-                            MouseEventKind::ScrollDown => {
-                                ed.scroll_terminal(-(TERMINAL_SCROLL_STEP as isize));
-                            }
-                            _ => {}
-                        }
-                    } else {
-                        match kind {
-                            MouseEventKind::Down(MouseButton::Left) => {
-                                ed.handle_mouse_click(
-                                    column,
-                                    row,
-                                    rows,
-                                    cols,
-                                    modifiers.contains(KeyModifiers::SHIFT),
-                                );
-                            }
-                            MouseEventKind::Drag(MouseButton::Left) => {
-                                ed.handle_mouse_drag(column, row, rows, cols);
-                            }
-                            MouseEventKind::Up(MouseButton::Left) => {
-                                ed.handle_mouse_release();
-                            }
-                            MouseEventKind::ScrollUp => {
-                                ed.handle_mouse_scroll(rows, true);
-                            }
-                            MouseEventKind::ScrollDown => {
-                                ed.handle_mouse_scroll(rows, false);
-                            }
-                            _ => {}
-                        }
+            }
+            if self.is_prose_buffer() {
+                if settings.dictionary {
+                    for word in prose::dictionary_words() {
+                        let word = prose::match_case(word, typed);
+                        candidates.push(candidate(&word, &word, CompletionSource::Dictionary));
                     }
                 }
-                Event::Key(KeyEvent {
-                    code,
-                    modifiers,
-                    kind: KeyEventKind::Press,
-                    ..
-                }) => {
-                    match ed.mode {
-                        EditorMode::Dashboard => match (code, modifiers) {
-                            (KeyCode::Char('q'), KeyModifiers::CONTROL) => {
-                                break;
-                            }
-                            (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
-                                if ed.dashboard_selection > 0 {
-                                    ed.dashboard_selection -= 1;
-                                    ed.dirty = true;
-                                }
-                            }
-                            (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
-                                let action_count = ed.dashboard_actions().len();
-                                if ed.dashboard_selection + 1 < action_count {
-                                    ed.dashboard_selection += 1;
-                                    ed.dirty = true;
-                                }
-                            }
-                            (KeyCode::Home, _) => {
-                                ed.dashboard_selection = 0;
-                                ed.dirty = true;
-                            }
-                            (KeyCode::End, _) => {
-                                ed.dashboard_selection =
-                                    ed.dashboard_actions().len().saturating_sub(1);
-                                ed.dirty = true;
-                            }
-                            (KeyCode::Enter, _) => {
-                                ed.activate_dashboard_selection();
-                            }
-                            (KeyCode::Esc, _) | (KeyCode::Char('o'), _) => {
-                                ed.dashboard_selection = 0;
-                                ed.activate_dashboard_selection();
-                            }
-                            (KeyCode::Char('n'), _) => {
-                                ed.dashboard_selection = 1;
-                                ed.activate_dashboard_selection();
-                            }
-                            (KeyCode::Char('r'), _) => {
-                                ed.refresh_dashboard_recents();
-                            }
-                            (KeyCode::Char(c), _) if c.is_ascii_digit() => {
-                                let index = c.to_digit(10).unwrap_or_default() as usize;
-                                let action_count = ed.dashboard_actions().len();
-                                if index > 0 && index <= action_count.min(9) {
-                                    ed.dashboard_selection = index - 1;
-                                    ed.activate_dashboard_selection();
-                                }
-                            }
-                            _ => {}
-                        },
-                        EditorMode::Search => match (code, modifiers) {
-                            (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
-                                ed.cancel_search();
-                            }
-                            (KeyCode::Enter, _) => {
-                                ed.update_search();
-                            }
-                            (KeyCode::Backspace, _) => {
-                                ed.search_query.pop();
-                                ed.update_search();
-                                ed.dirty = true;
-                            }
-                            (KeyCode::Tab, _) | (KeyCode::F(3), _) => {
-                                ed.next_search_result();
-                            }
-                            (KeyCode::Char(c), m) if !m.contains(KeyModifiers::CONTROL) => {
-                                ed.search_query.push(c);
-                                ed.update_search();
-                                ed.dirty = true;
-                            }
-                            _ => {}
-                        },
-                        EditorMode::CreateFile | EditorMode::CreateDir => match (code, modifiers) {
-                            (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
-                                ed.cancel_create();
-                            }
-                            (KeyCode::Enter, _) => {
-                                let _ = ed.create_file_or_dir();
-                            }
-                            (KeyCode::Backspace, _) => {
-                                ed.create_name.pop();
-                                ed.dirty = true;
-                            }
-                            (KeyCode::Char(c), m) if !m.contains(KeyModifiers::CONTROL) => {
-                                ed.create_name.push(c);
-                                ed.dirty = true;
-                            }
-                            _ => {}
-                        },
-                        EditorMode::DeleteConfirm => match (code, modifiers) {
-                            (KeyCode::Char('y') | KeyCode::Char('Y'), _) => {
-                                let _ = ed.confirm_delete();
-                            }
-                            (KeyCode::Char('n') | KeyCode::Char('N'), _) | (KeyCode::Esc, _) => {
-                                ed.cancel_delete();
-                            }
-                            _ => {}
-                        },
-                        EditorMode::Rename => match (code, modifiers) {
-                            (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
-                                ed.cancel_rename();
-                            }
-                            (KeyCode::Enter, _) => {
-                                let _ = ed.confirm_rename();
-                            }
-                            (KeyCode::Backspace, _) => {
-                                ed.rename_name.pop();
-                                ed.dirty = true;
-                            }
-                            (KeyCode::Char(c), m) if !m.contains(KeyModifiers::CONTROL) => {
-                                ed.rename_name.push(c);
-                                ed.dirty = true;
-                            }
-                            _ => {}
-                        },
-                        EditorMode::GoToLine => match (code, modifiers) {
-                            (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
-                                ed.cancel_goto_line();
-                            }
-                            (KeyCode::Enter, _) => {
-                                ed.confirm_goto_line();
-                            }
-                            (KeyCode::Backspace, _) => {
-                                ed.goto_line_input.pop();
-                                ed.dirty = true;
-                            }
-                            (KeyCode::Char(c), m)
-                                if !m.contains(KeyModifiers::CONTROL) && c.is_ascii_digit() =>
-                            {
-                                ed.goto_line_input.push(c);
-                                ed.dirty = true;
+            } else if settings.keywords {
+                for kw in get_keywords(&self.language) {
+                    candidates.push(candidate(kw, kw, CompletionSource::Keyword));
+                }
+            }
+            if settings.snippets {
+                for (trigger, body) in &settings.snippet_bodies {
+                    candidates.push(candidate(trigger, body, CompletionSource::Snippet));
+                }
+            }
+        }
+        if settings.paths {
+            candidates.extend(self.path_completions());
+        }
+        candidates
+    }
+
+    fn start_autocomplete(&mut self) {
+        self.ghost_completion = None;
+        if self.is_large_file() {
+            return;
+        }
+        let word = self.completion_word();
+        let candidates = self.local_completions(word.as_ref());
+        let request_lsp = self.config.autocomplete.lsp && word.is_some();
+        if let Some((prefix, _)) = word {
+            self.autocomplete_prefix = prefix;
+        }
+
+        self.show_completions(candidates);
+        if request_lsp {
+            self.request_lsp_completions();
+        }
+    }
+
+    // Ghost text is only offered at the end of a line, and only when exactly
+    // one candidate continues the typed word with matching case.
+    fn update_ghost_completion(&mut self) {
+        self.ghost_completion = None;
+        if !self.config.autocomplete.ghost_text
+            || self.is_large_file()
+            || self.cursor_x != self.buffer[self.cursor_y].len()
+        {
+            return;
+        }
+        let Some(word) = self.completion_word() else {
+            return;
+        };
+        let mut strong = self
+            .rank_completions(self.local_completions(Some(&word)))
+            .into_iter()
+            .filter(|candidate| {
+                candidate.source != CompletionSource::Path
+                    && candidate.label.starts_with(&word.0)
+                    && candidate.insert_text.starts_with(&word.0)
+            });
+        if let (Some(candidate), None) = (strong.next(), strong.next()) {
+            self.ghost_completion = Some((self.cursor_y, self.cursor_x, candidate));
+        }
+    }
+
+    fn visible_ghost_completion(&self) -> Option<&Completion> {
+        match &self.ghost_completion {
+            Some((y, x, candidate)) if (*y, *x) == (self.cursor_y, self.cursor_x) => {
+                Some(candidate)
+            }
+            _ => None,
+        }
+    }
+
+    fn accept_ghost_completion(&mut self) -> bool {
+        let Some(candidate) = self.visible_ghost_completion().cloned() else {
+            return false;
+        };
+        self.ghost_completion = None;
+        self.autocomplete_suggestions = vec![candidate];
+        self.autocomplete_index = 0;
+        self.apply_autocomplete();
+        true
+    }
+
+    fn request_lsp_completions(&mut self) {
+        let Some(path) = self.file_path.clone() else {
+            return;
+        };
+        let position = self.lsp_position(self.cursor_y, self.cursor_x);
+        self.lsp_sync_current();
+        if let Some(client) = self.current_lsp_client() {
+            if client.completion(&path, position).is_ok() {
+                self.autocomplete_lsp_request = Some((self.cursor_y, self.cursor_x));
+            }
+        }
+    }
+
+    fn merge_lsp_completions(&mut self, items: Vec<lsp::CompletionItem>) {
+        if self.autocomplete_lsp_request.take() != Some((self.cursor_y, self.cursor_x))
+            || !matches!(self.mode, EditorMode::Normal | EditorMode::Autocomplete)
+        {
+            return;
+        }
+        let Some((_, start)) = self.get_word_at_cursor() else {
+            return;
+        };
+        let mut candidates = std::mem::take(&mut self.autocomplete_suggestions);
+        candidates.extend(items.into_iter().map(|item| Completion {
+            label: item.label,
+            insert_text: item.insert_text,
+            source: CompletionSource::Lsp,
+            start,
+        }));
+        self.show_completions(candidates);
+    }
+
+    fn apply_autocomplete(&mut self) {
+        if self.autocomplete_suggestions.is_empty() {
+            self.cancel_autocomplete();
+            return;
+        }
+
+        let selected = self.autocomplete_suggestions[self.autocomplete_index].clone();
+        let start = selected.start.min(self.cursor_x);
+        self.save_history_state();
+
+        let rest = self.buffer[self.cursor_y].split_off(self.cursor_x);
+        self.buffer[self.cursor_y].truncate(start);
+        let mut lines = selected.insert_text.split('\n');
+        if let Some(first) = lines.next() {
+            self.buffer[self.cursor_y].extend(first.chars());
+        }
+        for line in lines {
+            self.cursor_y += 1;
+            self.buffer.insert(self.cursor_y, line.chars().collect());
+        }
+        self.cursor_x = self.buffer[self.cursor_y].len();
+        self.buffer[self.cursor_y].extend(rest);
+        self.mark_file_dirty();
+
+        if selected.source != CompletionSource::Path {
+            self.record_word_usage(&selected.label);
+        }
+
+        self.cancel_autocomplete();
+    }
+
+    fn cancel_autocomplete(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.autocomplete_suggestions.clear();
+        self.autocomplete_index = 0;
+        self.autocomplete_prefix.clear();
+        self.autocomplete_lsp_request = None;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn next_autocomplete(&mut self) {
+        if !self.autocomplete_suggestions.is_empty() {
+            self.autocomplete_index =
+                (self.autocomplete_index + 1) % self.autocomplete_suggestions.len();
+            self.dirty = true;
+        }
+    }
+
+    fn prev_autocomplete(&mut self) {
+        if !self.autocomplete_suggestions.is_empty() {
+            if self.autocomplete_index == 0 {
+                self.autocomplete_index = self.autocomplete_suggestions.len() - 1;
+            } else {
+                self.autocomplete_index -= 1;
+            }
+            self.dirty = true;
+        }
+    }
+
+    fn execute_terminal_command(&mut self) {
+        self.write_terminal_bytes(b"\r");
+    }
+
+    fn current_lsp_client(&mut self) -> Option<&mut LspClient> {
+        let server = lsp::server_for(&self.language)?;
+        self.lsp_clients.get_mut(server.command)
+    }
+
+    fn lsp_open_current(&mut self) {
+        let Some(path) = self.file_path.clone() else {
+            return;
+        };
+        let Some(server) = lsp::server_for(&self.language) else {
+            return;
+        };
+        if self.lsp_unavailable.contains(server.command) || self.safe_mode || replaying() {
+            return;
+        }
+
+        if !self.lsp_clients.contains_key(server.command) {
+            match LspClient::start(&server, &lsp::find_project_root(&path), self.waker.clone()) {
+                Ok(client) => {
+                    self.lsp_clients.insert(server.command, client);
+                }
+                Err(_) => {
+                    self.lsp_unavailable.insert(server.command);
+                    return;
+                }
+            }
+        }
+
+        let text = self.buffer_text();
+        if let Some(client) = self.lsp_clients.get_mut(server.command) {
+            let _ = client.did_open(&path, server.language_id, &text);
+        }
+        self.lsp_needs_sync = false;
+        self.request_semantic_tokens();
+    }
+
+    fn request_semantic_tokens(&mut self) {
+        let Some(path) = self.file_path.clone() else {
+            return;
+        };
+        if let Some(client) = self.current_lsp_client() {
+            let _ = client.semantic_tokens(&path);
+        }
+    }
+
+    fn store_semantic_tokens(&mut self, tokens: Vec<lsp::SemanticToken>) {
+        let Some(path) = self.file_path.clone() else {
+            return;
+        };
+        let mut lines: HashMap<usize, SemanticLine> = HashMap::new();
+        for token in tokens {
+            let Some(text) = self.buffer.get(token.line) else {
+                continue;
+            };
+            let (_, start) = self.buffer_position(token.line, token.start);
+            let (_, end) = self.buffer_position(token.line, token.start + token.length);
+            let token_type = match token.kind {
+                lsp::SemanticKind::Function => TokenType::Function,
+                lsp::SemanticKind::Type => TokenType::Type,
+                lsp::SemanticKind::Parameter => TokenType::Parameter,
+                lsp::SemanticKind::Macro => TokenType::Macro,
+            };
+            lines
+                .entry(token.line)
+                .or_insert_with(|| SemanticLine {
+                    text: text.clone(),
+                    spans: vec![],
+                })
+                .spans
+                .push((start, end, token_type));
+        }
+        self.semantic_tokens.insert(path, lines);
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn lsp_sync_current(&mut self) {
+        if !self.lsp_needs_sync {
+            return;
+        }
+        let Some(path) = self.file_path.clone() else {
+            return;
+        };
+        let text = self.buffer_text();
+        if let Some(client) = self.current_lsp_client() {
+            let _ = client.did_change(&path, &text);
+        }
+        self.lsp_needs_sync = false;
+    }
+
+    fn close_lsp_clients(&mut self) {
+        for client in self.lsp_clients.values_mut() {
+            client.shutdown();
+        }
+        self.lsp_clients.clear();
+    }
+
+    fn lsp_position(&self, y: usize, x: usize) -> (usize, usize) {
+        let character = self
+            .buffer
+            .get(y)
+            .map(|line| line.iter().take(x).map(|c| c.len_utf16()).sum())
+            .unwrap_or(0);
+        (y, character)
+    }
+
+    fn buffer_position(&self, line: usize, character: usize) -> (usize, usize) {
+        if line >= self.buffer.len() {
+            let last = self.buffer.len().saturating_sub(1);
+            return (last, self.buffer.get(last).map_or(0, |l| l.len()));
+        }
+
+        let mut units = 0;
+        let mut x = 0;
+        for c in &self.buffer[line] {
+            if units >= character {
+                break;
+            }
+            units += c.len_utf16();
+            x += 1;
+        }
+        (line, x)
+    }
+
+    fn drain_lsp_events(&mut self) {
+        let mut events = Vec::new();
+        let mut exited = Vec::new();
+        for (command, client) in self.lsp_clients.iter_mut() {
+            for event in client.poll() {
+                if matches!(event, LspEvent::Exited) {
+                    exited.push(*command);
+                } else {
+                    events.push(event);
+                }
+            }
+        }
+
+        for command in exited {
+            self.lsp_clients.remove(command);
+            self.lsp_unavailable.insert(command);
+        }
+
+        let current_uri = self.file_path.as_deref().map(lsp::path_to_uri);
+        for event in events {
+            match event {
+                LspEvent::Diagnostics { uri, diagnostics } => {
+                    self.lsp_diagnostics.insert(uri, diagnostics);
+                }
+                LspEvent::CodeActions { id, uri, actions } => {
+                    if current_uri.as_deref() != Some(uri.as_str())
+                        || !matches!(self.mode, EditorMode::Normal)
+                    {
+                        continue;
+                    }
+                    if self
+                        .import_request
+                        .as_ref()
+                        .is_some_and(|(request, _)| *request == id)
+                    {
+                        if let Some((_, ident)) = self.import_request.take() {
+                            self.offer_imports(&ident, actions);
+                        }
+                        continue;
+                    }
+                    if self.organize_request == Some(id) {
+                        self.organize_request = None;
+                        match actions.first() {
+                            Some(action) => {
+                                self.apply_text_edits(&action.edits);
+                                self.status = "Organized imports".into();
                             }
-                            _ => {}
-                        },
-                        EditorMode::Terminal => {
-                            if matches!(
-                                (code, modifiers),
-                                (KeyCode::Char('c'), KeyModifiers::CONTROL)
-                            ) {
-                                ed.interrupt_terminal();
-                            } else if matches!((code, modifiers), (KeyCode::Enter, _)) {
-                                ed.execute_terminal_command();
+                            None => self.organize_imports_locally(),
+                        }
+                        continue;
+                    }
+                    // An answer to a request since superseded.
+                    if self.quick_fix_request != Some(id) {
+                        continue;
+                    }
+                    self.quick_fix_request = None;
+                    if actions.is_empty() {
+                        self.status = "No quick fixes available here".into();
+                    } else {
+                        self.quick_fixes = actions;
+                        self.quick_fix_index = 0;
+                        self.mode = EditorMode::QuickFix;
+                    }
+                    self.needs_full_redraw = true;
+                    self.dirty = true;
+                }
+                LspEvent::Completions { uri, items } => {
+                    if current_uri.as_deref() == Some(uri.as_str()) {
+                        self.merge_lsp_completions(items);
+                    }
+                }
+                LspEvent::SemanticTokens { uri, tokens } => {
+                    if current_uri.as_deref() != Some(uri.as_str()) {
+                        continue;
+                    }
+                    // The buffer was edited after the request; ask again
+                    // for the text as it is now.
+                    if self.lsp_needs_sync {
+                        self.lsp_sync_current();
+                        self.request_semantic_tokens();
+                    } else {
+                        self.store_semantic_tokens(tokens);
+                    }
+                }
+                LspEvent::Exited => {}
+            }
+        }
+    }
+
+    /// Adds an import for the identifier under the cursor. The language
+    /// server's import fixes come first; without any, the project is
+    /// searched for a definition.
+    fn add_import(&mut self) {
+        let Some(path) = self.file_path.clone() else {
+            self.status = "Imports need a saved file".into();
+            self.dirty = true;
+            return;
+        };
+        if imports::style(&path).is_none() {
+            self.status = "No import syntax known for this file".into();
+            self.dirty = true;
+            return;
+        }
+        let line = &self.buffer[self.cursor_y];
+        let is_ident = |c: &char| c.is_alphanumeric() || *c == '_';
+        let start = self.cursor_x
+            - line[..self.cursor_x]
+                .iter()
+                .rev()
+                .take_while(|c| is_ident(c))
+                .count();
+        let end = self.cursor_x
+            + line[self.cursor_x..]
+                .iter()
+                .take_while(|c| is_ident(c))
+                .count();
+        let Some(ident) = Some(line[start..end].iter().collect::<String>())
+            .filter(|word| word.starts_with(|c: char| c.is_alphabetic() || c == '_'))
+        else {
+            self.status = "No identifier under the cursor".into();
+            self.dirty = true;
+            return;
+        };
+        if self.current_lsp_client().is_some() {
+            self.request_quick_fixes();
+            if let Some(id) = self.quick_fix_request.take() {
+                self.import_request = Some((id, ident));
+                return;
+            }
+        }
+        self.offer_imports(&ident, Vec::new());
+    }
+
+    /// Sorts and deduplicates the import block, through the language
+    /// server's organize-imports action when it has one.
+    fn organize_imports(&mut self) {
+        let Some(path) = self.file_path.clone() else {
+            self.organize_imports_locally();
+            return;
+        };
+        if self.current_lsp_client().is_none() {
+            self.organize_imports_locally();
+            return;
+        }
+        let last = self.buffer.len() - 1;
+        let end = self.lsp_position(last, self.buffer[last].len());
+        self.lsp_sync_current();
+        let sent = self.current_lsp_client().and_then(|client| {
+            client
+                .code_actions(&path, (0, 0), end, Vec::new(), &["source.organizeImports"])
+                .ok()
+        });
+        if sent.is_some() {
+            self.organize_request = sent;
+            self.status = "Organizing imports...".into();
+            self.dirty = true;
+        } else {
+            self.organize_imports_locally();
+        }
+    }
+
+    fn organize_imports_locally(&mut self) {
+        let organized = self
+            .file_path
+            .as_deref()
+            .and_then(imports::style)
+            .and_then(|style| imports::organize(&self.buffer, style));
+        let Some(((start, end), lines)) = organized else {
+            self.status = "No imports to organize".into();
+            self.dirty = true;
+            return;
+        };
+        let lines: Vec<Vec<char>> = lines.iter().map(|line| line.chars().collect()).collect();
+        if self.buffer[start..=end] == lines[..] {
+            self.status = "Imports are already organized".into();
+            self.dirty = true;
+            return;
+        }
+        self.save_history_state();
+        let count = lines.len();
+        self.buffer.splice(start..=end, lines);
+        if self.cursor_y > end {
+            self.cursor_y = (self.cursor_y + count).saturating_sub(end + 1 - start);
+        }
+        self.cursor_y = self.cursor_y.min(self.buffer.len() - 1);
+        self.cursor_x = self.cursor_x.min(self.buffer[self.cursor_y].len());
+        self.mark_file_dirty();
+        self.status = "Organized imports".into();
+    }
+
+    fn offer_imports(&mut self, ident: &str, actions: Vec<lsp::CodeAction>) {
+        let actions: Vec<lsp::CodeAction> = actions
+            .into_iter()
+            .filter(|action| {
+                let title = action.title.to_lowercase();
+                title.contains("import") || title.contains("include")
+            })
+            .collect();
+        if actions.is_empty() {
+            self.start_import_scan(ident);
+        } else {
+            self.show_import_actions(ident, actions);
+        }
+    }
+
+    fn show_import_actions(&mut self, ident: &str, actions: Vec<lsp::CodeAction>) {
+        let already_imported = || {
+            let style = self.file_path.as_deref().and_then(imports::style);
+            self.buffer.iter().any(|line| {
+                let line: String = line.iter().collect();
+                style.is_some_and(|style| imports::is_import(&line, style))
+                    && line
+                        .split(|c: char| !c.is_alphanumeric() && c != '_')
+                        .any(|word| word == ident)
+            })
+        };
+        match actions.len() {
+            0 if already_imported() => {
+                self.status = format!("`{}` is already imported", ident);
+            }
+            0 => self.status = format!("No definition of `{}` found in the project", ident),
+            1 => {
+                self.quick_fixes = actions;
+                self.quick_fix_index = 0;
+                self.apply_quick_fix();
+            }
+            _ => {
+                self.quick_fixes = actions;
+                self.quick_fix_index = 0;
+                self.mode = EditorMode::QuickFix;
+            }
+        }
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    // Reading thousands of files takes a while in a big project, so the
+    // search runs on a thread of its own.
+    fn start_import_scan(&mut self, ident: &str) {
+        let Some(path) = self.file_path.clone() else {
+            return;
+        };
+        let Some(style) = imports::style(&path) else {
+            return;
+        };
+        let root = lsp::find_project_root(&path);
+        let (tx, rx) = mpsc::channel();
+        let waker = self.waker.clone();
+        let (scan_path, scan_ident) = (path.clone(), ident.to_string());
+        thread::spawn(move || {
+            let _ = tx.send(imports::candidates(&root, &scan_path, &scan_ident, style));
+            waker.wake();
+        });
+        self.import_scan = Some(ImportScan {
+            path,
+            ident: ident.to_string(),
+            rx,
+        });
+        self.status = format!("Searching the project for `{}`...", ident);
+        self.dirty = true;
+    }
+
+    fn drain_import_scan(&mut self) {
+        let Some(scan) = self.import_scan.as_ref() else {
+            return;
+        };
+        let statements = match scan.rx.try_recv() {
+            Ok(statements) => statements,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Vec::new(),
+        };
+        let Some(scan) = self.import_scan.take() else {
+            return;
+        };
+        // The answer is for a file no longer in front of the user.
+        if self.file_path.as_ref() != Some(&scan.path) || !matches!(self.mode, EditorMode::Normal) {
+            return;
+        }
+        let actions = self.local_import_actions(statements);
+        self.show_import_actions(&scan.ident, actions);
+    }
+
+    fn local_import_actions(&self, statements: Vec<String>) -> Vec<lsp::CodeAction> {
+        let Some(style) = self.file_path.as_deref().and_then(imports::style) else {
+            return Vec::new();
+        };
+        statements
+            .into_iter()
+            .filter(|statement| {
+                !self
+                    .buffer
+                    .iter()
+                    .any(|line| line.iter().collect::<String>().trim() == statement)
+            })
+            .map(|statement| {
+                let (y, separate) = imports::insertion_line(&self.buffer, style, &statement);
+                let edit = if y < self.buffer.len() {
+                    lsp::TextEdit {
+                        start: (y, 0),
+                        end: (y, 0),
+                        new_text: format!("{}\n{}", statement, if separate { "\n" } else { "" }),
+                    }
+                } else {
+                    let last = self.buffer.len() - 1;
+                    let end = self.lsp_position(last, self.buffer[last].len());
+                    lsp::TextEdit {
+                        start: end,
+                        end,
+                        new_text: format!("\n{}", statement),
+                    }
+                };
+                lsp::CodeAction {
+                    title: statement,
+                    edits: vec![edit],
+                }
+            })
+            .collect()
+    }
+
+    fn request_quick_fixes(&mut self) {
+        let Some(path) = self.file_path.clone() else {
+            self.status = "Quick fixes need a saved file".into();
+            self.dirty = true;
+            return;
+        };
+        if self.current_lsp_client().is_none() {
+            self.status = "No language server running for this file".into();
+            self.dirty = true;
+            return;
+        }
+
+        let (start, end) = match (self.selection_start, self.selection_end) {
+            (Some(a), Some(b)) if a != b => (a.min(b), a.max(b)),
+            _ => (
+                (self.cursor_y, self.cursor_x),
+                (self.cursor_y, self.cursor_x),
+            ),
+        };
+        let range_start = self.lsp_position(start.0, start.1);
+        let range_end = self.lsp_position(end.0, end.1);
+
+        let diagnostics: Vec<serde_json::Value> = self
+            .lsp_diagnostics
+            .get(&lsp::path_to_uri(&path))
+            .into_iter()
+            .flatten()
+            .filter(|diagnostic| {
+                let first = diagnostic["range"]["start"]["line"].as_u64().unwrap_or(0) as usize;
+                let last = diagnostic["range"]["end"]["line"].as_u64().unwrap_or(0) as usize;
+                first <= end.0 && last >= start.0
+            })
+            .cloned()
+            .collect();
+
+        self.lsp_sync_current();
+        if let Some(client) = self.current_lsp_client() {
+            match client.code_actions(&path, range_start, range_end, diagnostics, &[]) {
+                Ok(id) => {
+                    self.quick_fix_request = Some(id);
+                    self.status = "Looking for quick fixes...".into();
+                }
+                Err(_) => self.status = "Language server is not responding".into(),
+            }
+        }
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn apply_text_edits(&mut self, edits: &[lsp::TextEdit]) {
+        if edits.is_empty() {
+            return;
+        }
+
+        type Position = (usize, usize);
+        let mut resolved: Vec<(Position, Position, &str)> = edits
+            .iter()
+            .map(|edit| {
+                (
+                    self.buffer_position(edit.start.0, edit.start.1),
+                    self.buffer_position(edit.end.0, edit.end.1),
+                    edit.new_text.as_str(),
+                )
+            })
+            .collect();
+        resolved.sort_by_key(|edit| std::cmp::Reverse(edit.0));
+
+        self.save_history_state();
+
+        for (start, end, new_text) in resolved {
+            let (end_y, end_x) = if end < start { start } else { end };
+            let prefix: Vec<char> = self.buffer[start.0][..start.1].to_vec();
+            let suffix: Vec<char> =
+                self.buffer[end_y][end_x.min(self.buffer[end_y].len())..].to_vec();
+
+            let normalized = new_text.replace("\r\n", "\n");
+            let mut replacement: Vec<Vec<char>> = normalized
+                .split('\n')
+                .map(|line| line.chars().collect())
+                .collect();
+            if let Some(first) = replacement.first_mut() {
+                let mut line = prefix;
+                line.extend(first.iter());
+                *first = line;
+            }
+            if let Some(last) = replacement.last_mut() {
+                last.extend(suffix);
+            }
+
+            // Keep the cursor on its line when lines come or go above it.
+            if end_y < self.cursor_y {
+                self.cursor_y =
+                    (self.cursor_y + replacement.len()).saturating_sub(end_y + 1 - start.0);
+            }
+            self.buffer.splice(start.0..=end_y, replacement);
+        }
+
+        if self.buffer.is_empty() {
+            self.buffer.push(vec![]);
+        }
+        self.cursor_y = self.cursor_y.min(self.buffer.len() - 1);
+        self.cursor_x = self.cursor_x.min(self.buffer[self.cursor_y].len());
+        self.mark_file_dirty();
+        self.update_bracket_matching();
+    }
+
+    /// Whether line `y` starts a function or test case, with its name and
+    /// whether it is a test. Python names include their classes, as pytest
+    /// node ids do.
+    fn test_definition(&self, y: usize) -> Option<(String, bool)> {
+        let line_text = |y: usize| -> String { self.buffer[y].iter().collect() };
+        let text = line_text(y);
+        let trimmed = text.trim_start();
+        let ident = |rest: &str| -> String {
+            rest.chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect()
+        };
+
+        match self.language {
+            Language::Rust => {
+                let rest = trimmed
+                    .strip_prefix("fn ")
+                    .or_else(|| trimmed.strip_prefix("async fn "))
+                    .or_else(|| trimmed.strip_prefix("pub fn "))?;
+                let mut attr_y = y;
+                let mut is_test = false;
+                while attr_y > 0 {
+                    attr_y -= 1;
+                    let attr = line_text(attr_y);
+                    let attr = attr.trim();
+                    if !attr.starts_with("#[") && !attr.starts_with("///") {
+                        break;
+                    }
+                    if attr.starts_with("#[test]") || attr.ends_with("::test]") {
+                        is_test = true;
+                    }
+                }
+                Some((ident(rest), is_test))
+            }
+            Language::Python => {
+                let rest = trimmed
+                    .strip_prefix("def ")
+                    .or_else(|| trimmed.strip_prefix("async def "))?;
+                let name = ident(rest);
+                if !name.starts_with("test") {
+                    return Some((name, false));
+                }
+
+                // Each enclosing class is the nearest line above indented
+                // less than what it contains.
+                let mut node_id = name;
+                let mut indent = text.len() - trimmed.len();
+                for class_y in (0..y).rev() {
+                    if indent == 0 {
+                        break;
+                    }
+                    let class_line = line_text(class_y);
+                    let class_trimmed = class_line.trim_start();
+                    let class_indent = class_line.len() - class_trimmed.len();
+                    if class_trimmed.is_empty()
+                        || class_trimmed.starts_with('#')
+                        || class_indent >= indent
+                    {
+                        continue;
+                    }
+                    let Some(class_rest) = class_trimmed.strip_prefix("class ") else {
+                        break;
+                    };
+                    node_id = format!("{}::{}", ident(class_rest), node_id);
+                    indent = class_indent;
+                }
+                Some((node_id, true))
+            }
+            Language::JavaScript => {
+                let is_test_call = ["it(", "test(", "it.only(", "test.only("]
+                    .iter()
+                    .any(|prefix| trimmed.starts_with(prefix));
+                if !is_test_call {
+                    return None;
+                }
+                Some(match first_string_literal(trimmed) {
+                    Some(name) => (name, true),
+                    None => (String::new(), false),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn find_test_at_cursor(&self) -> Option<TestTarget> {
+        let path = self.file_path.clone()?;
+        let y = (0..=self.cursor_y.min(self.buffer.len().saturating_sub(1)))
+            .rev()
+            .find(|&y| self.test_definition(y).is_some())?;
+        let (name, true) = self.test_definition(y)? else {
+            return None;
+        };
+        let cwd = lsp::find_project_root(&path);
+
+        match self.language {
+            Language::Rust => Some(TestTarget {
+                args: vec!["test".into(), name.clone()],
+                name,
+                program: "cargo".into(),
+                cwd,
+            }),
+            Language::Python => {
+                let relative = path
+                    .canonicalize()
+                    .ok()
+                    .and_then(|abs| abs.strip_prefix(&cwd).ok().map(|p| p.to_path_buf()))
+                    .unwrap_or_else(|| path.clone());
+                Some(TestTarget {
+                    args: vec![
+                        "-m".into(),
+                        "pytest".into(),
+                        format!("{}::{}", relative.to_string_lossy(), name),
+                    ],
+                    name,
+                    program: python_program().into(),
+                    cwd,
+                })
+            }
+            Language::JavaScript => Some(TestTarget {
+                args: vec![
+                    "jest".into(),
+                    path.to_string_lossy().into_owned(),
+                    "-t".into(),
+                    name.clone(),
+                ],
+                name,
+                program: npx_program().into(),
+                cwd,
+            }),
+            _ => None,
+        }
+    }
+
+    fn run_test_at_cursor(&mut self) {
+        let Some(target) = self.find_test_at_cursor() else {
+            self.status = "No test found under the cursor".into();
+            self.dirty = true;
+            return;
+        };
+        let Some(path) = self.file_path.clone() else {
+            return;
+        };
+
+        if self.dirty_files.contains(&path) {
+            let _ = self.save();
+        }
+
+        let command_line = format!("{} {}", target.program, target.args.join(" "));
+        match spawn_command_job(&target.program, &target.args, &target.cwd, &self.waker) {
+            Ok(rx) => {
+                self.jobs.push(Job {
+                    kind: JobKind::Test {
+                        path: path.clone(),
+                        name: target.name.clone(),
+                    },
+                    rx,
+                });
+                self.test_results
+                    .entry(path)
+                    .or_default()
+                    .insert(target.name.clone(), TestStatus::Running);
+                self.output_panel = Some(OutputPanel {
+                    title: format!("Test {}", target.name),
+                    lines: vec![format!("$ {}", command_line)],
+                    scroll: 0,
+                    status: Some(TestStatus::Running),
+                });
+                self.show_output_panel = true;
+                self.status = format!("Running test {}...", target.name);
+            }
+            Err(err) => {
+                self.status = format!("Failed to run {}: {}", target.program, err);
+            }
+        }
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn drain_jobs(&mut self) {
+        let mut finished = Vec::new();
+        let mut git_output = Vec::new();
+        let mut terminal_output = Vec::new();
+        let mut changed = false;
+
+        for (index, job) in self.jobs.iter().enumerate() {
+            loop {
+                match job.rx.try_recv() {
+                    Ok(JobEvent::Error(line)) if matches!(job.kind, JobKind::Evaluate { .. }) => {
+                        self.evaluation_errors.push(line);
+                    }
+                    Ok(JobEvent::Output(line) | JobEvent::Error(line)) => {
+                        match job.kind {
+                            JobKind::Test { .. } => {
+                                if let Some(panel) = self.output_panel.as_mut() {
+                                    panel.lines.push(line);
+                                }
+                            }
+                            JobKind::Scaffold { .. } => {
+                                terminal_output.push(line);
+                            }
+                            JobKind::Share { .. } => {
+                                self.share_response.push(line);
+                            }
+                            JobKind::Evaluate { .. } => {
+                                self.evaluation_output.push(line);
+                            }
+                            JobKind::UpdateCheck { .. } => {
+                                self.update_response.push(line);
+                            }
+                            JobKind::Git { action } => {
+                                if !line.trim().is_empty() {
+                                    git_output.push(format!("git {}: {}", action.label(), line));
+                                }
+                            }
+                        }
+                        changed = true;
+                    }
+                    Ok(JobEvent::Finished(success)) => {
+                        finished.push((index, success));
+                        break;
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        finished.push((index, false));
+                        break;
+                    }
+                }
+            }
+        }
+
+        for (index, success) in finished.into_iter().rev() {
+            let job = self.jobs.remove(index);
+            match job.kind {
+                JobKind::Test { path, name } => {
+                    let status = if success {
+                        TestStatus::Passed
+                    } else {
+                        TestStatus::Failed
+                    };
+                    self.test_results
+                        .entry(path)
+                        .or_default()
+                        .insert(name, status);
+                    if let Some(panel) = self.output_panel.as_mut() {
+                        panel.status = Some(status);
+                    }
+                    self.status = if success {
+                        "Test passed".into()
+                    } else {
+                        "Test failed".into()
+                    };
+                    if self.show_coverage {
+                        self.reload_coverage();
+                    }
+                }
+                JobKind::Git { action } => {
+                    self.status = if success {
+                        format!("git {} finished", action.label())
+                    } else {
+                        format!("git {} failed - see notification log (F9)", action.label())
+                    };
+                    git_output.push(self.status.clone());
+                    self.refresh_after_git_action(action);
+                }
+                JobKind::Scaffold { root } => {
+                    for line in terminal_output.drain(..) {
+                        self.append_terminal_message(&format!("{}\r\n", line));
+                    }
+                    if success {
+                        self.append_terminal_message("[Project created]\r\n");
+                        self.terminal_show = false;
+                        self.open_workspace(&root);
+                        self.status = format!("Created project {}", root.to_string_lossy());
+                    } else {
+                        self.append_terminal_message("[Scaffolder failed]\r\n");
+                        self.status = "Scaffolder failed - see terminal (Ctrl+1)".into();
+                    }
+                }
+                JobKind::Share { upload } => {
+                    // Removes the request files, token included.
+                    drop(upload);
+                    let response = std::mem::take(&mut self.share_response).join("\n");
+                    match share::paste_url(&self.config.share, &response) {
+                        Ok(url) => {
+                            if let Ok(mut clipboard) = arboard::Clipboard::new() {
+                                let _ = clipboard.set_text(&url);
+                            }
+                            self.clipboard = Some(url.clone());
+                            self.status = format!("Shared: {} (URL copied)", url);
+                        }
+                        Err(reason) => {
+                            self.status = format!("Share failed: {}", reason);
+                        }
+                    }
+                    self.notify(self.status.clone());
+                }
+                JobKind::Evaluate { replace } => {
+                    self.finish_evaluation(success, replace);
+                }
+                JobKind::UpdateCheck { manual } => {
+                    let response = std::mem::take(&mut self.update_response).join("\n");
+                    match version::latest_release(&response) {
+                        Ok(latest) if version::is_newer(&latest) => {
+                            self.status = format!(
+                                "termi {} is available (you have {}): {}",
+                                latest,
+                                version::VERSION,
+                                version::RELEASES_PAGE
+                            );
+                            self.notify(self.status.clone());
+                        }
+                        Ok(_) if manual => {
+                            self.status = format!("termi {} is up to date", version::VERSION);
+                        }
+                        Err(reason) if manual => {
+                            self.status = format!("Update check failed: {}", reason);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            changed = true;
+        }
+
+        for line in terminal_output {
+            self.append_terminal_message(&format!("{}\r\n", line));
+        }
+
+        for message in git_output {
+            self.notify(message);
+        }
+
+        if changed {
+            self.needs_full_redraw = true;
+            self.dirty = true;
+        }
+    }
+
+    /// Asks GitHub for the latest release with curl, in the background.
+    /// A newer one is only announced; nothing is downloaded.
+    fn check_for_updates(&mut self, manual: bool) {
+        if replaying() {
+            return;
+        }
+        if self
+            .jobs
+            .iter()
+            .any(|job| matches!(job.kind, JobKind::UpdateCheck { .. }))
+        {
+            return;
+        }
+        let cwd = env::temp_dir();
+        match spawn_command_job("curl", &version::check_args(), &cwd, &self.waker) {
+            Ok(rx) => {
+                self.jobs.push(Job {
+                    kind: JobKind::UpdateCheck { manual },
+                    rx,
+                });
+                self.update_response.clear();
+                if manual {
+                    self.status = "Checking for updates...".into();
+                }
+            }
+            Err(err) if manual => self.status = format!("Update check failed: {}", err),
+            Err(_) => {}
+        }
+        self.dirty = true;
+    }
+
+    /// Uploads the selection, or the whole buffer, to the `[share]` service
+    /// with curl; the paste's URL lands on the clipboard when it finishes.
+    fn share_selection(&mut self) {
+        if replaying() {
+            self.status = "Not uploading in a replay".into();
+            self.dirty = true;
+            return;
+        }
+        if self
+            .jobs
+            .iter()
+            .any(|job| matches!(job.kind, JobKind::Share { .. }))
+        {
+            self.status = "An upload is already running".into();
+            self.dirty = true;
+            return;
+        }
+
+        let text = self
+            .get_selected_text()
+            .unwrap_or_else(|| self.buffer_text());
+        let file_name = self
+            .file_name
+            .clone()
+            .unwrap_or_else(|| "snippet.txt".into());
+        let started = share::prepare(&self.config.share, &file_name, &text).and_then(|upload| {
+            let rx = spawn_command_job("curl", &upload.args, &self.project_root(), &self.waker)?;
+            Ok(Job {
+                kind: JobKind::Share { upload },
+                rx,
+            })
+        });
+        match started {
+            Ok(job) => {
+                self.jobs.push(job);
+                self.share_response.clear();
+                self.status = format!("Uploading {}...", file_name);
+            }
+            Err(err) => self.status = format!("Share failed: {}", err),
+        }
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    /// Runs the selection, or the current line, through the evaluator
+    /// configured for the file's extension and shows or inserts the result.
+    fn evaluate_selection(&mut self, replace: bool) {
+        if self
+            .jobs
+            .iter()
+            .any(|job| matches!(job.kind, JobKind::Evaluate { .. }))
+        {
+            self.status = "An evaluation is already running".into();
+            self.dirty = true;
+            return;
+        }
+
+        let (start, end) = match (self.selection_start, self.selection_end) {
+            (Some(a), Some(b)) if a != b => (a.min(b), a.max(b)),
+            _ => (
+                (self.cursor_y, 0),
+                (self.cursor_y, self.buffer[self.cursor_y].len()),
+            ),
+        };
+        let text = self.text_between(start, end);
+        if text.trim().is_empty() {
+            self.status = "Nothing to evaluate".into();
+            self.dirty = true;
+            return;
+        }
+
+        let ext = self
+            .file_name
+            .as_deref()
+            .and_then(|name| Path::new(name).extension())
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let Some(evaluator) = self
+            .config
+            .evaluators
+            .get(&ext)
+            .or_else(|| self.config.evaluators.get("*"))
+        else {
+            self.status = if ext.is_empty() {
+                "No evaluator for this file - see [evaluators] in config.toml".into()
+            } else {
+                format!(
+                    "No evaluator for .{} - see [evaluators] in config.toml",
+                    ext
+                )
+            };
+            self.dirty = true;
+            return;
+        };
+        let expression = text.trim();
+        let program = evaluator.program.clone();
+        let rustc = Path::new(&program)
+            .file_stem()
+            .is_some_and(|stem| stem == "rustc");
+        let mut args: Vec<String> = evaluator
+            .args
+            .iter()
+            .map(|arg| arg.replace("{}", expression))
+            .collect();
+        if !evaluator.args.iter().any(|arg| arg.contains("{}")) {
+            args.push(expression.to_string());
+        }
+
+        let spawned = if rustc {
+            spawn_rust_evaluation(&program, &evaluator.args, expression, &self.waker)
+        } else {
+            spawn_command_job(&program, &args, &self.project_root(), &self.waker)
+        };
+        match spawned {
+            Ok(rx) => {
+                self.jobs.push(Job {
+                    kind: JobKind::Evaluate {
+                        replace: replace.then_some(EvaluatedText { start, end, text }),
+                    },
+                    rx,
+                });
+                self.evaluation_output.clear();
+                self.evaluation_errors.clear();
+                self.status = format!("Evaluating with {}...", program);
+            }
+            Err(err) => self.status = format!("Failed to run {}: {}", program, err),
+        }
+        self.dirty = true;
+    }
+
+    fn finish_evaluation(&mut self, success: bool, replace: Option<EvaluatedText>) {
+        let output = std::mem::take(&mut self.evaluation_output);
+        let errors = std::mem::take(&mut self.evaluation_errors);
+        let result = output.join("\n").trim_end().to_string();
+        if !success {
+            // The last thing said on stderr, or on stdout without any.
+            let reason = output
+                .iter()
+                .chain(&errors)
+                .rev()
+                .find(|line| !line.trim().is_empty())
+                .cloned()
+                .unwrap_or_else(|| "evaluator failed".into());
+            self.status = format!("Evaluation failed: {}", reason);
+            return;
+        }
+
+        match replace {
+            Some(EvaluatedText { start, end, text }) => {
+                let still_there = end.0 < self.buffer.len()
+                    && end.1 <= self.buffer[end.0].len()
+                    && self.text_between(start, end) == text;
+                if !still_there {
+                    self.status = "Text changed while evaluating; result not inserted".into();
+                    return;
+                }
+                // Keep the whitespace around the expression that was trimmed
+                // before evaluating it.
+                let leading = &text[..text.len() - text.trim_start().len()];
+                let trailing = &text[text.trim_end().len()..];
+                self.save_history_state();
+                self.replace_between(start, end, &format!("{}{}{}", leading, result, trailing));
+                self.selection_start = None;
+                self.selection_end = None;
+                self.is_selecting = false;
+                self.mark_file_dirty();
+                self.status = "Replaced with result".into();
+            }
+            None if output.len() > 1 => {
+                self.output_panel = Some(OutputPanel {
+                    title: "Evaluation".into(),
+                    lines: output,
+                    scroll: 0,
+                    status: None,
+                });
+                self.show_output_panel = true;
+                self.status = "Evaluated - result in the output panel (F6)".into();
+            }
+            None => self.status = format!("= {}", result),
+        }
+    }
+
+    fn text_between(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        (start.0..=end.0)
+            .map(|y| {
+                let line = &self.buffer[y];
+                let from = if y == start.0 { start.1 } else { 0 };
+                let to = if y == end.0 { end.1 } else { line.len() };
+                line[from.min(to)..to].iter().collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Swaps the text between two positions for `text` and leaves the cursor
+    /// after it.
+    fn replace_between(&mut self, start: (usize, usize), end: (usize, usize), text: &str) {
+        let suffix = self.buffer[end.0].split_off(end.1);
+        self.buffer[start.0].truncate(start.1);
+        self.buffer.drain(start.0 + 1..=end.0);
+
+        let mut lines = text.split('\n');
+        let mut y = start.0;
+        self.buffer[y].extend(lines.next().unwrap_or_default().chars());
+        for line in lines {
+            y += 1;
+            self.buffer.insert(y, line.chars().collect());
+        }
+        self.cursor_y = y;
+        self.cursor_x = self.buffer[y].len();
+        self.buffer[y].extend(suffix);
+    }
+
+    fn notify(&mut self, message: String) {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        self.notifications.push(Notification {
+            timestamp_ms,
+            message,
+        });
+        if self.notifications.len() > MAX_NOTIFICATIONS {
+            let overflow = self.notifications.len() - MAX_NOTIFICATIONS;
+            self.notifications.drain(..overflow);
+        }
+    }
+
+    fn run_git_action(&mut self, action: GitAction) {
+        if replaying() && matches!(action, GitAction::Pull | GitAction::Push) {
+            self.status = format!("Not running git {} in a replay", action.label());
+            self.dirty = true;
+            return;
+        }
+        if self
+            .jobs
+            .iter()
+            .any(|job| matches!(job.kind, JobKind::Git { .. }))
+        {
+            self.status = "A git command is already running".into();
+            self.dirty = true;
+            return;
+        }
+
+        let root = self.project_root();
+        let Some(root) = git::repo_root(&root) else {
+            self.status = "Not inside a git repository".into();
+            self.dirty = true;
+            return;
+        };
+
+        let args: Vec<String> = action.args().iter().map(|arg| arg.to_string()).collect();
+        match spawn_command_job("git", &args, &root, &self.waker) {
+            Ok(rx) => {
+                self.jobs.push(Job {
+                    kind: JobKind::Git { action },
+                    rx,
+                });
+                self.notify(format!("$ git {}", args.join(" ")));
+                self.status = format!("Running git {}...", action.label());
+            }
+            Err(err) => {
+                self.status = format!("Failed to run git: {}", err);
+                self.notify(self.status.clone());
+            }
+        }
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn refresh_after_git_action(&mut self, action: GitAction) {
+        if action != GitAction::Push {
+            self.reload_clean_buffers();
+        }
+        self.refresh_git_branch();
+    }
+
+    fn refresh_git_branch(&mut self) {
+        self.git_branch =
+            git::repo_root(&self.project_root()).and_then(|root| git::current_branch(&root));
+    }
+
+    // Checkouts, stashes and pulls rewrite the working tree, so pick up the new
+    // contents of every buffer that has no edits of its own.
+    fn reload_clean_buffers(&mut self) {
+        let dirty_files = &self.dirty_files;
+        self.file_buffers
+            .retain(|path, _| dirty_files.contains(path));
+
+        let Some(path) = self.file_path.clone() else {
+            return;
+        };
+        if self.dirty_files.contains(&path) {
+            return;
+        }
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return;
+        };
+        let contents = contents.strip_prefix(BOM).unwrap_or(&contents);
+        let mut lines: Vec<Vec<char>> = contents.lines().map(|l| l.chars().collect()).collect();
+        if lines.is_empty() {
+            lines.push(vec![]);
+        }
+        if lines != self.buffer {
+            self.save_history_state();
+            self.buffer = lines;
+            self.cursor_y = self.cursor_y.min(self.buffer.len() - 1);
+            self.cursor_x = self.cursor_x.min(self.buffer[self.cursor_y].len());
+            self.lsp_needs_sync = true;
+            self.buffer_edits += 1;
+        }
+        self.file_buffers.insert(path, self.buffer.clone());
+        if self.show_coverage {
+            self.reload_coverage();
+        }
+    }
+
+    fn refresh_tree(&mut self) {
+        let Some(root) = self
+            .tree
+            .first()
+            .and_then(|node| node.path.parent())
+            .map(|parent| parent.to_path_buf())
+        else {
+            return;
+        };
+        let expanded: HashSet<PathBuf> = self
+            .tree
+            .iter()
+            .filter(|node| node.expanded)
+            .map(|node| node.path.clone())
+            .collect();
+        let cursor_path = self
+            .tree
+            .get(self.tree_cursor)
+            .map(|node| node.path.clone());
+
+        self.tree.clear();
+        self.load_dir(root, 0);
+        let mut index = 0;
+        while index < self.tree.len() {
+            if self.tree[index].is_dir && expanded.contains(&self.tree[index].path) {
+                self.toggle_dir(index);
+            }
+            index += 1;
+        }
+
+        self.tree_cursor = cursor_path
+            .and_then(|path| self.tree.iter().position(|node| node.path == path))
+            .unwrap_or(0)
+            .min(self.tree.len().saturating_sub(1));
+        self.tree_scroll = self.tree_scroll.min(self.tree_cursor);
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn start_blame(&mut self) {
+        let Some((root, relative)) = self.current_git_file() else {
+            return;
+        };
+        match git::blame(&root, &relative) {
+            Ok(lines) => {
+                let cursor = self.cursor_y.min(lines.len().saturating_sub(1));
+                self.blame_view = Some(BlameView {
+                    title: relative,
+                    root,
+                    lines,
+                    cursor,
+                    scroll: cursor.saturating_sub(5),
+                });
+                self.mode = EditorMode::Blame;
+                self.status = if self.dirty {
+                    "Blame reflects the saved file".into()
+                } else {
+                    String::new()
+                };
+            }
+            Err(err) => {
+                self.status = format!("git: {}", err);
+            }
+        }
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn close_blame(&mut self) {
+        if let Some(view) = self.blame_view.take() {
+            self.cursor_y = view.cursor.min(self.buffer.len().saturating_sub(1));
+            self.cursor_x = self.cursor_x.min(self.buffer[self.cursor_y].len());
+        }
+        self.mode = EditorMode::Normal;
+        self.restore_default_status();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn move_blame_cursor(&mut self, delta: isize, rows: u16) {
+        let visible = self.text_rows(rows).saturating_sub(1).max(1) as usize;
+        if let Some(view) = self.blame_view.as_mut() {
+            if view.lines.is_empty() {
+                return;
+            }
+            view.cursor = view
+                .cursor
+                .saturating_add_signed(delta)
+                .min(view.lines.len() - 1);
+            if view.cursor < view.scroll {
+                view.scroll = view.cursor;
+            } else if view.cursor >= view.scroll + visible {
+                view.scroll = view.cursor + 1 - visible;
+            }
+            self.needs_full_redraw = true;
+            self.dirty = true;
+        }
+    }
+
+    fn open_blame_commit(&mut self) {
+        let Some(view) = self.blame_view.as_ref() else {
+            return;
+        };
+        let Some(line) = view.lines.get(view.cursor) else {
+            return;
+        };
+        if line.is_uncommitted() {
+            self.status = "Line is not committed yet".into();
+            self.dirty = true;
+            return;
+        }
+        let Some(web_url) = git::remote_web_url(&view.root) else {
+            self.status = "No usable 'origin' remote".into();
+            self.dirty = true;
+            return;
+        };
+
+        let url = git::commit_url(&web_url, &line.hash);
+        self.status = match open_in_browser(&url) {
+            Ok(()) => format!("Opened {}", url),
+            Err(err) => format!("Failed to open browser: {}", err),
+        };
+        self.dirty = true;
+    }
+
+    fn start_branch_picker(&mut self) {
+        let Some(root) = git::repo_root(&self.project_root()) else {
+            self.status = "Not inside a git repository".into();
+            self.dirty = true;
+            return;
+        };
+        match git::local_branches(&root) {
+            Ok(branches) => {
+                self.branch_list = branches;
+                self.branch_query.clear();
+                self.branch_index = 0;
+                self.mode = EditorMode::BranchPicker;
+            }
+            Err(err) => {
+                self.status = format!("git: {}", err);
+            }
+        }
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn cancel_branch_picker(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.branch_list.clear();
+        self.branch_query.clear();
+        self.branch_index = 0;
+        self.branch_pending = None;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn branch_matches(&self) -> Vec<String> {
+        let query: String = self.branch_query.iter().collect();
+        fuzzy::rank(&query, self.branch_list.iter().map(|b| b.as_str()))
+            .into_iter()
+            .map(|index| self.branch_list[index].clone())
+            .collect()
+    }
+
+    fn next_branch_item(&mut self) {
+        let count = self.branch_matches().len();
+        if count > 0 {
+            self.branch_index = (self.branch_index + 1) % count;
+            self.dirty = true;
+        }
+    }
+
+    fn prev_branch_item(&mut self) {
+        let count = self.branch_matches().len();
+        if count > 0 {
+            self.branch_index = self.branch_index.checked_sub(1).unwrap_or(count - 1);
+            self.dirty = true;
+        }
+    }
+
+    fn confirm_branch_picker(&mut self) {
+        let Some(branch) = self.branch_matches().into_iter().nth(self.branch_index) else {
+            return;
+        };
+        if Some(&branch) == self.git_branch.as_ref() {
+            self.cancel_branch_picker();
+            self.status = format!("Already on {}", branch);
+            return;
+        }
+        let Some(root) = git::repo_root(&self.project_root()) else {
+            self.cancel_branch_picker();
+            return;
+        };
+
+        let dirty =
+            !self.dirty_files.is_empty() || git::has_uncommitted_changes(&root).unwrap_or(false);
+        if dirty {
+            self.branch_pending = Some(branch);
+            self.mode = EditorMode::BranchCheckoutConfirm;
+            self.needs_full_redraw = true;
+            self.dirty = true;
+        } else {
+            self.checkout_branch(&root, &branch);
+        }
+    }
+
+    fn confirm_branch_checkout(&mut self) {
+        let Some(branch) = self.branch_pending.take() else {
+            self.cancel_branch_picker();
+            return;
+        };
+        match git::repo_root(&self.project_root()) {
+            Some(root) => self.checkout_branch(&root, &branch),
+            None => self.cancel_branch_picker(),
+        }
+    }
+
+    fn checkout_branch(&mut self, root: &Path, branch: &str) {
+        self.cancel_branch_picker();
+        match git::checkout(root, branch) {
+            Ok(()) => {
+                self.reload_clean_buffers();
+                self.refresh_tree();
+                self.refresh_git_branch();
+                self.status = format!("Switched to branch {}", branch);
+                self.notify(self.status.clone());
+            }
+            Err(err) => {
+                self.status = format!("Checkout failed: {}", err);
+                self.notify(format!("git checkout {}: {}", branch, err));
+            }
+        }
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn start_notification_log(&mut self) {
+        self.mode = EditorMode::NotificationLog;
+        self.notification_scroll = usize::MAX;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn scroll_notification_log(&mut self, delta: isize) {
+        self.notification_scroll = self.notification_scroll.saturating_add_signed(delta);
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn close_notification_log(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn toggle_output_panel(&mut self) {
+        if self.output_panel.is_none() {
+            self.status = "Output panel is empty".into();
+        } else {
+            self.show_output_panel = !self.show_output_panel;
+        }
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn scroll_output_panel(&mut self, up: bool) {
+        if !self.show_output_panel {
+            return;
+        }
+        if let Some(panel) = self.output_panel.as_mut() {
+            if up {
+                panel.scroll =
+                    (panel.scroll + TERMINAL_SCROLL_STEP).min(panel.lines.len().saturating_sub(1));
+            } else {
+                panel.scroll = panel.scroll.saturating_sub(TERMINAL_SCROLL_STEP);
+            }
+            self.needs_full_redraw = true;
+            self.dirty = true;
+        }
+    }
+
+    /// Syntax tokens for a buffer line, refined by the language server's
+    /// semantic tokens while they still match the line.
+    fn line_tokens(&self, y: usize) -> Vec<(usize, usize, TokenType)> {
+        let line = &self.buffer[y];
+        let tokens = syntax_tokens(line, &self.language);
+        match self
+            .file_path
+            .as_ref()
+            .and_then(|path| self.semantic_tokens.get(path))
+            .and_then(|lines| lines.get(&y))
+            .filter(|semantic| &semantic.text == line)
+        {
+            Some(semantic) => merge_semantic_tokens(tokens, &semantic.spans),
+            None => tokens,
+        }
+    }
+
+    /// Writes the buffer, colored as on screen, to `<file>.html` beside it.
+    fn export_html(&mut self) {
+        let lines: Vec<export::HighlightedLine> = (0..self.buffer.len())
+            .map(|y| (self.buffer[y].as_slice(), self.line_tokens(y)))
+            .collect();
+        let title = self.file_name.clone().unwrap_or_else(|| "untitled".into());
+        let target = match &self.file_path {
+            Some(path) => path.with_file_name(format!("{}.html", title)),
+            None => self.project_root().join("untitled.html"),
+        };
+        self.status = match fs::write(&target, export::html_document(&title, &lines)) {
+            Ok(()) => format!("Exported to {}", target.display()),
+            Err(err) => format!("Export failed: {}", err),
+        };
+        self.needs_full_redraw = true;
+    }
+
+    fn project_root(&self) -> PathBuf {
+        match &self.file_path {
+            Some(path) => lsp::find_project_root(path),
+            None => lsp::find_project_root(&self.dashboard_workspace),
+        }
+    }
+
+    fn reload_coverage(&mut self) {
+        let root = self.project_root();
+        let Some(report) = coverage::find_report(&root) else {
+            self.status = "No coverage report found (lcov.info or llvm-cov JSON)".into();
+            self.needs_full_redraw = true;
+            self.dirty = true;
+            return;
+        };
+
+        match coverage::load_report(&report, &root) {
+            Ok(files) => {
+                self.status = format!(
+                    "Loaded coverage for {} files from {}",
+                    files.len(),
+                    report.file_name().unwrap_or_default().to_string_lossy()
+                );
+                self.coverage = files;
+                self.show_coverage = true;
+            }
+            Err(err) => {
+                self.status = format!("Failed to read coverage report: {}", err);
+            }
+        }
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn toggle_coverage(&mut self) {
+        if self.coverage.is_empty() {
+            self.reload_coverage();
+            return;
+        }
+        self.show_coverage = !self.show_coverage;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn current_coverage(&self) -> Option<&coverage::LineHits> {
+        if !self.show_coverage {
+            return None;
+        }
+        let path = self.file_path.as_ref()?;
+        self.coverage.get(&normalize_recent_path(path))
+    }
+
+    fn start_timeline(&mut self) {
+        let Some(path) = self.file_path.clone() else {
+            self.status = "Local history needs a saved file".into();
+            self.dirty = true;
+            return;
+        };
+
+        let entries = local_history::list(&path);
+        if entries.is_empty() {
+            self.status = "No local history yet - snapshots are taken on every save".into();
+            self.needs_full_redraw = true;
+            self.dirty = true;
+            return;
+        }
+
+        self.timeline_entries = entries;
+        self.timeline_index = 0;
+        self.mode = EditorMode::Timeline;
+        self.load_timeline_preview();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn load_timeline_preview(&mut self) {
+        self.timeline_preview = self
+            .timeline_entries
+            .get(self.timeline_index)
+            .and_then(|snapshot| snapshot.read().ok())
+            .map(|contents| contents.lines().map(|line| line.to_string()).collect())
+            .unwrap_or_default();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn next_timeline_entry(&mut self) {
+        if self.timeline_index + 1 < self.timeline_entries.len() {
+            self.timeline_index += 1;
+            self.load_timeline_preview();
+        }
+    }
+
+    fn prev_timeline_entry(&mut self) {
+        if self.timeline_index > 0 {
+            self.timeline_index -= 1;
+            self.load_timeline_preview();
+        }
+    }
+
+    fn cancel_timeline(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.timeline_entries.clear();
+        self.timeline_preview.clear();
+        self.timeline_index = 0;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn restore_timeline_entry(&mut self) {
+        let Some(snapshot) = self.timeline_entries.get(self.timeline_index) else {
+            self.cancel_timeline();
+            return;
+        };
+        let label = local_history::format_timestamp(snapshot.timestamp_ms);
+
+        match snapshot.read() {
+            Ok(contents) => {
+                self.save_history_state();
+                self.buffer = contents.lines().map(|l| l.chars().collect()).collect();
+                if self.buffer.is_empty() {
+                    self.buffer.push(vec![]);
+                }
+                self.cursor_y = self.cursor_y.min(self.buffer.len() - 1);
+                self.cursor_x = self.cursor_x.min(self.buffer[self.cursor_y].len());
+                self.is_selecting = false;
+                self.selection_start = None;
+                self.selection_end = None;
+                self.mark_file_dirty();
+                self.cancel_timeline();
+                self.status = format!("Restored version from {} (unsaved)", label);
+            }
+            Err(err) => {
+                self.cancel_timeline();
+                self.status = format!("Failed to read snapshot: {}", err);
+            }
+        }
+    }
+
+    fn open_diff(&mut self, title: String, old_text: &str) {
+        let old: Vec<String> = old_text.lines().map(|line| line.to_string()).collect();
+        self.diff_view = Some(DiffView::new(title, old));
+        self.refresh_diff();
+        self.mode = EditorMode::Diff;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn refresh_diff(&mut self) {
+        let new: Vec<String> = self
+            .buffer
+            .iter()
+            .map(|line| line.iter().collect())
+            .collect();
+        let Some(view) = self.diff_view.as_mut() else {
+            return;
+        };
+        let lines = diff::diff_lines(&view.old, &new);
+        let hunks = diff::hunks(&lines, 3);
+
+        self.status = if hunks.is_empty() {
+            "No differences".into()
+        } else {
+            let added = lines
+                .iter()
+                .filter(|l| l.kind == diff::DiffKind::Insert)
+                .count();
+            let removed = lines
+                .iter()
+                .filter(|l| l.kind == diff::DiffKind::Delete)
+                .count();
+            format!("{} hunks | +{} -{}", hunks.len(), added, removed)
+        };
+        view.set_hunks(hunks);
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn revert_diff_hunk(&mut self) {
+        let Some(hunk) = self
+            .diff_view
+            .as_ref()
+            .and_then(|view| view.hunks.get(view.selected).cloned())
+        else {
+            self.status = "No hunk to revert".into();
+            self.dirty = true;
+            return;
+        };
+
+        let replacement: Vec<Vec<char>> = hunk
+            .lines
+            .iter()
+            .filter(|line| line.kind != diff::DiffKind::Insert)
+            .map(|line| line.text.chars().collect())
+            .collect();
+        let end = (hunk.new_start + hunk.new_len).min(self.buffer.len());
+        let start = hunk.new_start.min(end);
+
+        self.save_history_state();
+        self.buffer.splice(start..end, replacement);
+        if self.buffer.is_empty() {
+            self.buffer.push(vec![]);
+        }
+        self.cursor_y = self.cursor_y.min(self.buffer.len() - 1);
+        self.cursor_x = self.cursor_x.min(self.buffer[self.cursor_y].len());
+        self.is_selecting = false;
+        self.selection_start = None;
+        self.selection_end = None;
+        self.mark_file_dirty();
+        self.refresh_diff();
+        self.status = format!("Reverted hunk {} | {}", hunk.header(), self.status);
+    }
+
+    fn close_diff(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.diff_view = None;
+        self.restore_default_status();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn current_file_name(&self) -> String {
+        self.file_name.clone().unwrap_or_else(|| "New".into())
+    }
+
+    fn compare_with_saved(&mut self) {
+        let Some(path) = self.file_path.clone() else {
+            self.status = "Buffer has never been saved".into();
+            self.dirty = true;
+            return;
+        };
+        match fs::read_to_string(&path) {
+            Ok(saved) => {
+                let title = format!(
+                    "{} (saved) ↔ {} (buffer)",
+                    self.current_file_name(),
+                    self.current_file_name()
+                );
+                self.open_diff(title, saved.strip_prefix(BOM).unwrap_or(&saved));
+            }
+            Err(err) => {
+                self.status = format!("Failed to read saved file: {}", err);
+                self.dirty = true;
+            }
+        }
+    }
+
+    fn current_git_file(&mut self) -> Option<(PathBuf, String)> {
+        let Some(path) = self.file_path.clone() else {
+            self.status = "Buffer is not a file in a git repository".into();
+            self.dirty = true;
+            return None;
+        };
+        let Some(root) = git::repo_root(&path) else {
+            self.status = "Not inside a git repository".into();
+            self.dirty = true;
+            return None;
+        };
+        let Some(relative) = git::relative_path(&root, &path) else {
+            self.status = "File is outside the repository".into();
+            self.dirty = true;
+            return None;
+        };
+        Some((root, relative))
+    }
+
+    fn compare_with_revision(&mut self, revision: &str) {
+        let Some((_, relative)) = self.current_git_file() else {
+            return;
+        };
+        self.compare_with_file_at(revision, &relative);
+    }
+
+    /// Diffs the buffer against `relative` as it was in `revision`; the
+    /// file may have had another name then.
+    fn compare_with_file_at(&mut self, revision: &str, relative: &str) {
+        let Some((root, _)) = self.current_git_file() else {
+            return;
+        };
+
+        match git::show_file(&root, revision, relative) {
+            Ok(contents) => {
+                let title = format!("{} @ {} ↔ buffer", relative, revision);
+                self.open_diff(title, contents.strip_prefix(BOM).unwrap_or(&contents));
+            }
+            Err(err) => {
+                self.status = format!("git: {}", err);
+                self.needs_full_redraw = true;
+                self.dirty = true;
+            }
+        }
+    }
+
+    fn start_git_log(&mut self) {
+        let Some((root, relative)) = self.current_git_file() else {
+            return;
+        };
+
+        match git::file_log(&root, &relative) {
+            Ok(entries) if entries.is_empty() => {
+                self.status = format!("No commits touch {}", relative);
+                self.dirty = true;
+            }
+            Ok(entries) => {
+                self.git_log_entries = entries;
+                self.git_log_index = 0;
+                self.git_log_scroll = 0;
+                self.mode = EditorMode::GitLog;
+                self.needs_full_redraw = true;
+                self.dirty = true;
+            }
+            Err(err) => {
+                self.status = format!("git: {}", err);
+                self.dirty = true;
+            }
+        }
+    }
+
+    fn cancel_git_log(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.git_log_entries.clear();
+        self.git_log_index = 0;
+        self.git_log_scroll = 0;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn move_git_log(&mut self, delta: isize, rows: u16) {
+        if self.git_log_entries.is_empty() {
+            return;
+        }
+        self.git_log_index = self
+            .git_log_index
+            .saturating_add_signed(delta)
+            .min(self.git_log_entries.len() - 1);
+
+        let visible = self.text_rows(rows).saturating_sub(1).max(1) as usize;
+        if self.git_log_index < self.git_log_scroll {
+            self.git_log_scroll = self.git_log_index;
+        } else if self.git_log_index >= self.git_log_scroll + visible {
+            self.git_log_scroll = self.git_log_index + 1 - visible;
+        }
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn diff_git_log_entry(&mut self) {
+        let Some((hash, path)) = self
+            .git_log_entries
+            .get(self.git_log_index)
+            .map(|commit| (commit.short_hash().to_string(), commit.path.clone()))
+        else {
+            return;
+        };
+        self.cancel_git_log();
+        self.compare_with_file_at(&hash, &path);
+    }
+
+    fn open_git_log_entry(&mut self) {
+        let Some(commit) = self.git_log_entries.get(self.git_log_index) else {
+            return;
+        };
+        let hash = commit.short_hash().to_string();
+        let subject = commit.subject.clone();
+        let relative = commit.path.clone();
+        let Some((root, _)) = self.current_git_file() else {
+            return;
+        };
+
+        match git::show_file(&root, &hash, &relative) {
+            Ok(contents) => {
+                self.cancel_git_log();
+                self.revision_view = Some(RevisionView {
+                    title: format!("{} @ {} (read-only) - {}", relative, hash, subject),
+                    lines: contents.lines().map(|line| line.to_string()).collect(),
+                    scroll: 0,
+                });
+                self.mode = EditorMode::RevisionView;
+                self.status = format!("Viewing {} at {}", relative, hash);
+            }
+            Err(err) => {
+                self.status = format!("git: {}", err);
+            }
+        }
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn close_revision_view(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.revision_view = None;
+        self.restore_default_status();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn scroll_revision_view(&mut self, delta: isize, rows: u16) {
+        let page = self.text_rows(rows).saturating_sub(1) as usize;
+        if let Some(view) = self.revision_view.as_mut() {
+            let max_scroll = view.lines.len().saturating_sub(page);
+            view.scroll = view.scroll.saturating_add_signed(delta).min(max_scroll);
+            self.needs_full_redraw = true;
+            self.dirty = true;
+        }
+    }
+
+    fn start_compare_revision(&mut self) {
+        self.mode = EditorMode::DiffRevision;
+        self.diff_revision_input.clear();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn cancel_compare_revision(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.diff_revision_input.clear();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn confirm_compare_revision(&mut self) {
+        let revision: String = self.diff_revision_input.iter().collect();
+        let revision = revision.trim().to_string();
+        self.cancel_compare_revision();
+        if !revision.is_empty() {
+            self.compare_with_revision(&revision);
+        }
+    }
+
+    fn start_compare_tree_file(&mut self) {
+        if self.tree.is_empty() {
+            self.status = "File tree is empty".into();
+            self.dirty = true;
+            return;
+        }
+        self.show_tree = true;
+        self.mode = EditorMode::DiffPickFile;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn confirm_compare_tree_file(&mut self) {
+        let Some(node) = self.tree.get(self.tree_cursor).cloned() else {
+            return;
+        };
+        if node.is_dir {
+            self.toggle_dir(self.tree_cursor);
+            self.dirty = true;
+            return;
+        }
+
+        self.mode = EditorMode::Normal;
+        match fs::read_to_string(&node.path) {
+            Ok(contents) => {
+                let title = format!("{} ↔ {} (buffer)", node.name, self.current_file_name());
+                self.open_diff(title, &contents);
+            }
+            Err(err) => {
+                self.status = format!("Failed to read {}: {}", node.name, err);
+                self.needs_full_redraw = true;
+                self.dirty = true;
+            }
+        }
+    }
+
+    fn compare_with_clipboard(&mut self) {
+        let clipboard_text = arboard::Clipboard::new()
+            .ok()
+            .and_then(|mut clipboard| clipboard.get_text().ok())
+            .or_else(|| self.clipboard.clone());
+        match clipboard_text {
+            Some(text) => {
+                let normalized = text.replace("\r\n", "\n");
+                let title = format!("clipboard ↔ {} (buffer)", self.current_file_name());
+                self.open_diff(title, &normalized);
+            }
+            None => {
+                self.status = "Clipboard is empty".into();
+                self.dirty = true;
+            }
+        }
+    }
+
+    fn scroll_diff(&mut self, delta: isize, rows: u16) {
+        let page = self.text_rows(rows).saturating_sub(1) as usize;
+        if let Some(view) = self.diff_view.as_mut() {
+            let max_scroll = view.rows.len().saturating_sub(page);
+            view.scroll = view.scroll.saturating_add_signed(delta).min(max_scroll);
+            view.selected = view.hunk_at_row(view.scroll);
+            self.needs_full_redraw = true;
+            self.dirty = true;
+        }
+    }
+
+    fn jump_diff_hunk(&mut self, forward: bool) {
+        let Some(view) = self.diff_view.as_mut() else {
+            return;
+        };
+        let target = if forward {
+            view.selected + 1
+        } else {
+            view.selected.wrapping_sub(1)
+        };
+        if target < view.hunks.len() {
+            view.selected = target;
+            view.scroll = view.header_row(target);
+            self.needs_full_redraw = true;
+            self.dirty = true;
+        }
+    }
+
+    /// The key that runs `command`: the user's own, the preset's, or the
+    /// built-in one when neither took it.
+    fn command_keys(&self, command: PaletteCommand) -> &str {
+        let user = &self.config.keys;
+        let preset = self.config.keymap.bindings();
+        if let Some(label) = keymap::label(user, command).or(keymap::label(preset, command)) {
+            return label;
+        }
+        if keymap::shadows(user, command.keys()) || keymap::shadows(preset, command.keys()) {
+            return "";
+        }
+        command.keys()
+    }
+
+    /// What the user's `[keys]` or the keymap preset bind the key to.
+    fn key_action(&self, code: KeyCode, modifiers: KeyModifiers) -> Option<keymap::Action> {
+        keymap::lookup(&self.config.keys, code, modifiers)
+            .or_else(|| keymap::lookup(self.config.keymap.bindings(), code, modifiers))
+    }
+
+    fn run_key_action(&mut self, action: keymap::Action) {
+        match action {
+            keymap::Action::Palette => self.start_palette(),
+            keymap::Action::Command(command) => self.run_palette_command(command),
+        }
+    }
+
+    fn start_keymap_picker(&mut self) {
+        self.keymap_return = match self.mode {
+            EditorMode::Dashboard => EditorMode::Dashboard,
+            _ => EditorMode::Normal,
+        };
+        self.keymap_index = keymap::Preset::ALL
+            .iter()
+            .position(|&preset| preset == self.config.keymap)
+            .unwrap_or(0);
+        self.mode = EditorMode::KeymapPicker;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn cancel_keymap_picker(&mut self) {
+        // Dismissing the first-run picker counts as keeping the default,
+        // so it does not come back on the next start.
+        if !config_path().exists() {
+            let _ = self.save_keymap();
+        }
+        self.mode = self.keymap_return;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn confirm_keymap_picker(&mut self) {
+        self.config.keymap = keymap::Preset::ALL[self.keymap_index];
+        self.status = match self.save_keymap() {
+            Ok(()) => format!(
+                "Keybindings: {} (saved to {})",
+                self.config.keymap.title(),
+                config_path().to_string_lossy()
+            ),
+            Err(err) => format!(
+                "Keybindings: {} for this session; could not save: {}",
+                self.config.keymap.title(),
+                err
+            ),
+        };
+        self.mode = self.keymap_return;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn save_keymap(&self) -> io::Result<()> {
+        if replaying() {
+            return Ok(());
+        }
+        config::set_editor_value("keymap", &format!("\"{}\"", self.config.keymap.name()))
+    }
+
+    fn start_palette(&mut self) {
+        self.mode = EditorMode::Palette;
+        self.palette_query.clear();
+        self.palette_index = 0;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn cancel_palette(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.palette_query.clear();
+        self.palette_index = 0;
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn palette_matches(&self) -> Vec<PaletteCommand> {
+        let query: String = self.palette_query.iter().collect::<String>().to_lowercase();
+        PaletteCommand::ALL
+            .iter()
+            .copied()
+            .filter(|command| command.title().to_lowercase().contains(&query))
+            .collect()
+    }
+
+    fn next_palette_item(&mut self) {
+        let count = self.palette_matches().len();
+        if count > 0 {
+            self.palette_index = (self.palette_index + 1) % count;
+            self.dirty = true;
+        }
+    }
+
+    fn prev_palette_item(&mut self) {
+        let count = self.palette_matches().len();
+        if count > 0 {
+            self.palette_index = self.palette_index.checked_sub(1).unwrap_or(count - 1);
+            self.dirty = true;
+        }
+    }
+
+    fn confirm_palette(&mut self) {
+        let matches = self.palette_matches();
+        let Some(command) = matches.get(self.palette_index).copied() else {
+            return;
+        };
+        self.cancel_palette();
+        self.run_palette_command(command);
+    }
+
+    fn run_palette_command(&mut self, command: PaletteCommand) {
+        if command.edits_buffer() && self.is_read_only() {
+            self.refuse_read_only_edit();
+            return;
+        }
+        match command {
+            PaletteCommand::Save => {
+                let _ = self.save();
+            }
+            PaletteCommand::Find => self.start_search(),
+            PaletteCommand::GoToLine => self.start_goto_line(),
+            PaletteCommand::ToggleTree => {
+                self.show_tree = !self.show_tree;
+                self.needs_full_redraw = true;
+                self.dirty = true;
+            }
+            PaletteCommand::ToggleTerminal => self.toggle_terminal(),
+            PaletteCommand::SelectAll => self.select_all(),
+            PaletteCommand::Undo => self.undo(),
+            PaletteCommand::Redo => self.redo(),
+            PaletteCommand::QuickFix => self.request_quick_fixes(),
+            PaletteCommand::RunTestAtCursor => self.run_test_at_cursor(),
+            PaletteCommand::ToggleOutputPanel => self.toggle_output_panel(),
+            PaletteCommand::ReloadCoverage => self.reload_coverage(),
+            PaletteCommand::ToggleCoverage => self.toggle_coverage(),
+            PaletteCommand::LocalHistory => self.start_timeline(),
+            PaletteCommand::DiffWithSaved => self.compare_with_saved(),
+            PaletteCommand::DiffWithHead => self.compare_with_revision("HEAD"),
+            PaletteCommand::DiffWithRevision => self.start_compare_revision(),
+            PaletteCommand::DiffWithTreeFile => self.start_compare_tree_file(),
+            PaletteCommand::DiffWithClipboard => self.compare_with_clipboard(),
+            PaletteCommand::FileHistory => self.start_git_log(),
+            PaletteCommand::GitStash => self.run_git_action(GitAction::Stash),
+            PaletteCommand::GitStashPop => self.run_git_action(GitAction::StashPop),
+            PaletteCommand::GitPull => self.run_git_action(GitAction::Pull),
+            PaletteCommand::GitPush => self.run_git_action(GitAction::Push),
+            PaletteCommand::NotificationLog => self.start_notification_log(),
+            PaletteCommand::SwitchBranch => self.start_branch_picker(),
+            PaletteCommand::Blame => self.start_blame(),
+            PaletteCommand::NewProject => self.start_scaffold_picker(),
+            PaletteCommand::MatchingPair => self.jump_to_matching_pair(),
+            PaletteCommand::SaveAndQuit => self.save_and_quit(),
+            PaletteCommand::Quit => self.request_quit(),
+            PaletteCommand::Detach => self.request_detach(),
+            PaletteCommand::EditAnyway => self.edit_anyway(),
+            PaletteCommand::ExportHtml => self.export_html(),
+            PaletteCommand::CopyWithFormatting => self.copy_with_formatting(),
+            PaletteCommand::Share => self.share_selection(),
+            PaletteCommand::Evaluate => self.evaluate_selection(false),
+            PaletteCommand::EvaluateAndReplace => self.evaluate_selection(true),
+            PaletteCommand::LocalOptions => self.start_local_options(),
+            PaletteCommand::CloseBuffer => self.close_buffer(),
+            PaletteCommand::ReopenClosedBuffer => self.reopen_closed_buffer(),
+            PaletteCommand::CloseOtherBuffers => self.close_all_buffers(true),
+            PaletteCommand::CloseAllBuffers => self.close_all_buffers(false),
+            PaletteCommand::TogglePin => self.toggle_pin(),
+            PaletteCommand::GoToSymbol => self.start_symbol_picker(),
+            PaletteCommand::Tutorial => self.start_tutor(),
+            PaletteCommand::KeymapPreset => self.start_keymap_picker(),
+            PaletteCommand::About => {
+                self.status = version::describe();
+                self.dirty = true;
+            }
+            PaletteCommand::CheckForUpdates => self.check_for_updates(true),
+            PaletteCommand::TogglePreview => self.toggle_preview(),
+            PaletteCommand::ToggleTypewriter => self.toggle_typewriter(),
+            PaletteCommand::ToggleFocusMode => self.toggle_focus_mode(),
+            PaletteCommand::ToggleSoftWrap => self.toggle_soft_wrap(),
+            PaletteCommand::PrettyPrint => self.pretty_print(),
+            PaletteCommand::FormatTable => self.format_table(),
+            PaletteCommand::ReflowParagraph => self.reflow_paragraph(false),
+            PaletteCommand::JustifyParagraph => self.reflow_paragraph(true),
+            PaletteCommand::Outline => self.start_outline(),
+            PaletteCommand::QuickJump => self.start_quick_jump(),
+            PaletteCommand::BlockStart => self.jump_to_block_edge(false),
+            PaletteCommand::BlockEnd => self.jump_to_block_edge(true),
+            PaletteCommand::HealthCheck => self.start_health_check(),
+            PaletteCommand::SwitchHeaderSource => self.switch_header_source(),
+            PaletteCommand::AddImport => self.add_import(),
+            PaletteCommand::OrganizeImports => self.organize_imports(),
+            PaletteCommand::PreviousFunction => self.jump_to_function(false),
+            PaletteCommand::NextFunction => self.jump_to_function(true),
+        }
+    }
+
+    fn apply_quick_fix(&mut self) {
+        if self.quick_fixes.is_empty() {
+            self.cancel_quick_fix();
+            return;
+        }
+
+        let fix = self.quick_fixes.remove(self.quick_fix_index);
+        self.is_selecting = false;
+        self.selection_start = None;
+        self.selection_end = None;
+        self.apply_text_edits(&fix.edits);
+        self.cancel_quick_fix();
+        self.status = format!("Applied: {}", fix.title);
+    }
+
+    fn cancel_quick_fix(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.quick_fixes.clear();
+        self.quick_fix_index = 0;
+        self.restore_default_status();
+        self.needs_full_redraw = true;
+        self.dirty = true;
+    }
+
+    fn next_quick_fix(&mut self) {
+        if !self.quick_fixes.is_empty() {
+            self.quick_fix_index = (self.quick_fix_index + 1) % self.quick_fixes.len();
+            self.dirty = true;
+        }
+    }
+
+    fn prev_quick_fix(&mut self) {
+        if !self.quick_fixes.is_empty() {
+            if self.quick_fix_index == 0 {
+                self.quick_fix_index = self.quick_fixes.len() - 1;
+            } else {
+                self.quick_fix_index -= 1;
+            }
+            self.dirty = true;
+        }
+    }
+}
+
+fn dashboard_action_title(action: &DashboardAction) -> String {
+    match action {
+        DashboardAction::OpenWorkspace(_) => "Open current workspace".into(),
+        DashboardAction::NewBuffer => "Start with an empty buffer".into(),
+        DashboardAction::RecentProject(path) => {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            format!("Project  {}", name)
+        }
+        DashboardAction::RecentFile(path) => {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            format!("File     {}", name)
+        }
+    }
+}
+
+fn dashboard_action_meta(action: &DashboardAction, width: usize) -> String {
+    let raw = match action {
+        DashboardAction::OpenWorkspace(path) | DashboardAction::RecentProject(path) => {
+            path.to_string_lossy().into_owned()
+        }
+        DashboardAction::NewBuffer => "No file selected yet".into(),
+        DashboardAction::RecentFile(path) => path
+            .parent()
+            .map(|parent| parent.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string_lossy().into_owned()),
+    };
+    shorten_text_middle(&raw, width)
+}
+
+fn draw_centered_text(
+    out: &mut Vec<u8>,
+    row: u16,
+    cols: u16,
+    text: &str,
+    color: Color,
+    bold: bool,
+) -> io::Result<()> {
+    let text_width = text.chars().count() as u16;
+    let x = cols.saturating_sub(text_width) / 2;
+    execute!(out, cursor::MoveTo(x, row), SetForegroundColor(color))?;
+    if bold {
+        execute!(out, SetAttribute(Attribute::Bold))?;
+    }
+    write!(out, "{}", text)?;
+    execute!(
+        out,
+        SetAttribute(Attribute::Reset),
+        SetForegroundColor(Color::Reset)
+    )?;
+    Ok(())
+}
+
+fn draw_dashboard(ed: &mut Editor, out: &mut Vec<u8>, cols: u16, rows: u16) -> io::Result<()> {
+    execute!(
+        out,
+        terminal::Clear(terminal::ClearType::All),
+        cursor::Hide,
+        SetAttribute(Attribute::Reset),
+        SetForegroundColor(Color::Reset),
+        crossterm::style::SetBackgroundColor(Color::Reset)
+    )?;
+
+    for (i, line) in DASHBOARD_LOGO.iter().enumerate() {
+        let color = match i {
+            0 | 1 => Color::Cyan,
+            2 | 3 => Color::Blue,
+            _ => Color::White,
+        };
+        draw_centered_text(out, 1 + i as u16, cols, line, color, true)?;
+    }
+
+    draw_centered_text(
+        out,
+        8,
+        cols,
+        "Launch pad for recent work, fast starts, and clean sessions",
+        Color::DarkGrey,
+        false,
+    )?;
+
+    let panel_width = cols.saturating_sub(6).min(86);
+    let panel_x = cols.saturating_sub(panel_width) / 2;
+    let mut row = 10;
+
+    execute!(
+        out,
+        cursor::MoveTo(panel_x, row),
+        SetForegroundColor(Color::DarkGrey),
+        SetAttribute(Attribute::Bold)
+    )?;
+    write!(out, "Start")?;
+    execute!(
+        out,
+        SetAttribute(Attribute::Reset),
+        SetForegroundColor(Color::Reset)
+    )?;
+    row += 1;
+
+    let actions = ed.dashboard_actions();
+    let meta_width = panel_width.saturating_sub(8) as usize;
+    let draw_item = |out: &mut Vec<u8>,
+                     row: &mut u16,
+                     index: usize,
+                     action: &DashboardAction|
+     -> io::Result<()> {
+        if *row >= rows.saturating_sub(6) {
+            return Ok(());
+        }
+
+        execute!(out, cursor::MoveTo(panel_x, *row))?;
+        let prefix = format!("{:>2}.", index + 1);
+        let title = dashboard_action_title(action);
+        let meta = dashboard_action_meta(action, meta_width);
+
+        if index == ed.dashboard_selection {
+            execute!(
+                out,
+                crossterm::style::SetBackgroundColor(Color::Blue),
+                SetForegroundColor(Color::White),
+                SetAttribute(Attribute::Bold)
+            )?;
+            write!(
+                out,
+                " {} {:<width$} ",
+                prefix,
+                shorten_text_middle(&title, panel_width.saturating_sub(6) as usize),
+                width = panel_width.saturating_sub(6) as usize
+            )?;
+            execute!(
+                out,
+                crossterm::style::SetBackgroundColor(Color::Reset),
+                SetForegroundColor(Color::Reset),
+                SetAttribute(Attribute::Reset)
+            )?;
+            *row += 1;
+            if *row < rows.saturating_sub(5) {
+                execute!(
+                    out,
+                    cursor::MoveTo(panel_x + 4, *row),
+                    SetForegroundColor(Color::Grey)
+                )?;
+                write!(
+                    out,
+                    "{:<width$}",
+                    meta,
+                    width = panel_width.saturating_sub(4) as usize
+                )?;
+                execute!(out, SetForegroundColor(Color::Reset))?;
+            }
+        } else {
+            execute!(out, SetForegroundColor(Color::White))?;
+            write!(
+                out,
+                " {} {}",
+                prefix,
+                shorten_text_middle(&title, panel_width.saturating_sub(6) as usize)
+            )?;
+            *row += 1;
+            if *row < rows.saturating_sub(5) {
+                execute!(
+                    out,
+                    cursor::MoveTo(panel_x + 4, *row),
+                    SetForegroundColor(Color::DarkGrey)
+                )?;
+                write!(out, "{}", meta)?;
+                execute!(out, SetForegroundColor(Color::Reset))?;
+            }
+        }
+
+        *row += 1;
+        Ok(())
+    };
+
+    draw_item(out, &mut row, 0, &actions[0])?;
+    draw_item(out, &mut row, 1, &actions[1])?;
+
+    row += 1;
+    execute!(
+        out,
+        cursor::MoveTo(panel_x, row),
+        SetForegroundColor(Color::DarkGrey),
+        SetAttribute(Attribute::Bold)
+    )?;
+    write!(out, "Recent Projects")?;
+    execute!(
+        out,
+        SetAttribute(Attribute::Reset),
+        SetForegroundColor(Color::Reset)
+    )?;
+    row += 1;
+
+    if ed.recent_projects.is_empty() {
+        execute!(
+            out,
+            cursor::MoveTo(panel_x + 4, row),
+            SetForegroundColor(Color::DarkGrey)
+        )?;
+        write!(out, "No recent projects yet")?;
+        execute!(out, SetForegroundColor(Color::Reset))?;
+        row += 2;
+    } else {
+        for (offset, action) in actions
+            .iter()
+            .skip(2)
+            .take(ed.recent_projects.len())
+            .enumerate()
+        {
+            draw_item(out, &mut row, 2 + offset, action)?;
+        }
+    }
+
+    execute!(
+        out,
+        cursor::MoveTo(panel_x, row),
+        SetForegroundColor(Color::DarkGrey),
+        SetAttribute(Attribute::Bold)
+    )?;
+    write!(out, "Recent Files")?;
+    execute!(
+        out,
+        SetAttribute(Attribute::Reset),
+        SetForegroundColor(Color::Reset)
+    )?;
+    row += 1;
+
+    if ed.recent_files.is_empty() {
+        execute!(
+            out,
+            cursor::MoveTo(panel_x + 4, row),
+            SetForegroundColor(Color::DarkGrey)
+        )?;
+        write!(out, "No recent files yet")?;
+        execute!(out, SetForegroundColor(Color::Reset))?;
+    } else {
+        for (offset, action) in actions
+            .iter()
+            .skip(2 + ed.recent_projects.len())
+            .take(ed.recent_files.len())
+            .enumerate()
+        {
+            draw_item(out, &mut row, 2 + ed.recent_projects.len() + offset, action)?;
+        }
+    }
+
+    let shortcuts_row = rows.saturating_sub(5);
+    execute!(
+        out,
+        cursor::MoveTo(panel_x, shortcuts_row),
+        SetForegroundColor(Color::DarkGrey),
+        SetAttribute(Attribute::Bold)
+    )?;
+    write!(out, "Shortcuts")?;
+    execute!(
+        out,
+        SetAttribute(Attribute::Reset),
+        SetForegroundColor(Color::Reset)
+    )?;
+
+    execute!(
+        out,
+        cursor::MoveTo(panel_x, shortcuts_row + 1),
+        SetForegroundColor(Color::Grey)
+    )?;
+    write!(out, "Ctrl+O Tree   Ctrl+1 Terminal   Ctrl+F Search")?;
+    execute!(out, cursor::MoveTo(panel_x, shortcuts_row + 2))?;
+    write!(out, "Ctrl+N File   Ctrl+M Folder     Ctrl+G Go to line")?;
+    execute!(out, SetForegroundColor(Color::Reset))?;
+
+    execute!(
+        out,
+        cursor::MoveTo(0, rows - 2),
+        SetForegroundColor(Color::DarkGrey)
+    )?;
+    write!(
+        out,
+        "{:^width$}",
+        "Enter open | Up/Down navigate | O workspace | N new buffer | P new project | R refresh | Ctrl+Q quit",
+        width = cols as usize
+    )?;
+    execute!(out, SetForegroundColor(Color::Reset))?;
+
+    execute!(out, cursor::MoveTo(0, rows - 1))?;
+    write!(out, "{:^width$}", "Termi Dashboard", width = cols as usize)?;
+
+    out.flush()?;
+    ed.needs_full_redraw = false;
+    Ok(())
+}
+
+fn draw_picker(
+    out: &mut Vec<u8>,
+    cols: u16,
+    max_lines: u16,
+    prompt: &str,
+    items: &[(String, String)],
+    selected: usize,
+    empty_text: &str,
+) -> io::Result<()> {
+    let width = cols.saturating_sub(4).min(70);
+    let x = cols.saturating_sub(width) / 2;
+    let inner = width.saturating_sub(2) as usize;
+
+    execute!(
+        out,
+        cursor::MoveTo(x, 1),
+        crossterm::style::SetBackgroundColor(Color::DarkGrey),
+        SetForegroundColor(Color::White),
+        SetAttribute(Attribute::Bold)
+    )?;
+    let prompt: String = prompt.chars().take(inner).collect();
+    write!(out, " {:<width$} ", prompt, width = inner)?;
+    execute!(out, SetAttribute(Attribute::Reset))?;
+
+    let first = selected.saturating_sub(PALETTE_VISIBLE_ITEMS.saturating_sub(1));
+    for (row, (i, (title, hint))) in items
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(PALETTE_VISIBLE_ITEMS)
+        .enumerate()
+    {
+        let y = 2 + row as u16;
+        if y >= max_lines {
+            break;
+        }
+        execute!(out, cursor::MoveTo(x, y))?;
+        if i == selected {
+            execute!(
+                out,
+                crossterm::style::SetBackgroundColor(Color::Blue),
+                SetForegroundColor(Color::White),
+                SetAttribute(Attribute::Bold)
+            )?;
+        } else {
+            execute!(
+                out,
+                crossterm::style::SetBackgroundColor(Color::Black),
+                SetForegroundColor(Color::White)
+            )?;
+        }
+        let title_width = inner.saturating_sub(hint.chars().count() + 1);
+        let title: String = title.chars().take(title_width).collect();
+        write!(out, " {:<tw$} {} ", title, hint, tw = title_width)?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+    }
+    if items.is_empty() && 2 < max_lines {
+        execute!(
+            out,
+            cursor::MoveTo(x, 2),
+            crossterm::style::SetBackgroundColor(Color::Black),
+            SetForegroundColor(Color::DarkGrey)
+        )?;
+        write!(out, " {:<width$} ", empty_text, width = inner)?;
+    }
+    execute!(
+        out,
+        crossterm::style::SetBackgroundColor(Color::Reset),
+        SetForegroundColor(Color::Reset)
+    )?;
+    Ok(())
+}
+
+// Writes buffer text straight into the frame without collecting a `String`.
+fn write_chars(out: &mut Vec<u8>, chars: &[char]) {
+    let mut encoded = [0; 4];
+    for c in chars {
+        out.extend_from_slice(c.encode_utf8(&mut encoded).as_bytes());
+    }
+}
+
+/// Screen rows a line takes when wrapped to `width`; the cursor may sit
+/// one past the end, so a full last row adds one.
+fn wrapped_rows(line: &[char], width: usize) -> usize {
+    line.len() / width + 1
+}
+
+/// The text area with long lines wrapped: plain text, the selection, and
+/// line numbers on each line's first row.
+fn draw_wrapped_text(
+    ed: &Editor,
+    out: &mut Vec<u8>,
+    gutter_x: u16,
+    text_x: u16,
+    width: usize,
+    max_lines: u16,
+) -> io::Result<()> {
+    let selection = match (ed.selection_start, ed.selection_end) {
+        (Some(a), Some(b)) => Some((a.min(b), a.max(b))),
+        _ => None,
+    };
+    let is_selected =
+        |pos: (usize, usize)| selection.is_some_and(|(start, end)| pos >= start && pos < end);
+    let mut pos = Some((ed.scroll_y, ed.wrap_row));
+    for screen_y in 0..max_lines {
+        let Some((y, row)) = pos else {
+            execute!(out, cursor::MoveTo(gutter_x, screen_y))?;
+            write!(out, "\x1b[K")?;
+            continue;
+        };
+        if ed.show_line_numbers {
+            execute!(out, cursor::MoveTo(gutter_x, screen_y))?;
+            if row == 0 {
+                write!(out, "{:>4}  {}", y + 1, ed.glyphs.separator)?;
+            } else {
+                write!(out, "{:>4}  {}", "", ed.glyphs.separator)?;
+            }
+        }
+        execute!(out, cursor::MoveTo(text_x, screen_y))?;
+        let line = &ed.buffer[y];
+        let start = (row * width).min(line.len());
+        let end = (start + width).min(line.len());
+        let mut x = start;
+        while x < end {
+            let selected = is_selected((y, x));
+            let mut segment_end = x + 1;
+            while segment_end < end && is_selected((y, segment_end)) == selected {
+                segment_end += 1;
+            }
+            if selected {
+                execute!(out, SetAttribute(Attribute::Reverse))?;
+            }
+            write_chars(out, &line[x..segment_end]);
+            if selected {
+                execute!(out, SetAttribute(Attribute::Reset))?;
+            }
+            x = segment_end;
+        }
+        write!(out, "{:width$}", "", width = width - (end - start))?;
+        pos = ed.wrap_step((y, row), false, width);
+    }
+    Ok(())
+}
+
+fn write_padded(out: &mut Vec<u8>, chars: &[char], width: usize) {
+    write_chars(out, chars);
+    out.resize(out.len() + width.saturating_sub(chars.len()), b' ');
+}
+
+/// The rendered Markdown beside the text, scrolled to the first line shown
+/// on the left.
+fn draw_markdown_preview(
+    ed: &Editor,
+    out: &mut Vec<u8>,
+    x: u16,
+    width: u16,
+    rows: u16,
+) -> io::Result<()> {
+    let content_width = width.saturating_sub(2) as usize;
+    let rendered = markdown::render(&ed.buffer, content_width, ed.glyphs);
+    let top = rendered
+        .iter()
+        .position(|line| line.source >= ed.scroll_y)
+        .unwrap_or(rendered.len());
+
+    for screen_y in 0..rows {
+        execute!(
+            out,
+            cursor::MoveTo(x, screen_y),
+            SetForegroundColor(Color::DarkGrey)
+        )?;
+        write!(out, "{} ", ed.glyphs.separator)?;
+        execute!(out, SetForegroundColor(Color::Reset))?;
+        let mut used = 0;
+        if let Some(line) = rendered.get(top + screen_y as usize) {
+            for (text, style) in &line.spans {
+                let text: String = text.chars().take(content_width - used).collect();
+                used += text.chars().count();
+                write!(out, "{}", style.apply(text))?;
+            }
+        }
+        write!(out, "{:width$}", "", width = content_width - used)?;
+    }
+    Ok(())
+}
+
+fn draw(ed: &mut Editor, out: &mut Vec<u8>) -> io::Result<()> {
+    let (cols, rows) = ed.screen_size;
+
+    if matches!(ed.mode, EditorMode::Terminal) {
+        let formatted = ed.terminal_parser.screen().contents_formatted();
+        out.write_all(&formatted)?;
+        out.flush()?;
+        return Ok(());
+    }
+
+    if matches!(ed.mode, EditorMode::Dashboard) {
+        return draw_dashboard(ed, out, cols, rows);
+    }
+
+    execute!(
+        out,
+        cursor::Hide,
+        SetAttribute(Attribute::Reset),
+        SetForegroundColor(Color::Reset),
+        crossterm::style::SetBackgroundColor(Color::Reset)
+    )?;
+
+    let max_lines = ed.text_rows(rows);
+    let tree_offset = if ed.show_tree { TREE_WIDTH } else { 0 };
+    let line_num_offset = if ed.show_line_numbers {
+        LINE_NUM_WIDTH
+    } else {
+        0
+    };
+    let text_offset = tree_offset + line_num_offset;
+
+    let scroll_changed = ed.scroll_y != ed.last_scroll_y || ed.scroll_x != ed.last_scroll_x;
+    let should_clear = ed.needs_full_redraw || scroll_changed;
+
+    if should_clear {
+        if scroll_changed && !ed.needs_full_redraw {
+            for y in 0..max_lines {
+                execute!(out, cursor::MoveTo(0, y))?;
+                write!(out, "\x1b[K")?;
+            }
+        } else {
+            execute!(out, terminal::Clear(terminal::ClearType::All))?;
+        }
+    }
+
+    if matches!(ed.mode, EditorMode::DeleteConfirm) {
+        let dialog_y = rows / 2;
+        let dialog_x = (cols / 2).saturating_sub(20);
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y))?;
+        execute!(out, SetForegroundColor(Color::Red))?;
+        execute!(out, SetAttribute(Attribute::Bold))?;
+        write!(out, "{}", ed.glyphs.rule.to_string().repeat(27))?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+        execute!(out, SetForegroundColor(Color::White))?;
+
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 1))?;
+        if let Some(target) = &ed.delete_target {
+            let item_type = if target.is_dir() { "Folder" } else { "File" };
+            let name = target.file_name().unwrap_or_default().to_string_lossy();
+            write!(out, " Delete {}?", item_type)?;
+            execute!(out, cursor::MoveTo(dialog_x, dialog_y + 2))?;
+            write!(out, "  {}", name)?;
+        }
+
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 3))?;
+        write!(out, " Y - Yes  |  N - No")?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 4))?;
+        execute!(out, SetForegroundColor(Color::Red))?;
+        write!(out, "{}", ed.glyphs.rule.to_string().repeat(27))?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+        execute!(out, SetForegroundColor(Color::White))?;
+    }
+
+    if matches!(ed.mode, EditorMode::Rename) {
+        let dialog_y = rows / 2;
+        let dialog_x = (cols / 2).saturating_sub(20);
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y))?;
+        execute!(out, SetForegroundColor(Color::Cyan))?;
+        execute!(out, SetAttribute(Attribute::Bold))?;
+        write!(out, "{}", ed.glyphs.rule.to_string().repeat(27))?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+        execute!(out, SetForegroundColor(Color::White))?;
+
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 1))?;
+        write!(out, " Rename:")?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 2))?;
+        let rename_name: String = ed.rename_name.iter().collect();
+        if let Some(check) = ed.prompt_check() {
+            execute!(out, SetForegroundColor(check.color()))?;
+        }
+        write!(out, "  {}", rename_name)?;
+        execute!(out, SetForegroundColor(Color::White))?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 3))?;
+        write!(out, " Enter - Confirm  |  Esc - Cancel")?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 4))?;
+        execute!(out, SetForegroundColor(Color::Cyan))?;
+        write!(out, "{}", ed.glyphs.rule.to_string().repeat(27))?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+        execute!(out, SetForegroundColor(Color::White))?;
+    }
+
+    if matches!(ed.mode, EditorMode::GoToLine) {
+        let dialog_y = rows / 2;
+        let dialog_x = (cols / 2).saturating_sub(20);
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y))?;
+        execute!(out, SetForegroundColor(Color::Yellow))?;
+        execute!(out, SetAttribute(Attribute::Bold))?;
+        write!(out, "{}", ed.glyphs.rule.to_string().repeat(27))?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+        execute!(out, SetForegroundColor(Color::White))?;
+
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 1))?;
+        write!(out, " Go to Line:")?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 2))?;
+        let line_input: String = ed.goto_line_input.iter().collect();
+        if let Some(check) = ed.prompt_check() {
+            execute!(out, SetForegroundColor(check.color()))?;
+        }
+        write!(out, "  {}", line_input)?;
+        execute!(out, SetForegroundColor(Color::White))?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 3))?;
+        write!(out, " Enter - Go  |  Esc - Cancel")?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 4))?;
+        execute!(out, SetForegroundColor(Color::Yellow))?;
+        write!(out, "{}", ed.glyphs.rule.to_string().repeat(27))?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+        execute!(out, SetForegroundColor(Color::White))?;
+    }
+
+    if ed.show_tree {
+        let tree_max_scroll = ed.tree.len().saturating_sub(max_lines as usize);
+        ed.tree_scroll = ed.tree_scroll.min(tree_max_scroll);
+
+        let tree_scroll_changed = ed.tree_scroll != ed.last_tree_scroll || ed.needs_full_redraw;
+        if tree_scroll_changed {
+            for y in 0..max_lines {
+                execute!(out, cursor::MoveTo(0, y))?;
+                write!(out, "{:width$}", "", width = TREE_WIDTH as usize)?;
+            }
+        }
+
+        for (screen_i, tree_i) in (ed.tree_scroll..ed.tree.len())
+            .enumerate()
+            .take(max_lines as usize)
+        {
+            if let Some(n) = ed.tree.get(tree_i) {
+                execute!(out, cursor::MoveTo(0, screen_i as u16))?;
+                let mark = if tree_i == ed.tree_cursor { ">" } else { " " };
+                let icon = if n.is_dir {
+                    ed.glyphs.folder
+                } else {
+                    ed.glyphs.file
+                };
+                let prefix = if !n.is_dir && ed.dirty_files.contains(&n.path) {
+                    "."
+                } else {
+                    ""
+                };
+                let name_display = format!(
+                    "{} {}{} {}{}",
+                    mark,
+                    "  ".repeat(n.depth),
+                    icon,
+                    prefix,
+                    n.name
+                );
+                let truncated: String = name_display.chars().take(TREE_WIDTH as usize).collect();
+                write!(out, "{:<width$}", truncated, width = TREE_WIDTH as usize)?;
+            }
+        }
+
+        let visible_tree_items =
+            (ed.tree.len().saturating_sub(ed.tree_scroll)).min(max_lines as usize);
+        if visible_tree_items < max_lines as usize {
+            for y in visible_tree_items..max_lines as usize {
+                execute!(out, cursor::MoveTo(0, y as u16))?;
+                write!(out, "{:width$}", "", width = TREE_WIDTH as usize)?;
+            }
+        }
+
+        ed.last_tree_scroll = ed.tree_scroll;
+    }
+
+    let wrap_width = ed.soft_wrap_width();
+    if ed.show_line_numbers && wrap_width.is_none() {
+        let line_coverage = ed.current_coverage();
+        for screen_y in 0..max_lines {
+            let buf_y = ed.scroll_y + screen_y as usize;
+            if ed.buffer.get(buf_y).is_some() {
+                execute!(out, cursor::MoveTo(tree_offset, screen_y))?;
+                let line_num = buf_y + 1;
+                match line_coverage.and_then(|hits| hits.get(&buf_y)) {
+                    Some(0) => {
+                        execute!(
+                            out,
+                            crossterm::style::SetBackgroundColor(Color::DarkRed),
+                            SetForegroundColor(Color::White)
+                        )?;
+                    }
+                    Some(_) => {
+                        execute!(
+                            out,
+                            crossterm::style::SetBackgroundColor(Color::DarkGreen),
+                            SetForegroundColor(Color::White)
+                        )?;
+                    }
+                    None => {}
+                }
+                write!(out, "{:>4}", line_num)?;
+                execute!(
+                    out,
+                    crossterm::style::SetBackgroundColor(Color::Reset),
+                    SetForegroundColor(Color::Reset)
+                )?;
+                let test_status = ed
+                    .file_path
+                    .as_ref()
+                    .and_then(|path| ed.test_results.get(path))
+                    .filter(|results| !results.is_empty())
+                    .and_then(|results| match ed.test_definition(buf_y)? {
+                        (name, true) => results.get(&name),
+                        _ => None,
+                    });
+                match test_status {
+                    Some(TestStatus::Running) => {
+                        execute!(out, SetForegroundColor(Color::Yellow))?;
+                        write!(out, "{}", ed.glyphs.running)?;
+                        execute!(out, SetForegroundColor(Color::Reset))?;
+                    }
+                    Some(TestStatus::Passed) => {
+                        execute!(out, SetForegroundColor(Color::Green))?;
+                        write!(out, "{}", ed.glyphs.passed)?;
+                        execute!(out, SetForegroundColor(Color::Reset))?;
+                    }
+                    Some(TestStatus::Failed) => {
+                        execute!(out, SetForegroundColor(Color::Red))?;
+                        write!(out, "{}", ed.glyphs.failed)?;
+                        execute!(out, SetForegroundColor(Color::Reset))?;
+                    }
+                    None => write!(out, " ")?,
+                }
+                write!(out, "{}", ed.glyphs.separator)?;
+            }
+        }
+    }
+
+    let preview_width = ed.preview_width(cols);
+    let available_width = (cols - text_offset - preview_width) as usize;
+    let is_search_mode = !ed.search_results.is_empty() && matches!(ed.mode, EditorMode::Search);
+    let query: &[char] = if is_search_mode {
+        &ed.search_query
+    } else {
+        &[]
+    };
+    let focus = ed.focus_range();
+    // The wrapped view draws its own rows.
+    let unwrapped_rows = if wrap_width.is_some() { 0 } else { max_lines };
+    if let Some(width) = wrap_width {
+        draw_wrapped_text(ed, out, tree_offset, text_offset, width, max_lines)?;
+    }
+    for screen_y in 0..unwrapped_rows {
+        let buf_y = ed.scroll_y + screen_y as usize;
+        execute!(out, cursor::MoveTo(text_offset, screen_y))?;
+        if let Some(line) = ed.buffer.get(buf_y) {
+            let line_len = line.len();
+
+            let start_char_idx = ed.scroll_x.min(line_len);
+            let end_char_idx = (ed.scroll_x + available_width).min(line_len);
+
+            if start_char_idx >= line_len {
+                write!(out, "{:width$}", "", width = available_width)?;
+            } else {
+                let visible_part = &line[start_char_idx..end_char_idx];
+
+                let tokens = ed.line_tokens(buf_y);
+
+                let (actual_start_y, actual_start_x, actual_end_y, actual_end_x) =
+                    if let (Some((sel_start_y, sel_start_x)), Some((sel_end_y, sel_end_x))) =
+                        (ed.selection_start, ed.selection_end)
+                    {
+                        if (sel_start_y, sel_start_x) < (sel_end_y, sel_end_x) {
+                            (
+                                Some(sel_start_y),
+                                Some(sel_start_x),
+                                Some(sel_end_y),
+                                Some(sel_end_x),
+                            )
+                        } else {
+                            (
+                                Some(sel_end_y),
+                                Some(sel_end_x),
+                                Some(sel_start_y),
+                                Some(sel_start_x),
+                            )
+                        }
+                    } else {
+                        (None, None, None, None)
+                    };
+
+                let is_char_selected = |char_idx: usize| -> bool {
+                    if let (Some(start_y), Some(start_x), Some(end_y), Some(end_x)) =
+                        (actual_start_y, actual_start_x, actual_end_y, actual_end_x)
+                    {
+                        if buf_y >= start_y && buf_y <= end_y {
+                            if buf_y == start_y && buf_y == end_y {
+                                char_idx >= start_x && char_idx < end_x
+                            } else if buf_y == start_y {
+                                char_idx >= start_x
+                            } else if buf_y == end_y {
+                                char_idx < end_x
+                            } else {
+                                true
+                            }
+                        } else {
+                            false
+                        }
+                    } else {
+                        false
+                    }
+                };
+
+                let dimmed = focus.is_some_and(|(start, end)| buf_y < start || buf_y > end);
+                if dimmed {
+                    // Out of focus: plain grey, keeping only the selection.
+                    execute!(out, SetForegroundColor(Color::DarkGrey))?;
+                    let mut current_idx = start_char_idx;
+                    while current_idx < end_char_idx {
+                        let is_selected = is_char_selected(current_idx);
+                        let mut segment_end = current_idx + 1;
+                        while segment_end < end_char_idx
+                            && is_char_selected(segment_end) == is_selected
+                        {
+                            segment_end += 1;
+                        }
+                        if is_selected {
+                            execute!(out, SetAttribute(Attribute::Reverse))?;
+                        }
+                        write_chars(out, &line[current_idx..segment_end]);
+                        if is_selected {
+                            execute!(out, SetAttribute(Attribute::Reset))?;
+                            execute!(out, SetForegroundColor(Color::DarkGrey))?;
+                        }
+                        current_idx = segment_end;
+                    }
+                    execute!(out, SetForegroundColor(Color::White))?;
+                    write!(
+                        out,
+                        "{:width$}",
+                        "",
+                        width = available_width - (end_char_idx - start_char_idx)
+                    )?;
+                } else if is_search_mode || ed.language != Language::None {
+                    let mut char_idx = start_char_idx;
+                    let mut token_idx = 0;
+                    let mut output_len = 0;
+
+                    while char_idx < end_char_idx && output_len < available_width {
+                        while token_idx < tokens.len() && tokens[token_idx].1 <= char_idx {
+                            token_idx += 1;
+                        }
+
+                        let token = if token_idx < tokens.len() {
+                            tokens[token_idx]
+                        } else {
+                            (line_len, line_len, TokenType::Normal)
+                        };
+
+                        let search_match = if query.is_empty() {
+                            None
+                        } else {
+                            line[char_idx..]
+                                .windows(query.len())
+                                .position(|window| window == query)
+                                .map(|p| char_idx + p)
+                        };
+
+                        if let Some(match_start) = search_match {
+                            if match_start < end_char_idx {
+                                if match_start > char_idx {
+                                    let token_type = if token.0 <= char_idx && token.1 > char_idx {
+                                        token.2
+                                    } else {
+                                        TokenType::Normal
+                                    };
+
+                                    let mut current_idx = char_idx;
+                                    while current_idx < match_start && output_len < available_width
+                                    {
+                                        let is_selected = is_char_selected(current_idx);
+                                        let segment_end = {
+                                            let mut end = current_idx + 1;
+                                            while end < match_start
+                                                && is_char_selected(end) == is_selected
+                                            {
+                                                end += 1;
+                                            }
+                                            end
+                                        };
+
+                                        execute!(
+                                            out,
+                                            SetForegroundColor(get_token_color(token_type))
+                                        )?;
+                                        if is_selected {
+                                            execute!(out, SetAttribute(Attribute::Reverse))?;
+                                        }
+
+                                        write_chars(out, &line[current_idx..segment_end]);
+                                        output_len += segment_end - current_idx;
+
+                                        if is_selected {
+                                            execute!(out, SetAttribute(Attribute::Reset))?;
+                                        }
+                                        execute!(out, SetForegroundColor(Color::White))?;
+
+                                        current_idx = segment_end;
+                                    }
+                                }
+
+                                let match_end = (match_start + query.len()).min(end_char_idx);
+                                let current_result = ed.search_results[ed.current_search_index];
+
+                                let mut current_idx = match_start;
+                                while current_idx < match_end && output_len < available_width {
+                                    let is_selected = is_char_selected(current_idx);
+                                    let segment_end = {
+                                        let mut end = current_idx + 1;
+                                        while end < match_end
+                                            && is_char_selected(end) == is_selected
+                                        {
+                                            end += 1;
+                                        }
+                                        end
+                                    };
+
+                                    if current_result == (buf_y, match_start) {
+                                        execute!(out, SetAttribute(Attribute::Reverse))?;
+                                    } else {
+                                        execute!(out, SetAttribute(Attribute::Bold))?;
+                                    }
+                                    execute!(out, SetForegroundColor(Color::White))?;
+
+                                    if is_selected && current_result != (buf_y, match_start) {
+                                        execute!(out, SetAttribute(Attribute::Reverse))?;
+                                    }
+
+                                    write_chars(out, &line[current_idx..segment_end]);
+                                    output_len += segment_end - current_idx;
+
+                                    execute!(out, SetAttribute(Attribute::Reset))?;
+                                    current_idx = segment_end;
+                                }
+                                char_idx = match_end;
+                            } else {
+                                break;
+                            }
+                        } else {
+                            let token_end = token.1.min(end_char_idx);
+                            if token_end > char_idx {
+                                let is_matched_bracket = if let Some((match_y, match_x)) =
+                                    ed.matched_bracket
+                                {
+                                    (buf_y == match_y && char_idx <= match_x && match_x < token_end)
+                                        || (buf_y == ed.cursor_y
+                                            && char_idx <= ed.cursor_x
+                                            && ed.cursor_x < token_end)
+                                } else {
+                                    false
+                                };
+
+                                let mut current_idx = char_idx;
+                                while current_idx < token_end && output_len < available_width {
+                                    let is_selected = is_char_selected(current_idx);
+                                    let segment_end = {
+                                        let mut end = current_idx + 1;
+                                        while end < token_end
+                                            && is_char_selected(end) == is_selected
+                                        {
+                                            end += 1;
+                                        }
+                                        end
+                                    };
+
+                                    let bracket_in_segment = is_matched_bracket
+                                        && ((buf_y == ed.cursor_y
+                                            && current_idx <= ed.cursor_x
+                                            && ed.cursor_x < segment_end)
+                                            || (if let Some((match_y, match_x)) = ed.matched_bracket
+                                            {
+                                                buf_y == match_y
+                                                    && current_idx <= match_x
+                                                    && match_x < segment_end
+                                            } else {
+                                                false
+                                            }));
+
+                                    if bracket_in_segment {
+                                        execute!(out, SetForegroundColor(Color::Yellow))?;
+                                        execute!(out, SetAttribute(Attribute::Bold))?;
+                                    } else {
+                                        execute!(
+                                            out,
+                                            SetForegroundColor(get_token_color(token.2))
+                                        )?;
+                                        if let Some(attribute) = get_token_attribute(token.2) {
+                                            execute!(out, SetAttribute(attribute))?;
+                                        }
+                                    }
+
+                                    if is_selected {
+                                        execute!(out, SetAttribute(Attribute::Reverse))?;
+                                    }
+
+                                    write_chars(out, &line[current_idx..segment_end]);
+                                    output_len += segment_end - current_idx;
+
+                                    if is_selected {
+                                        execute!(out, SetAttribute(Attribute::Reset))?;
+                                    }
+                                    if bracket_in_segment || get_token_attribute(token.2).is_some()
+                                    {
+                                        execute!(out, SetAttribute(Attribute::Reset))?;
+                                    }
+                                    execute!(out, SetForegroundColor(Color::White))?;
+
+                                    current_idx = segment_end;
+                                }
+                                char_idx = token_end;
+                                token_idx += 1;
+                            } else {
+                                break;
+                            }
+                        }
+                    }
+
+                    let remaining_width = available_width.saturating_sub(output_len);
+                    if remaining_width > 0 {
+                        write!(out, "{:width$}", "", width = remaining_width)?;
+                    }
+                } else {
+                    if let (Some((sel_start_y, sel_start_x)), Some((sel_end_y, sel_end_x))) =
+                        (ed.selection_start, ed.selection_end)
+                    {
+                        let (actual_start_y, actual_start_x, actual_end_y, actual_end_x) =
+                            if (sel_start_y, sel_start_x) < (sel_end_y, sel_end_x) {
+                                (sel_start_y, sel_start_x, sel_end_y, sel_end_x)
+                            } else {
+                                (sel_end_y, sel_end_x, sel_start_y, sel_start_x)
+                            };
+
+                        if buf_y >= actual_start_y && buf_y <= actual_end_y {
+                            let mut char_idx = start_char_idx;
+                            let mut output_len = 0;
+
+                            while char_idx < end_char_idx && output_len < available_width {
+                                let is_selected =
+                                    if buf_y == actual_start_y && buf_y == actual_end_y {
+                                        char_idx >= actual_start_x && char_idx < actual_end_x
+                                    } else if buf_y == actual_start_y {
+                                        char_idx >= actual_start_x
+                                    } else if buf_y == actual_end_y {
+                                        char_idx < actual_end_x
+                                    } else {
+                                        true
+                                    };
+
+                                let next_pos = if is_selected {
+                                    if buf_y == actual_start_y && buf_y == actual_end_y {
+                                        actual_end_x.min(end_char_idx)
+                                    } else if buf_y == actual_start_y {
+                                        end_char_idx
+                                    } else if buf_y == actual_end_y {
+                                        actual_end_x.min(end_char_idx)
+                                    } else {
+                                        end_char_idx
+                                    }
+                                } else {
+                                    if buf_y == actual_start_y {
+                                        actual_start_x.min(end_char_idx)
+                                    } else if buf_y == actual_end_y {
+                                        actual_end_x.min(end_char_idx)
+                                    } else {
+                                        end_char_idx
+                                    }
+                                };
+
+                                if next_pos > char_idx {
+                                    let is_matched_bracket =
+                                        if let Some((match_y, match_x)) = ed.matched_bracket {
+                                            (buf_y == match_y
+                                                && char_idx <= match_x
+                                                && match_x < next_pos)
+                                                || (buf_y == ed.cursor_y
+                                                    && char_idx <= ed.cursor_x
+                                                    && ed.cursor_x < next_pos)
+                                        } else {
+                                            false
+                                        };
+
+                                    if is_selected {
+                                        execute!(out, SetAttribute(Attribute::Reverse))?;
+                                    }
+
+                                    if is_matched_bracket {
+                                        execute!(out, SetForegroundColor(Color::Yellow))?;
+                                        execute!(out, SetAttribute(Attribute::Bold))?;
+                                    }
+
+                                    write_chars(out, &line[char_idx..next_pos]);
+
+                                    if is_matched_bracket {
+                                        execute!(out, SetAttribute(Attribute::Reset))?;
+                                        execute!(out, SetForegroundColor(Color::White))?;
+                                    }
+
+                                    if is_selected {
+                                        execute!(out, SetAttribute(Attribute::Reset))?;
+                                    }
+                                    output_len += next_pos - char_idx;
+                                    char_idx = next_pos;
+                                } else {
+                                    break;
+                                }
+                            }
+
+                            let remaining_width = available_width.saturating_sub(output_len);
+                            if remaining_width > 0 {
+                                write!(out, "{:width$}", "", width = remaining_width)?;
+                            }
+                        } else {
+                            write_padded(out, visible_part, available_width);
+                        }
+                    } else {
+                        write_padded(out, visible_part, available_width);
+                    }
+                }
+            }
+        }
+    }
+
+    // Continuation markers on lines cut off by horizontal scrolling.
+    execute!(out, SetForegroundColor(Color::DarkGrey))?;
+    for screen_y in 0..unwrapped_rows {
+        let Some(line) = ed.buffer.get(ed.scroll_y + screen_y as usize) else {
+            break;
+        };
+        if ed.scroll_x > 0 && !line.is_empty() {
+            execute!(out, cursor::MoveTo(text_offset, screen_y))?;
+            write!(out, "<")?;
+        }
+        if available_width > 1 && line.len() > ed.scroll_x + available_width {
+            execute!(
+                out,
+                cursor::MoveTo(text_offset + available_width as u16 - 1, screen_y)
+            )?;
+            write!(out, ">")?;
+        }
+    }
+    execute!(out, SetForegroundColor(Color::Reset))?;
+
+    if preview_width > 0 {
+        draw_markdown_preview(ed, out, cols - preview_width, preview_width, max_lines)?;
+    }
+
+    if matches!(ed.mode, EditorMode::QuickJump) {
+        for target in &ed.jump_targets {
+            let Some(rest) = target.label.strip_prefix(ed.jump_input.as_str()) else {
+                continue;
+            };
+            let (x, y) = target.screen;
+            let room = available_width.saturating_sub(x as usize);
+            let rest: String = rest.chars().take(room).collect();
+            execute!(
+                out,
+                cursor::MoveTo(text_offset + x, y),
+                crossterm::style::SetBackgroundColor(Color::Yellow),
+                SetForegroundColor(Color::Black),
+                SetAttribute(Attribute::Bold)
+            )?;
+            write!(out, "{}", rest)?;
+            execute!(
+                out,
+                SetAttribute(Attribute::Reset),
+                crossterm::style::SetBackgroundColor(Color::Reset),
+                SetForegroundColor(Color::White)
+            )?;
+        }
+    }
+
+    if let Some((current, other)) = ed.matched_tag.filter(|_| wrap_width.is_none()) {
+        for span in [current, other] {
+            let Some(screen_y) = span.y.checked_sub(ed.scroll_y) else {
+                continue;
+            };
+            if screen_y >= max_lines as usize {
+                continue;
+            }
+            let start = span.name_start.max(ed.scroll_x);
+            let end = span.name_end.min(ed.scroll_x + available_width);
+            if start >= end {
+                continue;
+            }
+            let name: String = ed.buffer[span.y][start..end].iter().collect();
+            execute!(
+                out,
+                cursor::MoveTo(text_offset + (start - ed.scroll_x) as u16, screen_y as u16),
+                SetForegroundColor(Color::Yellow),
+                SetAttribute(Attribute::Underlined)
+            )?;
+            write!(out, "{}", name)?;
+            execute!(
+                out,
+                SetAttribute(Attribute::Reset),
+                SetForegroundColor(Color::White)
+            )?;
+        }
+    }
+
+    if matches!(
+        ed.mode,
+        EditorMode::Normal | EditorMode::Autocomplete | EditorMode::QuickFix
+    ) {
+        let (cursor_screen_x, cursor_screen_y) = ed.cursor_screen_pos(max_lines as usize);
+
+        if cursor_screen_y < max_lines as usize && cursor_screen_x < available_width {
+            execute!(
+                out,
+                cursor::MoveTo(text_offset + cursor_screen_x as u16, cursor_screen_y as u16),
+                SetAttribute(Attribute::Reverse)
+            )?;
+            if let Some(line) = ed.buffer.get(ed.cursor_y) {
+                if ed.cursor_x < line.len() {
+                    write!(out, "{}", line[ed.cursor_x])?;
+                } else {
+                    write!(out, " ")?;
+                }
+            } else {
+                write!(out, " ")?;
+            }
+            execute!(out, SetAttribute(Attribute::Reset))?;
+        }
+    }
+
+    if let Some(ghost) = ed
+        .visible_ghost_completion()
+        .filter(|_| matches!(ed.mode, EditorMode::Normal) && wrap_width.is_none())
+        .filter(|_| ed.cursor_x >= ed.scroll_x && ed.cursor_y >= ed.scroll_y)
+    {
+        let typed = ed.cursor_x - ghost.start.min(ed.cursor_x);
+        let room = available_width.saturating_sub(ed.cursor_x - ed.scroll_x);
+        let rest: String = ghost
+            .insert_text
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .skip(typed)
+            .take(room)
+            .collect();
+        execute!(
+            out,
+            cursor::MoveTo(
+                text_offset + (ed.cursor_x - ed.scroll_x) as u16,
+                (ed.cursor_y - ed.scroll_y) as u16
+            ),
+            SetForegroundColor(Color::DarkGrey),
+            SetAttribute(Attribute::Dim)
+        )?;
+        write!(out, "{}", rest)?;
+        execute!(
+            out,
+            SetAttribute(Attribute::Reset),
+            SetForegroundColor(Color::Reset)
+        )?;
+    }
+
+    if matches!(ed.mode, EditorMode::Autocomplete) && !ed.autocomplete_suggestions.is_empty() {
+        let (cursor_screen_x, cursor_screen_y) = ed.cursor_screen_pos(max_lines as usize);
+
+        let popup_x = text_offset + cursor_screen_x as u16;
+        let popup_y = cursor_screen_y as u16 + 1;
+
+        let max_suggestions = 8.min(ed.autocomplete_suggestions.len());
+        let cursor_line = &ed.buffer[ed.cursor_y];
+        let max_width = ed
+            .autocomplete_suggestions
+            .iter()
+            .take(max_suggestions)
+            .map(|s| s.label.chars().count())
+            .max()
+            .unwrap_or(10)
+            .max(10);
+
+        for (i, suggestion) in ed
+            .autocomplete_suggestions
+            .iter()
+            .take(max_suggestions)
+            .enumerate()
+        {
+            let y = popup_y + i as u16;
+            if y >= max_lines {
+                break;
+            }
+
+            execute!(out, cursor::MoveTo(popup_x, y))?;
+
+            let selected = i == ed.autocomplete_index;
+            let background = if selected {
+                Color::Blue
+            } else {
+                Color::DarkGrey
+            };
+            let typed: String = cursor_line[suggestion.start.min(ed.cursor_x)..ed.cursor_x]
+                .iter()
+                .collect();
+            let matched = fuzzy::match_positions(&typed, &suggestion.label);
+
+            execute!(out, crossterm::style::SetBackgroundColor(background))?;
+            if selected {
+                execute!(out, SetAttribute(Attribute::Bold))?;
+            }
+            write!(out, " ")?;
+            for (index, c) in suggestion.label.chars().enumerate() {
+                let color = if matched.contains(&index) {
+                    Color::Yellow
+                } else {
+                    Color::White
+                };
+                execute!(out, SetForegroundColor(color))?;
+                write!(out, "{}", c)?;
+            }
+            execute!(out, SetForegroundColor(Color::White))?;
+            write!(
+                out,
+                "{:width$} ",
+                "",
+                width = max_width - suggestion.label.chars().count()
+            )?;
+            execute!(out, SetAttribute(Attribute::Reset))?;
+            execute!(out, crossterm::style::SetBackgroundColor(Color::Reset))?;
+        }
+    }
+
+    if matches!(ed.mode, EditorMode::QuickFix) && !ed.quick_fixes.is_empty() {
+        let (cursor_screen_x, cursor_screen_y) = ed.cursor_screen_pos(max_lines as usize);
+
+        let max_fixes = 8.min(ed.quick_fixes.len());
+        let first = ed.quick_fix_index.saturating_sub(max_fixes - 1);
+        let max_width = ed
+            .quick_fixes
+            .iter()
+            .map(|fix| fix.title.chars().count())
+            .max()
+            .unwrap_or(10)
+            .clamp(10, (cols - text_offset).saturating_sub(2) as usize);
+
+        let popup_x = (text_offset + cursor_screen_x as u16)
+            .min(cols.saturating_sub(max_width as u16 + 2))
+            .max(text_offset);
+        let popup_y = cursor_screen_y as u16 + 1;
+
+        for (i, fix) in ed
+            .quick_fixes
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(max_fixes)
+        {
+            let y = popup_y + (i - first) as u16;
+            if y >= max_lines {
+                break;
+            }
+
+            execute!(out, cursor::MoveTo(popup_x, y))?;
+            let title: String = fix.title.chars().take(max_width).collect();
+
+            if i == ed.quick_fix_index {
+                execute!(out, crossterm::style::SetBackgroundColor(Color::Blue))?;
+                execute!(out, SetForegroundColor(Color::White))?;
+                execute!(out, SetAttribute(Attribute::Bold))?;
+                write!(out, " {:<width$} ", title, width = max_width)?;
+                execute!(out, SetAttribute(Attribute::Reset))?;
+                execute!(out, crossterm::style::SetBackgroundColor(Color::Reset))?;
+            } else {
+                execute!(out, crossterm::style::SetBackgroundColor(Color::DarkGrey))?;
+                execute!(out, SetForegroundColor(Color::White))?;
+                write!(out, " {:<width$} ", title, width = max_width)?;
+                execute!(out, crossterm::style::SetBackgroundColor(Color::Reset))?;
+            }
+        }
+    }
+
+    if let (true, Some(panel)) = (ed.show_output_panel, ed.output_panel.as_ref()) {
+        let panel_top = max_lines;
+        let panel_rows = rows.saturating_sub(STATUS_HEIGHT).saturating_sub(panel_top);
+        if panel_rows > 0 {
+            let (state, color) = match panel.status {
+                Some(TestStatus::Running) => ("running", Color::Yellow),
+                Some(TestStatus::Passed) => ("passed", Color::Green),
+                Some(TestStatus::Failed) => ("failed", Color::Red),
+                None => ("", Color::White),
+            };
+            execute!(
+                out,
+                cursor::MoveTo(0, panel_top),
+                crossterm::style::SetBackgroundColor(Color::DarkGrey),
+                SetForegroundColor(Color::White),
+                SetAttribute(Attribute::Bold)
+            )?;
+            let header = format!(" {} ", panel.title);
+            write!(out, "{}", header)?;
+            execute!(out, SetForegroundColor(color))?;
+            write!(out, "{}", state)?;
+            execute!(out, SetForegroundColor(Color::White))?;
+            let hint = "  F6 hide | wheel scroll";
+            let used = header.chars().count() + state.chars().count();
+            let remaining = (cols as usize).saturating_sub(used);
+            let hint: String = hint.chars().take(remaining).collect();
+            write!(out, "{:<width$}", hint, width = remaining)?;
+            execute!(
+                out,
+                SetAttribute(Attribute::Reset),
+                crossterm::style::SetBackgroundColor(Color::Reset),
+                SetForegroundColor(Color::Reset)
+            )?;
+
+            let body_rows = (panel_rows - 1) as usize;
+            let end = panel.lines.len().saturating_sub(panel.scroll);
+            let start = end.saturating_sub(body_rows);
+            for i in 0..body_rows {
+                execute!(out, cursor::MoveTo(0, panel_top + 1 + i as u16))?;
+                let text: String = panel
+                    .lines
+                    .get(start + i)
+                    .filter(|_| start + i < end)
+                    .map(|line| line.chars().take(cols as usize).collect())
+                    .unwrap_or_default();
+                write!(out, "{:<width$}", text, width = cols as usize)?;
+            }
+        }
+    }
+
+    if matches!(ed.mode, EditorMode::Timeline) {
+        // The snapshot list sits on top and the selected snapshot shows
+        // below it.
+        let shown = ed.timeline_entries.len().clamp(1, PALETTE_VISIBLE_ITEMS) as u16;
+        let preview_y = 2 + shown + 1;
+        let width = cols.saturating_sub(text_offset) as usize;
+        for y in 0..max_lines {
+            execute!(
+                out,
+                cursor::MoveTo(text_offset, y),
+                crossterm::style::SetBackgroundColor(Color::Reset),
+                SetForegroundColor(Color::Grey)
+            )?;
+            let preview: String = y
+                .checked_sub(preview_y)
+                .and_then(|i| ed.timeline_preview.get(i as usize))
+                .map(|line| line.chars().take(width).collect())
+                .unwrap_or_default();
+            write!(out, "{:<width$}", preview, width = width)?;
+        }
+        execute!(out, SetForegroundColor(Color::Reset))?;
+        let items: Vec<(String, String)> = ed
+            .timeline_entries
+            .iter()
+            .map(|snapshot| {
+                (
+                    local_history::format_timestamp(snapshot.timestamp_ms),
+                    local_history::format_age(snapshot.timestamp_ms),
+                )
+            })
+            .collect();
+        draw_picker(
+            out,
+            cols,
+            max_lines,
+            "Local history",
+            &items,
+            ed.timeline_index,
+            "No snapshots",
+        )?;
+    }
+
+    if let (EditorMode::Diff, Some(view)) = (&ed.mode, ed.diff_view.as_ref()) {
+        let width = cols.saturating_sub(tree_offset) as usize;
+        execute!(
+            out,
+            cursor::MoveTo(tree_offset, 0),
+            crossterm::style::SetBackgroundColor(Color::DarkGrey),
+            SetForegroundColor(Color::White),
+            SetAttribute(Attribute::Bold)
+        )?;
+        let title: String = ed
+            .glyphs
+            .text(format!(" {}", view.title))
+            .chars()
+            .take(width)
+            .collect();
+        write!(out, "{:<width$}", title, width = width)?;
+        execute!(
+            out,
+            SetAttribute(Attribute::Reset),
+            crossterm::style::SetBackgroundColor(Color::Reset)
+        )?;
+
+        let text_width = width.saturating_sub(13);
+        for y in 1..max_lines {
+            execute!(out, cursor::MoveTo(tree_offset, y))?;
+            let row = view.rows.get(view.scroll + y as usize - 1);
+            match row {
+                Some(DiffRow::Header(hunk)) => {
+                    let selected = *hunk == view.selected;
+                    if selected {
+                        execute!(
+                            out,
+                            crossterm::style::SetBackgroundColor(Color::DarkBlue),
+                            SetForegroundColor(Color::White)
+                        )?;
+                    } else {
+                        execute!(out, SetForegroundColor(Color::Cyan))?;
+                    }
+                    let marker = if selected { ed.glyphs.pointer } else { ' ' };
+                    let header: String = format!("{}{}", marker, view.hunks[*hunk].header())
+                        .chars()
+                        .take(width)
+                        .collect();
+                    write!(out, "{:<width$}", header, width = width)?;
+                    execute!(out, crossterm::style::SetBackgroundColor(Color::Reset))?;
+                }
+                Some(DiffRow::Line(hunk, line)) => {
+                    let line = &view.hunks[*hunk].lines[*line];
+                    let number = |n: Option<usize>| {
+                        n.map(|n| format!("{:>5}", n + 1))
+                            .unwrap_or_else(|| "     ".into())
+                    };
+                    execute!(out, SetForegroundColor(Color::DarkGrey))?;
+                    write!(out, "{} {} ", number(line.old_line), number(line.new_line))?;
+                    let (sign, color) = match line.kind {
+                        diff::DiffKind::Equal => (' ', Color::White),
+                        diff::DiffKind::Delete => ('-', Color::Red),
+                        diff::DiffKind::Insert => ('+', Color::Green),
+                    };
+                    execute!(out, SetForegroundColor(color))?;
+                    let text: String = line.text.chars().take(text_width).collect();
+                    write!(out, "{}{:<width$}", sign, text, width = text_width)?;
+                }
+                None if view.rows.is_empty() && y == 1 => {
+                    execute!(out, SetForegroundColor(Color::DarkGrey))?;
+                    write!(out, "{:<width$}", " No differences", width = width)?;
+                }
+                None => write!(out, "{:width$}", "", width = width)?,
+            }
+        }
+        execute!(out, SetForegroundColor(Color::Reset))?;
+    }
+
+    if matches!(ed.mode, EditorMode::NotificationLog) {
+        let width = cols.saturating_sub(tree_offset) as usize;
+        execute!(
+            out,
+            cursor::MoveTo(tree_offset, 0),
+            crossterm::style::SetBackgroundColor(Color::DarkGrey),
+            SetForegroundColor(Color::White),
+            SetAttribute(Attribute::Bold)
+        )?;
+        let title = format!(" Notifications ({})", ed.notifications.len());
+        write!(out, "{:<width$}", title, width = width)?;
+        execute!(
+            out,
+            SetAttribute(Attribute::Reset),
+            crossterm::style::SetBackgroundColor(Color::Reset)
+        )?;
+
+        let visible = max_lines.saturating_sub(1) as usize;
+        let start = ed
+            .notification_scroll
+            .min(ed.notifications.len().saturating_sub(visible));
+        let text_width = width.saturating_sub(10);
+        for y in 1..max_lines {
+            execute!(out, cursor::MoveTo(tree_offset, y))?;
+            match ed.notifications.get(start + y as usize - 1) {
+                Some(notification) => {
+                    let time = local_history::format_timestamp(notification.timestamp_ms);
+                    execute!(out, SetForegroundColor(Color::DarkGrey))?;
+                    write!(out, " {} ", &time[11..19])?;
+                    execute!(out, SetForegroundColor(Color::Grey))?;
+                    let message: String = notification.message.chars().take(text_width).collect();
+                    write!(out, "{:<width$}", message, width = text_width)?;
+                }
+                None => write!(out, "{:width$}", "", width = width)?,
+            }
+        }
+        execute!(out, SetForegroundColor(Color::Reset))?;
+    }
+
+    if matches!(ed.mode, EditorMode::GitLog) {
+        let width = cols.saturating_sub(tree_offset) as usize;
+        execute!(
+            out,
+            cursor::MoveTo(tree_offset, 0),
+            crossterm::style::SetBackgroundColor(Color::DarkGrey),
+            SetForegroundColor(Color::White),
+            SetAttribute(Attribute::Bold)
+        )?;
+        let title = format!(" File history ({} commits)", ed.git_log_entries.len());
+        write!(out, "{:<width$}", title, width = width)?;
+        execute!(
+            out,
+            SetAttribute(Attribute::Reset),
+            crossterm::style::SetBackgroundColor(Color::Reset)
+        )?;
+
+        for y in 1..max_lines {
+            execute!(out, cursor::MoveTo(tree_offset, y))?;
+            let index = ed.git_log_scroll + y as usize - 1;
+            let Some(commit) = ed.git_log_entries.get(index) else {
+                write!(out, "{:width$}", "", width = width)?;
+                continue;
+            };
+            if index == ed.git_log_index {
+                execute!(
+                    out,
+                    crossterm::style::SetBackgroundColor(Color::Blue),
+                    SetAttribute(Attribute::Bold)
+                )?;
+            }
+            let author: String = commit.author.chars().take(16).collect();
+            let hash = format!(" {} ", commit.short_hash());
+            let rest = format!(" {}  {:<16}  {}", commit.date, author, commit.subject);
+            let rest_width = width.saturating_sub(hash.chars().count());
+            let rest: String = rest.chars().take(rest_width).collect();
+            execute!(out, SetForegroundColor(Color::Yellow))?;
+            write!(out, "{}", hash)?;
+            execute!(out, SetForegroundColor(Color::White))?;
+            write!(out, "{:<width$}", rest, width = rest_width)?;
+            execute!(
+                out,
+                SetAttribute(Attribute::Reset),
+                crossterm::style::SetBackgroundColor(Color::Reset)
+            )?;
+        }
+        execute!(out, SetForegroundColor(Color::Reset))?;
+    }
+
+    if let (EditorMode::Blame, Some(view)) = (&ed.mode, ed.blame_view.as_ref()) {
+        let width = cols.saturating_sub(tree_offset) as usize;
+        execute!(
+            out,
+            cursor::MoveTo(tree_offset, 0),
+            crossterm::style::SetBackgroundColor(Color::DarkGrey),
+            SetForegroundColor(Color::White),
+            SetAttribute(Attribute::Bold)
+        )?;
+        let title: String = match view.lines.get(view.cursor) {
+            Some(line) if !line.is_uncommitted() => {
+                format!(
+                    " Blame {} - {} {}",
+                    view.title,
+                    &line.hash[..8],
+                    line.summary
+                )
+            }
+            _ => format!(" Blame {}", view.title),
+        };
+        let title: String = title.chars().take(width).collect();
+        write!(out, "{:<width$}", title, width = width)?;
+        execute!(
+            out,
+            SetAttribute(Attribute::Reset),
+            crossterm::style::SetBackgroundColor(Color::Reset)
+        )?;
+
+        const ANNOTATION_WIDTH: usize = 38;
+        let text_width = width.saturating_sub(ANNOTATION_WIDTH + 6);
+        for y in 1..max_lines {
+            execute!(out, cursor::MoveTo(tree_offset, y))?;
+            let index = view.scroll + y as usize - 1;
+            let Some(line) = view.lines.get(index) else {
+                write!(out, "{:width$}", "", width = width)?;
+                continue;
+            };
+
+            let annotation = if line.is_uncommitted() {
+                " Not committed yet".to_string()
+            } else {
+                let date = local_history::format_timestamp(line.time * 1000);
+                let author: String = line.author.chars().take(16).collect();
+                format!(" {} {:<16} {}", &line.hash[..8], author, &date[..10])
+            };
+            if index == view.cursor {
+                execute!(
+                    out,
+                    crossterm::style::SetBackgroundColor(Color::Blue),
+                    SetForegroundColor(Color::White)
+                )?;
+            } else {
+                execute!(out, SetForegroundColor(Color::Yellow))?;
+            }
+            let annotation: String = annotation.chars().take(ANNOTATION_WIDTH).collect();
+            write!(out, "{:<width$}", annotation, width = ANNOTATION_WIDTH)?;
+            execute!(out, crossterm::style::SetBackgroundColor(Color::Reset))?;
+            execute!(out, SetForegroundColor(Color::DarkGrey))?;
+            write!(out, "{:>5} ", index + 1)?;
+            execute!(out, SetForegroundColor(Color::Grey))?;
+            let text: String = line.text.chars().take(text_width).collect();
+            write!(out, "{:<width$}", text, width = text_width)?;
+        }
+        execute!(out, SetForegroundColor(Color::Reset))?;
+    }
+
+    if let (EditorMode::RevisionView, Some(view)) = (&ed.mode, ed.revision_view.as_ref()) {
+        let width = cols.saturating_sub(tree_offset) as usize;
+        execute!(
+            out,
+            cursor::MoveTo(tree_offset, 0),
+            crossterm::style::SetBackgroundColor(Color::DarkGrey),
+            SetForegroundColor(Color::White),
+            SetAttribute(Attribute::Bold)
+        )?;
+        let title: String = ed
+            .glyphs
+            .text(format!(" {}", view.title))
+            .chars()
+            .take(width)
+            .collect();
+        write!(out, "{:<width$}", title, width = width)?;
+        execute!(
+            out,
+            SetAttribute(Attribute::Reset),
+            crossterm::style::SetBackgroundColor(Color::Reset)
+        )?;
+
+        let text_width = width.saturating_sub(6);
+        for y in 1..max_lines {
+            execute!(out, cursor::MoveTo(tree_offset, y))?;
+            let index = view.scroll + y as usize - 1;
+            match view.lines.get(index) {
+                Some(line) => {
+                    execute!(out, SetForegroundColor(Color::DarkGrey))?;
+                    write!(out, "{:>5} ", index + 1)?;
+                    execute!(out, SetForegroundColor(Color::Grey))?;
+                    let text: String = line.chars().take(text_width).collect();
+                    write!(out, "{:<width$}", text, width = text_width)?;
+                }
+                None => write!(out, "{:width$}", "", width = width)?,
+            }
+        }
+        execute!(out, SetForegroundColor(Color::Reset))?;
+    }
+
+    if matches!(ed.mode, EditorMode::DiffRevision) {
+        let dialog_y = rows / 2;
+        let dialog_x = (cols / 2).saturating_sub(20);
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y))?;
+        execute!(out, SetForegroundColor(Color::Magenta))?;
+        execute!(out, SetAttribute(Attribute::Bold))?;
+        write!(out, "{}", ed.glyphs.rule.to_string().repeat(27))?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+        execute!(out, SetForegroundColor(Color::White))?;
+
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 1))?;
+        write!(out, " Compare with revision:")?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 2))?;
+        let revision: String = ed.diff_revision_input.iter().collect();
+        write!(out, "  {}", revision)?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 3))?;
+        write!(out, " Enter - Compare  |  Esc - Cancel")?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 4))?;
+        execute!(out, SetForegroundColor(Color::Magenta))?;
+        write!(out, "{}", ed.glyphs.rule.to_string().repeat(27))?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+        execute!(out, SetForegroundColor(Color::White))?;
+    }
+
+    if matches!(ed.mode, EditorMode::Palette) {
+        let query: String = ed.palette_query.iter().collect();
+        let items: Vec<(String, String)> = ed
+            .palette_matches()
+            .iter()
+            .map(|&command| {
+                (
+                    command.title().to_string(),
+                    ed.command_keys(command).to_string(),
+                )
+            })
+            .collect();
+        draw_picker(
+            out,
+            cols,
+            max_lines,
+            &format!("> {}", query),
+            &items,
+            ed.palette_index,
+            "No matching commands",
+        )?;
+    }
+
+    if matches!(ed.mode, EditorMode::ScaffoldPicker) {
+        let items: Vec<(String, String)> = ed
+            .config
+            .scaffolders
+            .iter()
+            .map(|scaffolder| (scaffolder.name.clone(), scaffolder.program.clone()))
+            .collect();
+        draw_picker(
+            out,
+            cols,
+            max_lines,
+            "New project",
+            &items,
+            ed.scaffold_index,
+            "No scaffolders configured",
+        )?;
+    }
+
+    if matches!(ed.mode, EditorMode::ScaffoldDirectory) {
+        let input: String = ed.scaffold_dir_input.iter().collect();
+        draw_picker(
+            out,
+            cols,
+            max_lines,
+            &format!("Directory > {}", input),
+            &[],
+            0,
+            "Enter to create the project here",
+        )?;
+    }
+
+    if matches!(ed.mode, EditorMode::TemplatePicker) {
+        let mut items = vec![("Empty file".to_string(), String::new())];
+        items.extend(
+            ed.template_choices
+                .iter()
+                .map(|template| (template.label.to_string(), "template".to_string())),
+        );
+        draw_picker(
+            out,
+            cols,
+            max_lines,
+            &format!("New {} from", ed.file_name.as_deref().unwrap_or("file")),
+            &items,
+            ed.template_index,
+            "",
+        )?;
+    }
+
+    if matches!(ed.mode, EditorMode::Health) {
+        let items: Vec<(String, String)> = ed
+            .health_rows()
+            .into_iter()
+            .map(|row| (row.label, row.hint))
+            .collect();
+        draw_picker(
+            out,
+            cols,
+            max_lines,
+            &format!(
+                "Health of {}",
+                ed.file_name.as_deref().unwrap_or("this buffer")
+            ),
+            &items,
+            ed.health_index,
+            "",
+        )?;
+    }
+
+    if matches!(ed.mode, EditorMode::KeymapPicker) {
+        let items: Vec<(String, String)> = keymap::Preset::ALL
+            .iter()
+            .map(|preset| (preset.title().to_string(), preset.summary().to_string()))
+            .collect();
+        draw_picker(
+            out,
+            cols,
+            max_lines,
+            "Keybinding preset",
+            &items,
+            ed.keymap_index,
+            "",
+        )?;
+    }
+
+    if matches!(ed.mode, EditorMode::LocalOptions) {
+        draw_picker(
+            out,
+            cols,
+            max_lines,
+            &format!(
+                "Options for {}",
+                ed.file_name.as_deref().unwrap_or("this buffer")
+            ),
+            &ed.local_option_rows(),
+            ed.local_options_index,
+            "",
+        )?;
+    }
+
+    if matches!(ed.mode, EditorMode::SymbolPicker) {
+        let query: String = ed.symbol_query.iter().collect();
+        let root = fs::canonicalize(ed.project_root()).unwrap_or_default();
+        let items: Vec<(String, String)> = ed
+            .symbol_matches()
+            .into_iter()
+            .map(|i| {
+                let (path, symbol) = &ed.project_symbols[i];
+                let path = path.strip_prefix(&root).unwrap_or(path);
+                (
+                    symbol.name.clone(),
+                    format!("{}:{}", path.to_string_lossy(), symbol.y + 1),
+                )
+            })
+            .collect();
+        draw_picker(
+            out,
+            cols,
+            max_lines,
+            &format!("Symbol > {}", query),
+            &items,
+            ed.symbol_index,
+            "No matching symbols",
+        )?;
+    }
+
+    if matches!(ed.mode, EditorMode::Outline) {
+        let query: String = ed.outline_query.iter().collect();
+        let items: Vec<(String, String)> = ed
+            .outline_matches()
+            .into_iter()
+            .map(|i| {
+                let heading = &ed.outline[i];
+                (
+                    format!("{}{}", "  ".repeat(heading.level - 1), heading.text),
+                    format!("{}", heading.y + 1),
+                )
+            })
+            .collect();
+        draw_picker(
+            out,
+            cols,
+            max_lines,
+            &format!("Outline > {}", query),
+            &items,
+            ed.outline_index,
+            "No matching symbols",
+        )?;
+    }
+
+    if matches!(ed.mode, EditorMode::BranchPicker) {
+        let query: String = ed.branch_query.iter().collect();
+        let current = ed.git_branch.as_deref();
+        let items: Vec<(String, String)> = ed
+            .branch_matches()
+            .into_iter()
+            .map(|branch| {
+                let marker = if Some(branch.as_str()) == current {
+                    "current"
+                } else {
+                    ""
+                };
+                (branch, marker.to_string())
+            })
+            .collect();
+        draw_picker(
+            out,
+            cols,
+            max_lines,
+            &format!("Branch > {}", query),
+            &items,
+            ed.branch_index,
+            "No matching branches",
+        )?;
+    }
+
+    if let Some((path, pid)) = &ed.lock_warning {
+        let dialog_y = rows / 2;
+        let dialog_x = (cols / 2).saturating_sub(27);
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let holder = match pid {
+            Some(pid) => format!("another termi (pid {})", pid),
+            None => "another termi".to_string(),
+        };
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y))?;
+        execute!(out, SetForegroundColor(Color::Yellow))?;
+        execute!(out, SetAttribute(Attribute::Bold))?;
+        write!(out, "{}", ed.glyphs.rule.to_string().repeat(54))?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+        execute!(out, SetForegroundColor(Color::White))?;
+
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 1))?;
+        write!(out, " '{}' is being edited in {}.", name, holder)?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 2))?;
+        write!(out, " Opened read-only so neither overwrites the other.")?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 3))?;
+        write!(out, " E - Edit anyway  |  Any other key - Keep read-only")?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 4))?;
+        execute!(out, SetForegroundColor(Color::Yellow))?;
+        write!(out, "{}", ed.glyphs.rule.to_string().repeat(54))?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+        execute!(out, SetForegroundColor(Color::White))?;
+    }
+
+    if let Some((path, size)) = &ed.large_file_warning {
+        let dialog_y = rows / 2;
+        let dialog_x = (cols / 2).saturating_sub(27);
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y))?;
+        execute!(out, SetForegroundColor(Color::Yellow))?;
+        execute!(out, SetAttribute(Attribute::Bold))?;
+        write!(out, "{}", ed.glyphs.rule.to_string().repeat(54))?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+        execute!(out, SetForegroundColor(Color::White))?;
+
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 1))?;
+        write!(out, " '{}' is {} MB.", name, size / (1024 * 1024))?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 2))?;
+        write!(
+            out,
+            " Large-file mode: no highlighting, completion or undo."
+        )?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 3))?;
+        write!(
+            out,
+            " L - Large-file mode  |  O - Normal  |  Other - Cancel"
+        )?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 4))?;
+        execute!(out, SetForegroundColor(Color::Yellow))?;
+        write!(out, "{}", ed.glyphs.rule.to_string().repeat(54))?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+        execute!(out, SetForegroundColor(Color::White))?;
+    }
+
+    if let Some(length) = ed.minified_warning.filter(|_| ed.lock_warning.is_none()) {
+        let dialog_y = rows / 2;
+        let dialog_x = (cols / 2).saturating_sub(27);
+        let choices = if ed.file_path.as_deref().and_then(minified::kind).is_some() {
+            " P - Pretty-print  |  W - Soft wrap  |  Other - As is"
+        } else {
+            " W - Soft wrap  |  Any other key - Edit as is"
+        };
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y))?;
+        execute!(out, SetForegroundColor(Color::Yellow))?;
+        execute!(out, SetAttribute(Attribute::Bold))?;
+        write!(out, "{}", ed.glyphs.rule.to_string().repeat(54))?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+        execute!(out, SetForegroundColor(Color::White))?;
+
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 1))?;
+        write!(
+            out,
+            " This file looks minified ({} KB on one line).",
+            length / 1024
+        )?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 2))?;
+        write!(out, " Lines that long are shown without highlighting.")?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 3))?;
+        write!(out, "{}", choices)?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 4))?;
+        execute!(out, SetForegroundColor(Color::Yellow))?;
+        write!(out, "{}", ed.glyphs.rule.to_string().repeat(54))?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+        execute!(out, SetForegroundColor(Color::White))?;
+    }
+
+    if matches!(ed.mode, EditorMode::BranchCheckoutConfirm) {
+        let dialog_y = rows / 2;
+        let dialog_x = (cols / 2).saturating_sub(24);
+        let branch = ed.branch_pending.as_deref().unwrap_or_default();
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y))?;
+        execute!(out, SetForegroundColor(Color::Yellow))?;
+        execute!(out, SetAttribute(Attribute::Bold))?;
+        write!(out, "{}", ed.glyphs.rule.to_string().repeat(43))?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+        execute!(out, SetForegroundColor(Color::White))?;
+
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 1))?;
+        write!(out, " Uncommitted changes in the working tree.")?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 2))?;
+        write!(out, " Checkout '{}' anyway?", branch)?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 3))?;
+        write!(out, " Y - Checkout  |  N/Esc - Cancel")?;
+        execute!(out, cursor::MoveTo(dialog_x, dialog_y + 4))?;
+        execute!(out, SetForegroundColor(Color::Yellow))?;
+        write!(out, "{}", ed.glyphs.rule.to_string().repeat(43))?;
+        execute!(out, SetAttribute(Attribute::Reset))?;
+        execute!(out, SetForegroundColor(Color::White))?;
+    }
+
+    execute!(out, cursor::MoveTo(0, rows - 1))?;
+    let status_text = match ed.mode {
+        EditorMode::Dashboard => "Dashboard".to_string(),
+        EditorMode::Search => {
+            let query: String = ed.search_query.iter().collect();
+            format!(
+                "Search: {} | {} results found{}",
+                query,
+                ed.search_results.len(),
+                if !ed.search_results.is_empty() {
+                    format!(
+                        " ({}/{})",
+                        ed.current_search_index + 1,
+                        ed.search_results.len()
+                    )
+                } else {
+                    String::new()
+                }
+            )
+        }
+        EditorMode::CreateFile | EditorMode::CreateDir => {
+            let name: String = ed.create_name.iter().collect();
+            let prompt = if matches!(ed.mode, EditorMode::CreateFile) {
+                "New file name"
+            } else {
+                "New folder name"
+            };
+            format!("{}: {}", prompt, name)
+        }
+        EditorMode::DeleteConfirm => ed.status.clone(),
+        EditorMode::Rename => {
+            let name: String = ed.rename_name.iter().collect();
+            format!("Rename: {}", name)
+        }
+        EditorMode::GoToLine => {
+            let line_input: String = ed.goto_line_input.iter().collect();
+            format!("Go to line: {}", line_input)
+        }
+        EditorMode::Terminal => "Terminal mode".to_string(),
+        EditorMode::Autocomplete => {
+            format!(
+                "Autocomplete: ↑↓ select | Tab/Enter confirm | Esc cancel | {}/{}",
+                ed.autocomplete_index + 1,
+                ed.autocomplete_suggestions.len()
+            )
+        }
+        EditorMode::Palette => "Command palette: ↑↓ select | Enter run | Esc cancel".to_string(),
+        EditorMode::Diff => format!(
+            "Diff: ↑↓ PgUp/PgDn scroll | n/p next/prev hunk | r revert hunk | Esc close | {}",
+            ed.status
+        ),
+        EditorMode::DiffPickFile => {
+            "Compare with: pick a file in the tree and press Enter | Esc cancel".to_string()
+        }
+        EditorMode::DiffRevision => {
+            let revision: String = ed.diff_revision_input.iter().collect();
+            format!("Compare with revision: {}", revision)
+        }
+        EditorMode::GitLog => format!(
+            "File history: ↑↓ browse | Enter diff with working copy | o open read-only | Esc close | {}/{}",
+            ed.git_log_index + 1,
+            ed.git_log_entries.len()
+        ),
+        EditorMode::NotificationLog => {
+            "Notifications (UTC): ↑↓ PgUp/PgDn scroll | Esc close".to_string()
+        }
+        EditorMode::RevisionView => format!("Read-only: ↑↓ PgUp/PgDn scroll | Esc close | {}", ed.status),
+        EditorMode::Timeline => format!(
+            "Local history: ↑↓ browse | Enter restore into buffer | Esc close | {}/{}",
+            ed.timeline_index + 1,
+            ed.timeline_entries.len()
+        ),
+        EditorMode::QuickFix => {
+            format!(
+                "Quick fix: ↑↓ select | Enter apply | Esc cancel | {}/{}",
+                ed.quick_fix_index + 1,
+                ed.quick_fixes.len()
+            )
+        }
+        EditorMode::Blame => format!(
+            "Blame: ↑↓ PgUp/PgDn move | o open commit on remote | Esc close | {}",
+            ed.status
+        ),
+        EditorMode::ScaffoldPicker => {
+            "New project: ↑↓ choose a scaffolder | Enter next | Esc cancel".to_string()
+        }
+        EditorMode::ScaffoldDirectory => {
+            "New project: type the target directory | Enter create | Esc cancel".to_string()
+        }
+        EditorMode::TemplatePicker => {
+            "New file: ↑↓ choose a template | Enter apply | Esc keep empty".to_string()
+        }
+        EditorMode::QuickJump => match ed.jump_char {
+            None => "Quick jump: type the character to jump to | Esc cancel".to_string(),
+            Some(c) => format!(
+                "Quick jump to '{}': type a label | Esc cancel {}",
+                c, ed.jump_input
+            ),
+        },
+        EditorMode::Health => "Health check: ↑↓ select | Enter fix | Esc close".to_string(),
+        EditorMode::KeymapPicker => {
+            "Keybinding preset: ↑↓ select | Enter use | Esc keep current".to_string()
+        }
+        EditorMode::LocalOptions => {
+            "Buffer options: ↑↓ select | Enter change | Del use global | Esc close".to_string()
+        }
+        EditorMode::Outline => {
+            "Outline: type to filter | ↑↓ select | Enter jump | Esc cancel".to_string()
+        }
+        EditorMode::SymbolPicker => format!(
+            "Go to symbol: type to filter | ↑↓ select | Enter open | Esc cancel | {} symbols",
+            ed.project_symbols.len()
+        ),
+        EditorMode::BranchPicker => {
+            "Switch branch: type to filter | ↑↓ select | Enter checkout | Esc cancel".to_string()
+        }
+        EditorMode::BranchCheckoutConfirm => {
+            "Uncommitted changes - Y to checkout anyway, N to cancel".to_string()
+        }
+        EditorMode::Normal => {
+            format!(
+                "[{}]{}{}{}{}{}{}{}{} Line:{} Col:{}{}{} | {}",
+                ed.file_name.as_deref().unwrap_or("New"),
+                if ed.safe_mode { " [Safe]" } else { "" },
+                if ed.is_read_only() { " [RO]" } else { "" },
+                if ed.is_pinned() { " [Pinned]" } else { "" },
+                ed.tutor_mark(),
+                ed.local_option_marks(),
+                if ed.is_large_file() { " [Large]" } else { "" },
+                if ed.has_bom() { " UTF-8 BOM" } else { "" },
+                ed.git_branch
+                    .as_deref()
+                    .map(|branch| format!(" {} {}", ed.glyphs.branch, branch))
+                    .unwrap_or_default(),
+                ed.cursor_y + 1,
+                ed.cursor_x + 1,
+                ed.prose_stats(),
+                ed.function_context(),
+                ed.status
+            )
+        }
+    };
+
+    let status_text = ed.glyphs.text(status_text);
+    let status_text_truncated: String = status_text.chars().take(cols as usize).collect();
+    write!(
+        out,
+        "{:<width$}",
+        status_text_truncated,
+        width = cols as usize
+    )?;
+
+    // Prompt feedback goes after the input, in its own color.
+    let input_end = status_text_truncated.chars().count() as u16 + 2;
+    if let Some(check) = ed.prompt_check().filter(|_| input_end < cols) {
+        let message: String = ed
+            .glyphs
+            .text(format!("[{}]", check.message()))
+            .chars()
+            .take((cols - input_end) as usize)
+            .collect();
+        execute!(out, cursor::MoveTo(input_end, rows - 1))?;
+        execute!(out, SetForegroundColor(check.color()))?;
+        write!(out, "{}", message)?;
+        execute!(out, SetForegroundColor(Color::White))?;
+    }
+
+    out.flush()?;
+
+    ed.last_scroll_y = ed.scroll_y;
+    ed.last_scroll_x = ed.scroll_x;
+    ed.needs_full_redraw = false;
+
+    Ok(())
+}
+
+/// Background work and scrolling that happen once per loop iteration,
+/// before drawing.
+fn update(ed: &mut Editor) {
+    ed.drain_terminal_output();
+    ed.drain_lsp_events();
+    ed.drain_jobs();
+    ed.drain_import_scan();
+    ed.drain_instance_requests();
+    ed.check_tutor();
+
+    if !ed.cursor_locked {
+        let (cols, rows) = ed.screen_size;
+        ed.ensure_cursor_visible(rows, cols);
+    }
+    ed.step_scroll_animation();
+}
+
+/// How long the loop may sleep: animations need a frame every SCROLL_FRAME;
+/// otherwise until input or a background thread wakes it.
+fn wait_timeout(ed: &Editor) -> Option<Duration> {
+    if ed.scroll_target.is_some() {
+        Some(SCROLL_FRAME)
+    } else {
+        ed.config.idle_poll
+    }
+}
+
+fn handle_event(ed: &mut Editor, event: Event) {
+    match event {
+        Event::Resize(cols, rows) => {
+            ed.screen_size = (cols, rows);
+            ed.resize_terminal_session(rows, cols);
+            ed.needs_full_redraw = true;
+        }
+        Event::Mouse(MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers,
+            ..
+        }) => {
+            let (cols, rows) = ed.screen_size;
+            if matches!(ed.mode, EditorMode::Terminal) {
+                match kind {
+                    MouseEventKind::ScrollUp => {
+                        ed.scroll_terminal(TERMINAL_SCROLL_STEP as isize);
+                    }
+                    MouseEventKind::ScrollDown => {
+                        ed.scroll_terminal(-(TERMINAL_SCROLL_STEP as isize));
+                    }
+                    _ => {}
+                }
+            } else {
+                match kind {
+                    MouseEventKind::Down(MouseButton::Left) => {
+                        ed.handle_mouse_click(
+                            column,
+                            row,
+                            rows,
+                            cols,
+                            modifiers.contains(KeyModifiers::SHIFT),
+                        );
+                    }
+                    MouseEventKind::Drag(MouseButton::Left) => {
+                        ed.handle_mouse_drag(column, row, rows, cols);
+                    }
+                    MouseEventKind::Up(MouseButton::Left) => {
+                        ed.handle_mouse_release();
+                    }
+                    MouseEventKind::ScrollUp if row >= ed.text_rows(rows) => {
+                        ed.scroll_output_panel(true);
+                    }
+                    MouseEventKind::ScrollDown if row >= ed.text_rows(rows) => {
+                        ed.scroll_output_panel(false);
+                    }
+                    MouseEventKind::ScrollUp => {
+                        ed.handle_mouse_scroll(rows, true);
+                    }
+                    MouseEventKind::ScrollDown => {
+                        ed.handle_mouse_scroll(rows, false);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) => {
+            if ed.quit_confirm
+                && !matches!(
+                    (code, modifiers),
+                    (KeyCode::Char('q'), KeyModifiers::CONTROL)
+                )
+            {
+                ed.quit_confirm = false;
+                ed.needs_full_redraw = true;
+                ed.status = Editor::default_status();
+            }
+            // The lock warning takes the first key after opening.
+            if ed.lock_warning.take().is_some() {
+                if matches!(code, KeyCode::Char('e') | KeyCode::Char('E')) {
+                    ed.edit_anyway();
+                } else {
+                    ed.status = "Opened read-only".into();
+                }
+                ed.needs_full_redraw = true;
+                return;
+            }
+            if let Some((path, _)) = ed.large_file_warning.take() {
+                // Ctrl+O and friends must not pick an option by accident.
+                let plain = !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+                match code {
+                    KeyCode::Char('l') | KeyCode::Char('L') if plain => {
+                        ed.open_large_file(&path, true)
+                    }
+                    KeyCode::Char('o') | KeyCode::Char('O') if plain => {
+                        ed.open_large_file(&path, false)
+                    }
+                    _ => {
+                        ed.status = format!("Did not open {}", path.to_string_lossy());
+                        ed.needs_full_redraw = true;
+                    }
+                }
+                return;
+            }
+            if ed.minified_warning.take().is_some() {
+                match code {
+                    KeyCode::Char('p') | KeyCode::Char('P') => ed.pretty_print(),
+                    KeyCode::Char('w') | KeyCode::Char('W') if !ed.wraps() => {
+                        ed.toggle_soft_wrap();
+                    }
+                    _ => {}
+                }
+                ed.needs_full_redraw = true;
+                return;
+            }
+            match ed.mode {
+                EditorMode::Dashboard => match (code, modifiers) {
+                    (KeyCode::Char('q'), KeyModifiers::CONTROL) => {
+                        ed.request_quit();
+                    }
+                    (KeyCode::Up, _) | (KeyCode::Char('k'), _) if ed.dashboard_selection > 0 => {
+                        ed.dashboard_selection -= 1;
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
+                        let action_count = ed.dashboard_actions().len();
+                        if ed.dashboard_selection + 1 < action_count {
+                            ed.dashboard_selection += 1;
+                            ed.dirty = true;
+                        }
+                    }
+                    (KeyCode::Home, _) => {
+                        ed.dashboard_selection = 0;
+                        ed.dirty = true;
+                    }
+                    (KeyCode::End, _) => {
+                        ed.dashboard_selection = ed.dashboard_actions().len().saturating_sub(1);
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Enter, _) => {
+                        ed.activate_dashboard_selection();
+                    }
+                    (KeyCode::Esc, _) | (KeyCode::Char('o'), _) => {
+                        ed.dashboard_selection = 0;
+                        ed.activate_dashboard_selection();
+                    }
+                    (KeyCode::Char('n'), _) => {
+                        ed.dashboard_selection = 1;
+                        ed.activate_dashboard_selection();
+                    }
+                    (KeyCode::Char('r'), _) => {
+                        ed.refresh_dashboard_recents();
+                    }
+                    (KeyCode::Char('p'), _) => {
+                        ed.start_scaffold_picker();
+                    }
+                    (KeyCode::Char(c), _) if c.is_ascii_digit() => {
+                        let index = c.to_digit(10).unwrap_or_default() as usize;
+                        let action_count = ed.dashboard_actions().len();
+                        if index > 0 && index <= action_count.min(9) {
+                            ed.dashboard_selection = index - 1;
+                            ed.activate_dashboard_selection();
+                        }
+                    }
+                    _ => {}
+                },
+                EditorMode::Search => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_search();
+                    }
+                    (KeyCode::Enter, _) => {
+                        ed.update_search();
+                    }
+                    (KeyCode::Backspace, _) => {
+                        ed.search_query.pop();
+                        ed.update_search();
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Tab, _) | (KeyCode::F(3), _) => {
+                        ed.next_search_result();
+                    }
+                    (KeyCode::Char(c), m) if !m.contains(KeyModifiers::CONTROL) => {
+                        ed.search_query.push(c);
+                        ed.update_search();
+                        ed.dirty = true;
+                    }
+                    _ => {}
+                },
+                EditorMode::CreateFile | EditorMode::CreateDir => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_create();
+                    }
+                    (KeyCode::Enter, _) => {
+                        let _ = ed.create_file_or_dir();
+                    }
+                    (KeyCode::Backspace, _) => {
+                        ed.create_name.pop();
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Char(c), m) if !m.contains(KeyModifiers::CONTROL) => {
+                        ed.create_name.push(c);
+                        ed.dirty = true;
+                    }
+                    _ => {}
+                },
+                EditorMode::DeleteConfirm => match (code, modifiers) {
+                    (KeyCode::Char('y') | KeyCode::Char('Y'), _) => {
+                        let _ = ed.confirm_delete();
+                    }
+                    (KeyCode::Char('n') | KeyCode::Char('N'), _) | (KeyCode::Esc, _) => {
+                        ed.cancel_delete();
+                    }
+                    _ => {}
+                },
+                EditorMode::Rename => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_rename();
+                    }
+                    (KeyCode::Enter, _) => {
+                        let _ = ed.confirm_rename();
+                    }
+                    (KeyCode::Backspace, _) => {
+                        ed.rename_name.pop();
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Char(c), m) if !m.contains(KeyModifiers::CONTROL) => {
+                        ed.rename_name.push(c);
+                        ed.dirty = true;
+                    }
+                    _ => {}
+                },
+                EditorMode::GoToLine => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_goto_line();
+                    }
+                    (KeyCode::Enter, _) => {
+                        ed.confirm_goto_line();
+                    }
+                    (KeyCode::Backspace, _) => {
+                        ed.goto_line_input.pop();
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Char(c), m)
+                        if !m.contains(KeyModifiers::CONTROL) && c.is_ascii_digit() =>
+                    {
+                        ed.goto_line_input.push(c);
+                        ed.dirty = true;
+                    }
+                    _ => {}
+                },
+                EditorMode::Terminal => {
+                    if matches!(
+                        (code, modifiers),
+                        (KeyCode::Char('c'), KeyModifiers::CONTROL)
+                    ) {
+                        ed.interrupt_terminal();
+                    } else if matches!((code, modifiers), (KeyCode::Enter, _)) {
+                        ed.execute_terminal_command();
+                    } else {
+                        ed.handle_terminal_key_event(code, modifiers);
+                    }
+                }
+                EditorMode::Autocomplete => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_autocomplete();
+                    }
+                    (KeyCode::Enter, _) | (KeyCode::Tab, _) => {
+                        ed.apply_autocomplete();
+                    }
+                    (KeyCode::Down, _) => {
+                        ed.next_autocomplete();
+                    }
+                    (KeyCode::Up, _) => {
+                        ed.prev_autocomplete();
+                    }
+                    (KeyCode::Char(c), m) if !m.contains(KeyModifiers::CONTROL) => {
+                        ed.cancel_autocomplete();
+                        ed.insert(c);
+                        ed.start_autocomplete();
+                    }
+                    (KeyCode::Backspace, _) => {
+                        ed.cancel_autocomplete();
+                        ed.backspace();
+                        ed.start_autocomplete();
+                    }
+                    _ => {
+                        ed.cancel_autocomplete();
+                    }
+                },
+                EditorMode::Palette => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_palette();
+                    }
+                    (KeyCode::Enter, _) => {
+                        ed.confirm_palette();
+                    }
+                    (KeyCode::Down, _) | (KeyCode::Tab, _) => {
+                        ed.next_palette_item();
+                    }
+                    (KeyCode::Up, _) | (KeyCode::BackTab, _) => {
+                        ed.prev_palette_item();
+                    }
+                    (KeyCode::Backspace, _) => {
+                        ed.palette_query.pop();
+                        ed.palette_index = 0;
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Char(c), m) if !m.contains(KeyModifiers::CONTROL) => {
+                        ed.palette_query.push(c);
+                        ed.palette_index = 0;
+                        ed.needs_full_redraw = true;
+                        ed.dirty = true;
+                    }
+                    _ => {}
+                },
+                EditorMode::Blame => {
+                    let (_, rows) = ed.screen_size;
+                    let page = ed.text_rows(rows).saturating_sub(1) as isize;
+                    match (code, modifiers) {
+                        (KeyCode::Esc, _) | (KeyCode::Char('q'), _) => {
+                            ed.close_blame();
+                        }
+                        (KeyCode::Char('o'), _) | (KeyCode::Enter, _) => {
+                            ed.open_blame_commit();
+                        }
+                        (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
+                            ed.move_blame_cursor(1, rows);
+                        }
+                        (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
+                            ed.move_blame_cursor(-1, rows);
+                        }
+                        (KeyCode::PageDown, _) => ed.move_blame_cursor(page, rows),
+                        (KeyCode::PageUp, _) => ed.move_blame_cursor(-page, rows),
+                        _ => {}
+                    }
+                }
+                EditorMode::ScaffoldPicker => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_scaffold();
+                    }
+                    (KeyCode::Enter, _) => {
+                        ed.confirm_scaffold_picker();
+                    }
+                    (KeyCode::Down, _) | (KeyCode::Tab, _) => {
+                        let count = ed.config.scaffolders.len().max(1);
+                        ed.scaffold_index = (ed.scaffold_index + 1) % count;
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Up, _) | (KeyCode::BackTab, _) => {
+                        let count = ed.config.scaffolders.len().max(1);
+                        ed.scaffold_index = ed.scaffold_index.checked_sub(1).unwrap_or(count - 1);
+                        ed.dirty = true;
+                    }
+                    _ => {}
+                },
+                EditorMode::ScaffoldDirectory => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_scaffold();
+                    }
+                    (KeyCode::Enter, _) => {
+                        ed.confirm_scaffold_directory();
+                    }
+                    (KeyCode::Backspace, _) => {
+                        ed.scaffold_dir_input.pop();
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Char(c), m) if !m.contains(KeyModifiers::CONTROL) => {
+                        ed.scaffold_dir_input.push(c);
+                        ed.dirty = true;
+                    }
+                    _ => {}
+                },
+                EditorMode::TemplatePicker => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_template_picker();
+                    }
+                    (KeyCode::Enter, _) => {
+                        ed.confirm_template_picker();
+                    }
+                    (KeyCode::Down, _) | (KeyCode::Tab, _) => {
+                        ed.template_index =
+                            (ed.template_index + 1) % (ed.template_choices.len() + 1);
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Up, _) | (KeyCode::BackTab, _) => {
+                        ed.template_index = ed
+                            .template_index
+                            .checked_sub(1)
+                            .unwrap_or(ed.template_choices.len());
+                        ed.dirty = true;
+                    }
+                    _ => {}
+                },
+                EditorMode::QuickJump => match (code, modifiers) {
+                    (KeyCode::Char(c), m) if !m.contains(KeyModifiers::CONTROL) => {
+                        ed.quick_jump_key(c);
+                    }
+                    _ => ed.cancel_quick_jump(),
+                },
+                EditorMode::Health => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_health_check();
+                    }
+                    (KeyCode::Enter, _) => {
+                        ed.apply_health_fix();
+                    }
+                    (KeyCode::Down, _) | (KeyCode::Tab, _) => {
+                        let count = ed.health_rows().len();
+                        ed.health_index = (ed.health_index + 1) % count;
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Up, _) | (KeyCode::BackTab, _) => {
+                        let count = ed.health_rows().len();
+                        ed.health_index = ed.health_index.checked_sub(1).unwrap_or(count - 1);
+                        ed.dirty = true;
+                    }
+                    _ => {}
+                },
+                EditorMode::KeymapPicker => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_keymap_picker();
+                    }
+                    (KeyCode::Enter, _) | (KeyCode::Char(' '), _) => {
+                        ed.confirm_keymap_picker();
+                    }
+                    (KeyCode::Down, _) | (KeyCode::Tab, _) => {
+                        ed.keymap_index = (ed.keymap_index + 1) % keymap::Preset::ALL.len();
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Up, _) | (KeyCode::BackTab, _) => {
+                        ed.keymap_index = ed
+                            .keymap_index
+                            .checked_sub(1)
+                            .unwrap_or(keymap::Preset::ALL.len() - 1);
+                        ed.dirty = true;
+                    }
+                    _ => {}
+                },
+                EditorMode::LocalOptions => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_local_options();
+                    }
+                    (KeyCode::Enter, _) | (KeyCode::Char(' '), _) => {
+                        ed.change_local_option();
+                    }
+                    (KeyCode::Delete, _) | (KeyCode::Backspace, _) => {
+                        ed.reset_local_option();
+                    }
+                    (KeyCode::Down, _) | (KeyCode::Tab, _) => {
+                        let count = ed.local_option_rows().len();
+                        ed.local_options_index = (ed.local_options_index + 1) % count;
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Up, _) | (KeyCode::BackTab, _) => {
+                        let count = ed.local_option_rows().len();
+                        ed.local_options_index =
+                            ed.local_options_index.checked_sub(1).unwrap_or(count - 1);
+                        ed.dirty = true;
+                    }
+                    _ => {}
+                },
+                EditorMode::SymbolPicker => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_symbol_picker();
+                    }
+                    (KeyCode::Enter, _) => {
+                        ed.confirm_symbol_picker();
+                    }
+                    (KeyCode::Down, _) | (KeyCode::Tab, _) => {
+                        let count = ed.symbol_matches().len();
+                        if count > 0 {
+                            ed.symbol_index = (ed.symbol_index + 1) % count;
+                            ed.dirty = true;
+                        }
+                    }
+                    (KeyCode::Up, _) | (KeyCode::BackTab, _) => {
+                        let count = ed.symbol_matches().len();
+                        if count > 0 {
+                            ed.symbol_index = ed.symbol_index.checked_sub(1).unwrap_or(count - 1);
+                            ed.dirty = true;
+                        }
+                    }
+                    (KeyCode::Backspace, _) => {
+                        ed.symbol_query.pop();
+                        ed.symbol_index = 0;
+                        ed.needs_full_redraw = true;
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Char(c), m) if !m.contains(KeyModifiers::CONTROL) => {
+                        ed.symbol_query.push(c);
+                        ed.symbol_index = 0;
+                        ed.needs_full_redraw = true;
+                        ed.dirty = true;
+                    }
+                    _ => {}
+                },
+                EditorMode::Outline => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_outline();
+                    }
+                    (KeyCode::Enter, _) => {
+                        ed.confirm_outline();
+                    }
+                    (KeyCode::Down, _) | (KeyCode::Tab, _) => {
+                        ed.next_outline_item();
+                    }
+                    (KeyCode::Up, _) | (KeyCode::BackTab, _) => {
+                        ed.prev_outline_item();
+                    }
+                    (KeyCode::Backspace, _) => {
+                        ed.outline_query.pop();
+                        ed.outline_index = 0;
+                        ed.needs_full_redraw = true;
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Char(c), m) if !m.contains(KeyModifiers::CONTROL) => {
+                        ed.outline_query.push(c);
+                        ed.outline_index = 0;
+                        ed.needs_full_redraw = true;
+                        ed.dirty = true;
+                    }
+                    _ => {}
+                },
+                EditorMode::BranchPicker => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_branch_picker();
+                    }
+                    (KeyCode::Enter, _) => {
+                        ed.confirm_branch_picker();
+                    }
+                    (KeyCode::Down, _) | (KeyCode::Tab, _) => {
+                        ed.next_branch_item();
+                    }
+                    (KeyCode::Up, _) | (KeyCode::BackTab, _) => {
+                        ed.prev_branch_item();
+                    }
+                    (KeyCode::Backspace, _) => {
+                        ed.branch_query.pop();
+                        ed.branch_index = 0;
+                        ed.needs_full_redraw = true;
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Char(c), m) if !m.contains(KeyModifiers::CONTROL) => {
+                        ed.branch_query.push(c);
+                        ed.branch_index = 0;
+                        ed.needs_full_redraw = true;
+                        ed.dirty = true;
+                    }
+                    _ => {}
+                },
+                EditorMode::BranchCheckoutConfirm => match code {
+                    KeyCode::Char('y') | KeyCode::Char('Y') => {
+                        ed.confirm_branch_checkout();
+                    }
+                    KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                        ed.cancel_branch_picker();
+                    }
+                    _ => {}
+                },
+                EditorMode::Diff => {
+                    let (_, rows) = ed.screen_size;
+                    let page = ed.text_rows(rows).saturating_sub(1) as isize;
+                    match (code, modifiers) {
+                        (KeyCode::Esc, _) | (KeyCode::Char('q'), _) => {
+                            ed.close_diff();
+                        }
+                        (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
+                            ed.scroll_diff(1, rows);
+                        }
+                        (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
+                            ed.scroll_diff(-1, rows);
+                        }
+                        (KeyCode::PageDown, _) | (KeyCode::Char(' '), _) => {
+                            ed.scroll_diff(page, rows);
+                        }
+                        (KeyCode::PageUp, _) => {
+                            ed.scroll_diff(-page, rows);
+                        }
+                        (KeyCode::Char('n'), _) => {
+                            ed.jump_diff_hunk(true);
+                        }
+                        (KeyCode::Char('p'), _) => {
+                            ed.jump_diff_hunk(false);
+                        }
+                        (KeyCode::Char('r'), _) | (KeyCode::Enter, _) if ed.is_read_only() => {
+                            ed.refuse_read_only_edit();
+                        }
+                        (KeyCode::Char('r'), _) | (KeyCode::Enter, _) => {
+                            ed.revert_diff_hunk();
+                        }
+                        _ => {}
+                    }
+                }
+                EditorMode::DiffPickFile => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.mode = EditorMode::Normal;
+                        ed.needs_full_redraw = true;
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Up, _) => ed.tree_up(),
+                    (KeyCode::Down, _) => ed.tree_down(),
+                    (KeyCode::Enter, _) => ed.confirm_compare_tree_file(),
+                    _ => {}
+                },
+                EditorMode::DiffRevision => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_compare_revision();
+                    }
+                    (KeyCode::Enter, _) => {
+                        ed.confirm_compare_revision();
+                    }
+                    (KeyCode::Backspace, _) => {
+                        ed.diff_revision_input.pop();
+                        ed.dirty = true;
+                    }
+                    (KeyCode::Char(c), m) if !m.contains(KeyModifiers::CONTROL) => {
+                        ed.diff_revision_input.push(c);
+                        ed.dirty = true;
+                    }
+                    _ => {}
+                },
+                EditorMode::GitLog => {
+                    let (_, rows) = ed.screen_size;
+                    let page = ed.text_rows(rows).saturating_sub(1) as isize;
+                    match (code, modifiers) {
+                        (KeyCode::Esc, _)
+                        | (KeyCode::Char('q'), _)
+                        | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                            ed.cancel_git_log();
+                        }
+                        (KeyCode::Enter, _) => ed.diff_git_log_entry(),
+                        (KeyCode::Char('o'), _) => ed.open_git_log_entry(),
+                        (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
+                            ed.move_git_log(1, rows);
+                        }
+                        (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
+                            ed.move_git_log(-1, rows);
+                        }
+                        (KeyCode::PageDown, _) => ed.move_git_log(page, rows),
+                        (KeyCode::PageUp, _) => ed.move_git_log(-page, rows),
+                        _ => {}
+                    }
+                }
+                EditorMode::NotificationLog => {
+                    let (_, rows) = ed.screen_size;
+                    let page = ed.text_rows(rows).saturating_sub(1) as usize;
+                    let max_scroll = ed.notifications.len().saturating_sub(page);
+                    ed.notification_scroll = ed.notification_scroll.min(max_scroll);
+                    match (code, modifiers) {
+                        (KeyCode::Esc, _) | (KeyCode::Char('q'), _) | (KeyCode::F(9), _) => {
+                            ed.close_notification_log();
+                        }
+                        (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
+                            ed.scroll_notification_log(1);
+                        }
+                        (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
+                            ed.scroll_notification_log(-1);
+                        }
+                        (KeyCode::PageDown, _) => ed.scroll_notification_log(page as isize),
+                        (KeyCode::PageUp, _) => ed.scroll_notification_log(-(page as isize)),
+                        _ => {}
+                    }
+                }
+                EditorMode::RevisionView => {
+                    let (_, rows) = ed.screen_size;
+                    let page = ed.text_rows(rows).saturating_sub(1) as isize;
+                    match (code, modifiers) {
+                        (KeyCode::Esc, _) | (KeyCode::Char('q'), _) => {
+                            ed.close_revision_view();
+                        }
+                        (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
+                            ed.scroll_revision_view(1, rows);
+                        }
+                        (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
+                            ed.scroll_revision_view(-1, rows);
+                        }
+                        (KeyCode::PageDown, _) | (KeyCode::Char(' '), _) => {
+                            ed.scroll_revision_view(page, rows);
+                        }
+                        (KeyCode::PageUp, _) => ed.scroll_revision_view(-page, rows),
+                        _ => {}
+                    }
+                }
+                EditorMode::Timeline => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_timeline();
+                    }
+                    (KeyCode::Enter, _) if ed.is_read_only() => {
+                        ed.refuse_read_only_edit();
+                    }
+                    (KeyCode::Enter, _) => {
+                        ed.restore_timeline_entry();
+                    }
+                    (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
+                        ed.next_timeline_entry();
+                    }
+                    (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
+                        ed.prev_timeline_entry();
+                    }
+                    _ => {}
+                },
+                EditorMode::QuickFix => match (code, modifiers) {
+                    (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                        ed.cancel_quick_fix();
+                    }
+                    (KeyCode::Enter, _) | (KeyCode::Tab, _) => {
+                        ed.apply_quick_fix();
+                    }
+                    (KeyCode::Down, _) => {
+                        ed.next_quick_fix();
+                    }
+                    (KeyCode::Up, _) => {
+                        ed.prev_quick_fix();
+                    }
+                    _ => {}
+                },
+                EditorMode::Normal => {
+                    if let Some(action) = ed.key_action(code, modifiers) {
+                        ed.run_key_action(action);
+                        return;
+                    }
+                    match (code, modifiers) {
+                        (KeyCode::Char('z' | 'y' | 'v' | ' ' | '.'), KeyModifiers::CONTROL)
+                        | (KeyCode::Char('q'), KeyModifiers::ALT)
+                            if ed.is_read_only() =>
+                        {
+                            ed.refuse_read_only_edit();
+                        }
+                        (KeyCode::Char('q'), KeyModifiers::CONTROL) => {
+                            ed.request_quit();
+                        }
+                        (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
+                            let _ = ed.save();
+                        }
+                        (KeyCode::Char('o'), KeyModifiers::CONTROL) => {
+                            ed.show_tree = !ed.show_tree;
+                            ed.needs_full_redraw = true;
+                            ed.dirty = true;
+                        }
+                        (KeyCode::Char('f'), KeyModifiers::CONTROL) => {
+                            ed.start_search();
+                        }
+                        (KeyCode::Char('g'), KeyModifiers::CONTROL) => {
+                            ed.start_goto_line();
+                        }
+                        (KeyCode::Char('z'), KeyModifiers::CONTROL) => {
+                            ed.undo();
+                        }
+                        (KeyCode::Char('y'), KeyModifiers::CONTROL) => {
+                            ed.redo();
+                        }
+                        (KeyCode::Char('1'), KeyModifiers::CONTROL) => {
+                            ed.toggle_terminal();
+                        }
+                        (KeyCode::Char('a'), KeyModifiers::CONTROL) => {
+                            ed.select_all();
+                        }
+                        (KeyCode::Char(' '), KeyModifiers::CONTROL) => {
+                            ed.start_autocomplete();
+                        }
+                        (KeyCode::Char('.'), KeyModifiers::CONTROL) => {
+                            ed.request_quick_fixes();
+                        }
+                        (KeyCode::Char('p'), KeyModifiers::CONTROL) => {
+                            ed.start_palette();
+                        }
+                        (KeyCode::Char('w'), KeyModifiers::CONTROL) => {
+                            ed.close_buffer();
+                        }
+                        // Without the keyboard protocol, terminals send
+                        // Ctrl+Shift+T as plain Ctrl+T.
+                        (KeyCode::Char('t') | KeyCode::Char('T'), m)
+                            if m.contains(KeyModifiers::CONTROL) =>
+                        {
+                            ed.reopen_closed_buffer();
+                        }
+                        (KeyCode::F(5), _) => {
+                            ed.run_test_at_cursor();
+                        }
+                        (KeyCode::F(6), _) => {
+                            ed.toggle_output_panel();
+                        }
+                        (KeyCode::F(7), _) => {
+                            ed.start_timeline();
+                        }
+                        (KeyCode::F(9), _) => {
+                            ed.start_notification_log();
+                        }
+                        (KeyCode::Char('b'), KeyModifiers::CONTROL) => {
+                            ed.start_branch_picker();
+                        }
+                        (KeyCode::F(4), _) => {
+                            ed.start_outline();
+                        }
+                        (KeyCode::Char('q'), KeyModifiers::ALT) => {
+                            ed.reflow_paragraph(false);
+                        }
+                        (KeyCode::Char('j'), KeyModifiers::ALT) => {
+                            ed.start_quick_jump();
+                        }
+                        (KeyCode::Char('o'), KeyModifiers::ALT) => {
+                            ed.switch_header_source();
+                        }
+                        (KeyCode::Up, KeyModifiers::ALT) if ed.is_markdown() => {
+                            ed.jump_to_heading(false);
+                        }
+                        (KeyCode::Down, KeyModifiers::ALT) if ed.is_markdown() => {
+                            ed.jump_to_heading(true);
+                        }
+                        (KeyCode::Up, KeyModifiers::ALT) => {
+                            ed.jump_to_block_edge(false);
+                        }
+                        (KeyCode::Down, KeyModifiers::ALT) => {
+                            ed.jump_to_block_edge(true);
+                        }
+                        (KeyCode::Up, KeyModifiers::CONTROL) => {
+                            ed.jump_to_function(false);
+                        }
+                        (KeyCode::Down, KeyModifiers::CONTROL) => {
+                            ed.jump_to_function(true);
+                        }
+                        // Most terminals report Ctrl+] as Ctrl+5.
+                        (KeyCode::Char(']'), KeyModifiers::CONTROL)
+                        | (KeyCode::Char('5'), KeyModifiers::CONTROL) => {
+                            ed.jump_to_matching_pair();
+                        }
+                        (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                            ed.copy_selection();
+                            ed.is_selecting = false;
+                        }
+                        (KeyCode::Char('v'), KeyModifiers::CONTROL) => {
+                            ed.paste();
+                        }
+                        (KeyCode::Char('n'), m)
+                            if ed.show_tree
+                                && m.contains(KeyModifiers::CONTROL)
+                                && !m.contains(KeyModifiers::SHIFT) =>
+                        {
+                            ed.start_create_file();
+                        }
+                        (KeyCode::Char('m'), m)
+                            if ed.show_tree
+                                && m.contains(KeyModifiers::CONTROL)
+                                && !m.contains(KeyModifiers::SHIFT)
+                                && !m.contains(KeyModifiers::ALT) =>
+                        {
+                            ed.start_create_dir();
+                        }
+                        (KeyCode::Char('r'), KeyModifiers::CONTROL) | (KeyCode::F(2), _)
+                            if ed.show_tree =>
+                        {
+                            ed.start_rename();
+                        }
+                        (KeyCode::Delete, _) | (KeyCode::F(8), _) if ed.show_tree => {
+                            ed.start_delete();
+                        }
+
+                        (KeyCode::Up, m) if ed.show_tree && !m.contains(KeyModifiers::SHIFT) => {
+                            ed.tree_up();
+                        }
+                        (KeyCode::Down, m) if ed.show_tree && !m.contains(KeyModifiers::SHIFT) => {
+                            ed.tree_down();
+                        }
+                        (KeyCode::Enter, _) if ed.show_tree => {
+                            let n = ed.tree[ed.tree_cursor].clone();
+                            if n.is_dir {
+                                ed.toggle_dir(ed.tree_cursor);
                             } else {
-                                ed.handle_terminal_key_event(code, modifiers);
+                                let _ = ed.open_file(&n.path);
                             }
+                            ed.dirty = true;
                         }
-                        EditorMode::Autocomplete => match (code, modifiers) {
-                            (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
-                                ed.cancel_autocomplete();
-                            }
-                            (KeyCode::Enter, _) | (KeyCode::Tab, _) => {
-                                ed.apply_autocomplete();
-                            }
-                            (KeyCode::Down, _) => {
-                                ed.next_autocomplete();
-                            }
-                            (KeyCode::Up, _) => {
-                                ed.prev_autocomplete();
-                            }
-                            (KeyCode::Char(c), m) if !m.contains(KeyModifiers::CONTROL) => {
-                                ed.cancel_autocomplete();
-                                ed.insert(c);
-                                ed.start_autocomplete();
-                            }
-                            (KeyCode::Backspace, _) => {
-                                ed.cancel_autocomplete();
-                                ed.backspace();
-                                ed.start_autocomplete();
-                            }
-                            _ => {
-                                ed.cancel_autocomplete();
-                            }
-                        },
-                        EditorMode::Normal => {
-                            if ed.quit_confirm
-                                && !matches!(
-                                    (code, modifiers),
-                                    (KeyCode::Char('q'), KeyModifiers::CONTROL)
-                                )
+
+                        (KeyCode::Left, m) => {
+                            if m.contains(KeyModifiers::CONTROL) && m.contains(KeyModifiers::SHIFT)
                             {
-                                ed.quit_confirm = false;
-                                ed.needs_full_redraw = true;
-                                ed.status = "Ctrl+O Tree | Ctrl+N File | Ctrl+M Folder | F2 Rename | Del Delete | Ctrl+S Save | Ctrl+F Find | Ctrl+G Go to Line | Shift+Arrow Select | Ctrl+C Copy | Ctrl+V Paste | Ctrl+Arrow Word | Ctrl+1 Terminal | Ctrl+Q Quit".into();
-                            }
-                            match (code, modifiers) {
-                                (KeyCode::Char('q'), KeyModifiers::CONTROL) => {
-                                    if ed.quit_confirm {
-                                        break;
-                                    } else if ed.dirty {
-                                        ed.quit_confirm = true;
-                                        ed.status = "File not saved! Press Ctrl+Q again to quit, any other key to cancel".into();
-                                        ed.needs_full_redraw = true;
-                                    } else {
-                                        break;
-                                    }
+                                if !ed.is_selecting {
+                                    ed.start_selection();
                                 }
-                                (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
-                                    let _ = ed.save();
-                                }
-                                (KeyCode::Char('o'), KeyModifiers::CONTROL) => {
-                                    ed.show_tree = !ed.show_tree;
-                                    ed.needs_full_redraw = true;
-                                    ed.dirty = true;
+                                ed.word_left();
+                                ed.update_selection_end();
+                            } else if m.contains(KeyModifiers::CONTROL) {
+                                if ed.is_selecting {
+                                    ed.is_selecting = false;
+                                    ed.selection_start = None;
+                                    ed.selection_end = None;
                                 }
-                                (KeyCode::Char('f'), KeyModifiers::CONTROL) => {
-                                    ed.start_search();
+                                ed.word_left();
+                            } else if m.contains(KeyModifiers::SHIFT) {
+                                if !ed.is_selecting {
+                                    ed.start_selection();
                                 }
-                                (KeyCode::Char('g'), KeyModifiers::CONTROL) => {
-                                    ed.start_goto_line();
+                                ed.left();
+                            } else {
+                                if ed.is_selecting {
+                                    ed.is_selecting = false;
+                                    ed.selection_start = None;
+                                    ed.selection_end = None;
                                 }
-                                (KeyCode::Char('z'), KeyModifiers::CONTROL) => {
-                                    ed.undo();
+                                ed.left();
+                            }
+                        }
+                        (KeyCode::Right, m) => {
+                            if m.contains(KeyModifiers::CONTROL) && m.contains(KeyModifiers::SHIFT)
+                            {
+                                if !ed.is_selecting {
+                                    ed.start_selection();
                                 }
-                                (KeyCode::Char('y'), KeyModifiers::CONTROL) => {
-                                    ed.redo();
+                                ed.word_right();
+                                ed.update_selection_end();
+                            } else if m.contains(KeyModifiers::CONTROL) {
+                                if ed.is_selecting {
+                                    ed.is_selecting = false;
+                                    ed.selection_start = None;
+                                    ed.selection_end = None;
                                 }
-                                (KeyCode::Char('1'), KeyModifiers::CONTROL) => {
-                                    ed.toggle_terminal();
+                                ed.word_right();
+                            } else if m.contains(KeyModifiers::SHIFT) {
+                                if !ed.is_selecting {
+                                    ed.start_selection();
                                 }
-                                (KeyCode::Char('a'), KeyModifiers::CONTROL) => {
-                                    ed.select_all();
+                                ed.right();
+                            } else {
+                                if ed.is_selecting {
+                                    ed.is_selecting = false;
+                                    ed.selection_start = None;
+                                    ed.selection_end = None;
                                 }
-                                (KeyCode::Char(' '), KeyModifiers::CONTROL) => {
-                                    ed.start_autocomplete();
+                                ed.right();
+                            }
+                        }
+                        (KeyCode::Up, m) => {
+                            if m.contains(KeyModifiers::SHIFT) {
+                                if !ed.is_selecting {
+                                    ed.start_selection();
                                 }
-                                (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
-                                    ed.copy_selection();
+                                ed.up();
+                            } else {
+                                if ed.is_selecting {
                                     ed.is_selecting = false;
+                                    ed.selection_start = None;
+                                    ed.selection_end = None;
                                 }
-                                (KeyCode::Char('v'), KeyModifiers::CONTROL) => {
-                                    ed.paste();
-                                }
-                                (KeyCode::Char('n'), m)
-                                    if ed.show_tree
-                                        && m.contains(KeyModifiers::CONTROL)
-                                        && !m.contains(KeyModifiers::SHIFT) =>
-                                {
-                                    ed.start_create_file();
+                                ed.up();
+                            }
+                        }
+                        (KeyCode::Down, m) => {
+                            if m.contains(KeyModifiers::SHIFT) {
+                                if !ed.is_selecting {
+                                    ed.start_selection();
                                 }
-                                (KeyCode::Char('m'), m)
-                                    if ed.show_tree
-                                        && m.contains(KeyModifiers::CONTROL)
-                                        && !m.contains(KeyModifiers::SHIFT)
-                                        && !m.contains(KeyModifiers::ALT) =>
-                                {
-                                    ed.start_create_dir();
+                                ed.down();
+                            } else {
+                                if ed.is_selecting {
+                                    ed.is_selecting = false;
+                                    ed.selection_start = None;
+                                    ed.selection_end = None;
                                 }
-                                (KeyCode::Char('r'), KeyModifiers::CONTROL)
-                                | (KeyCode::F(2), _)
-                                    if ed.show_tree =>
-                                {
-                                    ed.start_rename();
+                                ed.down();
+                            }
+                        }
+                        (KeyCode::Home, m) | (KeyCode::End, m)
+                            if !m.contains(KeyModifiers::CONTROL) =>
+                        {
+                            if m.contains(KeyModifiers::SHIFT) {
+                                if !ed.is_selecting {
+                                    ed.start_selection();
                                 }
-                                (KeyCode::Delete, _) | (KeyCode::F(8), _) if ed.show_tree => {
-                                    ed.start_delete();
+                            } else if ed.is_selecting {
+                                ed.is_selecting = false;
+                                ed.selection_start = None;
+                                ed.selection_end = None;
+                            }
+                            if code == KeyCode::Home {
+                                ed.line_home();
+                            } else {
+                                ed.line_end();
+                            }
+                        }
+                        (KeyCode::PageUp, m) | (KeyCode::PageDown, m) => {
+                            if m.contains(KeyModifiers::SHIFT) {
+                                if !ed.is_selecting {
+                                    ed.start_selection();
                                 }
+                            } else if ed.is_selecting {
+                                ed.is_selecting = false;
+                                ed.selection_start = None;
+                                ed.selection_end = None;
+                            }
+                            ed.page_move(ed.screen_size.1, code == KeyCode::PageDown);
+                        }
 
-                                (KeyCode::Up, m)
-                                    if ed.show_tree && !m.contains(KeyModifiers::SHIFT) =>
-                                {
-                                    if ed.tree_cursor > 0 {
-                                        ed.tree_cursor -= 1;
-                                        let (_, rows) = terminal::size().unwrap_or((80, 24));
-                                        let max_tree_lines = (rows - STATUS_HEIGHT) as usize;
-                                        if ed.tree_cursor < ed.tree_scroll {
-                                            ed.tree_scroll = ed.tree_cursor;
-                                        }
-                                        ed.dirty = true;
-                                    }
-                                }
-                                (KeyCode::Down, m)
-                                    if ed.show_tree && !m.contains(KeyModifiers::SHIFT) =>
-                                {
-                                    if ed.tree_cursor + 1 < ed.tree.len() {
-                                        ed.tree_cursor += 1;
-                                        let (_, rows) = terminal::size().unwrap_or((80, 24));
-                                        let max_tree_lines = (rows - STATUS_HEIGHT) as usize;
-                                        if ed.tree_cursor >= ed.tree_scroll + max_tree_lines {
-                                            ed.tree_scroll = ed.tree_cursor - max_tree_lines + 1;
-                                        }
-                                        ed.dirty = true;
-                                    }
-                                }
-                                (KeyCode::Enter, _) if ed.show_tree => {
-                                    let n = ed.tree[ed.tree_cursor].clone();
-                                    if n.is_dir {
-                                        ed.toggle_dir(ed.tree_cursor);
-                                    } else {
-                                        let _ = ed.open_file(&n.path);
-                                    }
-                                    ed.dirty = true;
+                        // Whatever reaches the typing keys below would edit.
+                        (
+                            KeyCode::Backspace
+                            | KeyCode::Delete
+                            | KeyCode::Enter
+                            | KeyCode::Tab
+                            | KeyCode::BackTab,
+                            _,
+                        ) if ed.is_read_only() => {
+                            ed.refuse_read_only_edit();
+                        }
+                        (KeyCode::Char(_), m)
+                            if ed.is_read_only()
+                                && (!m.contains(KeyModifiers::CONTROL)
+                                    || m.contains(KeyModifiers::ALT)) =>
+                        {
+                            ed.refuse_read_only_edit();
+                        }
+                        (KeyCode::Backspace, m) => {
+                            if ed.is_selecting {
+                                ed.is_selecting = false;
+                                ed.selection_start = None;
+                                ed.selection_end = None;
+                            }
+                            if m.contains(KeyModifiers::CONTROL) {
+                                ed.delete_word_backward();
+                            } else {
+                                ed.backspace();
+                            }
+                        }
+                        (KeyCode::Delete, m) => {
+                            if ed.is_selecting {
+                                ed.is_selecting = false;
+                                ed.selection_start = None;
+                                ed.selection_end = None;
+                            }
+                            if m.contains(KeyModifiers::CONTROL) {
+                                ed.delete_word_forward();
+                            } else {
+                                ed.delete();
+                            }
+                        }
+                        (KeyCode::Enter, _) => {
+                            if ed.is_selecting {
+                                ed.is_selecting = false;
+                                ed.selection_start = None;
+                                ed.selection_end = None;
+                            }
+                            ed.newline();
+                        }
+                        (code @ (KeyCode::Tab | KeyCode::BackTab), m) => {
+                            if code == KeyCode::BackTab || m.contains(KeyModifiers::SHIFT) {
+                                if !ed.table_next_cell(true) {
+                                    ed.unindent();
                                 }
+                            } else if !ed.table_next_cell(false) && !ed.accept_ghost_completion() {
+                                ed.indent();
+                            }
+                        }
+                        // Tüm karakterleri kabul et, sadece tek başına CONTROL kombinasyonlarını reddet
+                        // ALT/ALTGR (ALT+CTRL) tuşlarıyla yazılan karakterleri de kabul et (#, |, @, vb.)
+                        (KeyCode::Char(c), m)
+                            if !m.contains(KeyModifiers::CONTROL)
+                                || m.contains(KeyModifiers::ALT) =>
+                        {
+                            if ed.is_selecting {
+                                ed.is_selecting = false;
+                                ed.selection_start = None;
+                                ed.selection_end = None;
+                            }
+                            ed.insert(c);
+                            ed.update_ghost_completion();
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
 
-                                (KeyCode::Left, m) => {
-                                    if m.contains(KeyModifiers::CONTROL)
-                                        && m.contains(KeyModifiers::SHIFT)
-                                    {
-                                        if !ed.is_selecting {
-                                            ed.start_selection();
-                                        }
-                                        ed.word_left();
-                                        ed.update_selection_end();
-                                    } else if m.contains(KeyModifiers::CONTROL) {
-                                        if ed.is_selecting {
-                                            ed.is_selecting = false;
-                                            ed.selection_start = None;
-                                            ed.selection_end = None;
-                                        }
-                                        ed.word_left();
-                                    } else if m.contains(KeyModifiers::SHIFT) {
-                                        if !ed.is_selecting {
-                                            ed.start_selection();
-                                        }
-                                        ed.left();
-                                    } else {
-                                        if ed.is_selecting {
-                                            ed.is_selecting = false;
-                                            ed.selection_start = None;
-                                            ed.selection_end = None;
-                                        }
-                                        ed.left();
-                                    }
-                                }
-                                (KeyCode::Right, m) => {
-                                    if m.contains(KeyModifiers::CONTROL)
-                                        && m.contains(KeyModifiers::SHIFT)
-                                    {
-                                        if !ed.is_selecting {
-                                            ed.start_selection();
-                                        }
-                                        ed.word_right();
-                                        ed.update_selection_end();
-                                    } else if m.contains(KeyModifiers::CONTROL) {
-                                        if ed.is_selecting {
-                                            ed.is_selecting = false;
-                                            ed.selection_start = None;
-                                            ed.selection_end = None;
-                                        }
-                                        ed.word_right();
-                                    } else if m.contains(KeyModifiers::SHIFT) {
-                                        if !ed.is_selecting {
-                                            ed.start_selection();
-                                        }
-                                        ed.right();
-                                    } else {
-                                        if ed.is_selecting {
-                                            ed.is_selecting = false;
-                                            ed.selection_start = None;
-                                            ed.selection_end = None;
-                                        }
-                                        ed.right();
-                                    }
-                                }
-                                (KeyCode::Up, m) => {
-                                    if m.contains(KeyModifiers::SHIFT) {
-                                        if !ed.is_selecting {
-                                            ed.start_selection();
-                                        }
-                                        ed.up();
-                                    } else {
-                                        if ed.is_selecting {
-                                            ed.is_selecting = false;
-                                            ed.selection_start = None;
-                                            ed.selection_end = None;
-                                        }
-                                        ed.up();
-                                    }
-                                }
-                                (KeyCode::Down, m) => {
-                                    if m.contains(KeyModifiers::SHIFT) {
-                                        if !ed.is_selecting {
-                                            ed.start_selection();
-                                        }
-                                        ed.down();
-                                    } else {
-                                        if ed.is_selecting {
-                                            ed.is_selecting = false;
-                                            ed.selection_start = None;
-                                            ed.selection_end = None;
-                                        }
-                                        ed.down();
-                                    }
-                                }
+const USAGE: &str =
+    "usage: termi [--safe] [--must-exist] [--wait] [--server] [--record file] [path]\n       termi [--safe] --attach [host:port]\n       termi --print file\n       termi --tutor\n       termi [--safe] --replay recording [path]\n       termi --dump-config | --dump-keys | --version";
+
+#[derive(PartialEq)]
+enum Mode {
+    Local,
+    // Headless: the editor keeps running while terminal clients come and go.
+    Server,
+    // A thin client for a server; `path` is the server's address.
+    Attach,
+    // Write `path` to stdout with syntax colors and exit.
+    Print,
+    // Open the interactive tutorial instead of a file.
+    Tutor,
+    // Print the default config.toml, or the key bindings, and exit.
+    DumpConfig,
+    DumpKeys,
+    Version,
+    // Feed a `--record`ed file to an editor without a terminal and print
+    // the screen it ends on.
+    Replay,
+}
 
-                                (KeyCode::Backspace, m) => {
-                                    if ed.is_selecting {
-                                        ed.is_selecting = false;
-                                        ed.selection_start = None;
-                                        ed.selection_end = None;
-                                    }
-                                    if m.contains(KeyModifiers::CONTROL) {
-                                        ed.delete_word_backward();
-                                    } else {
-                                        ed.backspace();
-                                    }
-                                }
-                                (KeyCode::Delete, m) => {
-                                    if ed.is_selecting {
-                                        ed.is_selecting = false;
-                                        ed.selection_start = None;
-                                        ed.selection_end = None;
-                                    }
-                                    if m.contains(KeyModifiers::CONTROL) {
-                                        ed.delete_word_forward();
-                                    } else {
-                                        ed.delete();
-                                    }
-                                }
-                                (KeyCode::Enter, _) => {
-                                    if ed.is_selecting {
-                                        ed.is_selecting = false;
-                                        ed.selection_start = None;
-                                        ed.selection_end = None;
-                                    }
-                                    ed.newline();
-                                }
-                                (KeyCode::Tab, m) => {
-                                    if m.contains(KeyModifiers::SHIFT) {
-                                        ed.unindent();
-                                    } else {
-                                        ed.indent();
-                                    }
-                                }
-                                (KeyCode::Char(c), m) => {
-                                    // Tüm karakterleri kabul et, sadece tek başına CONTROL kombinasyonlarını reddet
-                                    // ALT/ALTGR (ALT+CTRL) tuşlarıyla yazılan karakterleri de kabul et (#, |, @, vb.)
-                                    if !m.contains(KeyModifiers::CONTROL)
-                                        || m.contains(KeyModifiers::ALT)
-                                    {
-                                        if ed.is_selecting {
-                                            ed.is_selecting = false;
-                                            ed.selection_start = None;
-                                            ed.selection_end = None;
-                                        }
-                                        ed.insert(c);
-                                    }
-                                }
-                                _ => {}
-                            }
+struct Args {
+    path: Option<String>,
+    // Fail instead of falling back to the current directory when `path`
+    // does not exist, so scripts can tell.
+    must_exist: bool,
+    // Return only once the forwarded file is closed, for use as `$EDITOR`.
+    wait: bool,
+    // Skip the user's config and everything that loads code or talks to
+    // other programs, to tell whether a problem comes from them.
+    safe: bool,
+    // Where `--record` writes input events; the recording for `--replay`.
+    events_file: Option<String>,
+    mode: Mode,
+}
+
+fn parse_args() -> io::Result<Args> {
+    let mut args = Args {
+        path: None,
+        must_exist: false,
+        wait: false,
+        safe: false,
+        events_file: None,
+        mode: Mode::Local,
+    };
+    let mut argv = env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "--record" | "--replay"
+                if args.events_file.is_none()
+                    && (arg == "--record" || args.mode == Mode::Local) =>
+            {
+                if arg == "--replay" {
+                    args.mode = Mode::Replay;
+                }
+                args.events_file = Some(argv.next().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{} needs a file\n{}", arg, USAGE),
+                    )
+                })?);
+            }
+            "--must-exist" => args.must_exist = true,
+            "--wait" => args.wait = true,
+            "--safe" => args.safe = true,
+            "--server" if args.mode == Mode::Local => args.mode = Mode::Server,
+            "--attach" if args.mode == Mode::Local => args.mode = Mode::Attach,
+            "--print" if args.mode == Mode::Local => args.mode = Mode::Print,
+            "--tutor" if args.mode == Mode::Local => args.mode = Mode::Tutor,
+            "--dump-config" if args.mode == Mode::Local => args.mode = Mode::DumpConfig,
+            "--dump-keys" if args.mode == Mode::Local => args.mode = Mode::DumpKeys,
+            "--version" | "-V" if args.mode == Mode::Local => args.mode = Mode::Version,
+            flag if flag.starts_with("--") => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unknown option {}\n{}", flag, USAGE),
+                ));
+            }
+            _ if args.path.is_some() => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE));
+            }
+            _ => args.path = Some(arg),
+        }
+    }
+    Ok(args)
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("termi: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> io::Result<()> {
+    let args = parse_args()?;
+    match args.mode {
+        Mode::Local | Mode::Tutor | Mode::Replay => {}
+        _ if args.events_file.is_some() => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE));
+        }
+        _ => {}
+    }
+    match args.mode {
+        Mode::Attach => return run_attach(args.path.as_deref(), args.safe),
+        Mode::Print => return print_highlighted(args.path.as_deref()),
+        Mode::Tutor | Mode::DumpConfig | Mode::DumpKeys | Mode::Version if args.path.is_some() => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE));
+        }
+        Mode::DumpConfig => return print_stdout(&config::default_toml()),
+        Mode::DumpKeys => return print_stdout(&keymap::default_table()),
+        Mode::Version => return print_stdout(&format!("{}\n", version::describe())),
+        Mode::Local | Mode::Server | Mode::Tutor | Mode::Replay => {}
+    }
+    let initial_path = args.path.as_deref().unwrap_or(".");
+    if args.must_exist && !Path::new(initial_path).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{}: no such file or directory", initial_path),
+        ));
+    }
+    if args.mode == Mode::Server {
+        return run_server(initial_path, args.path.is_none(), args.safe);
+    }
+    if let (Mode::Replay, Some(recording)) = (&args.mode, &args.events_file) {
+        return run_replay(recording, initial_path, args.path.is_none(), args.safe);
+    }
+    let mut recorder = match (&args.mode, &args.events_file) {
+        (Mode::Local | Mode::Tutor, Some(file)) => Some(
+            replay::Recorder::create(Path::new(file))
+                .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", file, err)))?,
+        ),
+        _ => None,
+    };
+    let _cache = match recorder {
+        Some(_) => Some(replay::FreshCache::create()?),
+        None => None,
+    };
+    if args.path.is_some() && !args.safe && instance::forward(Path::new(initial_path), args.wait) {
+        return Ok(());
+    }
+
+    if !capabilities::supports_escape_sequences() {
+        return Err(io::Error::other(
+            "this console does not support escape sequences; use Windows 10 or later, or Windows Terminal",
+        ));
+    }
+
+    terminal::enable_raw_mode()?;
+    let mut out = io::stdout();
+    execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+    let events = EventLoop::new();
+    let show_dashboard = args.path.is_none() && args.mode != Mode::Tutor;
+    let mut ed = Editor::new_with_options(initial_path, show_dashboard, args.safe, events.waker());
+    if args.mode == Mode::Tutor {
+        ed.start_tutor();
+    }
+    // Only a dashboard start asks: opening a file, as `$EDITOR` or over the
+    // tutor, should not stop for it. A replay never shows the picker, so a
+    // recording must not either.
+    if show_dashboard && !args.safe && recorder.is_none() && !config_path().exists() {
+        ed.start_keymap_picker();
+    }
+    if ed.config.check_updates {
+        ed.check_for_updates(false);
+    }
+    let _instance = if ed.config.single_instance {
+        instance::serve(events.waker()).ok().map(|(server, rx)| {
+            ed.instance_rx = Some(rx);
+            server
+        })
+    } else {
+        None
+    };
+    let capabilities = Capabilities::detect(&ed.config.terminal);
+    ed.glyphs = capabilities.glyphs();
+    enable_input_features(&mut out, &capabilities)?;
+    events.listen();
+    let (cols, rows) = terminal::size()?;
+    let mut screen = screen::ScreenBuffer::new(rows, cols, capabilities.colors);
+    if let Some(recorder) = recorder.as_mut() {
+        recorder.record(&Event::Resize(cols, rows), false)?;
+    }
+
+    loop {
+        if ed.should_quit {
+            break;
+        }
+        ed.screen_size = terminal::size()?;
+        update(&mut ed);
+
+        if ed.dirty || ed.needs_full_redraw {
+            let (cols, rows) = ed.screen_size;
+            draw(&mut ed, &mut screen.frame)?;
+            screen.present(&mut out, rows, cols)?;
+            ed.dirty = false;
+            ed.needs_full_redraw = false;
+        }
+
+        if let Some(event) = events.wait(wait_timeout(&ed))? {
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.record(&event, ed.types_text())?;
+            }
+            if let Event::Resize(cols, rows) = event {
+                screen.invalidate();
+                ed.screen_size = (cols, rows);
+            }
+            handle_event(&mut ed, event);
+        }
+    }
+
+    ed.remember_position();
+    ed.save_symbol_cache();
+    ed.close_terminal_session();
+    ed.close_lsp_clients();
+    ed.close_discord();
+    restore_terminal(&mut out, &capabilities)
+}
+
+/// Plays a recording made with `--record` into an editor without a
+/// terminal, drawing after each event as the real loop would, and prints
+/// the final screen as plain text.
+fn run_replay(
+    recording: &str,
+    initial_path: &str,
+    show_dashboard: bool,
+    safe_mode: bool,
+) -> io::Result<()> {
+    let recorded = replay::load(Path::new(recording))?;
+    let _cache = replay::FreshCache::create()?;
+    let (_scratch, initial_path) = replay::ScratchTree::create(Path::new(initial_path))?;
+    let initial_path = &*initial_path.to_string_lossy();
+    let events = EventLoop::new();
+    let mut ed = Editor::new_with_options(initial_path, show_dashboard, safe_mode, events.waker());
+    ed.screen_size = (80, 24);
+    let mut screen = vt100::Parser::new(24, 80, 0);
+    let mut frame = Vec::new();
+    for event in recorded.into_iter().map(Some).chain([None]) {
+        update(&mut ed);
+        if ed.dirty || ed.needs_full_redraw {
+            let (cols, rows) = ed.screen_size;
+            screen.screen_mut().set_size(rows, cols);
+            frame.clear();
+            draw(&mut ed, &mut frame)?;
+            screen.process(&frame);
+            ed.dirty = false;
+            ed.needs_full_redraw = false;
+        }
+        match event {
+            Some(event) if !ed.should_quit => handle_event(&mut ed, event),
+            _ => break,
+        }
+    }
+    ed.close_terminal_session();
+    ed.close_lsp_clients();
+    ed.close_discord();
+    print_stdout(&format!("{}\n", screen.screen().contents()))
+}
+
+fn print_highlighted(path: Option<&str>) -> io::Result<()> {
+    let Some(path) = path else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE));
+    };
+    let text = fs::read_to_string(path)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path, err)))?;
+    let language = detect_language(Path::new(path));
+    let text = text.strip_prefix(BOM).unwrap_or(&text);
+    let lines: Vec<Vec<char>> = text.lines().map(|line| line.chars().collect()).collect();
+    let highlighted: Vec<export::HighlightedLine> = lines
+        .iter()
+        .map(|line| (line.as_slice(), syntax_tokens(line, &language)))
+        .collect();
+    print_stdout(&export::ansi(&highlighted))
+}
+
+fn print_stdout(text: &str) -> io::Result<()> {
+    match io::stdout().write_all(text.as_bytes()) {
+        // Piped into `head` and the like.
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+fn enable_input_features(out: &mut impl Write, capabilities: &Capabilities) -> io::Result<()> {
+    if capabilities.mouse {
+        execute!(out, EnableMouseCapture)?;
+    }
+    if capabilities.keyboard_enhancement {
+        execute!(
+            out,
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+        )?;
+    }
+    Ok(())
+}
+
+fn restore_terminal(out: &mut impl Write, capabilities: &Capabilities) -> io::Result<()> {
+    if capabilities.keyboard_enhancement {
+        execute!(out, PopKeyboardEnhancementFlags)?;
+    }
+    if capabilities.mouse {
+        execute!(out, DisableMouseCapture)?;
+    }
+    execute!(out, terminal::LeaveAlternateScreen, cursor::Show)?;
+    terminal::disable_raw_mode()
+}
+
+struct SessionClient {
+    id: usize,
+    cols: u16,
+    rows: u16,
+    writer: session::FrameWriter,
+    // What this client's terminal shows, so it only gets the cells that
+    // changed for it.
+    screen: screen::ScreenBuffer,
+    // Frames were drawn while the last one was still being sent; the next
+    // present catches up on all of them at once.
+    behind: bool,
+}
+
+impl SessionClient {
+    // Sends what changed since this client was last updated.
+    fn present(&mut self) -> bool {
+        let mut bytes = Vec::new();
+        self.behind = false;
+        self.screen
+            .present(&mut bytes, self.rows, self.cols)
+            .is_ok()
+            && self.writer.send(bytes)
+    }
+}
+
+/// Runs the editor without a terminal. Clients attach over a loopback
+/// socket; all of them see the same frames and any of them can type.
+/// Frames are drawn at the size of the smallest client. Quitting ends the
+/// session, while detaching or losing the client leaves it running for the
+/// next attach.
+fn run_server(initial_path: &str, show_dashboard: bool, safe_mode: bool) -> io::Result<()> {
+    let events = EventLoop::new();
+    let mut ed = Editor::new_with_options(initial_path, show_dashboard, safe_mode, events.waker());
+    ed.serving_session = true;
+    let server = session::SessionServer::start(events.waker())?;
+    println!(
+        "termi: session listening on 127.0.0.1:{}; attach with `termi --attach`",
+        server.port()
+    );
+
+    // Every client has to fit, so the editor is as wide as the narrowest
+    // and as tall as the shortest.
+    let fit = |ed: &mut Editor, clients: &[SessionClient]| {
+        let cols = clients.iter().map(|client| client.cols).min();
+        let rows = clients.iter().map(|client| client.rows).min();
+        if let (Some(cols), Some(rows)) = (cols, rows) {
+            if ed.screen_size != (cols, rows) {
+                handle_event(ed, Event::Resize(cols, rows));
+            }
+        }
+    };
+
+    let mut clients: Vec<SessionClient> = Vec::new();
+    while !ed.should_quit {
+        for event in server.poll() {
+            match event {
+                SessionEvent::Attached {
+                    id,
+                    cols,
+                    rows,
+                    stream,
+                } => {
+                    clients.push(SessionClient {
+                        id,
+                        cols,
+                        rows,
+                        writer: session::FrameWriter::new(stream, events.waker()),
+                        screen: screen::ScreenBuffer::new(rows, cols, ColorSupport::TrueColor),
+                        behind: false,
+                    });
+                    fit(&mut ed, &clients);
+                    // The newcomer needs the whole screen.
+                    ed.needs_full_redraw = true;
+                }
+                SessionEvent::Input {
+                    id,
+                    event: Event::Resize(cols, rows),
+                } => {
+                    if let Some(client) = clients.iter_mut().find(|client| client.id == id) {
+                        (client.cols, client.rows) = (cols, rows);
+                        client.screen.invalidate();
+                    }
+                    fit(&mut ed, &clients);
+                    ed.needs_full_redraw = true;
+                }
+                SessionEvent::Input { id, event } => {
+                    handle_event(&mut ed, event);
+                    if ed.detach_requested {
+                        ed.detach_requested = false;
+                        if let Some(index) = clients.iter().position(|client| client.id == id) {
+                            clients.remove(index).writer.close();
+                            fit(&mut ed, &clients);
                         }
                     }
                 }
-                _ => {}
+                SessionEvent::Detached { id } => {
+                    clients.retain(|client| client.id != id);
+                    fit(&mut ed, &clients);
+                }
             }
         }
+        update(&mut ed);
+
+        // With nobody attached the frame stays pending until someone is.
+        if (ed.dirty || ed.needs_full_redraw) && !clients.is_empty() {
+            let mut frame = Vec::new();
+            draw(&mut ed, &mut frame)?;
+            clients.retain_mut(|client| {
+                client.screen.frame.extend_from_slice(&frame);
+                if client.writer.busy() {
+                    client.screen.absorb(client.rows, client.cols);
+                    client.behind = true;
+                    true
+                } else {
+                    client.present()
+                }
+            });
+            ed.dirty = false;
+            ed.needs_full_redraw = false;
+        }
+        // A slow client whose last frame has now gone out.
+        clients.retain_mut(|client| !client.behind || client.writer.busy() || client.present());
+
+        events.wait(wait_timeout(&ed))?;
     }
 
+    let closing: Vec<_> = clients
+        .into_iter()
+        .map(|client| client.writer.close())
+        .collect();
+    for thread in closing {
+        let _ = thread.join();
+    }
+    ed.remember_position();
+    ed.save_symbol_cache();
     ed.close_terminal_session();
+    ed.close_lsp_clients();
     ed.close_discord();
-    execute!(
-        out,
-        DisableMouseCapture,
-        terminal::LeaveAlternateScreen,
-        cursor::Show
-    )?;
-    terminal::disable_raw_mode()?;
     Ok(())
 }
+
+/// Shows a session started with `--server` in this terminal, forwarding
+/// input to it until it quits or this client detaches.
+fn run_attach(addr: Option<&str>, safe_mode: bool) -> io::Result<()> {
+    if !capabilities::supports_escape_sequences() {
+        return Err(io::Error::other(
+            "this console does not support escape sequences; use Windows 10 or later, or Windows Terminal",
+        ));
+    }
+    let config = if safe_mode {
+        config::Config::default()
+    } else {
+        config::load().0
+    };
+    let events = EventLoop::new();
+    let (cols, rows) = terminal::size()?;
+    let (mut stream, frames) = session::attach(addr, cols, rows, events.waker())?;
+
+    terminal::enable_raw_mode()?;
+    let mut out = io::stdout();
+    execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+    let capabilities = Capabilities::detect(&config.terminal);
+    enable_input_features(&mut out, &capabilities)?;
+    events.listen();
+
+    'session: loop {
+        for frame in frames.try_iter() {
+            if frame.is_empty() {
+                break 'session;
+            }
+            out.write_all(&capabilities::downgrade_colors(&frame, capabilities.colors))?;
+            out.flush()?;
+        }
+        if let Some(event) = events.wait(None)? {
+            let Some(line) = session::encode_event(&event) else {
+                continue;
+            };
+            if writeln!(stream, "{}", line).is_err() {
+                break;
+            }
+        }
+    }
+
+    restore_terminal(&mut out, &capabilities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    /// Times the tokenizer on a large file: this one repeated to 100k lines.
+    /// Run it with `cargo test --release -- --ignored --nocapture tokenize`.
+    /// A release build gets through them in 50-70ms, under a microsecond a
+    /// line.
+    #[test]
+    #[ignore]
+    fn tokenize_large_file() {
+        let lines: Vec<Vec<char>> = include_str!("main.rs")
+            .lines()
+            .cycle()
+            .take(100_000)
+            .map(|line| line.chars().collect())
+            .collect();
+        let start = Instant::now();
+        let tokens: usize = lines
+            .iter()
+            .map(|line| syntax_tokens(line, &Language::Rust).len())
+            .sum();
+        let elapsed = start.elapsed();
+        println!(
+            "tokenized {} lines into {} tokens in {:?}",
+            lines.len(),
+            tokens,
+            elapsed
+        );
+        assert!(tokens > lines.len());
+    }
+}
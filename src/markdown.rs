@@ -0,0 +1,581 @@
+use crate::capabilities::Glyphs;
+use crossterm::style::{Attribute, Color, ContentStyle};
+use std::path::Path;
+
+pub fn is_markdown_path(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| ext == "md" || ext == "markdown")
+}
+
+/// One row of the rendered preview and the source line it came from, which
+/// keeps the preview scrolled alongside the text.
+pub struct PreviewLine {
+    pub source: usize,
+    pub spans: Vec<(String, ContentStyle)>,
+}
+
+fn style(color: Color) -> ContentStyle {
+    ContentStyle {
+        foreground_color: Some(color),
+        ..ContentStyle::default()
+    }
+}
+
+fn with(mut style: ContentStyle, attribute: Attribute) -> ContentStyle {
+    style.attributes.set(attribute);
+    style
+}
+
+/// Renders the buffer line by line (soft line breaks are kept, so every
+/// source line maps to at least one preview row), wrapped to `width`.
+pub fn render(lines: &[Vec<char>], width: usize, glyphs: &Glyphs) -> Vec<PreviewLine> {
+    let width = width.max(1);
+    let mut rendered = Vec::with_capacity(lines.len());
+    let mut in_fence = false;
+
+    for (source, chars) in lines.iter().enumerate() {
+        let line: String = chars.iter().collect();
+        let trimmed = line.trim_start();
+        let mut push =
+            |spans: Vec<(String, ContentStyle)>| rendered.push(PreviewLine { source, spans });
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            push(vec![]);
+            continue;
+        }
+        if in_fence {
+            // Code keeps its layout; overlong lines are cut rather than wrapped.
+            let code: String = line.chars().take(width.saturating_sub(2)).collect();
+            push(vec![(format!("  {}", code), style(Color::DarkYellow))]);
+            continue;
+        }
+
+        if is_rule(trimmed) {
+            push(vec![(
+                glyphs.rule.to_string().repeat(width),
+                style(Color::DarkGrey),
+            )]);
+            continue;
+        }
+
+        if let Some((level, text)) = heading(trimmed) {
+            let color = if level <= 2 { Color::Cyan } else { Color::Blue };
+            let mut base = with(style(color), Attribute::Bold);
+            if level == 1 {
+                base = with(base, Attribute::Underlined);
+            }
+            for row in wrap(inline(text, base), width, "", "") {
+                push(row);
+            }
+            continue;
+        }
+
+        if let Some(text) = trimmed.strip_prefix('>') {
+            let bar = format!("{} ", glyphs.separator);
+            let base = with(style(Color::Grey), Attribute::Italic);
+            for mut row in wrap(inline(text.trim_start(), base), width, &bar, &bar) {
+                if let Some(first) = row.first_mut() {
+                    first.1 = style(Color::DarkGrey);
+                }
+                push(row);
+            }
+            continue;
+        }
+
+        let indent = " ".repeat(line.len() - trimmed.len());
+        if let Some((marker, text)) = list_item(trimmed) {
+            let marker = match marker {
+                ListMarker::Bullet => format!("{}{} ", indent, glyphs.bullet),
+                ListMarker::Number(number) => format!("{}{} ", indent, number),
+            };
+            let hang = " ".repeat(marker.chars().count());
+            for row in wrap(inline(text, ContentStyle::default()), width, &marker, &hang) {
+                push(row);
+            }
+            continue;
+        }
+
+        if trimmed.starts_with('|') {
+            // Tables are already laid out in the source.
+            let row: String = line.chars().take(width).collect();
+            push(vec![(row, ContentStyle::default())]);
+            continue;
+        }
+
+        for row in wrap(
+            inline(trimmed, ContentStyle::default()),
+            width,
+            &indent,
+            &indent,
+        ) {
+            push(row);
+        }
+    }
+    rendered
+}
+
+fn is_rule(line: &str) -> bool {
+    let line: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    line.len() >= 3
+        && ["-", "*", "_"]
+            .iter()
+            .any(|marker| line.chars().all(|c| c.to_string() == *marker))
+}
+
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let text = &line[level..];
+    if !text.is_empty() && !text.starts_with(' ') {
+        return None;
+    }
+    Some((level, text.trim().trim_end_matches('#').trim_end()))
+}
+
+pub struct Heading {
+    pub y: usize,
+    pub level: usize,
+    pub text: String,
+}
+
+/// The document's `#` headings, in order, skipping fenced code.
+pub fn headings(lines: &[Vec<char>]) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut in_fence = false;
+    for (y, chars) in lines.iter().enumerate() {
+        let line: String = chars.iter().collect();
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        } else if !in_fence {
+            if let Some((level, text)) = heading(trimmed) {
+                headings.push(Heading {
+                    y,
+                    level,
+                    text: text.to_string(),
+                });
+            }
+        }
+    }
+    headings
+}
+
+enum ListMarker {
+    Bullet,
+    Number(String),
+}
+
+fn list_item(line: &str) -> Option<(ListMarker, &str)> {
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(text) = line.strip_prefix(bullet) {
+            return Some((ListMarker::Bullet, text));
+        }
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let rest = &line[digits..];
+    if digits > 0 && (rest.starts_with(". ") || rest.starts_with(") ")) {
+        return Some((
+            ListMarker::Number(line[..digits + 1].to_string()),
+            &rest[2..],
+        ));
+    }
+    None
+}
+
+fn flush(current: &mut String, spans: &mut Vec<(String, ContentStyle)>, style: ContentStyle) {
+    if !current.is_empty() {
+        spans.push((std::mem::take(current), style));
+    }
+}
+
+/// Applies inline markup: `code`, **bold**, *italic* / _italic_ and
+/// [links](url), which show their text only. Markers without a closing
+/// partner are left as typed.
+fn inline(text: &str, base: ContentStyle) -> Vec<(String, ContentStyle)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut spans: Vec<(String, ContentStyle)> = Vec::new();
+    let mut current = String::new();
+    let (mut bold, mut italic) = (false, false);
+    let current_style = |bold: bool, italic: bool| {
+        let mut style = base;
+        if bold {
+            style = with(style, Attribute::Bold);
+        }
+        if italic {
+            style = with(style, Attribute::Italic);
+        }
+        style
+    };
+    let closes = |from: usize, marker: &[char]| {
+        chars
+            .get(from..)
+            .is_some_and(|rest| rest.windows(marker.len()).any(|window| window == marker))
+    };
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' && i + 1 < chars.len() {
+            current.push(chars[i + 1]);
+            i += 2;
+            continue;
+        }
+        if c == '`' {
+            if let Some(end) = chars[i + 1..].iter().position(|&c| c == '`') {
+                flush(&mut current, &mut spans, current_style(bold, italic));
+                let code: String = chars[i + 1..i + 1 + end].iter().collect();
+                spans.push((code, style(Color::DarkYellow)));
+                i += end + 2;
+                continue;
+            }
+        }
+        if c == '[' {
+            let close = chars[i..].iter().position(|&c| c == ']').map(|p| i + p);
+            if let Some(close) = close.filter(|&close| chars.get(close + 1) == Some(&'(')) {
+                if let Some(end) = chars[close..].iter().position(|&c| c == ')') {
+                    flush(&mut current, &mut spans, current_style(bold, italic));
+                    let label: String = chars[i + 1..close].iter().collect();
+                    spans.push((label, with(style(Color::Blue), Attribute::Underlined)));
+                    i = close + end + 1;
+                    continue;
+                }
+            }
+        }
+        let double = chars.get(i + 1) == Some(&c);
+        if (c == '*' || c == '_') && double && (bold || closes(i + 2, &[c, c])) {
+            flush(&mut current, &mut spans, current_style(bold, italic));
+            bold = !bold;
+            i += 2;
+            continue;
+        }
+        // `_` inside a word (snake_case) is not emphasis.
+        let in_word = |j: usize| chars.get(j).is_some_and(|c| c.is_alphanumeric());
+        let underscore_ok = c != '_'
+            || if italic {
+                !in_word(i + 1)
+            } else {
+                i == 0 || !in_word(i - 1)
+            };
+        if (c == '*' || c == '_') && underscore_ok && (italic || closes(i + 1, &[c])) {
+            flush(&mut current, &mut spans, current_style(bold, italic));
+            italic = !italic;
+            i += 1;
+            continue;
+        }
+        current.push(c);
+        i += 1;
+    }
+    flush(&mut current, &mut spans, current_style(bold, italic));
+    spans
+}
+
+/// Word-wraps styled spans to `width`, starting the first row with `first`
+/// and the following ones with `rest`.
+fn wrap(
+    spans: Vec<(String, ContentStyle)>,
+    width: usize,
+    first: &str,
+    rest: &str,
+) -> Vec<Vec<(String, ContentStyle)>> {
+    // Deep indents keep at least one column for text.
+    let prefix_width = width.saturating_sub(1);
+    let first: String = first.chars().take(prefix_width).collect();
+    let rest: String = rest.chars().take(prefix_width).collect();
+    let rest_width = rest.chars().count();
+    let mut used = first.chars().count();
+    let mut rows = vec![vec![(first, ContentStyle::default())]];
+    let word_length = |piece: &[char]| piece.iter().rev().skip_while(|&&c| c == ' ').count();
+
+    for (text, style) in spans {
+        for piece in text.split_inclusive(' ') {
+            let mut piece: Vec<char> = piece.chars().collect();
+            if used + word_length(&piece) > width && used > rest_width {
+                rows.push(vec![(rest.to_string(), ContentStyle::default())]);
+                used = rest_width;
+            }
+            // A word longer than a whole row is split.
+            while !piece.is_empty() && used + word_length(&piece) > width {
+                let room = width.saturating_sub(used).max(1);
+                let head: String = piece.drain(..room.min(piece.len())).collect();
+                if let Some(row) = rows.last_mut() {
+                    row.push((head, style));
+                }
+                rows.push(vec![(rest.to_string(), ContentStyle::default())]);
+                used = rest_width;
+            }
+            if !piece.is_empty() {
+                used += piece.len();
+                if let Some(row) = rows.last_mut() {
+                    row.push((piece.into_iter().collect(), style));
+                }
+            }
+        }
+    }
+    rows
+}
+
+fn is_table_row(line: &[char]) -> bool {
+    line.iter().find(|c| !c.is_whitespace()) == Some(&'|')
+}
+
+/// First and last line of the pipe table around line `y`, if any.
+pub fn table_bounds(lines: &[Vec<char>], y: usize) -> Option<(usize, usize)> {
+    if !lines.get(y).is_some_and(|line| is_table_row(line)) {
+        return None;
+    }
+    let start = (0..y)
+        .rev()
+        .take_while(|&i| is_table_row(&lines[i]))
+        .last()
+        .unwrap_or(y);
+    let end = (y + 1..lines.len())
+        .take_while(|&i| is_table_row(&lines[i]))
+        .last()
+        .unwrap_or(y);
+    Some((start, end))
+}
+
+/// Positions of the pipes that delimit cells; `\|` is text.
+fn pipe_positions(line: &[char]) -> Vec<usize> {
+    let mut positions = Vec::new();
+    let mut escaped = false;
+    for (i, &c) in line.iter().enumerate() {
+        if c == '|' && !escaped {
+            positions.push(i);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    positions
+}
+
+fn cells(line: &[char]) -> Vec<String> {
+    let pipes = pipe_positions(line);
+    let mut cells: Vec<String> = pipes
+        .windows(2)
+        .map(|pair| {
+            line[pair[0] + 1..pair[1]]
+                .iter()
+                .collect::<String>()
+                .trim()
+                .to_string()
+        })
+        .collect();
+    // The closing pipe is optional.
+    if let Some(&last) = pipes.last() {
+        let rest: String = line[last + 1..].iter().collect();
+        if !rest.trim().is_empty() {
+            cells.push(rest.trim().to_string());
+        }
+    }
+    cells
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Align {
+    Default,
+    Left,
+    Center,
+    Right,
+}
+
+fn separator_align(cell: &str) -> Option<Align> {
+    let inner = cell.trim_start_matches(':').trim_end_matches(':');
+    if inner.is_empty() || !inner.chars().all(|c| c == '-') {
+        return None;
+    }
+    Some(match (cell.starts_with(':'), cell.ends_with(':')) {
+        (true, true) => Align::Center,
+        (true, false) => Align::Left,
+        (false, true) => Align::Right,
+        (false, false) => Align::Default,
+    })
+}
+
+pub fn is_separator_row(line: &[char]) -> bool {
+    let cells = cells(line);
+    !cells.is_empty() && cells.iter().all(|cell| separator_align(cell).is_some())
+}
+
+/// Pads every cell to its column's width, honoring the alignment row, and
+/// rewrites the separators to match.
+pub fn format_table(rows: &[Vec<char>]) -> Vec<Vec<char>> {
+    let indent: String = rows
+        .first()
+        .map(|row| row.iter().take_while(|c| c.is_whitespace()).collect())
+        .unwrap_or_default();
+    let parsed: Vec<Vec<String>> = rows.iter().map(|row| cells(row)).collect();
+    let separator: Vec<bool> = rows.iter().map(|row| is_separator_row(row)).collect();
+    let columns = parsed.iter().map(Vec::len).max().unwrap_or(0);
+
+    let mut aligns = vec![Align::Default; columns];
+    if let Some(row) = separator.iter().position(|&is| is) {
+        for (column, cell) in parsed[row].iter().enumerate() {
+            aligns[column] = separator_align(cell).unwrap_or(Align::Default);
+        }
+    }
+    let mut widths = vec![3; columns];
+    for (row, cells) in parsed.iter().enumerate() {
+        if separator[row] {
+            continue;
+        }
+        for (column, cell) in cells.iter().enumerate() {
+            widths[column] = widths[column].max(cell.chars().count());
+        }
+    }
+
+    parsed
+        .iter()
+        .enumerate()
+        .map(|(row, cells)| {
+            let formatted: Vec<String> = (0..columns)
+                .map(|column| {
+                    let width = widths[column];
+                    if separator[row] {
+                        return match aligns[column] {
+                            Align::Default => "-".repeat(width),
+                            Align::Left => format!(":{}", "-".repeat(width - 1)),
+                            Align::Right => format!("{}:", "-".repeat(width - 1)),
+                            Align::Center => format!(":{}:", "-".repeat(width - 2)),
+                        };
+                    }
+                    let cell = cells.get(column).map(String::as_str).unwrap_or("");
+                    let padding = width - cell.chars().count();
+                    match aligns[column] {
+                        Align::Right => format!("{}{}", " ".repeat(padding), cell),
+                        Align::Center => format!(
+                            "{}{}{}",
+                            " ".repeat(padding / 2),
+                            cell,
+                            " ".repeat(padding - padding / 2)
+                        ),
+                        Align::Default | Align::Left => format!("{}{}", cell, " ".repeat(padding)),
+                    }
+                })
+                .collect();
+            format!("{}| {} |", indent, formatted.join(" | "))
+                .chars()
+                .collect()
+        })
+        .collect()
+}
+
+pub fn cell_count(line: &[char]) -> usize {
+    cells(line).len()
+}
+
+/// Index of the cell that column `x` falls in.
+pub fn cell_at(line: &[char], x: usize) -> usize {
+    pipe_positions(line)
+        .iter()
+        .filter(|&&pipe| pipe < x)
+        .count()
+        .saturating_sub(1)
+}
+
+/// Where the text of cell `cell` starts, past its pipe and padding. Empty
+/// cells put the cursor one space in.
+pub fn cell_start(line: &[char], cell: usize) -> Option<usize> {
+    let pipes = pipe_positions(line);
+    let pipe = *pipes.get(cell)?;
+    let end = pipes.get(cell + 1).copied().unwrap_or(line.len());
+    let text = (pipe + 1..end).find(|&i| !line[i].is_whitespace());
+    Some(text.unwrap_or(pipe + 2).min(line.len()))
+}
+
+/// A row of empty cells shaped like `row`.
+pub fn empty_row(row: &[char]) -> Vec<char> {
+    let pipes = pipe_positions(row);
+    row.iter()
+        .enumerate()
+        .map(|(i, &c)| {
+            if c.is_whitespace() || pipes.contains(&i) {
+                c
+            } else {
+                ' '
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capabilities::UNICODE_GLYPHS;
+
+    fn lines(text: &str) -> Vec<Vec<char>> {
+        text.lines().map(|line| line.chars().collect()).collect()
+    }
+
+    fn text(lines: &[Vec<char>]) -> Vec<String> {
+        lines.iter().map(|line| line.iter().collect()).collect()
+    }
+
+    fn rows(text: &str, width: usize) -> Vec<(usize, String)> {
+        render(&lines(text), width, &UNICODE_GLYPHS)
+            .into_iter()
+            .map(|row| {
+                let line = row.spans.iter().map(|(text, _)| text.as_str()).collect();
+                (row.source, line)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wrapped_rows_keep_their_source_line() {
+        assert_eq!(
+            rows("# Title\n- one two three\n", 10),
+            [
+                (0, "Title".to_string()),
+                (1, "• one two ".to_string()),
+                (1, "  three".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn inline_markup_is_styled_and_markers_dropped() {
+        let spans = inline("a **b** `c` [d](e) snake_case", ContentStyle::default());
+        let texts: Vec<&str> = spans.iter().map(|(text, _)| text.as_str()).collect();
+        assert_eq!(texts, ["a ", "b", " ", "c", " ", "d", " snake_case"]);
+        assert!(spans[1].1.attributes.has(Attribute::Bold));
+        assert_eq!(spans[3].1.foreground_color, Some(Color::DarkYellow));
+        assert!(spans[5].1.attributes.has(Attribute::Underlined));
+    }
+
+    #[test]
+    fn headings_skip_fenced_code() {
+        let found = headings(&lines("# One\n```\n# not\n```\n### Three ##"));
+        let found: Vec<_> = found
+            .iter()
+            .map(|h| (h.y, h.level, h.text.as_str()))
+            .collect();
+        assert_eq!(found, [(0, 1, "One"), (4, 3, "Three")]);
+    }
+
+    #[test]
+    fn tables_are_found_and_formatted() {
+        let doc = lines("text\n|a|bb|\n|:-|-:|\n|ccc|d|\nafter");
+        assert_eq!(table_bounds(&doc, 2), Some((1, 3)));
+        assert_eq!(table_bounds(&doc, 0), None);
+        assert!(is_separator_row(&doc[2]));
+        assert_eq!(
+            text(&format_table(&doc[1..4])),
+            ["| a   |  bb |", "| :-- | --: |", "| ccc |   d |"]
+        );
+    }
+
+    #[test]
+    fn cells_are_addressed_by_pipe() {
+        let row: Vec<char> = "| a | b \\| c |   |".chars().collect();
+        assert_eq!(cell_count(&row), 3);
+        assert_eq!(cell_at(&row, 2), 0);
+        assert_eq!(cell_at(&row, 6), 1);
+        assert_eq!(cell_start(&row, 1), Some(6));
+        assert_eq!(cell_start(&row, 2), Some(15));
+        assert_eq!(text(&[empty_row(&row)]), ["|   |        |   |"]);
+    }
+}
@@ -0,0 +1,272 @@
+use std::path::Path;
+
+/// Lines longer than this are drawn without syntax highlighting, and a file
+/// holding one is treated as minified.
+pub const LONG_LINE: usize = 20_000;
+
+const INDENT: &str = "  ";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    Json,
+    /// JavaScript and CSS: statements end in `;` and blocks are braced.
+    Script,
+}
+
+pub fn kind(path: &Path) -> Option<Kind> {
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    match ext.as_str() {
+        "json" | "map" | "webmanifest" => Some(Kind::Json),
+        "js" | "mjs" | "cjs" | "css" => Some(Kind::Script),
+        _ => None,
+    }
+}
+
+/// Length of the longest line, if it is long enough to call the file
+/// minified.
+pub fn longest_line(lines: &[Vec<char>]) -> Option<usize> {
+    lines
+        .iter()
+        .map(Vec::len)
+        .max()
+        .filter(|&len| len > LONG_LINE)
+}
+
+fn newline(out: &mut String, depth: usize) {
+    let trimmed = out.trim_end_matches(' ').len();
+    out.truncate(trimmed);
+    out.push('\n');
+    for _ in 0..depth {
+        out.push_str(INDENT);
+    }
+}
+
+// Copies a quoted string starting at `chars[i]` and returns the index past
+// its closing quote.
+fn copy_string(chars: &[char], mut i: usize, out: &mut String) -> usize {
+    let quote = chars[i];
+    out.push(quote);
+    i += 1;
+    while i < chars.len() {
+        let c = chars[i];
+        out.push(c);
+        i += 1;
+        if c == '\\' && i < chars.len() {
+            out.push(chars[i]);
+            i += 1;
+        } else if c == quote {
+            break;
+        }
+    }
+    i
+}
+
+fn pretty_json(chars: &[char]) -> String {
+    let mut out = String::with_capacity(chars.len() * 2);
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '"' => {
+                i = copy_string(chars, i, &mut out);
+                continue;
+            }
+            '{' | '[' => {
+                let close = if c == '{' { '}' } else { ']' };
+                let next = chars[i + 1..].iter().position(|c| !c.is_whitespace());
+                if next.is_some_and(|n| chars[i + 1 + n] == close) {
+                    out.push(c);
+                    out.push(close);
+                    i += next.unwrap() + 2;
+                    continue;
+                }
+                out.push(c);
+                depth += 1;
+                newline(&mut out, depth);
+            }
+            '}' | ']' => {
+                depth = depth.saturating_sub(1);
+                newline(&mut out, depth);
+                out.push(c);
+            }
+            ',' => {
+                out.push(c);
+                newline(&mut out, depth);
+            }
+            ':' => out.push_str(": "),
+            c if c.is_whitespace() => {}
+            c => out.push(c),
+        }
+        i += 1;
+    }
+    out
+}
+
+// A `/` starts a regular expression rather than a division when it follows
+// an operator or opening bracket.
+fn starts_regex(out: &str) -> bool {
+    out.trim_end()
+        .chars()
+        .last()
+        .is_none_or(|c| "(,=:[!&|?{};+-*%<>~^".contains(c))
+}
+
+fn copy_regex(chars: &[char], mut i: usize, out: &mut String) -> usize {
+    let mut in_class = false;
+    out.push(chars[i]);
+    i += 1;
+    while i < chars.len() {
+        let c = chars[i];
+        out.push(c);
+        i += 1;
+        match c {
+            '\\' if i < chars.len() => {
+                out.push(chars[i]);
+                i += 1;
+            }
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '/' if !in_class => break,
+            _ => {}
+        }
+    }
+    i
+}
+
+fn pretty_script(chars: &[char]) -> String {
+    let mut out = String::with_capacity(chars.len() * 2);
+    let mut depth = 0usize;
+    // `;` inside `for (...)` does not end a statement.
+    let mut parens = 0usize;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '"' | '\'' | '`' => {
+                i = copy_string(chars, i, &mut out);
+                continue;
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                let end = (i + 2..chars.len().saturating_sub(1))
+                    .find(|&j| chars[j] == '*' && chars[j + 1] == '/')
+                    .map_or(chars.len(), |j| j + 2);
+                out.extend(&chars[i..end]);
+                i = end;
+                continue;
+            }
+            '/' if chars.get(i + 1) == Some(&'/') => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|&c| c == '\n')
+                    .map_or(chars.len(), |n| i + n);
+                out.extend(&chars[i..end]);
+                newline(&mut out, depth);
+                i = end;
+                continue;
+            }
+            '/' if starts_regex(&out) => {
+                i = copy_regex(chars, i, &mut out);
+                continue;
+            }
+            '(' => {
+                parens += 1;
+                out.push(c);
+            }
+            ')' => {
+                parens = parens.saturating_sub(1);
+                out.push(c);
+            }
+            '{' => {
+                if !out.ends_with([' ', '\n', '(']) && !out.is_empty() {
+                    out.push(' ');
+                }
+                out.push(c);
+                depth += 1;
+                newline(&mut out, depth);
+            }
+            '}' => {
+                depth = depth.saturating_sub(1);
+                newline(&mut out, depth);
+                out.push(c);
+                let rest: String = chars[i + 1..]
+                    .iter()
+                    .skip_while(|c| c.is_whitespace())
+                    .take(8)
+                    .collect();
+                if ["else", "catch", "finally", "while"]
+                    .iter()
+                    .any(|word| rest.starts_with(word))
+                {
+                    out.push(' ');
+                } else if !rest.starts_with([';', ',', ')', '.', ']']) {
+                    newline(&mut out, depth);
+                }
+            }
+            ';' if parens == 0 => {
+                out.push(c);
+                newline(&mut out, depth);
+            }
+            c if c.is_whitespace() => {
+                if !out.ends_with([' ', '\n']) && !out.is_empty() {
+                    out.push(' ');
+                }
+            }
+            c => out.push(c),
+        }
+        i += 1;
+    }
+    out.lines()
+        .map(str::trim_end)
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Lays the text out one statement or value per line.
+pub fn pretty_print(text: &str, kind: Kind) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    match kind {
+        Kind::Json => pretty_json(&chars),
+        Kind::Script => pretty_script(&chars),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_come_from_the_extension() {
+        assert!(kind(Path::new("data.JSON")) == Some(Kind::Json));
+        assert!(kind(Path::new("app.min.js")) == Some(Kind::Script));
+        assert!(kind(Path::new("style.css")) == Some(Kind::Script));
+        assert!(kind(Path::new("main.rs")).is_none());
+    }
+
+    #[test]
+    fn only_very_long_lines_count_as_minified() {
+        let short = vec![vec!['x'; 80]];
+        let long = vec![vec!['x'; 10], vec!['x'; LONG_LINE + 1]];
+        assert_eq!(longest_line(&short), None);
+        assert_eq!(longest_line(&long), Some(LONG_LINE + 1));
+    }
+
+    #[test]
+    fn json_is_indented_and_strings_are_kept() {
+        let text = r#"{"a":[1,2],"b":{},"c":"x,{y}"}"#;
+        assert_eq!(
+            pretty_print(text, Kind::Json),
+            "{\n  \"a\": [\n    1,\n    2\n  ],\n  \"b\": {},\n  \"c\": \"x,{y}\"\n}"
+        );
+    }
+
+    #[test]
+    fn scripts_break_after_statements_and_braces() {
+        let text = "function f(a){if(a){return 1;}else{return 2;}}var r=/;}/g;";
+        assert_eq!(
+            pretty_print(text, Kind::Script),
+            "function f(a) {\n  if(a) {\n    return 1;\n  } else {\n    return 2;\n  }\n}\nvar r=/;}/g;"
+        );
+    }
+}
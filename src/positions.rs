@@ -0,0 +1,75 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+// Files beyond this many, least recently left first, are forgotten.
+const MAX_FILES: usize = 1000;
+
+/// Where the cursor and view were when a file was last left.
+#[derive(Clone, Copy)]
+pub struct Position {
+    pub y: usize,
+    pub x: usize,
+    pub scroll_y: usize,
+}
+
+fn positions_file() -> PathBuf {
+    crate::cache_dir().join("positions")
+}
+
+// One file per line, most recent first: `<y> <x> <scroll_y>\t<path>`.
+fn read_all() -> Vec<(PathBuf, Position)> {
+    let Ok(contents) = fs::read_to_string(positions_file()) else {
+        return Vec::new();
+    };
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (numbers, path) = line.split_once('\t')?;
+            let mut numbers = numbers.split(' ').map(|n| n.parse::<usize>().ok());
+            let position = Position {
+                y: numbers.next()??,
+                x: numbers.next()??,
+                scroll_y: numbers.next()??,
+            };
+            Some((PathBuf::from(path), position))
+        })
+        .collect()
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+pub fn load(path: &Path) -> Option<Position> {
+    let path = canonical(path);
+    read_all()
+        .into_iter()
+        .find(|(entry, _)| *entry == path)
+        .map(|(_, position)| position)
+}
+
+pub fn remember(path: &Path, position: Position) -> io::Result<()> {
+    let path = canonical(path);
+    let mut entries = read_all();
+    entries.retain(|(entry, _)| *entry != path);
+    entries.insert(0, (path, position));
+    entries.truncate(MAX_FILES);
+
+    let mut contents = String::new();
+    for (path, position) in &entries {
+        contents.push_str(&format!(
+            "{} {} {}\t{}\n",
+            position.y,
+            position.x,
+            position.scroll_y,
+            path.to_string_lossy()
+        ));
+    }
+    let file = positions_file();
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(file, contents)
+}
@@ -0,0 +1,250 @@
+use std::path::Path;
+
+const PROSE_EXTENSIONS: &[&str] = &["md", "markdown", "txt", "text", "rst", "adoc"];
+
+const DICTIONARY: &str = include_str!("../assets/dictionary/words.txt");
+
+pub fn is_prose_path(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| PROSE_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Bundled lowercase English word list, one word per line.
+pub fn dictionary_words() -> impl Iterator<Item = &'static str> {
+    DICTIONARY.lines().filter(|word| !word.is_empty())
+}
+
+/// Matches the capitalization of what was typed so far: `Th` completes to
+/// `There`, `TH` to `THERE`.
+pub fn match_case(word: &str, typed: &str) -> String {
+    let mut typed_chars = typed.chars().filter(|c| c.is_alphabetic());
+    match (typed_chars.next(), typed_chars.next()) {
+        (Some(first), Some(second)) if first.is_uppercase() && second.is_uppercase() => {
+            word.to_uppercase()
+        }
+        (Some(first), _) if first.is_uppercase() => {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|c| c.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        }
+        _ => word.to_string(),
+    }
+}
+
+// A typical silent reading speed for prose.
+const WORDS_PER_MINUTE: usize = 230;
+
+pub fn word_count(lines: &[Vec<char>]) -> usize {
+    lines
+        .iter()
+        .map(|line| {
+            line.split(|c| c.is_whitespace())
+                .filter(|word| word.iter().any(|c| c.is_alphanumeric()))
+                .count()
+        })
+        .sum()
+}
+
+/// Whole minutes, rounded up; anything at all takes at least one.
+pub fn reading_minutes(words: usize) -> usize {
+    words.div_ceil(WORDS_PER_MINUTE)
+}
+
+fn quote_prefix_len(line: &[char]) -> usize {
+    line.iter()
+        .take_while(|&&c| c.is_whitespace() || c == '>')
+        .count()
+}
+
+// `- `, `* `, `+ `, `1. ` or `1) ` at the start of `text`.
+fn list_marker_len(text: &[char]) -> usize {
+    if matches!(text, ['-' | '*' | '+', ' ', ..]) {
+        return 2;
+    }
+    let digits = text.iter().take_while(|c| c.is_ascii_digit()).count();
+    match text.get(digits..digits + 2) {
+        Some(['.' | ')', ' ']) if digits > 0 => digits + 2,
+        _ => 0,
+    }
+}
+
+/// The prefix a paragraph's lines share: its indentation plus any `>`
+/// quote markers. A list marker on the first line becomes a hanging indent
+/// on the rest.
+fn paragraph_prefix(first: &[char]) -> (usize, Vec<char>) {
+    let len = quote_prefix_len(first);
+    let marker = list_marker_len(&first[len..]);
+    let mut rest = Vec::from(&first[..len]);
+    rest.extend(std::iter::repeat_n(' ', marker));
+    (len + marker, rest)
+}
+
+/// Spreads the gaps in `words` so the line is exactly `width` wide, giving
+/// the leftmost gaps the extra spaces.
+fn justify(words: &[&[char]], width: usize) -> Vec<char> {
+    let letters: usize = words.iter().map(|word| word.len()).sum();
+    let gaps = words.len() - 1;
+    let spaces = width.saturating_sub(letters).max(gaps);
+    let mut line = Vec::with_capacity(width);
+    for (i, word) in words.iter().enumerate() {
+        if i > 0 {
+            let extra = usize::from(i <= spaces % gaps);
+            line.extend(std::iter::repeat_n(' ', spaces / gaps + extra));
+        }
+        line.extend_from_slice(word);
+    }
+    line
+}
+
+/// Rewraps a paragraph to `width` columns, keeping the first line's
+/// prefix. With `justify`, every line but the last is padded to the full
+/// width.
+pub fn reflow(paragraph: &[Vec<char>], width: usize, justify_lines: bool) -> Vec<Vec<char>> {
+    let Some(first) = paragraph.first() else {
+        return Vec::new();
+    };
+    let (first_len, rest_prefix) = paragraph_prefix(first);
+    let first_prefix = &first[..first_len];
+
+    let mut words: Vec<&[char]> = first[first_len..]
+        .split(|c| c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .collect();
+    for line in &paragraph[1..] {
+        // Continuation lines carry their own copy of the prefix.
+        let skip = quote_prefix_len(line);
+        words.extend(
+            line[skip..]
+                .split(|c| c.is_whitespace())
+                .filter(|word| !word.is_empty()),
+        );
+    }
+
+    let mut rows: Vec<Vec<&[char]>> = vec![Vec::new()];
+    let mut used = 0;
+    for word in words {
+        let prefix = if rows.len() == 1 {
+            first_prefix.len()
+        } else {
+            rest_prefix.len()
+        };
+        let current = rows.last_mut().unwrap();
+        if !current.is_empty() && prefix + used + 1 + word.len() > width {
+            rows.push(vec![word]);
+            used = word.len();
+        } else {
+            used += word.len() + usize::from(!current.is_empty());
+            current.push(word);
+        }
+    }
+
+    let count = rows.len();
+    rows.iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let mut line = if i == 0 {
+                first_prefix.to_vec()
+            } else {
+                rest_prefix.clone()
+            };
+            let available = width.saturating_sub(line.len());
+            if justify_lines && i + 1 < count && row.len() > 1 {
+                line.extend(justify(row, available));
+            } else {
+                line.extend(row.join(&' '));
+            }
+            line
+        })
+        .collect()
+}
+
+fn starts_list_item(line: &[char]) -> bool {
+    list_marker_len(&line[quote_prefix_len(line)..]) > 0
+}
+
+// Lines that are Markdown structure rather than running text.
+fn is_block_line(line: &[char]) -> bool {
+    let text: String = line.iter().collect();
+    let text = text.trim();
+    text.is_empty()
+        || text.starts_with('#')
+        || text.starts_with('|')
+        || text.starts_with("```")
+        || text.starts_with("~~~")
+}
+
+/// The run of text lines around line `y`, bounded by blank lines,
+/// Markdown blocks and list items.
+pub fn paragraph_bounds(lines: &[Vec<char>], y: usize) -> Option<(usize, usize)> {
+    if is_block_line(lines.get(y)?) {
+        return None;
+    }
+    let mut start = y;
+    while start > 0 && !starts_list_item(&lines[start]) && !is_block_line(&lines[start - 1]) {
+        start -= 1;
+    }
+    let mut end = y;
+    while end + 1 < lines.len()
+        && !is_block_line(&lines[end + 1])
+        && !starts_list_item(&lines[end + 1])
+    {
+        end += 1;
+    }
+    Some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<Vec<char>> {
+        text.lines().map(|line| line.chars().collect()).collect()
+    }
+
+    fn text(lines: &[Vec<char>]) -> Vec<String> {
+        lines.iter().map(|line| line.iter().collect()).collect()
+    }
+
+    #[test]
+    fn completions_follow_the_typed_case() {
+        assert_eq!(match_case("there", "th"), "there");
+        assert_eq!(match_case("there", "Th"), "There");
+        assert_eq!(match_case("there", "TH"), "THERE");
+    }
+
+    #[test]
+    fn words_and_reading_time() {
+        assert_eq!(word_count(&lines("# Title\n\nOne two -- three.\n")), 4);
+        assert_eq!(reading_minutes(0), 0);
+        assert_eq!(reading_minutes(1), 1);
+        assert_eq!(reading_minutes(231), 2);
+    }
+
+    #[test]
+    fn reflow_keeps_quote_and_list_prefixes() {
+        let quoted = reflow(&lines("> one two three\n> four five"), 12, false);
+        assert_eq!(text(&quoted), ["> one two", "> three four", "> five"]);
+
+        let item = reflow(&lines("- alpha beta gamma delta"), 12, false);
+        assert_eq!(text(&item), ["- alpha beta", "  gamma", "  delta"]);
+    }
+
+    #[test]
+    fn justify_pads_all_but_the_last_line() {
+        let lines = reflow(&lines("a bb ccc dd e f"), 10, true);
+        assert_eq!(text(&lines), ["a  bb  ccc", "dd e f"]);
+    }
+
+    #[test]
+    fn paragraphs_stop_at_blank_lines_headings_and_list_items() {
+        let doc = lines("# Head\none\ntwo\n\n- item\n  more\n- next");
+        assert_eq!(paragraph_bounds(&doc, 0), None);
+        assert_eq!(paragraph_bounds(&doc, 2), Some((1, 2)));
+        assert_eq!(paragraph_bounds(&doc, 3), None);
+        assert_eq!(paragraph_bounds(&doc, 5), Some((4, 5)));
+        assert_eq!(paragraph_bounds(&doc, 6), Some((6, 6)));
+    }
+}
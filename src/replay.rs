@@ -0,0 +1,178 @@
+use crate::session;
+use crossterm::event::{Event, KeyCode, KeyModifiers};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    time::Instant,
+};
+
+const HEADER: &str =
+    "# termi input recording: <ms since start> <event>; text typed into files and the terminal is anonymized";
+
+/// Writes every key, mouse and resize event to a file as it happens, so a
+/// session that ends in a crash still leaves its input behind.
+pub struct Recorder {
+    file: File,
+    started: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Recorder> {
+        let mut file = File::create(path)?;
+        writeln!(file, "{}", HEADER)?;
+        Ok(Recorder {
+            file,
+            started: Instant::now(),
+        })
+    }
+
+    /// Writes `event`, with its text anonymized when `typed` says it goes
+    /// into the buffer.
+    pub fn record(&mut self, event: &Event, typed: bool) -> io::Result<()> {
+        let event = if typed {
+            anonymize(event.clone())
+        } else {
+            event.clone()
+        };
+        let Some(line) = session::encode_event(&event) else {
+            return Ok(());
+        };
+        writeln!(self.file, "{} {}", self.started.elapsed().as_millis(), line)?;
+        self.file.flush()
+    }
+}
+
+// Text typed into files and the integrated terminal becomes `x`, `X` and
+// `0` so a recording can be shared without what was written, passwords
+// typed at a shell prompt included. Punctuation and whitespace stay, since
+// brackets, quotes and indentation are what editing bugs tend to hinge on.
+// Shortcuts keep their letters, and so do prompts and dialogs, which the
+// replay needs to answer the same way.
+fn anonymize(event: Event) -> Event {
+    match event {
+        Event::Key(mut key)
+            if !key
+                .modifiers
+                .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+        {
+            if let KeyCode::Char(c) = key.code {
+                key.code = KeyCode::Char(if c.is_numeric() {
+                    '0'
+                } else if c.is_uppercase() {
+                    'X'
+                } else if c.is_alphabetic() {
+                    'x'
+                } else {
+                    c
+                });
+            }
+            Event::Key(key)
+        }
+        event => event,
+    }
+}
+
+/// The events of a recording, in order.
+pub fn load(path: &Path) -> io::Result<Vec<Event>> {
+    let contents = fs::read_to_string(path)?;
+    let mut events = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let event = line
+            .split_once(' ')
+            .and_then(|(_, event)| session::decode_event(event))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: not an event", path.to_string_lossy(), i + 1),
+                )
+            })?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+/// An empty cache folder for termi to use until this is dropped. Cursor
+/// positions, sessions and history left by earlier runs would otherwise make
+/// a replay go differently from its recording.
+pub struct FreshCache(PathBuf);
+
+impl FreshCache {
+    pub fn create() -> io::Result<FreshCache> {
+        let dir = std::env::temp_dir().join(format!("termi-replay-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        let _ = crate::CACHE_DIR_OVERRIDE.set(dir.clone());
+        Ok(FreshCache(dir))
+    }
+}
+
+impl Drop for FreshCache {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A copy of the tree a replay runs in, so the saves, renames and deletes
+/// it repeats change the copy rather than the user's files. The copy leaves
+/// out `.git`, so git commands find no repository either. Removed on drop.
+pub struct ScratchTree {
+    dir: PathBuf,
+    previous_dir: PathBuf,
+}
+
+impl ScratchTree {
+    /// Copies the folder `path` is in, or `path` itself when it is a
+    /// folder, and makes the copy the current directory. Returns the path
+    /// to open in place of `path`.
+    pub fn create(path: &Path) -> io::Result<(ScratchTree, PathBuf)> {
+        let (source, opened) = if path.is_dir() {
+            (path, PathBuf::from("."))
+        } else {
+            let name = path.file_name().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{}: not a file", path.to_string_lossy()),
+                )
+            })?;
+            let parent = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty());
+            (parent.unwrap_or(Path::new(".")), PathBuf::from(name))
+        };
+        let dir = std::env::temp_dir().join(format!("termi-replay-tree-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        copy_tree(source, &dir)?;
+        let previous_dir = std::env::current_dir()?;
+        std::env::set_current_dir(&dir)?;
+        let _ = crate::REPLAYING.set(());
+        Ok((ScratchTree { dir, previous_dir }, opened))
+    }
+}
+
+impl Drop for ScratchTree {
+    fn drop(&mut self) {
+        let _ = std::env::set_current_dir(&self.previous_dir);
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// Symbolic links are left out, since they may point outside the tree.
+fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let kind = entry.file_type()?;
+        let target = to.join(entry.file_name());
+        if kind.is_dir() && entry.file_name() != ".git" {
+            copy_tree(&entry.path(), &target)?;
+        } else if kind.is_file() {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
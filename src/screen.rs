@@ -0,0 +1,55 @@
+use crate::capabilities::{self, ColorSupport};
+use std::io::{self, Write};
+
+/// Double buffer for the terminal. `draw` writes escape sequences into
+/// `frame`, which are replayed onto an in-memory grid of cells; only the cells
+/// that differ from the last presented grid are sent to the real terminal.
+pub struct ScreenBuffer {
+    pub frame: Vec<u8>,
+    parser: vt100::Parser,
+    shown: Option<vt100::Screen>,
+    colors: ColorSupport,
+}
+
+impl ScreenBuffer {
+    pub fn new(rows: u16, cols: u16, colors: ColorSupport) -> Self {
+        Self {
+            frame: Vec::with_capacity(64 * 1024),
+            parser: vt100::Parser::new(rows, cols, 0),
+            shown: None,
+            colors,
+        }
+    }
+
+    /// Forgets what the terminal shows, so the next present repaints it all.
+    pub fn invalidate(&mut self) {
+        self.shown = None;
+    }
+
+    pub fn present(&mut self, out: &mut impl Write, rows: u16, cols: u16) -> io::Result<()> {
+        self.absorb(rows, cols);
+        let screen = self.parser.screen();
+        let bytes = match &self.shown {
+            Some(shown) => screen.contents_diff(shown),
+            None => screen.contents_formatted(),
+        };
+        out.write_all(&bytes)?;
+        out.flush()?;
+        self.shown = Some(screen.clone());
+        Ok(())
+    }
+
+    /// Applies `frame` to the grid without sending anything, so a later
+    /// `present` sends what changed since the terminal was last updated.
+    pub fn absorb(&mut self, rows: u16, cols: u16) {
+        if self.parser.screen().size() != (rows, cols) {
+            self.parser.screen_mut().set_size(rows, cols);
+            self.invalidate();
+        }
+
+        let frame = capabilities::downgrade_colors(&self.frame, self.colors);
+        self.parser.process(&frame);
+        drop(frame);
+        self.frame.clear();
+    }
+}
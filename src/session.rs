@@ -0,0 +1,495 @@
+use crate::event_loop::Waker;
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+// Wire format. The client opens with `<token> <cols> <rows>` and then sends
+// one input event per line. The server answers with frames of terminal
+// output, each prefixed by its length as 4 big-endian bytes; an empty frame
+// means the server is done with this client.
+
+// Far more than a full redraw of any real screen; a longer length prefix
+// means the stream is not a termi session.
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+pub fn session_file() -> PathBuf {
+    crate::cache_dir().join("session")
+}
+
+pub enum SessionEvent {
+    Attached {
+        id: usize,
+        cols: u16,
+        rows: u16,
+        stream: TcpStream,
+    },
+    Input {
+        id: usize,
+        event: Event,
+    },
+    Detached {
+        id: usize,
+    },
+}
+
+/// Accepts clients for a headless editor. The session file (port and token)
+/// is removed again when this is dropped.
+pub struct SessionServer {
+    port: u16,
+    contents: String,
+    rx: Receiver<SessionEvent>,
+}
+
+impl SessionServer {
+    pub fn start(waker: Waker) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let port = listener.local_addr()?.port();
+        let token = crate::instance::new_token();
+        let contents = format!("{} {}", port, token);
+        let file = session_file();
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        crate::instance::write_private(&file, &contents)?;
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for (id, stream) in listener.incoming().enumerate() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let (tx, waker, token) = (tx.clone(), waker.clone(), token.clone());
+                thread::spawn(move || serve_client(id, stream, &token, tx, waker));
+            }
+        });
+
+        Ok(Self { port, contents, rx })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn poll(&self) -> Vec<SessionEvent> {
+        self.rx.try_iter().collect()
+    }
+}
+
+impl Drop for SessionServer {
+    fn drop(&mut self) {
+        let file = session_file();
+        if fs::read_to_string(&file).is_ok_and(|contents| contents == self.contents) {
+            let _ = fs::remove_file(file);
+        }
+    }
+}
+
+fn serve_client(id: usize, stream: TcpStream, token: &str, tx: Sender<SessionEvent>, waker: Waker) {
+    let Ok(writer) = stream.try_clone() else {
+        return;
+    };
+    let mut lines = BufReader::new(stream).lines();
+    let Some(Ok(hello)) = lines.next() else {
+        return;
+    };
+    let mut fields = hello.split(' ');
+    if !fields
+        .next()
+        .is_some_and(|given| crate::instance::token_matches(given, token))
+    {
+        return;
+    }
+    let mut size = fields
+        .filter_map(|field| field.parse::<u16>().ok())
+        .filter(|&n| n > 0);
+    let (Some(cols), Some(rows)) = (size.next(), size.next()) else {
+        return;
+    };
+
+    let attached = SessionEvent::Attached {
+        id,
+        cols,
+        rows,
+        stream: writer,
+    };
+    if tx.send(attached).is_err() {
+        return;
+    }
+    waker.wake();
+    for line in lines.map_while(Result::ok) {
+        if let Some(event) = decode_event(&line) {
+            if tx.send(SessionEvent::Input { id, event }).is_err() {
+                return;
+            }
+            waker.wake();
+        }
+    }
+    let _ = tx.send(SessionEvent::Detached { id });
+    waker.wake();
+}
+
+fn send_frame(stream: &mut TcpStream, bytes: &[u8]) -> io::Result<()> {
+    stream.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    stream.write_all(bytes)
+}
+
+// How long a client may stall a single write before it is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends frames to one client from a thread of its own, so a slow
+/// connection never holds up the editor.
+pub struct FrameWriter {
+    tx: Sender<Vec<u8>>,
+    pending: Arc<AtomicUsize>,
+    failed: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl FrameWriter {
+    /// `waker` interrupts the main loop whenever a frame has gone out.
+    pub fn new(mut stream: TcpStream, waker: Waker) -> Self {
+        let (tx, rx) = mpsc::channel::<Vec<u8>>();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let failed = Arc::new(AtomicBool::new(false));
+        let (thread_pending, thread_failed) = (pending.clone(), failed.clone());
+        let thread = thread::spawn(move || {
+            let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+            for frame in rx {
+                if send_frame(&mut stream, &frame).is_err() {
+                    thread_failed.store(true, Ordering::Relaxed);
+                    break;
+                }
+                thread_pending.fetch_sub(1, Ordering::Relaxed);
+                waker.wake();
+                if frame.is_empty() {
+                    break;
+                }
+            }
+        });
+        Self {
+            tx,
+            pending,
+            failed,
+            thread,
+        }
+    }
+
+    /// Whether an earlier frame is still on its way.
+    pub fn busy(&self) -> bool {
+        self.pending.load(Ordering::Relaxed) > 0
+    }
+
+    /// Queues `frame`; false once the connection has failed.
+    pub fn send(&self, frame: Vec<u8>) -> bool {
+        if self.failed.load(Ordering::Relaxed) {
+            return false;
+        }
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        self.tx.send(frame).is_ok()
+    }
+
+    /// Tells the client the server is done with it. Joining the returned
+    /// thread waits until it has been told.
+    pub fn close(self) -> JoinHandle<()> {
+        self.send(Vec::new());
+        self.thread
+    }
+}
+
+/// Connects to a session at `addr` (or the one recorded in the session file)
+/// and returns the stream for input plus the frames the server sends.
+/// `TERMI_SESSION_TOKEN` supplies the token when attaching from elsewhere,
+/// e.g. through an SSH port forward.
+pub fn attach(
+    addr: Option<&str>,
+    cols: u16,
+    rows: u16,
+    waker: Waker,
+) -> io::Result<(TcpStream, Receiver<Vec<u8>>)> {
+    let recorded = fs::read_to_string(session_file()).ok();
+    let recorded = recorded
+        .as_deref()
+        .and_then(|contents| contents.trim().split_once(' '));
+    let token = match env::var("TERMI_SESSION_TOKEN") {
+        Ok(token) => token,
+        Err(_) => recorded
+            .map(|(_, token)| token.to_string())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no running session"))?,
+    };
+    let mut stream = match (addr, recorded) {
+        (Some(addr), _) => TcpStream::connect(addr)?,
+        (None, Some((port, _))) => {
+            let port = port.parse::<u16>().map_err(io::Error::other)?;
+            TcpStream::connect((Ipv4Addr::LOCALHOST, port))?
+        }
+        (None, None) => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no running session",
+            ))
+        }
+    };
+    writeln!(stream, "{} {} {}", token, cols, rows)?;
+
+    let mut reader = stream.try_clone()?;
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        loop {
+            let mut length = [0u8; 4];
+            if reader.read_exact(&mut length).is_err() {
+                break;
+            }
+            let length = u32::from_be_bytes(length) as usize;
+            if length > MAX_FRAME_LEN {
+                break;
+            }
+            let mut frame = vec![0u8; length];
+            if reader.read_exact(&mut frame).is_err() || frame.is_empty() {
+                break;
+            }
+            if tx.send(frame).is_err() {
+                return;
+            }
+            waker.wake();
+        }
+        let _ = tx.send(Vec::new());
+        waker.wake();
+    });
+    Ok((stream, rx))
+}
+
+fn encode_key_code(code: KeyCode) -> Option<String> {
+    let name = match code {
+        KeyCode::Char(c) => return Some(format!("c{}", c as u32)),
+        KeyCode::F(n) => return Some(format!("f{}", n)),
+        KeyCode::Enter => "enter",
+        KeyCode::Tab => "tab",
+        KeyCode::BackTab => "backtab",
+        KeyCode::Backspace => "backspace",
+        KeyCode::Esc => "esc",
+        KeyCode::Left => "left",
+        KeyCode::Right => "right",
+        KeyCode::Up => "up",
+        KeyCode::Down => "down",
+        KeyCode::Home => "home",
+        KeyCode::End => "end",
+        KeyCode::PageUp => "pageup",
+        KeyCode::PageDown => "pagedown",
+        KeyCode::Delete => "delete",
+        KeyCode::Insert => "insert",
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+fn decode_key_code(name: &str) -> Option<KeyCode> {
+    if let Some(code) = name.strip_prefix('c') {
+        return code
+            .parse()
+            .ok()
+            .and_then(char::from_u32)
+            .map(KeyCode::Char);
+    }
+    if let Some(n) = name.strip_prefix('f') {
+        return n.parse().ok().map(KeyCode::F);
+    }
+    Some(match name {
+        "enter" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "backtab" => KeyCode::BackTab,
+        "backspace" => KeyCode::Backspace,
+        "esc" => KeyCode::Esc,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "delete" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        _ => return None,
+    })
+}
+
+fn encode_mouse_kind(kind: MouseEventKind) -> String {
+    let button = |button: MouseButton| match button {
+        MouseButton::Left => "left",
+        MouseButton::Right => "right",
+        MouseButton::Middle => "middle",
+    };
+    match kind {
+        MouseEventKind::Down(b) => format!("down-{}", button(b)),
+        MouseEventKind::Up(b) => format!("up-{}", button(b)),
+        MouseEventKind::Drag(b) => format!("drag-{}", button(b)),
+        MouseEventKind::Moved => "moved".to_string(),
+        MouseEventKind::ScrollDown => "scrolldown".to_string(),
+        MouseEventKind::ScrollUp => "scrollup".to_string(),
+        MouseEventKind::ScrollLeft => "scrollleft".to_string(),
+        MouseEventKind::ScrollRight => "scrollright".to_string(),
+    }
+}
+
+fn decode_mouse_kind(name: &str) -> Option<MouseEventKind> {
+    let button = |name: &str| match name {
+        "left" => Some(MouseButton::Left),
+        "right" => Some(MouseButton::Right),
+        "middle" => Some(MouseButton::Middle),
+        _ => None,
+    };
+    if let Some((action, b)) = name.split_once('-') {
+        let b = button(b)?;
+        return match action {
+            "down" => Some(MouseEventKind::Down(b)),
+            "up" => Some(MouseEventKind::Up(b)),
+            "drag" => Some(MouseEventKind::Drag(b)),
+            _ => None,
+        };
+    }
+    Some(match name {
+        "moved" => MouseEventKind::Moved,
+        "scrolldown" => MouseEventKind::ScrollDown,
+        "scrollup" => MouseEventKind::ScrollUp,
+        "scrollleft" => MouseEventKind::ScrollLeft,
+        "scrollright" => MouseEventKind::ScrollRight,
+        _ => return None,
+    })
+}
+
+/// One line per event; events the editor ignores are not sent.
+pub fn encode_event(event: &Event) -> Option<String> {
+    match event {
+        Event::Key(key) if key.kind == KeyEventKind::Press => Some(format!(
+            "key {} {}",
+            encode_key_code(key.code)?,
+            key.modifiers.bits()
+        )),
+        Event::Mouse(mouse) => Some(format!(
+            "mouse {} {} {} {}",
+            encode_mouse_kind(mouse.kind),
+            mouse.column,
+            mouse.row,
+            mouse.modifiers.bits()
+        )),
+        Event::Resize(cols, rows) => Some(format!("resize {} {}", cols, rows)),
+        _ => None,
+    }
+}
+
+/// The event `encode_event` wrote as `line`. A resize to nothing is
+/// rejected, since the server sizes its frames to the smallest client.
+pub fn decode_event(line: &str) -> Option<Event> {
+    let fields: Vec<&str> = line.split(' ').collect();
+    let number = |i: usize| fields.get(i).and_then(|field| field.parse::<u16>().ok());
+    let modifiers = |i: usize| {
+        fields
+            .get(i)
+            .and_then(|field| field.parse::<u8>().ok())
+            .map(KeyModifiers::from_bits_truncate)
+    };
+    match *fields.first()? {
+        "key" => Some(Event::Key(KeyEvent::new(
+            decode_key_code(fields.get(1)?)?,
+            modifiers(2)?,
+        ))),
+        "mouse" => Some(Event::Mouse(MouseEvent {
+            kind: decode_mouse_kind(fields.get(1)?)?,
+            column: number(2)?,
+            row: number(3)?,
+            modifiers: modifiers(4)?,
+        })),
+        "resize" => {
+            let (cols, rows) = (number(1)?, number(2)?);
+            (cols > 0 && rows > 0).then_some(Event::Resize(cols, rows))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(event: Event) {
+        let line = encode_event(&event).unwrap();
+        assert_eq!(decode_event(&line), Some(event), "{}", line);
+    }
+
+    #[test]
+    fn events_survive_encoding() {
+        round_trip(Event::Key(KeyEvent::new(
+            KeyCode::Char('é'),
+            KeyModifiers::ALT,
+        )));
+        round_trip(Event::Key(KeyEvent::new(KeyCode::F(5), KeyModifiers::NONE)));
+        round_trip(Event::Key(KeyEvent::new(
+            KeyCode::BackTab,
+            KeyModifiers::SHIFT,
+        )));
+        round_trip(Event::Mouse(MouseEvent {
+            kind: MouseEventKind::Drag(MouseButton::Middle),
+            column: 12,
+            row: 3,
+            modifiers: KeyModifiers::CONTROL,
+        }));
+        round_trip(Event::Mouse(MouseEvent {
+            kind: MouseEventKind::ScrollUp,
+            column: 0,
+            row: 0,
+            modifiers: KeyModifiers::NONE,
+        }));
+        round_trip(Event::Resize(120, 40));
+    }
+
+    #[test]
+    fn releases_and_focus_are_not_sent() {
+        let release = KeyEvent::new_with_kind(
+            KeyCode::Char('a'),
+            KeyModifiers::NONE,
+            KeyEventKind::Release,
+        );
+        assert_eq!(encode_event(&Event::Key(release)), None);
+        assert_eq!(encode_event(&Event::FocusGained), None);
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        for line in [
+            "",
+            "key",
+            "key c97",
+            "key nope 0",
+            "mouse down-left 1",
+            "mouse sideways 1 1 0",
+            "resize 80",
+            "resize 0 0",
+            "resize 80 0",
+            "resize -1 24",
+            "paste hello",
+        ] {
+            assert_eq!(decode_event(line), None, "{}", line);
+        }
+    }
+
+    #[test]
+    fn tokens_must_match_exactly() {
+        assert!(crate::instance::token_matches("abc123", "abc123"));
+        assert!(!crate::instance::token_matches("abc124", "abc123"));
+        assert!(!crate::instance::token_matches("abc12", "abc123"));
+        assert!(!crate::instance::token_matches("", "abc123"));
+    }
+}
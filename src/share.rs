@@ -0,0 +1,115 @@
+use crate::config::ShareService;
+use serde_json::{json, Value};
+use std::{
+    env, fs, io,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const GIST_API: &str = "https://api.github.com/gists";
+
+/// A prepared upload: arguments for `curl`, plus the request files they
+/// point at. The files go away when the upload is dropped.
+pub struct Upload {
+    pub args: Vec<String>,
+    files: Vec<PathBuf>,
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        for file in &self.files {
+            let _ = fs::remove_file(file);
+        }
+    }
+}
+
+// Values in a curl config file are double-quoted with backslash escapes.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Writes the request for `service` to private files and returns the curl
+/// invocation that sends it. Everything, the token included, goes through a
+/// curl config file so nothing sensitive shows up in the process list.
+pub fn prepare(service: &ShareService, file_name: &str, contents: &str) -> io::Result<Upload> {
+    let dir = crate::cache_dir().join("share");
+    fs::create_dir_all(&dir)?;
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let payload = dir.join(format!("{}.body", stamp));
+    let config = dir.join(format!("{}.curlrc", stamp));
+
+    let mut lines = Vec::new();
+    match service {
+        ShareService::Gist { token, public } => {
+            let token = token
+                .clone()
+                .or_else(|| env::var("GITHUB_TOKEN").ok())
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "no GitHub token: set [share] token or GITHUB_TOKEN",
+                    )
+                })?;
+            let body = json!({
+                "description": format!("{} (shared from termi)", file_name),
+                "public": public,
+                "files": { file_name: { "content": contents } },
+            });
+            crate::instance::write_private(&payload, &body.to_string())?;
+            lines.push(format!("url = {}", quote(GIST_API)));
+            lines.push(format!(
+                "header = {}",
+                quote(&format!("Authorization: Bearer {}", token))
+            ));
+            lines.push(format!(
+                "header = {}",
+                quote("Accept: application/vnd.github+json")
+            ));
+        }
+        ShareService::Endpoint { url } => {
+            crate::instance::write_private(&payload, contents)?;
+            lines.push(format!("url = {}", quote(url)));
+        }
+    }
+    lines.push(format!(
+        "data-binary = {}",
+        quote(&format!("@{}", payload.to_string_lossy()))
+    ));
+    crate::instance::write_private(&config, &lines.join("\n"))?;
+
+    Ok(Upload {
+        args: vec![
+            "-sS".to_string(),
+            "--config".to_string(),
+            config.to_string_lossy().into_owned(),
+        ],
+        files: vec![payload, config],
+    })
+}
+
+/// Pulls the paste's URL out of the service's reply, or the reason it
+/// failed.
+pub fn paste_url(service: &ShareService, response: &str) -> Result<String, String> {
+    match service {
+        ShareService::Gist { .. } => {
+            let reply: Value = serde_json::from_str(response)
+                .map_err(|_| response.lines().next().unwrap_or("no response").to_string())?;
+            match reply.get("html_url").and_then(Value::as_str) {
+                Some(url) => Ok(url.to_string()),
+                None => Err(reply
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("unexpected reply from GitHub")
+                    .to_string()),
+            }
+        }
+        ShareService::Endpoint { .. } => response
+            .split_whitespace()
+            .find(|word| word.starts_with("http://") || word.starts_with("https://"))
+            .map(String::from)
+            .ok_or_else(|| response.lines().next().unwrap_or("no response").to_string()),
+    }
+}
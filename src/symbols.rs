@@ -0,0 +1,301 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+// Files with these extensions are indexed for functions and types;
+// Markdown files for their headings.
+const CODE_EXTENSIONS: &[&str] = &[
+    "rs", "js", "jsx", "mjs", "cjs", "ts", "tsx", "py", "pyw", "c", "h", "cpp", "cc", "cxx", "hpp",
+    "hh", "hxx", "java", "kt", "go", "cs", "swift", "rb", "php", "lua",
+];
+// Directories never indexed.
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target"];
+// The project walk stops after this many files.
+const MAX_FILES: usize = 20_000;
+// Bigger files are generated or data more often than not.
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+// Words that declare a type, followed by its name.
+const TYPE_KEYWORDS: &[&str] = &["struct", "enum", "trait", "class", "interface", "union"];
+// Words allowed before a type keyword.
+const MODIFIERS: &[&str] = &[
+    "pub",
+    "crate",
+    "super",
+    "export",
+    "default",
+    "public",
+    "private",
+    "protected",
+    "internal",
+    "abstract",
+    "final",
+    "static",
+    "sealed",
+    "data",
+    "open",
+    "partial",
+    "typedef",
+    "unsafe",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    Function,
+    Type,
+    Heading(usize),
+}
+
+#[derive(Clone)]
+pub struct Symbol {
+    pub y: usize,
+    pub kind: Kind,
+    pub name: String,
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// The name of the struct, enum, class or similar the line declares.
+fn type_name(line: &[char]) -> Option<String> {
+    let text: String = line.iter().collect();
+    let text = text.trim();
+    if text.starts_with("//") || text.starts_with('#') || text.ends_with(';') {
+        return None;
+    }
+    let mut words = text
+        .split(|c: char| !is_ident_char(c))
+        .filter(|word| !word.is_empty());
+    for word in words.by_ref() {
+        if TYPE_KEYWORDS.contains(&word) {
+            break;
+        }
+        if !MODIFIERS.contains(&word) {
+            return None;
+        }
+    }
+    // `enum class Name` in C++.
+    let name = words.find(|word| !TYPE_KEYWORDS.contains(word))?;
+    name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        .then(|| name.to_string())
+}
+
+pub fn is_indexed(path: &Path) -> bool {
+    crate::markdown::is_markdown_path(path)
+        || path.extension().is_some_and(|ext| {
+            CODE_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str())
+        })
+}
+
+/// Headings of a Markdown file; functions and types of anything else.
+pub fn extract(path: &Path, lines: &[Vec<char>]) -> Vec<Symbol> {
+    if crate::markdown::is_markdown_path(path) {
+        return crate::markdown::headings(lines)
+            .into_iter()
+            .map(|heading| Symbol {
+                y: heading.y,
+                kind: Kind::Heading(heading.level),
+                name: heading.text,
+            })
+            .collect();
+    }
+    lines
+        .iter()
+        .enumerate()
+        .filter_map(|(y, line)| {
+            let (kind, name) = match type_name(line) {
+                Some(name) => (Kind::Type, name),
+                None => (Kind::Function, crate::focus::function_name(line)?),
+            };
+            Some(Symbol { y, kind, name })
+        })
+        .collect()
+}
+
+fn modified(path: &Path) -> Option<u128> {
+    let time = fs::metadata(path).ok()?.modified().ok()?;
+    Some(time.duration_since(UNIX_EPOCH).ok()?.as_nanos())
+}
+
+fn cache_file() -> PathBuf {
+    crate::cache_dir().join("symbols")
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Symbols of every file seen, kept with the file's modification time so
+/// unchanged files are never read again.
+#[derive(Default)]
+pub struct Cache {
+    files: HashMap<PathBuf, (u128, Vec<Symbol>)>,
+    changed: bool,
+}
+
+impl Cache {
+    // A file's entry starts with `<mtime>\t<path>`; each of its symbols
+    // follows as `\t<line>\t<kind>\t<name>`, the kind being `f`, `t` or the
+    // heading level.
+    pub fn load() -> Cache {
+        let mut cache = Cache::default();
+        let Ok(contents) = fs::read_to_string(cache_file()) else {
+            return cache;
+        };
+        let mut current: Option<&mut Vec<Symbol>> = None;
+        for line in contents.lines() {
+            if let Some(entry) = line.strip_prefix('\t') {
+                let mut fields = entry.splitn(3, '\t');
+                let (Some(y), Some(kind), Some(name)) =
+                    (fields.next(), fields.next(), fields.next())
+                else {
+                    continue;
+                };
+                let kind = match kind {
+                    "f" => Kind::Function,
+                    "t" => Kind::Type,
+                    level => match level.parse() {
+                        Ok(level) => Kind::Heading(level),
+                        Err(_) => continue,
+                    },
+                };
+                if let (Some(symbols), Ok(y)) = (current.as_mut(), y.parse()) {
+                    symbols.push(Symbol {
+                        y,
+                        kind,
+                        name: name.to_string(),
+                    });
+                }
+            } else if let Some((mtime, path)) = line.split_once('\t') {
+                current = match mtime.parse() {
+                    Ok(mtime) => Some(
+                        &mut cache
+                            .files
+                            .entry(PathBuf::from(path))
+                            .or_insert((mtime, Vec::new()))
+                            .1,
+                    ),
+                    Err(_) => None,
+                };
+            }
+        }
+        cache
+    }
+
+    /// Writes the cache back if anything in it changed.
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        let mut contents = String::new();
+        for (path, (mtime, symbols)) in &self.files {
+            contents.push_str(&format!("{}\t{}\n", mtime, path.to_string_lossy()));
+            for symbol in symbols {
+                let kind = match symbol.kind {
+                    Kind::Function => "f".to_string(),
+                    Kind::Type => "t".to_string(),
+                    Kind::Heading(level) => level.to_string(),
+                };
+                contents.push_str(&format!("\t{}\t{}\t{}\n", symbol.y, kind, symbol.name));
+            }
+        }
+        let file = cache_file();
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(file, contents)?;
+        self.changed = false;
+        Ok(())
+    }
+
+    /// Records the symbols of a file that was just written from `lines`.
+    pub fn update(&mut self, path: &Path, lines: &[Vec<char>]) {
+        if !is_indexed(path) {
+            return;
+        }
+        let path = canonical(path);
+        if let Some(mtime) = modified(&path) {
+            let symbols = extract(&path, lines);
+            self.files.insert(path, (mtime, symbols));
+            self.changed = true;
+        }
+    }
+
+    /// Symbols of a file on disk, read again only when it changed since
+    /// they were cached.
+    fn file(&mut self, path: &Path, mtime: u128) -> &[Symbol] {
+        let stale = self
+            .files
+            .get(path)
+            .is_none_or(|(cached, _)| *cached != mtime);
+        if stale {
+            let symbols = fs::read_to_string(path)
+                .map(|text| {
+                    let lines: Vec<Vec<char>> = text.lines().map(|l| l.chars().collect()).collect();
+                    extract(path, &lines)
+                })
+                .unwrap_or_default();
+            self.files.insert(path.to_path_buf(), (mtime, symbols));
+            self.changed = true;
+        }
+        &self.files[path].1
+    }
+
+    /// Every symbol in the project under `root`. Entries for files that are
+    /// gone from it are dropped on the way.
+    pub fn project(&mut self, root: &Path) -> Vec<(PathBuf, Symbol)> {
+        let root = canonical(root);
+        let mut files = Vec::new();
+        collect_files(&root, &mut files);
+
+        let mut symbols = Vec::new();
+        let mut seen = HashSet::new();
+        for (path, mtime) in files {
+            for symbol in self.file(&path, mtime) {
+                symbols.push((path.clone(), symbol.clone()));
+            }
+            seen.insert(path);
+        }
+        let before = self.files.len();
+        self.files
+            .retain(|path, _| !path.starts_with(&root) || seen.contains(path));
+        self.changed |= self.files.len() != before;
+        symbols
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<(PathBuf, u128)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if files.len() >= MAX_FILES {
+            return;
+        }
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Ok(kind) = entry.file_type() else {
+            continue;
+        };
+        if kind.is_dir() {
+            if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_str()) {
+                collect_files(&path, files);
+            }
+        } else if is_indexed(&path) {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let mtime = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+            if let Some(mtime) = mtime.filter(|_| metadata.len() <= MAX_FILE_SIZE) {
+                files.push((path, mtime.as_nanos()));
+            }
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std::path::Path;
+
+const MARKUP_EXTENSIONS: &[&str] = &[
+    "html", "htm", "xhtml", "xml", "svg", "vue", "svelte", "jsx", "tsx",
+];
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+// A tag's end is only searched for this many lines past its start.
+const MAX_TAG_LINES: usize = 50;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct TagSpan {
+    pub y: usize,
+    pub name_start: usize,
+    pub name_end: usize,
+}
+
+struct Tag {
+    name: String,
+    span: TagSpan,
+    start: (usize, usize),
+    end: (usize, usize),
+    closing: bool,
+    self_closing: bool,
+}
+
+pub fn is_markup_path(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| MARKUP_EXTENSIONS.contains(&ext.as_str()))
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.')
+}
+
+fn is_void(name: &str) -> bool {
+    VOID_ELEMENTS
+        .iter()
+        .any(|void| void.eq_ignore_ascii_case(name))
+}
+
+// `a < b` and `Vec<T>` are not tags: an opening `<` must not directly follow
+// an identifier or a closing bracket.
+fn can_open_tag(line: &[char], x: usize) -> bool {
+    let previous = line[..x].iter().rev().find(|c| !c.is_whitespace());
+    !previous.is_some_and(|&c| c.is_alphanumeric() || matches!(c, '_' | ')' | ']'))
+}
+
+fn find_tag_end(buffer: &[Vec<char>], y: usize, x: usize) -> Option<((usize, usize), bool)> {
+    let mut quote: Option<char> = None;
+    let mut braces = 0usize;
+    let mut previous = ' ';
+    for (cy, line) in buffer.iter().enumerate().skip(y).take(MAX_TAG_LINES) {
+        let from = if cy == y { x } else { 0 };
+        for (cx, &c) in line.iter().enumerate().skip(from) {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"') | (None, '\'') => quote = Some(c),
+                (None, '{') => braces += 1,
+                (None, '}') => braces = braces.saturating_sub(1),
+                (None, '<') if braces == 0 => return None,
+                (None, '>') if braces == 0 => return Some(((cy, cx), previous == '/')),
+                _ => {}
+            }
+            previous = c;
+        }
+    }
+    None
+}
+
+fn scan_tags(buffer: &[Vec<char>]) -> Vec<Tag> {
+    let mut tags = Vec::new();
+    let mut y = 0;
+    let mut x = 0;
+
+    while y < buffer.len() {
+        let line = &buffer[y];
+        if x >= line.len() {
+            y += 1;
+            x = 0;
+            continue;
+        }
+        if line[x] != '<' {
+            x += 1;
+            continue;
+        }
+
+        if line[x..].starts_with(&['<', '!', '-', '-']) {
+            // Skip the whole comment, which may span lines.
+            let mut found = false;
+            let (mut cy, mut cx) = (y, x + 4);
+            while cy < buffer.len() {
+                let comment_line = &buffer[cy];
+                while cx + 3 <= comment_line.len() {
+                    if comment_line[cx..].starts_with(&['-', '-', '>']) {
+                        found = true;
+                        break;
+                    }
+                    cx += 1;
+                }
+                if found {
+                    break;
+                }
+                cy += 1;
+                cx = 0;
+            }
+            if !found {
+                break;
+            }
+            y = cy;
+            x = cx + 3;
+            continue;
+        }
+
+        let closing = line.get(x + 1) == Some(&'/');
+        let name_start = if closing { x + 2 } else { x + 1 };
+        let starts_name = line.get(name_start).is_some_and(|c| c.is_alphabetic());
+        if !starts_name || (!closing && !can_open_tag(line, x)) {
+            x += 1;
+            continue;
+        }
+
+        let mut name_end = name_start;
+        while name_end < line.len() && is_name_char(line[name_end]) {
+            name_end += 1;
+        }
+        let name: String = line[name_start..name_end].iter().collect();
+        match find_tag_end(buffer, y, name_end) {
+            Some((end, self_closing)) => {
+                tags.push(Tag {
+                    self_closing: self_closing || (!closing && is_void(&name)),
+                    name,
+                    span: TagSpan {
+                        y,
+                        name_start,
+                        name_end,
+                    },
+                    start: (y, x),
+                    end,
+                    closing,
+                });
+                y = end.0;
+                x = end.1 + 1;
+            }
+            None => x += 1,
+        }
+    }
+
+    tags
+}
+
+/// The tags of a buffer, scanned once per edit rather than on every cursor
+/// move.
+pub struct TagIndex {
+    tags: Vec<Tag>,
+}
+
+impl TagIndex {
+    pub fn scan(buffer: &[Vec<char>]) -> Self {
+        Self {
+            tags: scan_tags(buffer),
+        }
+    }
+
+    /// Finds the tag under (or just before) the cursor and its counterpart,
+    /// returning the name spans of both.
+    pub fn find_matching(&self, y: usize, x: usize) -> Option<(TagSpan, TagSpan)> {
+        let tags = &self.tags;
+        let index = tags
+            .iter()
+            .position(|tag| tag.start <= (y, x) && (y, x) <= (tag.end.0, tag.end.1 + 1))?;
+        let tag = &tags[index];
+        if tag.self_closing {
+            return None;
+        }
+
+        let mut depth = 0usize;
+        let same_name = |other: &Tag| other.name == tag.name && !other.self_closing;
+        if tag.closing {
+            for other in tags[..index].iter().rev().filter(|other| same_name(other)) {
+                if !other.closing {
+                    if depth == 0 {
+                        return Some((tag.span, other.span));
+                    }
+                    depth -= 1;
+                } else {
+                    depth += 1;
+                }
+            }
+        } else {
+            for other in tags[index + 1..].iter().filter(|other| same_name(other)) {
+                if other.closing {
+                    if depth == 0 {
+                        return Some((tag.span, other.span));
+                    }
+                    depth -= 1;
+                } else {
+                    depth += 1;
+                }
+            }
+        }
+        None
+    }
+}
+
+/// When `>` has just been typed at `cursor_x - 1`, returns the closing tag to
+/// insert after the cursor, if that `>` completes an opening tag on this line.
+pub fn closing_tag_for(line: &[char], cursor_x: usize) -> Option<String> {
+    if cursor_x < 2 || line.get(cursor_x - 1) != Some(&'>') || line[cursor_x - 2] == '/' {
+        return None;
+    }
+    let open = line[..cursor_x - 1].iter().rposition(|&c| c == '<')?;
+    let name_start = open + 1;
+    if !line.get(name_start).is_some_and(|c| c.is_alphabetic()) || !can_open_tag(line, open) {
+        return None;
+    }
+
+    let inner = &line[name_start..cursor_x - 1];
+    let quotes = inner.iter().filter(|&&c| c == '"').count();
+    let single_quotes = inner.iter().filter(|&&c| c == '\'').count();
+    let opened_braces = inner.iter().filter(|&&c| c == '{').count();
+    let closed_braces = inner.iter().filter(|&&c| c == '}').count();
+    if quotes % 2 != 0 || single_quotes % 2 != 0 || opened_braces != closed_braces {
+        return None;
+    }
+
+    let name: String = inner.iter().take_while(|&&c| is_name_char(c)).collect();
+    if name.is_empty() || is_void(&name) {
+        return None;
+    }
+    let closing = format!("</{}>", name);
+    let rest: String = line[cursor_x..].iter().collect();
+    if rest.trim_start().starts_with(&closing) {
+        return None;
+    }
+    Some(closing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<Vec<char>> {
+        text.lines().map(|line| line.chars().collect()).collect()
+    }
+
+    fn matching(text: &str, y: usize, x: usize) -> Option<((usize, usize), (usize, usize))> {
+        TagIndex::scan(&lines(text))
+            .find_matching(y, x)
+            .map(|(a, b)| ((a.y, a.name_start), (b.y, b.name_start)))
+    }
+
+    #[test]
+    fn markup_paths() {
+        assert!(is_markup_path(Path::new("index.HTML")));
+        assert!(is_markup_path(Path::new("App.tsx")));
+        assert!(!is_markup_path(Path::new("main.rs")));
+    }
+
+    #[test]
+    fn nested_tags_match_their_own_partner() {
+        let text = "<div>\n  <div><br></div>\n</div>";
+        assert_eq!(matching(text, 0, 1), Some(((0, 1), (2, 2))));
+        assert_eq!(matching(text, 2, 3), Some(((2, 2), (0, 1))));
+        assert_eq!(matching(text, 1, 4), Some(((1, 3), (1, 13))));
+        assert_eq!(matching(text, 1, 9), None);
+    }
+
+    #[test]
+    fn comments_and_comparisons_are_not_tags() {
+        let text = "<p><!-- <p> --></p>\nif a < b && Vec<T> {}";
+        assert_eq!(matching(text, 0, 1), Some(((0, 1), (0, 17))));
+        assert_eq!(matching(text, 1, 5), None);
+    }
+
+    #[test]
+    fn closing_tags_are_offered_after_an_opening_tag() {
+        let line: Vec<char> = "<a href=\"x>y\">".chars().collect();
+        assert_eq!(closing_tag_for(&line, line.len()), Some("</a>".to_string()));
+        for text in ["<br>", "<img/>", "a<b>", "<p>", "<a href=\"x>"] {
+            let line: Vec<char> = text.chars().collect();
+            let cursor = if text == "<p>" { 2 } else { line.len() };
+            assert_eq!(closing_tag_for(&line, cursor), None, "{text}");
+        }
+        let line: Vec<char> = "<p></p>".chars().collect();
+        assert_eq!(closing_tag_for(&line, 3), None);
+    }
+}
@@ -0,0 +1,89 @@
+pub struct Template {
+    pub label: &'static str,
+    file_names: &'static [&'static str],
+    contents: &'static str,
+}
+
+const GITIGNORE: &[&str] = &[".gitignore"];
+const LICENSE: &[&str] = &["LICENSE", "LICENSE.txt", "LICENSE.md", "COPYING"];
+const CARGO_TOML: &[&str] = &["Cargo.toml"];
+
+const TEMPLATES: &[Template] = &[
+    Template {
+        label: "Rust .gitignore",
+        file_names: GITIGNORE,
+        contents: include_str!("../assets/templates/rust.gitignore"),
+    },
+    Template {
+        label: "Node .gitignore",
+        file_names: GITIGNORE,
+        contents: include_str!("../assets/templates/node.gitignore"),
+    },
+    Template {
+        label: "Python .gitignore",
+        file_names: GITIGNORE,
+        contents: include_str!("../assets/templates/python.gitignore"),
+    },
+    Template {
+        label: "Go .gitignore",
+        file_names: GITIGNORE,
+        contents: include_str!("../assets/templates/go.gitignore"),
+    },
+    Template {
+        label: "MIT License",
+        file_names: LICENSE,
+        contents: include_str!("../assets/templates/mit.license"),
+    },
+    Template {
+        label: "BSD 3-Clause License",
+        file_names: LICENSE,
+        contents: include_str!("../assets/templates/bsd-3-clause.license"),
+    },
+    Template {
+        label: "Cargo package",
+        file_names: CARGO_TOML,
+        contents: include_str!("../assets/templates/cargo-bin.toml"),
+    },
+    Template {
+        label: "Cargo workspace",
+        file_names: CARGO_TOML,
+        contents: include_str!("../assets/templates/cargo-workspace.toml"),
+    },
+    Template {
+        label: "Node package.json",
+        file_names: &["package.json"],
+        contents: include_str!("../assets/templates/package.json"),
+    },
+    Template {
+        label: "Python pyproject.toml",
+        file_names: &["pyproject.toml"],
+        contents: include_str!("../assets/templates/pyproject.toml"),
+    },
+];
+
+pub fn for_file_name(name: &str) -> Vec<&'static Template> {
+    TEMPLATES
+        .iter()
+        .filter(|template| {
+            template
+                .file_names
+                .iter()
+                .any(|candidate| candidate.eq_ignore_ascii_case(name))
+        })
+        .collect()
+}
+
+/// Fills `{{key}}` placeholders from `vars`; unknown placeholders are kept.
+pub fn fill(text: &str, vars: &[(&str, &str)]) -> String {
+    let mut text = text.to_string();
+    for (key, value) in vars {
+        text = text.replace(&format!("{{{{{}}}}}", key), value);
+    }
+    text
+}
+
+impl Template {
+    pub fn render(&self, vars: &[(&str, &str)]) -> String {
+        fill(self.contents, vars)
+    }
+}
@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+
+const TEXT: &str = include_str!("../assets/tutor.md");
+
+/// What a lesson waits for.
+pub enum Goal {
+    /// A line of the lesson's section reads exactly this.
+    Line(&'static str),
+    /// The cursor is on a line starting with this.
+    CursorOn(&'static str),
+    CursorLine(usize),
+    Selection,
+    Palette,
+    Saved,
+}
+
+pub struct Lesson {
+    pub title: &'static str,
+    /// Shown in the status bar while the lesson is the current one.
+    pub hint: &'static str,
+    pub goal: Goal,
+}
+
+pub const LESSONS: &[Lesson] = &[
+    Lesson {
+        title: "Moving around",
+        hint: "move the cursor to the line marked `>`",
+        goal: Goal::CursorOn("> Put the cursor on this line."),
+    },
+    Lesson {
+        title: "Typing",
+        hint: "fix the line to read `> The cat sat on the mat.`",
+        goal: Goal::Line("> The cat sat on the mat."),
+    },
+    Lesson {
+        title: "Deleting",
+        hint: "remove the doubled words with Backspace or Delete",
+        goal: Goal::Line("> This line has some extra words."),
+    },
+    Lesson {
+        title: "Selecting",
+        hint: "hold Shift and move the cursor to select text",
+        goal: Goal::Selection,
+    },
+    Lesson {
+        title: "Copy and paste",
+        hint: "make the line read `> apple banana` with Ctrl+C and Ctrl+V",
+        goal: Goal::Line("> apple banana"),
+    },
+    Lesson {
+        title: "Finding",
+        hint: "Ctrl+F, search for `treasure` and Tab to the line starting `> Found`",
+        goal: Goal::CursorOn("> Found the treasure"),
+    },
+    Lesson {
+        title: "Going to a line",
+        hint: "Ctrl+G, then 1 and Enter",
+        goal: Goal::CursorLine(0),
+    },
+    Lesson {
+        title: "The command palette",
+        hint: "press Ctrl+P",
+        goal: Goal::Palette,
+    },
+    Lesson {
+        title: "Saving",
+        hint: "press Ctrl+S",
+        goal: Goal::Saved,
+    },
+];
+
+/// What a goal is checked against.
+pub struct State<'a> {
+    pub lines: &'a [Vec<char>],
+    pub cursor_y: usize,
+    pub selecting: bool,
+    pub palette: bool,
+    pub modified: bool,
+}
+
+/// A fresh copy of the tutorial in the cache folder, replacing whatever
+/// the last run left there.
+pub fn write_document() -> std::io::Result<PathBuf> {
+    let dir = crate::cache_dir();
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("tutor.md");
+    std::fs::write(&path, TEXT)?;
+    Ok(path)
+}
+
+fn starts_with(line: &[char], prefix: &str) -> bool {
+    line.len() >= prefix.chars().count() && line.iter().zip(prefix.chars()).all(|(a, b)| *a == b)
+}
+
+/// The lines from lesson `index`'s heading up to the next heading.
+fn section(lines: &[Vec<char>], index: usize) -> &[Vec<char>] {
+    let heading = format!("## Lesson {}:", index + 1);
+    let Some(start) = lines.iter().position(|line| starts_with(line, &heading)) else {
+        return &[];
+    };
+    let end = lines[start + 1..]
+        .iter()
+        .position(|line| starts_with(line, "## "))
+        .map_or(lines.len(), |offset| start + 1 + offset);
+    &lines[start..end]
+}
+
+impl Lesson {
+    pub fn done(&self, index: usize, state: &State) -> bool {
+        match self.goal {
+            Goal::Line(text) => section(state.lines, index)
+                .iter()
+                .any(|line| line.iter().copied().eq(text.chars())),
+            Goal::CursorOn(prefix) => state
+                .lines
+                .get(state.cursor_y)
+                .is_some_and(|line| starts_with(line, prefix)),
+            Goal::CursorLine(y) => state.cursor_y == y,
+            Goal::Selection => state.selecting,
+            Goal::Palette => state.palette,
+            Goal::Saved => !state.modified,
+        }
+    }
+}
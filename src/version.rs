@@ -0,0 +1,100 @@
+use serde_json::Value;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+const TARGET: &str = env!("TERMI_TARGET");
+const PROFILE: &str = env!("TERMI_PROFILE");
+const FEATURES: &str = env!("TERMI_FEATURES");
+
+const LATEST_RELEASE_API: &str = "https://api.github.com/repos/tuna4ll/termi/releases/latest";
+pub const RELEASES_PAGE: &str = "https://github.com/tuna4ll/termi/releases";
+
+/// Version, build target and enabled Cargo features on one line.
+pub fn describe() -> String {
+    format!(
+        "termi {} ({}, {} build, features: {})",
+        VERSION,
+        TARGET,
+        PROFILE,
+        if FEATURES.is_empty() {
+            "none"
+        } else {
+            FEATURES
+        }
+    )
+}
+
+/// Arguments for the `curl` that asks GitHub for the latest release.
+pub fn check_args() -> Vec<String> {
+    [
+        "-sS",
+        "--max-time",
+        "15",
+        "-H",
+        "Accept: application/vnd.github+json",
+        LATEST_RELEASE_API,
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect()
+}
+
+/// The latest release's version from GitHub's reply, or why there is none.
+pub fn latest_release(response: &str) -> Result<String, String> {
+    let reply: Value = serde_json::from_str(response)
+        .map_err(|_| response.lines().next().unwrap_or("no response").to_string())?;
+    match reply.get("tag_name").and_then(Value::as_str) {
+        Some(tag) => Ok(tag.trim_start_matches('v').to_string()),
+        None => Err(reply
+            .get("message")
+            .and_then(Value::as_str)
+            .unwrap_or("unexpected reply from GitHub")
+            .to_string()),
+    }
+}
+
+// `1.2.10` as [1, 2, 10]; anything after a `-` is a pre-release and ignored.
+fn numbers(version: &str) -> Vec<u64> {
+    version
+        .split('-')
+        .next()
+        .unwrap_or("")
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0))
+        .collect()
+}
+
+pub fn is_newer(latest: &str) -> bool {
+    numbers(latest) > numbers(VERSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_compare_by_number() {
+        assert_eq!(VERSION, "0.1.0");
+        assert!(is_newer("0.1.1"));
+        assert!(is_newer("0.10.0"));
+        assert!(is_newer("1.0"));
+        assert!(!is_newer("0.1.0"));
+        assert!(!is_newer("0.0.9"));
+        assert!(!is_newer("0.1.0-rc.1"));
+    }
+
+    #[test]
+    fn latest_release_reads_the_tag() {
+        assert_eq!(
+            latest_release(r#"{"tag_name": "v0.2.0"}"#),
+            Ok("0.2.0".to_string())
+        );
+        assert_eq!(
+            latest_release(r#"{"message": "Not Found"}"#),
+            Err("Not Found".to_string())
+        );
+        assert_eq!(
+            latest_release("curl: (6) Could not resolve host\n"),
+            Err("curl: (6) Could not resolve host".to_string())
+        );
+    }
+}